@@ -1,4 +1,11 @@
 use crate::ast::*;
+use crate::composite::{self, Composite, Stage};
+use crate::custom_ops::{CustomOp, SnippetContext};
+use crate::interpreter::{Plan, Step, Weights};
+use crate::layer_params::{FACTOR, KERNEL, SIZE, STRIDE, target_shape, VOCAB};
+use crate::quantization::{self, QuantizedModel};
+use crate::safetensors::{self, Entry};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::fmt::Write;
 
 /// Assembly target architecture
@@ -10,6 +17,18 @@ pub enum AsmTarget {
     WASMUI,    // WebAssembly for UI components
 }
 
+impl AsmTarget {
+    /// Backend name of the target, as selected with `--backend`
+    pub fn name(&self) -> &'static str {
+        match self {
+            AsmTarget::X86_64 => "asm-x86_64",
+            AsmTarget::ARM64 => "asm-arm64",
+            AsmTarget::WASM => "wasm",
+            AsmTarget::WASMUI => "wasm-ui",
+        }
+    }
+}
+
 use crate::extensions::ui_extensions::UIComponentNode;
 
 /// Compiler for GaiaScript to assembly
@@ -19,16 +38,215 @@ pub struct AsmCompiler {
     temp_var_count: usize,
     label_count: usize,
     ui_components: Vec<UIComponentNode>,
+    // Safetensors container embedded in the data section, with its tensors
+    weights: Option<(Vec<u8>, Vec<Entry>)>,
+    // Whether to emit the int8 kernels that run quantized components
+    int8_kernels: bool,
+    blocks: BlockMode,
+    native: NativeState,
+}
+
+/// Bookkeeping of the native functions emitted for X86_64 (System V), ARM64
+/// (AAPCS64) and WASM: one per component, `void component_<id>(const float *in,
+/// float *out)`, and one per composite, calling runtime kernels
+/// `gaia_<layer>_<activation>`
+///
+/// Activations alternate between two static buffers, so the functions are
+/// not reentrant.
+#[derive(Default)]
+struct NativeState {
+    plans: HashMap<String, Result<Plan, String>>,
+    composites: HashMap<String, Result<Composite, String>>,
+    // Definitions of each component still to come; only the last is compiled
+    definitions: HashMap<String, usize>,
+    quantized: BTreeSet<String>,
+    // Steps of the component being compiled, one entry per layer node; a
+    // layer inside a counted loop has one step per repetition
+    steps: VecDeque<Vec<Step>>,
+    in_loop: bool,
+    output: usize,
+    defined: BTreeSet<String>,
+    // Functions called but defined elsewhere, with their number of arguments
+    externs: BTreeMap<String, usize>,
+    // Label of the last function emitted, which WASM exports as `forward`
+    last_function: Option<String>,
+    // Labels of the parameters used, with their sizes in floats
+    parameters: BTreeMap<String, usize>,
+    // Parameter tables of loops over repetitions with their own parameters,
+    // with the parameter of each repetition
+    tables: Vec<(String, Vec<String>)>,
+    // Floats in each activation buffer, stage buffer and the prediction buffer
+    activations: usize,
+    stages: usize,
+    predictions: usize,
+}
+
+impl NativeState {
+    fn plan(&mut self, ast: &ASTNode) {
+        let network = match ast {
+            ASTNode::Network(network) => network,
+            _ => return,
+        };
+        for node in &network.body {
+            match node {
+                ASTNode::Component(component) => {
+                    *self.definitions.entry(component.id.clone()).or_insert(0) += 1;
+                    self.plans.insert(component.id.clone(), composite::component_plan(ast, &component.id, None));
+                },
+                ASTNode::Loss(loss) => {
+                    self.composites.insert(composite_name(loss), Composite::from_loss(ast, loss));
+                },
+                _ => {},
+            }
+        }
+    }
+    
+    // Whether a component gets a function
+    fn has_function(&self, id: &str) -> bool {
+        !self.quantized.contains(id)
+            && matches!(self.plans.get(id), Some(Ok(plan)) if !plan.steps.is_empty() && unsupported_step(plan).is_none())
+    }
+    
+    // Whether every repetition of `block`, next in the steps, sees the same
+    // shapes and can run in one loop body. A shared block reuses one set of
+    // parameters; any other block needs its own parameters per repetition,
+    // which the loop body picks from a table indexed by the counter
+    fn is_uniform(&self, block: &BlockNode) -> bool {
+        let per = layer_count(&block.content);
+        let repetitions = block.repetitions.max(1);
+        if per == 0 || self.steps.len() < per * repetitions {
+            return false;
+        }
+        (0..per).all(|j| (1..repetitions).all(|r| {
+            let (first, other) = (&self.steps[j][0], &self.steps[r * per + j][0]);
+            first.input == other.input && first.output == other.output && first.layer.activation == other.layer.activation
+                && (block.shared == (first.key == other.key))
+        }))
+    }
+    
+    // Make the steps of every repetition of `block` the cursor of its loop
+    // body, returning the steps after the block
+    fn enter_loop(&mut self, block: &BlockNode) -> VecDeque<Vec<Step>> {
+        let per = layer_count(&block.content);
+        let repetitions = block.repetitions.max(1);
+        let steps: Vec<Step> = self.steps.drain(..per * repetitions).map(|mut variants| variants.remove(0)).collect();
+        let body = (0..per).map(|j| (0..repetitions).map(|r| steps[r * per + j].clone()).collect()).collect();
+        self.in_loop = true;
+        std::mem::replace(&mut self.steps, body)
+    }
+    
+    fn leave_loop(&mut self, rest: VecDeque<Vec<Step>>) {
+        self.steps = rest;
+        self.in_loop = false;
+    }
+    
+    // Whether the function of a stage's component runs with the shapes the stage sees
+    fn runs_as_planned(&self, stage: &Stage) -> bool {
+        match self.plans.get(&stage.component) {
+            Some(Ok(plan)) => plan.steps.len() == stage.plan.steps.len()
+                && plan.steps.iter().zip(&stage.plan.steps).all(|(a, b)| a.input == b.input && a.output == b.output),
+            _ => false,
+        }
+    }
+}
+
+/// Argument of a runtime call in the generated code
+enum Arg {
+    // Callee-saved register holding an activation buffer
+    Register(&'static str),
+    Value(usize),
+    Address(String),
+    // Label of a parameter tensor, or one per repetition of the enclosing loop
+    Tensor(Vec<String>),
+}
+
+/// Registers of the native functions by role; all but the arguments and the
+/// scratch register are callee-saved, so they survive runtime calls
+struct Registers {
+    // Repetition of the enclosing loop
+    counter: &'static str,
+    // Input and output of the next layer, the other activation buffer, and `out`
+    input: &'static str,
+    output: &'static str,
+    spare: &'static str,
+    result: &'static str,
+    arguments: &'static [&'static str],
+    // Loads stack arguments and parameter tables
+    scratch: &'static str,
+}
+
+const X86_64_REGISTERS: Registers = Registers {
+    counter: "rbx",
+    input: "r12",
+    output: "r13",
+    spare: "r14",
+    result: "r15",
+    arguments: &["rdi", "rsi", "rdx", "rcx", "r8", "r9"],
+    scratch: "rax",
+};
+
+const ARM64_REGISTERS: Registers = Registers {
+    counter: "x19",
+    input: "x20",
+    output: "x21",
+    spare: "x22",
+    result: "x23",
+    arguments: &["x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7"],
+    scratch: "x9",
+};
+
+// WebAssembly functions keep the roles in locals; arguments go on the
+// operand stack, so none are needed for them or for loading them
+const WASM_LOCALS: Registers = Registers {
+    counter: "$repetition",
+    input: "$x",
+    output: "$y",
+    spare: "$spare",
+    result: "$out",
+    arguments: &[],
+    scratch: "",
+};
+
+impl Registers {
+    fn saved(&self) -> [&'static str; 5] {
+        [self.counter, self.input, self.output, self.spare, self.result]
+    }
 }
 
+// Offset of embedded weights in WebAssembly memory; the first page stays free
+const WASM_WEIGHTS_OFFSET: usize = 65536;
+
 impl AsmCompiler {
     pub fn new(target: AsmTarget) -> Self {
+        Self::create(target, None)
+    }
+    
+    /// Compiler that embeds `weights` in the output (not for `WASMUI`, which
+    /// imports its memory)
+    pub fn with_weights(target: AsmTarget, weights: &Weights) -> Self {
+        Self::create(target, Some(safetensors::encode_with_layout(weights)))
+    }
+    
+    /// Compiler that embeds int8 components together with the kernels that
+    /// run them
+    pub fn with_quantized(target: AsmTarget, models: &[QuantizedModel]) -> Self {
+        let mut compiler = Self::create(target, Some(quantization::encode(models)));
+        compiler.int8_kernels = true;
+        compiler.native.quantized = models.iter().map(|m| m.component.clone()).collect();
+        compiler
+    }
+    
+    fn create(target: AsmTarget, weights: Option<(Vec<u8>, Vec<Entry>)>) -> Self {
         let mut compiler = Self {
             target,
             code: String::new(),
             temp_var_count: 0,
             label_count: 0,
             ui_components: Vec::new(),
+            weights,
+            int8_kernels: false,
+            blocks: BlockMode::Loop,
+            native: NativeState::default(),
         };
         
         // Add assembly preamble based on target
@@ -37,6 +255,11 @@ impl AsmCompiler {
         compiler
     }
     
+    /// Choose between counted loops and unrolled copies for blocks
+    pub fn set_block_mode(&mut self, mode: BlockMode) {
+        self.blocks = mode;
+    }
+    
     /// Generate a new unique label
     fn new_label(&mut self, prefix: &str) -> String {
         let label = format!("{}_L{}", prefix, self.label_count);
@@ -55,22 +278,21 @@ impl AsmCompiler {
     fn add_preamble(&mut self) {
         match self.target {
             AsmTarget::X86_64 => {
-                writeln!(&mut self.code, "; GaiaScript X86-64 Assembly").unwrap();
+                writeln!(&mut self.code, "; GaiaScript X86-64 Assembly (NASM, System V AMD64 ABI)").unwrap();
+                writeln!(&mut self.code, "; Link with gaia_runtime_x86_64.s and libm").unwrap();
+                writeln!(&mut self.code, "default rel").unwrap();
                 writeln!(&mut self.code, "section .text").unwrap();
-                writeln!(&mut self.code, "global _start").unwrap();
-                writeln!(&mut self.code, "_start:").unwrap();
             },
             AsmTarget::ARM64 => {
-                writeln!(&mut self.code, "// GaiaScript ARM64 Assembly").unwrap();
+                writeln!(&mut self.code, "// GaiaScript ARM64 Assembly (GNU as, AAPCS64)").unwrap();
+                writeln!(&mut self.code, "// Link with gaia_runtime_arm64.s and libm").unwrap();
                 writeln!(&mut self.code, ".text").unwrap();
-                writeln!(&mut self.code, ".global _start").unwrap();
-                writeln!(&mut self.code, "_start:").unwrap();
             },
             AsmTarget::WASM => {
                 writeln!(&mut self.code, ";; GaiaScript WebAssembly").unwrap();
+                writeln!(&mut self.code, ";; forward(in, out) and component_<id>(in, out) take byte offsets of float32").unwrap();
+                writeln!(&mut self.code, ";; tensors in the exported memory; the first page is free for them").unwrap();
                 writeln!(&mut self.code, "(module").unwrap();
-                writeln!(&mut self.code, "  (memory (export \"memory\") 1)").unwrap();
-                writeln!(&mut self.code, "  (func (export \"run\") (result i32)").unwrap();
             },
             AsmTarget::WASMUI => {
                 writeln!(&mut self.code, ";; GaiaUI WebAssembly Component").unwrap();
@@ -79,11 +301,11 @@ impl AsmCompiler {
                 writeln!(&mut self.code, "  (import \"env\" \"memory\" (memory 1))").unwrap();
                 writeln!(&mut self.code, "  (import \"env\" \"log_value\" (func $log_value (param i32)))").unwrap();
                 writeln!(&mut self.code, "  (import \"env\" \"update_dom\" (func $update_dom (param i32 i32)))").unwrap();
-                writeln!(&mut self.code, "").unwrap();
+                writeln!(&mut self.code).unwrap();
                 writeln!(&mut self.code, "  ;; Global state management").unwrap();
                 writeln!(&mut self.code, "  (global $next_alloc_ptr (mut i32) (i32.const 1024))").unwrap();
                 writeln!(&mut self.code, "  (global $component_counter (mut i32) (i32.const 0))").unwrap();
-                writeln!(&mut self.code, "").unwrap();
+                writeln!(&mut self.code).unwrap();
                 // Add memory allocation helper
                 writeln!(&mut self.code, "  ;; Memory allocation").unwrap();
                 writeln!(&mut self.code, "  (func $allocate (export \"allocate\") (param $size i32) (result i32)").unwrap();
@@ -107,22 +329,22 @@ impl AsmCompiler {
     /// Add architecture-specific postamble (closing code)
     fn add_postamble(&mut self) {
         match self.target {
-            AsmTarget::X86_64 => {
-                writeln!(&mut self.code, "    ; Exit system call").unwrap();
-                writeln!(&mut self.code, "    mov rax, 60 ; sys_exit").unwrap();
-                writeln!(&mut self.code, "    mov rdi, 0  ; exit code 0").unwrap();
-                writeln!(&mut self.code, "    syscall").unwrap();
-            },
-            AsmTarget::ARM64 => {
-                writeln!(&mut self.code, "    // Exit").unwrap();
-                writeln!(&mut self.code, "    mov x0, #0  // Exit code 0").unwrap();
-                writeln!(&mut self.code, "    mov x8, #93 // sys_exit").unwrap();
-                writeln!(&mut self.code, "    svc #0").unwrap();
-            },
-            AsmTarget::WASM => {
-                writeln!(&mut self.code, "    i32.const 0 ;; Return value").unwrap();
-                writeln!(&mut self.code, "  )").unwrap();
-                writeln!(&mut self.code, ")").unwrap();
+            AsmTarget::X86_64 | AsmTarget::ARM64 | AsmTarget::WASM => {
+                let embedded: BTreeMap<String, usize> = self.weights.iter()
+                    .flat_map(|(_, entries)| entries)
+                    .map(|entry| (weight_label(&entry.name), entry.offset))
+                    .collect();
+                let weights_size = self.weights.as_ref().map(|(bytes, _)| bytes.len()).unwrap_or(0);
+                self.add_int8_kernels();
+                self.add_weights_section();
+                if self.target == AsmTarget::WASM {
+                    self.add_wasm_data(&embedded, weights_size);
+                    writeln!(&mut self.code).unwrap();
+                    self.code.push_str(include_str!("asm_runtime_wasm.wat"));
+                    writeln!(&mut self.code, ")").unwrap();
+                } else {
+                    self.add_native_data(&embedded);
+                }
             },
             AsmTarget::WASMUI => {
                 // Add main entry point (run function)
@@ -143,6 +365,234 @@ impl AsmCompiler {
         }
     }
     
+    /// Embed the weights as a safetensors container: `gaia_weights` is the
+    /// container and every tensor gets a label on its data
+    fn add_weights_section(&mut self) {
+        let (bytes, entries) = match self.weights.take() {
+            Some(weights) => weights,
+            None => return,
+        };
+        let header_end = entries.first().map(|e| e.offset).unwrap_or(bytes.len());
+        
+        match self.target {
+            AsmTarget::X86_64 | AsmTarget::ARM64 => {
+                let x86 = self.target == AsmTarget::X86_64;
+                let (comment, bytes_directive, words_directive) = if x86 { (";", "db", "dd") } else { ("//", ".byte", ".word") };
+                writeln!(&mut self.code).unwrap();
+                if x86 {
+                    writeln!(&mut self.code, "section .rodata").unwrap();
+                    writeln!(&mut self.code, "align 8").unwrap();
+                    writeln!(&mut self.code, "global gaia_weights").unwrap();
+                    writeln!(&mut self.code, "global gaia_weights_size").unwrap();
+                } else {
+                    writeln!(&mut self.code, ".section .rodata").unwrap();
+                    writeln!(&mut self.code, ".balign 8").unwrap();
+                    writeln!(&mut self.code, ".global gaia_weights").unwrap();
+                    writeln!(&mut self.code, ".global gaia_weights_size").unwrap();
+                }
+                writeln!(&mut self.code, "{} Network weights as a safetensors container ({} bytes)", comment, bytes.len()).unwrap();
+                writeln!(&mut self.code, "gaia_weights:").unwrap();
+                for line in bytes[..header_end].chunks(16) {
+                    let values: Vec<String> = line.iter().map(|b| format!("0x{:02x}", b)).collect();
+                    writeln!(&mut self.code, "    {} {}", bytes_directive, values.join(", ")).unwrap();
+                }
+                for entry in &entries {
+                    writeln!(&mut self.code, "{} {} {} {:?}", comment, entry.name, entry.dtype, entry.shape).unwrap();
+                    writeln!(&mut self.code, "{}:", weight_label(&entry.name)).unwrap();
+                    let data = &bytes[entry.offset..entry.offset + entry.len];
+                    if entry.dtype == "I8" {
+                        for line in data.chunks(16) {
+                            let values: Vec<String> = line.iter().map(|b| format!("0x{:02x}", b)).collect();
+                            writeln!(&mut self.code, "    {} {}", bytes_directive, values.join(", ")).unwrap();
+                        }
+                        continue;
+                    }
+                    for line in data.chunks(32) {
+                        let values: Vec<String> = line.chunks(4)
+                            .map(|w| format!("0x{:08x}", u32::from_le_bytes([w[0], w[1], w[2], w[3]])))
+                            .collect();
+                        writeln!(&mut self.code, "    {} {}", words_directive, values.join(", ")).unwrap();
+                    }
+                }
+                writeln!(&mut self.code, "gaia_weights_end:").unwrap();
+                writeln!(&mut self.code, "gaia_weights_size:").unwrap();
+                let quad = if x86 { "dq" } else { ".quad" };
+                writeln!(&mut self.code, "    {} gaia_weights_end - gaia_weights", quad).unwrap();
+            },
+            AsmTarget::WASM => {
+                writeln!(&mut self.code).unwrap();
+                writeln!(&mut self.code, "  ;; Network weights as a safetensors container ({} bytes)", bytes.len()).unwrap();
+                writeln!(&mut self.code, "  (global (export \"gaia_weights\") i32 (i32.const {}))", WASM_WEIGHTS_OFFSET).unwrap();
+                writeln!(&mut self.code, "  (global (export \"gaia_weights_size\") i32 (i32.const {}))", bytes.len()).unwrap();
+                writeln!(&mut self.code, "  (data (i32.const {})", WASM_WEIGHTS_OFFSET).unwrap();
+                let mut start = 0;
+                for entry in entries.iter().map(Some).chain(std::iter::once(None)) {
+                    let end = entry.map(|e| e.offset).unwrap_or(bytes.len());
+                    for line in bytes[start..end].chunks(32) {
+                        let escaped: String = line.iter().map(|b| format!("\\{:02x}", b)).collect();
+                        writeln!(&mut self.code, "    \"{}\"", escaped).unwrap();
+                    }
+                    if let Some(entry) = entry {
+                        writeln!(&mut self.code, "    ;; {} {} {:?} at {}", entry.name, entry.dtype, entry.shape, WASM_WEIGHTS_OFFSET + entry.offset).unwrap();
+                    }
+                    start = end;
+                }
+                writeln!(&mut self.code, "  )").unwrap();
+            },
+            AsmTarget::WASMUI => {},
+        }
+    }
+    
+    /// Parameter tables of loops, the activation buffers, and a zeroed
+    /// tensor for every parameter without embedded weights, for the host to fill
+    fn add_native_data(&mut self, embedded: &BTreeMap<String, usize>) {
+        let x86 = self.target == AsmTarget::X86_64;
+        let code = &mut self.code;
+        let native = &self.native;
+        if !native.tables.is_empty() {
+            writeln!(code).unwrap();
+            writeln!(code, "{}", if x86 { "section .data\nalign 8" } else { ".data\n.balign 8" }).unwrap();
+            for (table, labels) in &native.tables {
+                writeln!(code, "{}: {} {}", table, if x86 { "dq" } else { ".quad" }, labels.join(", ")).unwrap();
+            }
+        }
+        
+        let buffers = [
+            ("gaia_activations_a", native.activations),
+            ("gaia_activations_b", native.activations),
+            ("gaia_stage_a", native.stages),
+            ("gaia_stage_b", native.stages),
+            ("gaia_prediction", native.predictions),
+        ];
+        let missing: Vec<(&String, &usize)> = native.parameters.iter().filter(|(label, _)| !embedded.contains_key(*label)).collect();
+        if native.activations > 0 || !missing.is_empty() {
+            writeln!(code).unwrap();
+            writeln!(code, "{}", if x86 { "section .bss\nalignb 16" } else { ".bss\n.balign 16" }).unwrap();
+        }
+        let reserve = |code: &mut String, label: &str, floats: usize| {
+            if x86 {
+                writeln!(code, "{}: resd {}", label, floats).unwrap();
+            } else {
+                writeln!(code, "{}:\n    .zero {}", label, 4 * floats).unwrap();
+            }
+        };
+        for (label, floats) in buffers {
+            if floats > 0 {
+                reserve(code, label, floats);
+            }
+        }
+        if !missing.is_empty() {
+            writeln!(code, "{} Parameters without embedded weights", if x86 { ";" } else { "//" }).unwrap();
+            for (label, floats) in missing {
+                writeln!(code, "{}global {}", if x86 { "" } else { "." }, label).unwrap();
+                reserve(code, label, *floats);
+            }
+        }
+        
+        writeln!(code).unwrap();
+        if x86 {
+            writeln!(code, "section .note.GNU-stack noalloc noexec nowrite progbits").unwrap();
+        } else {
+            writeln!(code, ".section .note.GNU-stack,\"\",%progbits").unwrap();
+        }
+    }
+    
+    /// The same data in WebAssembly memory after the embedded weights, each
+    /// address in a global of the label's name; the parameters to fill are
+    /// exported, as are the module's memory, `gaia_heap`, the first byte after
+    /// the data, and `forward`, the last function emitted
+    fn add_wasm_data(&mut self, embedded: &BTreeMap<String, usize>, weights_size: usize) {
+        let code = &mut self.code;
+        let native = &self.native;
+        let mut globals = String::new();
+        let mut addresses = BTreeMap::new();
+        let mut next = (WASM_WEIGHTS_OFFSET + weights_size).next_multiple_of(16);
+        let mut missing = false;
+        for (label, floats) in &native.parameters {
+            let address = match embedded.get(label) {
+                Some(offset) => {
+                    writeln!(globals, "  (global ${} i32 (i32.const {}))", label, WASM_WEIGHTS_OFFSET + offset).unwrap();
+                    WASM_WEIGHTS_OFFSET + offset
+                },
+                None => {
+                    if !missing {
+                        writeln!(globals, "  ;; Parameters without embedded weights, zeroed").unwrap();
+                        missing = true;
+                    }
+                    writeln!(globals, "  (global ${} (export \"{}\") i32 (i32.const {}))", label, label, next).unwrap();
+                    next += 4 * floats;
+                    next - 4 * floats
+                },
+            };
+            addresses.insert(label.as_str(), address);
+        }
+        
+        let mut tables = String::new();
+        for (table, labels) in &native.tables {
+            let escaped: String = labels.iter()
+                .flat_map(|label| (addresses[label.as_str()] as u32).to_le_bytes())
+                .map(|b| format!("\\{:02x}", b))
+                .collect();
+            writeln!(globals, "  (global ${} i32 (i32.const {}))", table, next).unwrap();
+            writeln!(tables, "  (data (i32.const {}) \"{}\")  ;; {}", next, escaped, labels.join(", ")).unwrap();
+            next += 4 * labels.len();
+        }
+        
+        let buffers = [
+            ("gaia_activations_a", native.activations),
+            ("gaia_activations_b", native.activations),
+            ("gaia_stage_a", native.stages),
+            ("gaia_stage_b", native.stages),
+            ("gaia_prediction", native.predictions),
+        ];
+        for (label, floats) in buffers {
+            if floats > 0 {
+                writeln!(globals, "  (global ${} i32 (i32.const {}))", label, next).unwrap();
+                next += 4 * floats;
+            }
+        }
+        
+        writeln!(code).unwrap();
+        writeln!(code, "  (memory (export \"memory\") {})", next.div_ceil(65536)).unwrap();
+        code.push_str(&globals);
+        code.push_str(&tables);
+        writeln!(code, "  (global (export \"gaia_heap\") i32 (i32.const {}))", next).unwrap();
+        if let Some(label) = &native.last_function {
+            writeln!(code, "  (export \"forward\" (func ${}))", label).unwrap();
+        }
+    }
+    
+    /// Declare the runtime functions the code calls, after the preamble
+    fn add_externs(&mut self) {
+        let declarations: String = self.native.externs.iter()
+            .map(|(name, arguments)| match self.target {
+                // Imported from the host, taking pointers and sizes
+                AsmTarget::WASM => format!("  (import \"env\" \"{}\" (func ${}{}))\n", name, name, " (param i32)".repeat(*arguments)),
+                _ => format!("extern {}\n", name),
+            })
+            .collect();
+        let start = if self.target == AsmTarget::WASM { "(module\n" } else { "section .text\n" };
+        let position = self.code.find(start).map(|p| p + start.len()).unwrap_or(0);
+        self.code.insert_str(position, &declarations);
+    }
+    
+    /// Int8 kernels for quantized components: a strided dot product with
+    /// int32 accumulation, requantization to int8 and a dense layer built
+    /// from both, matching `quantization::dense_i8` and `requantize`
+    fn add_int8_kernels(&mut self) {
+        if !self.int8_kernels {
+            return;
+        }
+        let kernels = match self.target {
+            AsmTarget::X86_64 => X86_64_INT8_KERNELS,
+            AsmTarget::ARM64 => ARM64_INT8_KERNELS,
+            AsmTarget::WASM => WASM_INT8_KERNELS,
+            AsmTarget::WASMUI => return,
+        };
+        writeln!(&mut self.code).unwrap();
+        self.code.push_str(kernels);
+    }
+    
     /// Add WebAssembly functions for UI components
     fn add_ui_component_functions(&mut self) {
         writeln!(&mut self.code, "  ;; Component functions").unwrap();
@@ -262,8 +712,14 @@ impl AsmCompiler {
     
     /// Compile a GaiaScript AST to assembly
     pub fn compile(&mut self, ast: &ASTNode) -> String {
+        if self.is_native() {
+            self.native.plan(ast);
+        }
         self.generate_code(ast);
         self.add_postamble();
+        if matches!(self.target, AsmTarget::X86_64 | AsmTarget::WASM) {
+            self.add_externs();
+        }
         self.code.clone()
     }
     
@@ -307,26 +763,29 @@ impl AsmCompiler {
             },
         }
         
-        // Compile network body
+        // Compile network body; native code is made of functions, so layers
+        // outside components are left out
         for node in &network.body {
+            if self.is_native() && !matches!(node, ASTNode::Component(_) | ASTNode::Loss(_)) {
+                if layer_count(node) > 0 {
+                    let comment = self.native_comment();
+                    writeln!(&mut self.code, "{} Not in a component, so not compiled: {}", comment, node).unwrap();
+                }
+                continue;
+            }
             self.generate_code(node);
         }
     }
     
     /// Compile a component node
     fn compile_component(&mut self, component: &ComponentNode) {
-        let label = format!("component_{}", component.id);
-        
         match self.target {
-            AsmTarget::X86_64 => {
-                writeln!(&mut self.code, "{}:", label).unwrap();
-                writeln!(&mut self.code, "    ; Component: {}", component.id).unwrap();
-            },
-            AsmTarget::ARM64 => {
-                writeln!(&mut self.code, "{}:", label).unwrap();
-                writeln!(&mut self.code, "    // Component: {}", component.id).unwrap();
+            AsmTarget::X86_64 | AsmTarget::ARM64 | AsmTarget::WASM => {
+                if !self.begin_function(component) {
+                    return;
+                }
             },
-            AsmTarget::WASM | AsmTarget::WASMUI => {
+            AsmTarget::WASMUI => {
                 writeln!(&mut self.code, "    ;; Component: {}", component.id).unwrap();
                 // In WASM, we'd create a function for each component
                 writeln!(&mut self.code, "    ;; (func ${} (param i32) (result i32)", component.id).unwrap();
@@ -337,21 +796,136 @@ impl AsmCompiler {
         self.generate_code(&component.expr);
         
         // Return from component function
+        match self.target {
+            AsmTarget::X86_64 | AsmTarget::ARM64 | AsmTarget::WASM => self.end_function(),
+            AsmTarget::WASMUI => {
+                writeln!(&mut self.code, "    ;; )").unwrap();
+            },
+        }
+    }
+    
+    /// Open the function of a component, or explain in a comment why it has
+    /// none; the input pointer starts in the input register and the output
+    /// goes to the result register
+    fn begin_function(&mut self, component: &ComponentNode) -> bool {
+        let id = &component.id;
+        let comment = self.native_comment();
+        if let Some(count) = self.native.definitions.get_mut(id) {
+            *count -= 1;
+            if *count > 0 {
+                writeln!(&mut self.code, "{} Component {}: redefined below", comment, id).unwrap();
+                return false;
+            }
+        }
+        if self.native.quantized.contains(id) {
+            writeln!(&mut self.code, "{} Component {}: quantized, run with gaia_dense_i8", comment, id).unwrap();
+            return false;
+        }
+        let plan = match self.native.plans.get(id) {
+            Some(Ok(plan)) if !plan.steps.is_empty() => plan.clone(),
+            Some(Err(error)) => {
+                writeln!(&mut self.code, "{} Component {}: {}", comment, id, error).unwrap();
+                return false;
+            },
+            _ => {
+                writeln!(&mut self.code, "{} Component {}: no layers", comment, id).unwrap();
+                return false;
+            },
+        };
+        if let Some(step) = unsupported_step(&plan) {
+            writeln!(&mut self.code, "{} Component {}: the runtime has no kernel for `{}`", comment, id, step.layer.symbol()).unwrap();
+            return false;
+        }
+        
+        let label = function_label("component", id);
+        let registers = self.registers();
+        writeln!(&mut self.code).unwrap();
+        writeln!(&mut self.code, "{} Component {}: {} → {}", comment, id, plan.input, plan.output()).unwrap();
+        writeln!(&mut self.code, "{} void {}(const float *in, float *out)", comment, label).unwrap();
         match self.target {
             AsmTarget::X86_64 => {
-                writeln!(&mut self.code, "    ret").unwrap();
+                writeln!(&mut self.code, "global {}", label).unwrap();
+                writeln!(&mut self.code, "{}:", label).unwrap();
+                for register in registers.saved() {
+                    writeln!(&mut self.code, "    push {}", register).unwrap();
+                }
+                writeln!(&mut self.code, "    mov r12, rdi                ; input of the next layer").unwrap();
+                writeln!(&mut self.code, "    lea r13, [rel gaia_activations_a]  ; its output").unwrap();
+                writeln!(&mut self.code, "    lea r14, [rel gaia_activations_b]").unwrap();
+                writeln!(&mut self.code, "    mov r15, rsi").unwrap();
             },
-            AsmTarget::ARM64 => {
-                writeln!(&mut self.code, "    ret").unwrap();
+            AsmTarget::WASM => {
+                writeln!(&mut self.code, "  (func ${} (export \"{}\") (param $in i32) (param $out i32)", label, label).unwrap();
+                writeln!(&mut self.code, "    (local $x i32) (local $y i32) (local $spare i32) (local $repetition i32)").unwrap();
+                writeln!(&mut self.code, "    (local.set $x (local.get $in))  ;; input of the next layer").unwrap();
+                writeln!(&mut self.code, "    (local.set $y (global.get $gaia_activations_a))  ;; its output").unwrap();
+                writeln!(&mut self.code, "    (local.set $spare (global.get $gaia_activations_b))").unwrap();
             },
-            AsmTarget::WASM | AsmTarget::WASMUI => {
-                writeln!(&mut self.code, "    ;; )").unwrap();
+            _ => {
+                writeln!(&mut self.code, ".global {}", label).unwrap();
+                writeln!(&mut self.code, "{}:", label).unwrap();
+                writeln!(&mut self.code, "    stp x29, x30, [sp, #-64]!").unwrap();
+                writeln!(&mut self.code, "    mov x29, sp").unwrap();
+                writeln!(&mut self.code, "    stp x19, x20, [sp, #16]").unwrap();
+                writeln!(&mut self.code, "    stp x21, x22, [sp, #32]").unwrap();
+                writeln!(&mut self.code, "    str x23, [sp, #48]").unwrap();
+                writeln!(&mut self.code, "    mov x20, x0                 // input of the next layer").unwrap();
+                self.load_address("x21", "gaia_activations_a");
+                self.load_address("x22", "gaia_activations_b");
+                writeln!(&mut self.code, "    mov x23, x1").unwrap();
             },
         }
+        
+        let largest = plan.steps.iter().map(|s| s.output.elements()).max().unwrap_or(0);
+        self.native.activations = self.native.activations.max(largest);
+        self.native.output = plan.output().elements();
+        self.native.steps = plan.steps.into_iter().map(|step| vec![step]).collect();
+        self.native.last_function = Some(label);
+        true
+    }
+    
+    /// Copy the last activations to the output and return
+    fn end_function(&mut self) {
+        match self.target {
+            AsmTarget::X86_64 => {
+                writeln!(&mut self.code, "    mov rdi, r15").unwrap();
+                writeln!(&mut self.code, "    mov rsi, r12").unwrap();
+                writeln!(&mut self.code, "    mov rcx, {}", self.native.output).unwrap();
+                writeln!(&mut self.code, "    rep movsd").unwrap();
+                for register in X86_64_REGISTERS.saved().iter().rev() {
+                    writeln!(&mut self.code, "    pop {}", register).unwrap();
+                }
+            },
+            AsmTarget::WASM => {
+                self.call_runtime("gaia_flatten_none", vec![
+                    Arg::Register("$x"), Arg::Register("$out"), Arg::Value(1), Arg::Value(self.native.output),
+                ]);
+                writeln!(&mut self.code, "  )").unwrap();
+                return;
+            },
+            _ => {
+                self.call_runtime("gaia_flatten_none", vec![
+                    Arg::Register("x20"), Arg::Register("x23"), Arg::Value(1), Arg::Value(self.native.output),
+                ]);
+                writeln!(&mut self.code, "    ldr x23, [sp, #48]").unwrap();
+                writeln!(&mut self.code, "    ldp x21, x22, [sp, #32]").unwrap();
+                writeln!(&mut self.code, "    ldp x19, x20, [sp, #16]").unwrap();
+                writeln!(&mut self.code, "    ldp x29, x30, [sp], #64").unwrap();
+            },
+        }
+        writeln!(&mut self.code, "    ret").unwrap();
     }
     
     /// Compile a layer node
     fn compile_layer(&mut self, layer: &LayerNode) {
+        if self.is_native() {
+            self.compile_native_layer(layer);
+            return;
+        }
+        if let LayerType::Custom(op) = &layer.layer_type {
+            self.compile_custom(layer, op.as_ref());
+            return;
+        }
         let layer_str = match &layer.layer_type {
             LayerType::Convolutional(_) => "conv",
             LayerType::Dense(_) => "dense",
@@ -365,68 +939,379 @@ impl AsmCompiler {
             LayerType::BatchSize => "batch_size",
             LayerType::TransposeConv => "transpose_conv",
             LayerType::Attention => "attention",
+            LayerType::Custom(_) => unreachable!("custom operations are compiled by `compile_custom`"),
         };
         
-        let activation_str = match layer.activation {
-            ActivationFunction::ReLU => "relu",
-            ActivationFunction::Sigmoid => "sigmoid",
-            ActivationFunction::Tanh => "tanh",
-            ActivationFunction::Softmax => "softmax",
-            ActivationFunction::None => "none",
-        };
+        let activation_str = activation_name(&layer.activation);
         
         match self.target {
-            AsmTarget::X86_64 => {
-                writeln!(&mut self.code, "    ; Layer: {} with activation {}", layer_str, activation_str).unwrap();
-                writeln!(&mut self.code, "    call gaia_{}_{}", layer_str, activation_str).unwrap();
-            },
-            AsmTarget::ARM64 => {
-                writeln!(&mut self.code, "    // Layer: {} with activation {}", layer_str, activation_str).unwrap();
-                writeln!(&mut self.code, "    bl gaia_{}_{}", layer_str, activation_str).unwrap();
-            },
-            AsmTarget::WASM | AsmTarget::WASMUI => {
+            AsmTarget::X86_64 | AsmTarget::ARM64 | AsmTarget::WASM => unreachable!("native layers are compiled by `compile_native_layer`"),
+            AsmTarget::WASMUI => {
                 writeln!(&mut self.code, "    ;; Layer: {} with activation {}", layer_str, activation_str).unwrap();
                 writeln!(&mut self.code, "    call ${}_{}  ;; Call the layer function", layer_str, activation_str).unwrap();
             },
         }
         
-        // Generate parameter setup code
-        for (i, param) in layer.params.iter().enumerate() {
+        // Generate parameter setup code from the resolved schema values
+        for (name, value) in layer.resolved_params() {
             match self.target {
-                AsmTarget::X86_64 => {
-                    writeln!(&mut self.code, "    mov r{}, {}", i, param).unwrap();
-                },
-                AsmTarget::ARM64 => {
-                    writeln!(&mut self.code, "    mov x{}, #{}", i, param).unwrap();
-                },
-                AsmTarget::WASM | AsmTarget::WASMUI => {
-                    writeln!(&mut self.code, "    i32.const {}  ;; Parameter {}", param, i).unwrap();
+                AsmTarget::X86_64 | AsmTarget::ARM64 | AsmTarget::WASM => unreachable!("native layers are compiled by `compile_native_layer`"),
+                AsmTarget::WASMUI => {
+                    writeln!(&mut self.code, "    i32.const {}  ;; {}", value, name).unwrap();
                 },
             }
         }
     }
     
-    /// Compile a block node (repeated layers)
+    /// Compile a custom operation: its snippet for this target when it has one,
+    /// otherwise a call to `gaia_custom_<symbol>` provided at link time
+    fn compile_custom(&mut self, layer: &LayerNode, op: &dyn CustomOp) {
+        let comment = match self.target {
+            AsmTarget::X86_64 => ";",
+            AsmTarget::ARM64 => "//",
+            AsmTarget::WASM | AsmTarget::WASMUI => ";;",
+        };
+        writeln!(&mut self.code, "    {} Layer: {}", comment, layer).unwrap();
+        
+        match self.custom_snippet(layer, op) {
+            Some(code) => self.push_snippet(&code),
+            None => match self.target {
+                AsmTarget::X86_64 | AsmTarget::ARM64 | AsmTarget::WASM => unreachable!("native layers are compiled by `compile_native_layer`"),
+                AsmTarget::WASMUI => writeln!(&mut self.code, "    call $gaia_custom_{}", op.symbol()).unwrap(),
+            },
+        }
+    }
+    
+    /// Code the custom operation provides for this target, if any
+    fn custom_snippet(&self, layer: &LayerNode, op: &dyn CustomOp) -> Option<String> {
+        let params = layer.resolved_params();
+        match (&layer.input_shape, &layer.output_shape) {
+            (Some(input), Some(output)) => {
+                let name = format!("gaia_custom_{}", op.symbol());
+                op.snippet(&SnippetContext { backend: self.target.name(), params: &params, input, output, name: &name })
+            },
+            _ => None,
+        }
+    }
+    
+    fn push_snippet(&mut self, code: &str) {
+        for line in code.lines() {
+            writeln!(&mut self.code, "    {}", line.trim()).unwrap();
+        }
+    }
+    
+    /// Compile a layer as a call to its runtime kernel, with the shapes and
+    /// parameters of its step in the plan; the kernel reads the input register
+    /// and writes the output register, which then becomes the next input
+    fn compile_native_layer(&mut self, layer: &LayerNode) {
+        let comment = self.native_comment();
+        let variants = match self.native.steps.pop_front() {
+            Some(variants) => variants,
+            None => {
+                writeln!(&mut self.code, "    {} Layer: {} (not in the plan, not compiled)", comment, layer).unwrap();
+                return;
+            },
+        };
+        let step = variants[0].clone();
+        let layer = &step.layer;
+        writeln!(&mut self.code, "    {} Layer: {} ({} → {})", comment, layer, step.input, step.output).unwrap();
+        for variant in &variants {
+            for (key, shape) in variant.parameter_shapes() {
+                self.native.parameters.insert(parameter_label(&key), shape.iter().product());
+            }
+        }
+        
+        let tensor = |name: &str| Arg::Tensor(variants.iter().map(|s| parameter_label(&format!("{}.{}", s.key, name))).collect());
+        let dims = &step.input.dims;
+        let (height, width) = (dims.first().copied().unwrap_or(1), dims.get(1).copied().unwrap_or(1));
+        let c_in = dims.last().copied().unwrap_or(1);
+        let c_out = step.output.dims.last().copied().unwrap_or(1);
+        let registers = self.registers();
+        let (x, y) = (Arg::Register(registers.input), Arg::Register(registers.output));
+        let activation = activation_name(&layer.activation);
+        
+        let (kernel, args) = match layer.layer_type {
+            LayerType::Dense(_) => ("dense", vec![
+                x, y, tensor("weight"), tensor("bias"),
+                Arg::Value(step.input.elements() / c_in), Arg::Value(c_in), Arg::Value(c_out),
+            ]),
+            LayerType::Upsampling if target_shape(layer).is_some() => ("dense", vec![
+                x, y, tensor("weight"), tensor("bias"),
+                Arg::Value(1), Arg::Value(step.input.elements()), Arg::Value(step.output.elements()),
+            ]),
+            LayerType::Convolutional(_) => ("conv", vec![
+                x, y, tensor("weight"), tensor("bias"),
+                Arg::Value(height), Arg::Value(width), Arg::Value(c_in), Arg::Value(c_out), Arg::Value(layer.param(KERNEL)),
+            ]),
+            LayerType::TransposeConv => ("transpose_conv", vec![
+                x, y, tensor("weight"), tensor("bias"),
+                Arg::Value(height), Arg::Value(width), Arg::Value(c_in), Arg::Value(c_out),
+                Arg::Value(layer.param(KERNEL)), Arg::Value(layer.param(STRIDE)),
+            ]),
+            LayerType::Pooling => ("pooling", vec![
+                x, y, Arg::Value(height), Arg::Value(width), Arg::Value(c_in), Arg::Value(layer.param(SIZE).max(1)),
+            ]),
+            LayerType::Upsampling => ("upsampling", vec![
+                x, y, Arg::Value(height), Arg::Value(width), Arg::Value(c_in), Arg::Value(layer.param(FACTOR)),
+            ]),
+            LayerType::Embedding => ("embedding", vec![
+                x, y, tensor("weight"), Arg::Value(step.input.elements()),
+                Arg::Value(layer.param(VOCAB)), Arg::Value(c_out), Arg::Value(layer.positional as usize),
+            ]),
+            LayerType::LSTM | LayerType::Attention => unreachable!("components with `{}` have no function", layer.symbol()),
+            // Only the shape changes, so the values stay where they are
+            LayerType::Flatten | LayerType::Reshape | LayerType::AttentionHeads | LayerType::BatchSize
+                if layer.activation == ActivationFunction::None => return,
+            LayerType::Flatten | LayerType::Reshape | LayerType::AttentionHeads | LayerType::BatchSize => ("flatten", vec![
+                x, y, Arg::Value(step.output.elements() / c_out), Arg::Value(c_out),
+            ]),
+            LayerType::Custom(ref op) => {
+                // gaia_custom_<symbol>(x, y, n_in, n_out, parameters…), then the activation in place
+                let prefix = format!("{}.", step.key);
+                let mut args = vec![x, y, Arg::Value(step.input.elements()), Arg::Value(step.output.elements())];
+                for (key, _) in step.parameter_shapes() {
+                    args.push(tensor(key.strip_prefix(&prefix).unwrap_or(&key)));
+                }
+                let function = format!("gaia_custom_{}", op.symbol());
+                let arguments = args.len();
+                let reserved = self.pass_arguments(args);
+                match self.custom_snippet(layer, op.as_ref()) {
+                    Some(code) => self.push_snippet(&code),
+                    None => self.emit_call(&function, arguments),
+                }
+                self.release_arguments(reserved);
+                if layer.activation != ActivationFunction::None {
+                    let rows = step.output.elements() / c_out;
+                    self.call_runtime(&format!("gaia_flatten_{}", activation), vec![
+                        Arg::Register(registers.output), Arg::Register(registers.output), Arg::Value(rows), Arg::Value(c_out),
+                    ]);
+                }
+                self.next_activations();
+                return;
+            },
+        };
+        self.call_runtime(&format!("gaia_{}_{}", kernel, activation), args);
+        self.next_activations();
+    }
+    
+    /// Call a runtime function
+    fn call_runtime(&mut self, function: &str, args: Vec<Arg>) {
+        if self.target == AsmTarget::WASM {
+            let args: Vec<String> = args.iter().map(|arg| self.wasm_argument(arg)).collect();
+            writeln!(&mut self.code, "    (call ${} {})", function, args.join(" ")).unwrap();
+            return;
+        }
+        let arguments = args.len();
+        let reserved = self.pass_arguments(args);
+        self.emit_call(function, arguments);
+        self.release_arguments(reserved);
+    }
+    
+    /// Call a function, declaring it for NASM or importing it into WebAssembly
+    fn emit_call(&mut self, function: &str, arguments: usize) {
+        match self.target {
+            AsmTarget::X86_64 => {
+                writeln!(&mut self.code, "    call {}", function).unwrap();
+                self.native.externs.insert(function.to_string(), arguments);
+            },
+            AsmTarget::WASM => {
+                writeln!(&mut self.code, "    call ${}", function).unwrap();
+                self.native.externs.insert(function.to_string(), arguments);
+            },
+            _ => writeln!(&mut self.code, "    bl {}", function).unwrap(),
+        }
+    }
+    
+    /// Load the arguments of a call, those after the argument registers on
+    /// the stack; returns the stack space reserved for them
+    fn pass_arguments(&mut self, args: Vec<Arg>) -> usize {
+        if self.target == AsmTarget::WASM {
+            for arg in &args {
+                let value = self.wasm_argument(arg);
+                writeln!(&mut self.code, "    {}", value).unwrap();
+            }
+            return 0;
+        }
+        let registers = self.registers();
+        let (in_registers, stack) = args.split_at(args.len().min(registers.arguments.len()));
+        let reserved = (stack.len() * 8).next_multiple_of(16);
+        if reserved > 0 {
+            match self.target {
+                AsmTarget::X86_64 => writeln!(&mut self.code, "    sub rsp, {}", reserved).unwrap(),
+                _ => writeln!(&mut self.code, "    sub sp, sp, #{}", reserved).unwrap(),
+            }
+        }
+        for (i, arg) in stack.iter().enumerate() {
+            self.load_argument(registers.scratch, arg);
+            match self.target {
+                AsmTarget::X86_64 => writeln!(&mut self.code, "    mov [rsp + {}], rax", 8 * i).unwrap(),
+                _ => writeln!(&mut self.code, "    str x9, [sp, #{}]", 8 * i).unwrap(),
+            }
+        }
+        for (register, arg) in registers.arguments.iter().zip(in_registers) {
+            self.load_argument(register, arg);
+        }
+        reserved
+    }
+    
+    fn release_arguments(&mut self, reserved: usize) {
+        if reserved > 0 {
+            match self.target {
+                AsmTarget::X86_64 => writeln!(&mut self.code, "    add rsp, {}", reserved).unwrap(),
+                _ => writeln!(&mut self.code, "    add sp, sp, #{}", reserved).unwrap(),
+            }
+        }
+    }
+    
+    fn load_argument(&mut self, register: &str, arg: &Arg) {
+        match arg {
+            Arg::Register(source) => writeln!(&mut self.code, "    mov {}, {}", register, source).unwrap(),
+            Arg::Value(value) => self.load_value(register, *value as u64),
+            Arg::Address(label) => self.load_address(register, label),
+            Arg::Tensor(labels) if labels.iter().all(|label| *label == labels[0]) => self.load_address(register, &labels[0]),
+            Arg::Tensor(labels) => {
+                // Each repetition has its own parameters, indexed by the loop counter
+                let table = self.new_label("gaia_table");
+                self.native.tables.push((table.clone(), labels.clone()));
+                let registers = self.registers();
+                self.load_address(registers.scratch, &table);
+                match self.target {
+                    AsmTarget::X86_64 => writeln!(&mut self.code, "    mov {}, [rax + rbx * 8]", register).unwrap(),
+                    _ => writeln!(&mut self.code, "    ldr {}, [x9, x19, lsl #3]", register).unwrap(),
+                }
+            },
+        }
+    }
+    
+    // Expression of an argument on the WebAssembly operand stack
+    fn wasm_argument(&mut self, arg: &Arg) -> String {
+        match arg {
+            Arg::Register(local) => format!("(local.get {})", local),
+            Arg::Value(value) => format!("(i32.const {})", value),
+            Arg::Address(label) => format!("(global.get ${})", label),
+            Arg::Tensor(labels) if labels.iter().all(|label| *label == labels[0]) => format!("(global.get ${})", labels[0]),
+            Arg::Tensor(labels) => {
+                let table = self.new_label("gaia_table");
+                self.native.tables.push((table.clone(), labels.clone()));
+                format!("(i32.load (i32.add (global.get ${}) (i32.shl (local.get $repetition) (i32.const 2))))", table)
+            },
+        }
+    }
+    
+    fn load_value(&mut self, register: &str, value: u64) {
+        if self.target == AsmTarget::X86_64 {
+            writeln!(&mut self.code, "    mov {}, {}", register, value).unwrap();
+            return;
+        }
+        // Sixteen bits at a time beyond what one `mov` takes
+        writeln!(&mut self.code, "    mov {}, #{}", register, value & 0xffff).unwrap();
+        for shift in [16, 32, 48] {
+            let part = (value >> shift) & 0xffff;
+            if part != 0 {
+                writeln!(&mut self.code, "    movk {}, #{}, lsl #{}", register, part, shift).unwrap();
+            }
+        }
+    }
+    
+    fn load_address(&mut self, register: &str, label: &str) {
+        match self.target {
+            AsmTarget::X86_64 => writeln!(&mut self.code, "    lea {}, [rel {}]", register, label).unwrap(),
+            _ => {
+                writeln!(&mut self.code, "    adrp {}, {}", register, label).unwrap();
+                writeln!(&mut self.code, "    add {}, {}, :lo12:{}", register, register, label).unwrap();
+            },
+        }
+    }
+    
+    /// The output of the last layer becomes the input of the next one
+    fn next_activations(&mut self) {
+        match self.target {
+            AsmTarget::X86_64 => {
+                writeln!(&mut self.code, "    mov r12, r13").unwrap();
+                writeln!(&mut self.code, "    xchg r13, r14").unwrap();
+            },
+            AsmTarget::WASM => {
+                writeln!(&mut self.code, "    (local.set $x (local.get $y))").unwrap();
+                writeln!(&mut self.code, "    (local.set $y (local.get $spare))").unwrap();
+                writeln!(&mut self.code, "    (local.set $spare (local.get $x))").unwrap();
+            },
+            _ => {
+                writeln!(&mut self.code, "    mov x20, x21").unwrap();
+                writeln!(&mut self.code, "    mov x21, x22").unwrap();
+                writeln!(&mut self.code, "    mov x22, x20").unwrap();
+            },
+        }
+    }
+    
+    // Whether components compile to functions calling the runtime, as on
+    // every target but `WASMUI`
+    fn is_native(&self) -> bool {
+        self.target != AsmTarget::WASMUI
+    }
+    
+    fn registers(&self) -> &'static Registers {
+        match self.target {
+            AsmTarget::X86_64 => &X86_64_REGISTERS,
+            AsmTarget::WASM => &WASM_LOCALS,
+            _ => &ARM64_REGISTERS,
+        }
+    }
+    
+    fn native_comment(&self) -> &'static str {
+        match self.target {
+            AsmTarget::X86_64 => ";",
+            AsmTarget::ARM64 => "//",
+            AsmTarget::WASM | AsmTarget::WASMUI => ";;",
+        }
+    }
+    
+    /// Compile a block node (repeated layers): a counted loop around one copy
+    /// of the content, or a copy per repetition when unrolling or when the
+    /// repetitions see different shapes
     fn compile_block(&mut self, block: &BlockNode) {
+        let comment = match self.target {
+            AsmTarget::X86_64 => ";",
+            AsmTarget::ARM64 => "//",
+            AsmTarget::WASM | AsmTarget::WASMUI => ";;",
+        };
+        let parameters = if block.shared { "shared parameters" } else { "parameters per repetition" };
+        // Without shape inference the shapes are unknown and the block loops;
+        // native code loops only at the outer level, on the shapes of the plan
+        let uniform = match self.target {
+            AsmTarget::X86_64 | AsmTarget::ARM64 | AsmTarget::WASM => !self.native.in_loop && self.native.is_uniform(block),
+            _ => block.shapes.is_empty() || block.is_uniform(),
+        };
+        if self.blocks == BlockMode::Unroll || !uniform {
+            writeln!(&mut self.code, "    {} Block with {} repetitions, unrolled ({})", comment, block.repetitions, parameters).unwrap();
+            for iteration in 0..block.repetitions.max(1) {
+                writeln!(&mut self.code, "    {} Repetition {} of {}", comment, iteration + 1, block.repetitions).unwrap();
+                self.generate_code(&block.content);
+            }
+            return;
+        }
+        
         let loop_label = self.new_label("block_loop");
         let end_label = self.new_label("block_end");
         let counter_var = self.new_temp();
         
         match self.target {
             AsmTarget::X86_64 => {
-                writeln!(&mut self.code, "    ; Block with {} repetitions", block.repetitions).unwrap();
-                writeln!(&mut self.code, "    mov rcx, {}", block.repetitions).unwrap();
+                writeln!(&mut self.code, "    ; Block with {} repetitions ({})", block.repetitions, parameters).unwrap();
+                writeln!(&mut self.code, "    xor ebx, ebx                ; repetition").unwrap();
                 writeln!(&mut self.code, "{}:", loop_label).unwrap();
-                writeln!(&mut self.code, "    push rcx").unwrap();
             },
             AsmTarget::ARM64 => {
-                writeln!(&mut self.code, "    // Block with {} repetitions", block.repetitions).unwrap();
-                writeln!(&mut self.code, "    mov x19, #{}", block.repetitions).unwrap();
+                writeln!(&mut self.code, "    // Block with {} repetitions ({})", block.repetitions, parameters).unwrap();
+                writeln!(&mut self.code, "    mov x19, #0                 // repetition").unwrap();
                 writeln!(&mut self.code, "{}:", loop_label).unwrap();
-                writeln!(&mut self.code, "    str x19, [sp, #-16]!").unwrap();
             },
-            AsmTarget::WASM | AsmTarget::WASMUI => {
-                writeln!(&mut self.code, "    ;; Block with {} repetitions", block.repetitions).unwrap();
+            AsmTarget::WASM => {
+                writeln!(&mut self.code, "    ;; Block with {} repetitions ({})", block.repetitions, parameters).unwrap();
+                writeln!(&mut self.code, "    (local.set $repetition (i32.const 0))").unwrap();
+                writeln!(&mut self.code, "    (loop ${}", loop_label).unwrap();
+            },
+            AsmTarget::WASMUI => {
+                writeln!(&mut self.code, "    ;; Block with {} repetitions ({})", block.repetitions, parameters).unwrap();
                 writeln!(&mut self.code, "    (local ${} i32)  ;; Loop counter", counter_var).unwrap();
                 writeln!(&mut self.code, "    i32.const {}", block.repetitions).unwrap();
                 writeln!(&mut self.code, "    local.set ${}", counter_var).unwrap();
@@ -436,21 +1321,30 @@ impl AsmCompiler {
         }
         
         // Generate the block content
+        let rest = self.is_native().then(|| self.native.enter_loop(block));
         self.generate_code(&block.content);
+        if let Some(rest) = rest {
+            self.native.leave_loop(rest);
+        }
         
         // End the loop
         match self.target {
             AsmTarget::X86_64 => {
-                writeln!(&mut self.code, "    pop rcx").unwrap();
-                writeln!(&mut self.code, "    dec rcx").unwrap();
-                writeln!(&mut self.code, "    jnz {}", loop_label).unwrap();
+                writeln!(&mut self.code, "    inc rbx").unwrap();
+                writeln!(&mut self.code, "    cmp rbx, {}", block.repetitions).unwrap();
+                writeln!(&mut self.code, "    jb {}", loop_label).unwrap();
             },
             AsmTarget::ARM64 => {
-                writeln!(&mut self.code, "    ldr x19, [sp], #16").unwrap();
-                writeln!(&mut self.code, "    subs x19, x19, #1").unwrap();
-                writeln!(&mut self.code, "    bne {}", loop_label).unwrap();
+                writeln!(&mut self.code, "    add x19, x19, #1").unwrap();
+                self.load_value("x9", block.repetitions as u64);
+                writeln!(&mut self.code, "    cmp x19, x9").unwrap();
+                writeln!(&mut self.code, "    b.lo {}", loop_label).unwrap();
             },
-            AsmTarget::WASM | AsmTarget::WASMUI => {
+            AsmTarget::WASM => {
+                writeln!(&mut self.code, "    (local.set $repetition (i32.add (local.get $repetition) (i32.const 1)))").unwrap();
+                writeln!(&mut self.code, "    (br_if ${} (i32.lt_u (local.get $repetition) (i32.const {}))))", loop_label, block.repetitions).unwrap();
+            },
+            AsmTarget::WASMUI => {
                 writeln!(&mut self.code, "        local.get ${}", counter_var).unwrap();
                 writeln!(&mut self.code, "        i32.const 1").unwrap();
                 writeln!(&mut self.code, "        i32.sub").unwrap();
@@ -475,14 +1369,19 @@ impl AsmCompiler {
         
         match self.target {
             AsmTarget::X86_64 => {
+                // The input arrives through the function's `in` pointer
                 writeln!(&mut self.code, "    ; Input: {} with params {:?}", input_type, input.params).unwrap();
-                writeln!(&mut self.code, "    call gaia_input_{}", input_type).unwrap();
+                return;
             },
             AsmTarget::ARM64 => {
                 writeln!(&mut self.code, "    // Input: {} with params {:?}", input_type, input.params).unwrap();
-                writeln!(&mut self.code, "    bl gaia_input_{}", input_type).unwrap();
+                return;
             },
-            AsmTarget::WASM | AsmTarget::WASMUI => {
+            AsmTarget::WASM => {
+                writeln!(&mut self.code, "    ;; Input: {} with params {:?}", input_type, input.params).unwrap();
+                return;
+            },
+            AsmTarget::WASMUI => {
                 writeln!(&mut self.code, "    ;; Input: {} with params {:?}", input_type, input.params).unwrap();
                 writeln!(&mut self.code, "    call $input_{}", input_type).unwrap();
             },
@@ -491,13 +1390,8 @@ impl AsmCompiler {
         // Set up input parameters
         for (i, param) in input.params.iter().enumerate() {
             match self.target {
-                AsmTarget::X86_64 => {
-                    writeln!(&mut self.code, "    mov r{}, {}", i, param).unwrap();
-                },
-                AsmTarget::ARM64 => {
-                    writeln!(&mut self.code, "    mov x{}, #{}", i, param).unwrap();
-                },
-                AsmTarget::WASM | AsmTarget::WASMUI => {
+                AsmTarget::X86_64 | AsmTarget::ARM64 | AsmTarget::WASM => unreachable!("native inputs are function arguments"),
+                AsmTarget::WASMUI => {
                     writeln!(&mut self.code, "    i32.const {}  ;; Input parameter {}", param, i).unwrap();
                 },
             }
@@ -510,15 +1404,9 @@ impl AsmCompiler {
         self.generate_code(from);
         
         match self.target {
-            AsmTarget::X86_64 => {
-                writeln!(&mut self.code, "    ; Connect layers (dataflow)").unwrap();
-                writeln!(&mut self.code, "    push rax  ; Save output from previous layer").unwrap();
-            },
-            AsmTarget::ARM64 => {
-                writeln!(&mut self.code, "    // Connect layers (dataflow)").unwrap();
-                writeln!(&mut self.code, "    str x0, [sp, #-16]!  // Save output from previous layer").unwrap();
-            },
-            AsmTarget::WASM | AsmTarget::WASMUI => {
+            // Activations pass from layer to layer in the input register
+            AsmTarget::X86_64 | AsmTarget::ARM64 | AsmTarget::WASM => {},
+            AsmTarget::WASMUI => {
                 writeln!(&mut self.code, "    ;; Connect layers (dataflow)").unwrap();
                 writeln!(&mut self.code, "    ;; Previous layer result is on stack").unwrap();
             },
@@ -528,49 +1416,269 @@ impl AsmCompiler {
         self.generate_code(to);
         
         match self.target {
-            AsmTarget::X86_64 => {
-                writeln!(&mut self.code, "    pop rbx  ; Get input from previous layer").unwrap();
-                writeln!(&mut self.code, "    ; Connect layers and continue processing").unwrap();
-            },
-            AsmTarget::ARM64 => {
-                writeln!(&mut self.code, "    ldr x1, [sp], #16  // Get input from previous layer").unwrap();
-                writeln!(&mut self.code, "    // Connect layers and continue processing").unwrap();
-            },
-            AsmTarget::WASM | AsmTarget::WASMUI => {
+            AsmTarget::X86_64 | AsmTarget::ARM64 | AsmTarget::WASM => {},
+            AsmTarget::WASMUI => {
                 writeln!(&mut self.code, "    ;; Connect layers and continue processing").unwrap();
             },
         }
     }
     
-    /// Compile a loss node
+    /// Compile a loss node: the composite model it describes followed by the loss
     fn compile_loss(&mut self, loss: &LossNode) {
+        if self.is_native() {
+            self.compile_composite_function(loss);
+            return;
+        }
+        let comment = match self.target {
+            AsmTarget::X86_64 => ";",
+            AsmTarget::ARM64 => "//",
+            AsmTarget::WASM | AsmTarget::WASMUI => ";;",
+        };
+        let name = loss.label.clone().unwrap_or_else(|| loss.chain().join("_"));
+        writeln!(&mut self.code, "    {} Composite {}: {} with loss {}", comment, name, loss.chain().join(" ⊳ "), loss.function).unwrap();
+        
+        // Compile the 'from' expression, which inlines the source components
+        self.generate_code(&loss.from);
+        
+        // Feed the result through every injection target in turn
+        for target in &loss.targets {
+            if loss.is_frozen(target) {
+                writeln!(&mut self.code, "    {} Frozen stage: parameters of {} are not trained", comment, target).unwrap();
+            }
+            match self.target {
+                AsmTarget::X86_64 | AsmTarget::ARM64 | AsmTarget::WASM => unreachable!("native composites are compiled by `compile_composite_function`"),
+                AsmTarget::WASMUI => {
+                    writeln!(&mut self.code, "    ;; call ${}", target).unwrap();
+                },
+            }
+        }
+        
+        // Generate one loss function call per base loss; other terms are only used in training
+        for term in &loss.function.terms {
+            let base = match term.term {
+                LossTerm::Base(base) => base,
+                _ => {
+                    writeln!(&mut self.code, "    {} Training-only term: {}", comment, term.term).unwrap();
+                    continue;
+                }
+            };
+            match self.target {
+                AsmTarget::X86_64 | AsmTarget::ARM64 | AsmTarget::WASM => unreachable!("native composites are compiled by `compile_composite_function`"),
+                AsmTarget::WASMUI => {
+                    writeln!(&mut self.code, "    call $loss_{}", base.name()).unwrap();
+                },
+            }
+        }
+    }
+    
+    /// Emit `composite_<name>(in, out)`, running the stages of a loss one after
+    /// the other, and `float loss_<name>(in, target)`, the weighted sum of its
+    /// base losses on the prediction
+    fn compile_composite_function(&mut self, loss: &LossNode) {
+        let name = composite_name(loss);
+        let comment = self.native_comment();
+        let composite = match self.native.composites.get(&name) {
+            Some(Ok(composite)) => composite.clone(),
+            Some(Err(error)) => {
+                writeln!(&mut self.code, "{} Composite {}: {}", comment, name, error).unwrap();
+                return;
+            },
+            None => return,
+        };
+        let label = function_label("composite", &name);
+        if !self.native.defined.insert(label.clone()) {
+            writeln!(&mut self.code, "{} Composite {}: already defined", comment, name).unwrap();
+            return;
+        }
+        if let Some(stage) = composite.stages.iter().find(|s| !self.native.has_function(&s.component)) {
+            writeln!(&mut self.code, "{} Composite {}: component {} has no function", comment, name, stage.component).unwrap();
+            return;
+        }
+        if let Some(stage) = composite.stages.iter().find(|s| !self.native.runs_as_planned(s)) {
+            writeln!(&mut self.code, "{} Composite {}: component {} is fed {}, not its declared input", comment, name, stage.component, stage.plan.input).unwrap();
+            return;
+        }
+        
+        self.native.last_function = Some(label.clone());
+        if self.target == AsmTarget::WASM {
+            self.compile_wasm_composite(loss, &composite, &name);
+            return;
+        }
+        
+        // `out`, then the target of the loss, stays in the counter register
+        let registers = self.registers();
+        let (first, second, kept) = (registers.arguments[0], registers.arguments[1], registers.counter);
+        writeln!(&mut self.code).unwrap();
+        writeln!(&mut self.code, "{} Composite {}", comment, composite).unwrap();
+        writeln!(&mut self.code, "{} void {}(const float *in, float *out)", comment, label).unwrap();
+        self.begin_helper(&label);
+        writeln!(&mut self.code, "    mov {}, {}", kept, second).unwrap();
+        // Stage outputs alternate between two buffers; the last goes to `out`
+        let buffers = ["gaia_stage_a", "gaia_stage_b"];
+        let last = composite.stages.len() - 1;
+        for (i, stage) in composite.stages.iter().enumerate() {
+            if stage.frozen {
+                writeln!(&mut self.code, "    {} Frozen stage: parameters of {} are not trained", comment, stage.component).unwrap();
+            }
+            if i > 0 {
+                self.load_address(first, buffers[(i - 1) % 2]);
+            }
+            if i == last {
+                writeln!(&mut self.code, "    mov {}, {}", second, kept).unwrap();
+            } else {
+                self.load_address(second, buffers[i % 2]);
+                self.native.stages = self.native.stages.max(stage.plan.output().elements());
+            }
+            let component = function_label("component", &stage.component);
+            match self.target {
+                AsmTarget::X86_64 => writeln!(&mut self.code, "    call {}", component).unwrap(),
+                _ => writeln!(&mut self.code, "    bl {}", component).unwrap(),
+            }
+        }
+        self.end_helper();
+        
+        let loss_label = function_label("loss", &name);
+        let n = composite.output().elements();
+        self.native.predictions = self.native.predictions.max(n);
+        writeln!(&mut self.code).unwrap();
+        writeln!(&mut self.code, "{} float {}(const float *in, const float *target)", comment, loss_label).unwrap();
+        self.begin_helper(&loss_label);
+        writeln!(&mut self.code, "    mov {}, {}", kept, second).unwrap();
+        self.load_address(second, "gaia_prediction");
         match self.target {
             AsmTarget::X86_64 => {
-                writeln!(&mut self.code, "    ; Loss function: {} for component {}", loss.function, loss.to).unwrap();
-            },
-            AsmTarget::ARM64 => {
-                writeln!(&mut self.code, "    // Loss function: {} for component {}", loss.function, loss.to).unwrap();
+                writeln!(&mut self.code, "    call {}", label).unwrap();
+                writeln!(&mut self.code, "    mov dword [rsp], 0          ; running sum").unwrap();
             },
-            AsmTarget::WASM | AsmTarget::WASMUI => {
-                writeln!(&mut self.code, "    ;; Loss function: {} for component {}", loss.function, loss.to).unwrap();
+            _ => {
+                writeln!(&mut self.code, "    bl {}", label).unwrap();
+                writeln!(&mut self.code, "    str wzr, [sp, #24]          // running sum").unwrap();
             },
         }
+        for term in loss_terms(loss) {
+            let (base, coefficient) = match term {
+                Ok(term) => term,
+                Err(note) => {
+                    writeln!(&mut self.code, "    {} {}", comment, note).unwrap();
+                    continue;
+                },
+            };
+            self.call_runtime(&format!("gaia_loss_{}", base.name()), vec![
+                Arg::Address("gaia_prediction".to_string()), Arg::Register(kept), Arg::Value(n),
+            ]);
+            let bits = coefficient.to_bits();
+            match self.target {
+                AsmTarget::X86_64 => {
+                    if coefficient != 1.0 {
+                        writeln!(&mut self.code, "    mov eax, 0x{:08x}           ; {}", bits, coefficient).unwrap();
+                        writeln!(&mut self.code, "    movd xmm1, eax").unwrap();
+                        writeln!(&mut self.code, "    mulss xmm0, xmm1").unwrap();
+                    }
+                    writeln!(&mut self.code, "    addss xmm0, [rsp]").unwrap();
+                    writeln!(&mut self.code, "    movss [rsp], xmm0").unwrap();
+                },
+                _ => {
+                    if coefficient != 1.0 {
+                        writeln!(&mut self.code, "    mov w9, #0x{:04x}              // {}", bits & 0xffff, coefficient).unwrap();
+                        writeln!(&mut self.code, "    movk w9, #0x{:04x}, lsl #16", bits >> 16).unwrap();
+                        writeln!(&mut self.code, "    fmov s1, w9").unwrap();
+                        writeln!(&mut self.code, "    fmul s0, s0, s1").unwrap();
+                    }
+                    writeln!(&mut self.code, "    ldr s1, [sp, #24]").unwrap();
+                    writeln!(&mut self.code, "    fadd s0, s0, s1").unwrap();
+                    writeln!(&mut self.code, "    str s0, [sp, #24]").unwrap();
+                },
+            }
+        }
+        match self.target {
+            AsmTarget::X86_64 => writeln!(&mut self.code, "    movss xmm0, [rsp]").unwrap(),
+            _ => writeln!(&mut self.code, "    ldr s0, [sp, #24]").unwrap(),
+        }
+        self.end_helper();
+    }
+    
+    /// The composite and loss functions in WebAssembly, where the stages and
+    /// the base losses are calls on the operand stack
+    fn compile_wasm_composite(&mut self, loss: &LossNode, composite: &Composite, name: &str) {
+        let label = function_label("composite", name);
+        writeln!(&mut self.code).unwrap();
+        writeln!(&mut self.code, ";; Composite {}", composite).unwrap();
+        writeln!(&mut self.code, ";; void {}(const float *in, float *out)", label).unwrap();
+        writeln!(&mut self.code, "  (func ${} (export \"{}\") (param $in i32) (param $out i32)", label, label).unwrap();
+        // Stage outputs alternate between two buffers; the last goes to `out`
+        let buffers = ["(global.get $gaia_stage_a)", "(global.get $gaia_stage_b)"];
+        let last = composite.stages.len() - 1;
+        for (i, stage) in composite.stages.iter().enumerate() {
+            if stage.frozen {
+                writeln!(&mut self.code, "    ;; Frozen stage: parameters of {} are not trained", stage.component).unwrap();
+            }
+            let input = if i == 0 { "(local.get $in)" } else { buffers[(i - 1) % 2] };
+            let output = if i == last {
+                "(local.get $out)"
+            } else {
+                self.native.stages = self.native.stages.max(stage.plan.output().elements());
+                buffers[i % 2]
+            };
+            writeln!(&mut self.code, "    (call ${} {} {})", function_label("component", &stage.component), input, output).unwrap();
+        }
+        writeln!(&mut self.code, "  )").unwrap();
         
-        // Compile the 'from' expression
-        self.generate_code(&loss.from);
-        
-        // Generate loss function call
+        let loss_label = function_label("loss", name);
+        let n = composite.output().elements();
+        self.native.predictions = self.native.predictions.max(n);
+        writeln!(&mut self.code).unwrap();
+        writeln!(&mut self.code, ";; float {}(const float *in, const float *target)", loss_label).unwrap();
+        writeln!(&mut self.code, "  (func ${} (export \"{}\") (param $in i32) (param $target i32) (result f32)", loss_label, loss_label).unwrap();
+        writeln!(&mut self.code, "    (local $sum f32)").unwrap();
+        writeln!(&mut self.code, "    (call ${} (local.get $in) (global.get $gaia_prediction))", label).unwrap();
+        for term in loss_terms(loss) {
+            let (base, coefficient) = match term {
+                Ok(term) => term,
+                Err(note) => {
+                    writeln!(&mut self.code, "    ;; {}", note).unwrap();
+                    continue;
+                },
+            };
+            let call = format!("(call $gaia_loss_{} (global.get $gaia_prediction) (local.get $target) (i32.const {}))", base.name(), n);
+            let value = if coefficient == 1.0 { call } else { format!("(f32.mul {} (f32.const {}))", call, coefficient) };
+            writeln!(&mut self.code, "    (local.set $sum (f32.add {} (local.get $sum)))", value).unwrap();
+        }
+        writeln!(&mut self.code, "    (local.get $sum)").unwrap();
+        writeln!(&mut self.code, "  )").unwrap();
+    }
+    
+    /// Open a composite or loss function, saving the counter register and
+    /// keeping 4 bytes of stack for a running sum
+    fn begin_helper(&mut self, label: &str) {
         match self.target {
             AsmTarget::X86_64 => {
-                writeln!(&mut self.code, "    call gaia_loss_{}", loss.function).unwrap();
+                writeln!(&mut self.code, "global {}", label).unwrap();
+                writeln!(&mut self.code, "{}:", label).unwrap();
+                writeln!(&mut self.code, "    push rbx").unwrap();
+                writeln!(&mut self.code, "    sub rsp, 16").unwrap();
             },
-            AsmTarget::ARM64 => {
-                writeln!(&mut self.code, "    bl gaia_loss_{}", loss.function).unwrap();
+            _ => {
+                writeln!(&mut self.code, ".global {}", label).unwrap();
+                writeln!(&mut self.code, "{}:", label).unwrap();
+                writeln!(&mut self.code, "    stp x29, x30, [sp, #-32]!").unwrap();
+                writeln!(&mut self.code, "    mov x29, sp").unwrap();
+                writeln!(&mut self.code, "    str x19, [sp, #16]").unwrap();
             },
-            AsmTarget::WASM | AsmTarget::WASMUI => {
-                writeln!(&mut self.code, "    call $loss_{}", loss.function).unwrap();
+        }
+    }
+    
+    fn end_helper(&mut self) {
+        match self.target {
+            AsmTarget::X86_64 => {
+                writeln!(&mut self.code, "    add rsp, 16").unwrap();
+                writeln!(&mut self.code, "    pop rbx").unwrap();
+            },
+            _ => {
+                writeln!(&mut self.code, "    ldr x19, [sp, #16]").unwrap();
+                writeln!(&mut self.code, "    ldp x29, x30, [sp], #32").unwrap();
             },
         }
+        writeln!(&mut self.code, "    ret").unwrap();
     }
     
     /// Compile an expression (sequence of nodes)
@@ -585,4 +1693,299 @@ impl AsmCompiler {
 pub fn compile_to_asm(ast: &ASTNode, target: AsmTarget) -> String {
     let mut compiler = AsmCompiler::new(target);
     compiler.compile(ast)
-}
\ No newline at end of file
+}
+
+/// Compile to assembly with int8 components and kernels embedded
+pub fn compile_to_asm_quantized(ast: &ASTNode, target: AsmTarget, models: &[QuantizedModel]) -> String {
+    let mut compiler = AsmCompiler::with_quantized(target, models);
+    compiler.compile(ast)
+}
+
+/// Compile to assembly with the weights embedded in the data section
+pub fn compile_to_asm_with_weights(ast: &ASTNode, target: AsmTarget, weights: &Weights) -> String {
+    let mut compiler = AsmCompiler::with_weights(target, weights);
+    compiler.compile(ast)
+}
+
+// Assembler label of a tensor, e.g. `η.Ñ.C₁.kernel` becomes `gaia_weight_u00d1_C1_kernel`
+fn weight_label(name: &str) -> String {
+    let local = name.strip_prefix(safetensors::NAMESPACE).and_then(|n| n.strip_prefix('.')).unwrap_or(name);
+    format!("gaia_weight_{}", identifier(local, true))
+}
+
+// Label of the tensor an interpreter parameter key such as `Ñ.C₁.weight` is embedded as
+fn parameter_label(key: &str) -> String {
+    weight_label(&safetensors::qualified_name(key))
+}
+
+// Label of a generated function, e.g. `component_u00d1` for `Ñ`
+fn function_label(kind: &str, name: &str) -> String {
+    format!("{}_{}", kind, identifier(name, true))
+}
+
+fn activation_name(activation: &ActivationFunction) -> &'static str {
+    match activation {
+        ActivationFunction::ReLU => "relu",
+        ActivationFunction::Sigmoid => "sigmoid",
+        ActivationFunction::Tanh => "tanh",
+        ActivationFunction::Softmax => "softmax",
+        ActivationFunction::None => "none",
+    }
+}
+
+// Name of the composite a loss describes: its label, or the chain such as `G⊳D`
+fn composite_name(loss: &LossNode) -> String {
+    loss.label.clone().unwrap_or_else(|| loss.chain().join("⊳"))
+}
+
+// Base losses of a loss with their signed coefficients, or why a term is left out
+fn loss_terms(loss: &LossNode) -> Vec<Result<(BaseLoss, f32), String>> {
+    loss.function.terms.iter()
+        .map(|term| {
+            let base = match term.term {
+                LossTerm::Base(base) => base,
+                _ => return Err(format!("Training-only term: {}", term.term)),
+            };
+            let coefficient = match &term.coefficient {
+                None => 1.0,
+                Some(Coefficient::Value(value)) => *value,
+                Some(Coefficient::Symbol(symbol)) => {
+                    return Err(format!("Term {}: the coefficient {} is only set for training", term.term, symbol));
+                },
+            };
+            Ok((base, if term.negated { -coefficient } else { coefficient }))
+        })
+        .collect()
+}
+
+// First step of a plan the runtime has no kernel for; its convolutions run
+// with stride 1 and "same" padding, its pooling with non-overlapping windows
+fn unsupported_step(plan: &Plan) -> Option<&Step> {
+    plan.steps.iter().find(|s| match (&s.layer.layer_type, s.layer.window()) {
+        (LayerType::LSTM | LayerType::Attention, _) => true,
+        (LayerType::Convolutional(_), Some(window)) => window.stride != 1 || !window.same,
+        (LayerType::Pooling, Some(window)) => window.stride != window.size || window.same,
+        _ => false,
+    })
+}
+
+// Layer nodes `node` expands to, counting every repetition of blocks
+fn layer_count(node: &ASTNode) -> usize {
+    match node {
+        ASTNode::Layer(_) => 1,
+        ASTNode::Block(block) => block.repetitions.max(1) * layer_count(&block.content),
+        ASTNode::DataFlow(from, to) => layer_count(from) + layer_count(to),
+        ASTNode::Expression(nodes) => nodes.iter().map(layer_count).sum(),
+        _ => 0,
+    }
+}
+
+// System V calling convention; stack arguments are read after five pushes
+const X86_64_INT8_KERNELS: &str = r#"; Int8 kernels for quantized components
+; int32 gaia_dot_i8(const int8 *a, const int8 *b, int64 n, int64 stride, int32 a_zero)
+;   sum of (a[i] - a_zero) * b[i * stride]
+global gaia_dot_i8
+gaia_dot_i8:
+    xor eax, eax
+    test rdx, rdx
+    jz .done
+.next:
+    movsx r9d, byte [rdi]
+    sub r9d, r8d
+    movsx r10d, byte [rsi]
+    imul r9d, r10d
+    add eax, r9d
+    inc rdi
+    add rsi, rcx
+    dec rdx
+    jnz .next
+.done:
+    ret
+; int8 gaia_requantize_i8(int32 acc, float multiplier, int32 zero, int32 lo)
+;   clamp(round_half_even(acc * multiplier) + zero, lo, 127); lo is -128, or zero for ReLU
+global gaia_requantize_i8
+gaia_requantize_i8:
+    cvtsi2ss xmm1, edi
+    mulss xmm1, xmm0
+    cvtss2si eax, xmm1          ; rounds half to even under the default MXCSR
+    add eax, esi
+    cmp eax, edx
+    cmovl eax, edx
+    mov ecx, 127
+    cmp eax, ecx
+    cmovg eax, ecx
+    ret
+; void gaia_dense_i8(const int8 *x, const int8 *w, const int32 *bias, const float *multipliers,
+;                    int8 *y, int64 n_in, int64 n_out, int32 x_zero, int32 y_zero, int32 y_lo)
+;   w is [n_in, n_out]
+global gaia_dense_i8
+gaia_dense_i8:
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    mov r10, [rsp + 48]         ; n_out
+    mov r11d, [rsp + 56]        ; x_zero
+    xor ebx, ebx                ; output index
+.output:
+    cmp rbx, r10
+    jge .finished
+    mov eax, [rdx + rbx * 4]    ; bias[o]
+    lea r13, [rsi + rbx]        ; w[0][o]
+    xor r12, r12                ; input index
+.input:
+    cmp r12, r9
+    jge .requantize
+    movsx r14d, byte [rdi + r12]
+    sub r14d, r11d
+    movsx r15d, byte [r13]
+    imul r14d, r15d
+    add eax, r14d
+    add r13, r10
+    inc r12
+    jmp .input
+.requantize:
+    cvtsi2ss xmm0, eax
+    mulss xmm0, [rcx + rbx * 4]
+    cvtss2si eax, xmm0
+    add eax, [rsp + 64]         ; y_zero
+    mov r14d, [rsp + 72]        ; y_lo
+    cmp eax, r14d
+    cmovl eax, r14d
+    mov r14d, 127
+    cmp eax, r14d
+    cmovg eax, r14d
+    mov [r8 + rbx], al
+    inc rbx
+    jmp .output
+.finished:
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
+    ret
+"#;
+
+// AAPCS64; y_zero and y_lo of gaia_dense_i8 are passed on the stack
+const ARM64_INT8_KERNELS: &str = r#"// Int8 kernels for quantized components
+// int32 gaia_dot_i8(const int8 *a, const int8 *b, int64 n, int64 stride, int32 a_zero)
+//   sum of (a[i] - a_zero) * b[i * stride]
+.global gaia_dot_i8
+gaia_dot_i8:
+    mov w9, #0
+    cbz x2, 2f
+1:
+    ldrsb w10, [x0], #1
+    sub w10, w10, w4
+    ldrsb w11, [x1]
+    add x1, x1, x3
+    madd w9, w10, w11, w9
+    subs x2, x2, #1
+    b.ne 1b
+2:
+    mov w0, w9
+    ret
+// int8 gaia_requantize_i8(int32 acc, float multiplier, int32 zero, int32 lo)
+//   clamp(round_half_even(acc * multiplier) + zero, lo, 127); lo is -128, or zero for ReLU
+.global gaia_requantize_i8
+gaia_requantize_i8:
+    scvtf s1, w0
+    fmul s1, s1, s0
+    fcvtns w0, s1
+    add w0, w0, w1
+    cmp w0, w2
+    csel w0, w0, w2, gt
+    mov w9, #127
+    cmp w0, w9
+    csel w0, w0, w9, lt
+    ret
+// void gaia_dense_i8(const int8 *x, const int8 *w, const int32 *bias, const float *multipliers,
+//                    int8 *y, int64 n_in, int64 n_out, int32 x_zero, int32 y_zero, int32 y_lo)
+//   w is [n_in, n_out]
+.global gaia_dense_i8
+gaia_dense_i8:
+    ldr w15, [sp]               // y_zero
+    ldr w16, [sp, #8]           // y_lo
+    mov x9, #0                  // output index
+1:
+    cmp x9, x6
+    b.ge 4f
+    ldr w10, [x2, x9, lsl #2]   // bias[o]
+    add x11, x1, x9             // w[0][o]
+    mov x12, #0                 // input index
+2:
+    cmp x12, x5
+    b.ge 3f
+    ldrsb w13, [x0, x12]
+    sub w13, w13, w7
+    ldrsb w14, [x11]
+    madd w10, w13, w14, w10
+    add x11, x11, x6
+    add x12, x12, #1
+    b 2b
+3:
+    scvtf s0, w10
+    ldr s1, [x3, x9, lsl #2]
+    fmul s0, s0, s1
+    fcvtns w10, s0
+    add w10, w10, w15
+    cmp w10, w16
+    csel w10, w10, w16, gt
+    mov w13, #127
+    cmp w10, w13
+    csel w10, w10, w13, lt
+    strb w10, [x4, x9]
+    add x9, x9, #1
+    b 1b
+4:
+    ret
+"#;
+
+const WASM_INT8_KERNELS: &str = r#"  ;; Int8 kernels for quantized components
+  ;; Sum of (a[i] - a_zero) * b[i * stride] with int32 accumulation
+  (func $gaia_dot_i8 (export "gaia_dot_i8")
+    (param $a i32) (param $b i32) (param $n i32) (param $stride i32) (param $a_zero i32) (result i32)
+    (local $acc i32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.eqz (local.get $n)))
+        (local.set $acc (i32.add (local.get $acc)
+          (i32.mul (i32.sub (i32.load8_s (local.get $a)) (local.get $a_zero))
+                   (i32.load8_s (local.get $b)))))
+        (local.set $a (i32.add (local.get $a) (i32.const 1)))
+        (local.set $b (i32.add (local.get $b) (local.get $stride)))
+        (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+        (br $next)))
+    (local.get $acc))
+  ;; clamp(round_half_even(acc * multiplier) + zero, lo, 127); lo is -128, or zero for ReLU
+  (func $gaia_requantize_i8 (export "gaia_requantize_i8")
+    (param $acc i32) (param $multiplier f32) (param $zero i32) (param $lo i32) (result i32)
+    (local $q i32)
+    (local.set $q (i32.add
+      (i32.trunc_sat_f32_s (f32.nearest (f32.mul (f32.convert_i32_s (local.get $acc)) (local.get $multiplier))))
+      (local.get $zero)))
+    (if (i32.lt_s (local.get $q) (local.get $lo)) (then (local.set $q (local.get $lo))))
+    (if (i32.gt_s (local.get $q) (i32.const 127)) (then (local.set $q (i32.const 127))))
+    (local.get $q))
+  ;; Dense layer; w is [n_in, n_out], bias int32 and multipliers f32 per output
+  (func $gaia_dense_i8 (export "gaia_dense_i8")
+    (param $x i32) (param $w i32) (param $bias i32) (param $multipliers i32) (param $y i32)
+    (param $n_in i32) (param $n_out i32) (param $x_zero i32) (param $y_zero i32) (param $y_lo i32)
+    (local $o i32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $o) (local.get $n_out)))
+        (i32.store8 (i32.add (local.get $y) (local.get $o))
+          (call $gaia_requantize_i8
+            (i32.add
+              (i32.load (i32.add (local.get $bias) (i32.shl (local.get $o) (i32.const 2))))
+              (call $gaia_dot_i8 (local.get $x) (i32.add (local.get $w) (local.get $o))
+                                 (local.get $n_in) (local.get $n_out) (local.get $x_zero)))
+            (f32.load (i32.add (local.get $multipliers) (i32.shl (local.get $o) (i32.const 2))))
+            (local.get $y_zero)
+            (local.get $y_lo)))
+        (local.set $o (i32.add (local.get $o) (i32.const 1)))
+        (br $next))))
+"#;
@@ -0,0 +1,971 @@
+// GaiaScript AArch64 runtime: float32 kernels for the generated components
+//
+// Every public function follows AAPCS64: integer and pointer arguments in
+// x0-x7, then on the stack; float results in s0; x19-x28, x29, x30 and the
+// low halves of v8-v15 preserved; sp 16-byte aligned. Tensors are row-major
+// float32 with channels last, as in the interpreter, and the arithmetic
+// follows the interpreter step by step. The inner loops of dense and
+// convolution layers run four output channels per NEON instruction, with a
+// separate multiply and add, so every output sees the same roundings as in
+// the interpreter.
+//
+// Layer kernels are named gaia_<layer>_<activation> and apply the activation
+// to their output: none, relu, sigmoid, tanh or softmax (over the last axis).
+//
+//   as gaia_runtime_arm64.s -o gaia_runtime_arm64.o
+//   cc main.c app_arm64.o gaia_runtime_arm64.o -lm
+
+    .text
+
+// Kernel bodies are entered with the activation in w9 (0 none, 1 relu,
+// 2 sigmoid, 3 tanh, 4 softmax) and share one 128-byte frame:
+//   [x29, #16] to [x29, #95]   x19-x28
+//   [x29, #96] activation      [x29, #104] output
+//   [x29, #112] output rows    [x29, #120] values per row
+// Stack arguments start at [x29, #128]. The helpers copy and axpy only
+// clobber x10-x12, v1 and v2, so the kernels keep x0-x8 and x13-x17 across them.
+
+// void gaia_dense_<activation>(const float *x, float *y, const float *w, const float *b,
+//                              int64 rows, int64 n_in, int64 n_out)
+//   y[r] = x[r] w + b for every row; w is [n_in, n_out]
+    .global gaia_dense_none
+    .global gaia_dense_relu
+    .global gaia_dense_sigmoid
+    .global gaia_dense_tanh
+    .global gaia_dense_softmax
+gaia_dense_none:
+    mov w9, #0
+    b dense
+gaia_dense_relu:
+    mov w9, #1
+    b dense
+gaia_dense_sigmoid:
+    mov w9, #2
+    b dense
+gaia_dense_tanh:
+    mov w9, #3
+    b dense
+gaia_dense_softmax:
+    mov w9, #4
+    b dense
+
+dense:
+    stp x29, x30, [sp, #-128]!
+    mov x29, sp
+    stp x19, x20, [sp, #16]
+    stp x21, x22, [sp, #32]
+    stp x23, x24, [sp, #48]
+    stp x25, x26, [sp, #64]
+    stp x27, x28, [sp, #80]
+    str w9, [x29, #96]
+    str x1, [x29, #104]
+    str x4, [x29, #112]
+    str x6, [x29, #120]
+    mov x19, x0                 // row of x
+    mov x20, x1                 // row of y
+.Ldense_row:
+    cbz x4, finish
+    mov x10, x20
+    mov x11, x3
+    mov x12, x6
+    bl copy
+    mov x21, x2                 // row i of w
+    mov x22, #0                 // i
+.Ldense_input:
+    cmp x22, x5
+    b.ge .Ldense_next_row
+    ldr s0, [x19, x22, lsl #2]
+    mov x10, x20
+    mov x11, x21
+    mov x12, x6
+    bl axpy
+    add x21, x21, x6, lsl #2
+    add x22, x22, #1
+    b .Ldense_input
+.Ldense_next_row:
+    add x19, x19, x5, lsl #2
+    add x20, x20, x6, lsl #2
+    sub x4, x4, #1
+    b .Ldense_row
+
+// void gaia_conv_<activation>(const float *x, float *y, const float *w, const float *b,
+//                             int64 h, int64 width, int64 c_in, int64 c_out, int64 k)
+//   stride 1 with "same" padding; w is [k, k, c_in, c_out]
+    .global gaia_conv_none
+    .global gaia_conv_relu
+    .global gaia_conv_sigmoid
+    .global gaia_conv_tanh
+    .global gaia_conv_softmax
+gaia_conv_none:
+    mov w9, #0
+    b conv
+gaia_conv_relu:
+    mov w9, #1
+    b conv
+gaia_conv_sigmoid:
+    mov w9, #2
+    b conv
+gaia_conv_tanh:
+    mov w9, #3
+    b conv
+gaia_conv_softmax:
+    mov w9, #4
+    b conv
+
+conv:
+    stp x29, x30, [sp, #-128]!
+    mov x29, sp
+    stp x19, x20, [sp, #16]
+    stp x21, x22, [sp, #32]
+    stp x23, x24, [sp, #48]
+    stp x25, x26, [sp, #64]
+    stp x27, x28, [sp, #80]
+    str w9, [x29, #96]
+    str x1, [x29, #104]
+    mul x9, x4, x5
+    str x9, [x29, #112]
+    str x7, [x29, #120]
+    ldr x19, [x29, #128]        // k
+    sub x20, x19, #1
+    lsr x20, x20, #1            // padding
+    mov x21, x0
+    mov x22, x1                 // output pixel
+    mov x23, x2
+    mov x24, x3
+    mov x25, #0                 // oy
+.Lconv_row:
+    cmp x25, x4
+    b.ge finish
+    mov x26, #0                 // ox
+.Lconv_column:
+    cmp x26, x5
+    b.ge .Lconv_next_row
+    mov x10, x22
+    mov x11, x24
+    mov x12, x7
+    bl copy
+    mov x27, #0                 // ky
+.Lconv_kernel_row:
+    cmp x27, x19
+    b.ge .Lconv_next_column
+    add x13, x25, x27
+    subs x13, x13, x20          // iy
+    b.mi .Lconv_next_kernel_row
+    cmp x13, x4
+    b.ge .Lconv_next_kernel_row
+    mov x28, #0                 // kx
+.Lconv_kernel_column:
+    cmp x28, x19
+    b.ge .Lconv_next_kernel_row
+    add x14, x26, x28
+    subs x14, x14, x20          // ix
+    b.mi .Lconv_next_kernel_column
+    cmp x14, x5
+    b.ge .Lconv_next_kernel_column
+    madd x15, x13, x5, x14      // x[iy][ix]
+    mul x15, x15, x6
+    add x15, x21, x15, lsl #2
+    madd x16, x27, x19, x28     // w[ky][kx]
+    mul x16, x16, x6
+    mul x16, x16, x7
+    add x16, x23, x16, lsl #2
+    mov x17, #0                 // input channel
+.Lconv_channel:
+    cmp x17, x6
+    b.ge .Lconv_next_kernel_column
+    ldr s0, [x15, x17, lsl #2]
+    mov x10, x22
+    mov x11, x16
+    mov x12, x7
+    bl axpy
+    add x16, x16, x7, lsl #2
+    add x17, x17, #1
+    b .Lconv_channel
+.Lconv_next_kernel_column:
+    add x28, x28, #1
+    b .Lconv_kernel_column
+.Lconv_next_kernel_row:
+    add x27, x27, #1
+    b .Lconv_kernel_row
+.Lconv_next_column:
+    add x22, x22, x7, lsl #2
+    add x26, x26, #1
+    b .Lconv_column
+.Lconv_next_row:
+    add x25, x25, #1
+    b .Lconv_row
+
+// void gaia_transpose_conv_<activation>(const float *x, float *y, const float *w, const float *b,
+//                                       int64 h, int64 width, int64 c_in, int64 c_out,
+//                                       int64 k, int64 stride)
+//   output stride times the input size with "same" padding; w is [k, k, c_in, c_out]
+    .global gaia_transpose_conv_none
+    .global gaia_transpose_conv_relu
+    .global gaia_transpose_conv_sigmoid
+    .global gaia_transpose_conv_tanh
+    .global gaia_transpose_conv_softmax
+gaia_transpose_conv_none:
+    mov w9, #0
+    b transpose_conv
+gaia_transpose_conv_relu:
+    mov w9, #1
+    b transpose_conv
+gaia_transpose_conv_sigmoid:
+    mov w9, #2
+    b transpose_conv
+gaia_transpose_conv_tanh:
+    mov w9, #3
+    b transpose_conv
+gaia_transpose_conv_softmax:
+    mov w9, #4
+    b transpose_conv
+
+transpose_conv:
+    stp x29, x30, [sp, #-128]!
+    mov x29, sp
+    stp x19, x20, [sp, #16]
+    stp x21, x22, [sp, #32]
+    stp x23, x24, [sp, #48]
+    stp x25, x26, [sp, #64]
+    stp x27, x28, [sp, #80]
+    str w9, [x29, #96]
+    str x1, [x29, #104]
+    str x7, [x29, #120]
+    ldr x19, [x29, #128]        // k
+    ldr x8, [x29, #136]         // stride
+    mov x21, x0
+    mov x22, x1
+    mov x23, x2
+    mov x24, x3
+    mul x0, x4, x8              // output height
+    mul x1, x5, x8              // output width
+    mul x9, x0, x1
+    str x9, [x29, #112]
+    subs x20, x19, x8
+    csel x20, x20, xzr, ge
+    lsr x20, x20, #1            // padding
+    mov x14, x22                // every output pixel starts at the bias
+    mov x15, x9
+.Ltranspose_conv_fill:
+    cbz x15, .Ltranspose_conv_filled
+    mov x10, x14
+    mov x11, x24
+    mov x12, x7
+    bl copy
+    add x14, x14, x7, lsl #2
+    sub x15, x15, #1
+    b .Ltranspose_conv_fill
+.Ltranspose_conv_filled:
+    mov x25, #0                 // iy
+.Ltranspose_conv_row:
+    cmp x25, x4
+    b.ge finish
+    mov x26, #0                 // ix
+.Ltranspose_conv_column:
+    cmp x26, x5
+    b.ge .Ltranspose_conv_next_row
+    madd x2, x25, x5, x26       // x[iy][ix]
+    mul x2, x2, x6
+    add x2, x21, x2, lsl #2
+    mov x27, #0                 // ky
+.Ltranspose_conv_kernel_row:
+    cmp x27, x19
+    b.ge .Ltranspose_conv_next_column
+    madd x3, x25, x8, x27
+    subs x3, x3, x20            // oy
+    b.mi .Ltranspose_conv_next_kernel_row
+    cmp x3, x0
+    b.ge .Ltranspose_conv_next_kernel_row
+    mov x28, #0                 // kx
+.Ltranspose_conv_kernel_column:
+    cmp x28, x19
+    b.ge .Ltranspose_conv_next_kernel_row
+    madd x13, x26, x8, x28
+    subs x13, x13, x20          // ox
+    b.mi .Ltranspose_conv_next_kernel_column
+    cmp x13, x1
+    b.ge .Ltranspose_conv_next_kernel_column
+    madd x14, x3, x1, x13       // y[oy][ox]
+    mul x14, x14, x7
+    add x14, x22, x14, lsl #2
+    madd x15, x27, x19, x28     // w[ky][kx]
+    mul x15, x15, x6
+    mul x15, x15, x7
+    add x15, x23, x15, lsl #2
+    mov x16, #0                 // input channel
+.Ltranspose_conv_channel:
+    cmp x16, x6
+    b.ge .Ltranspose_conv_next_kernel_column
+    ldr s0, [x2, x16, lsl #2]
+    mov x10, x14
+    mov x11, x15
+    mov x12, x7
+    bl axpy
+    add x15, x15, x7, lsl #2
+    add x16, x16, #1
+    b .Ltranspose_conv_channel
+.Ltranspose_conv_next_kernel_column:
+    add x28, x28, #1
+    b .Ltranspose_conv_kernel_column
+.Ltranspose_conv_next_kernel_row:
+    add x27, x27, #1
+    b .Ltranspose_conv_kernel_row
+.Ltranspose_conv_next_column:
+    add x26, x26, #1
+    b .Ltranspose_conv_column
+.Ltranspose_conv_next_row:
+    add x25, x25, #1
+    b .Ltranspose_conv_row
+
+// void gaia_pooling_<activation>(const float *x, float *y, int64 h, int64 width, int64 c, int64 size)
+//   max over non-overlapping size × size windows
+    .global gaia_pooling_none
+    .global gaia_pooling_relu
+    .global gaia_pooling_sigmoid
+    .global gaia_pooling_tanh
+    .global gaia_pooling_softmax
+gaia_pooling_none:
+    mov w9, #0
+    b pooling
+gaia_pooling_relu:
+    mov w9, #1
+    b pooling
+gaia_pooling_sigmoid:
+    mov w9, #2
+    b pooling
+gaia_pooling_tanh:
+    mov w9, #3
+    b pooling
+gaia_pooling_softmax:
+    mov w9, #4
+    b pooling
+
+pooling:
+    stp x29, x30, [sp, #-128]!
+    mov x29, sp
+    stp x19, x20, [sp, #16]
+    stp x21, x22, [sp, #32]
+    stp x23, x24, [sp, #48]
+    stp x25, x26, [sp, #64]
+    stp x27, x28, [sp, #80]
+    str w9, [x29, #96]
+    str x1, [x29, #104]
+    str x4, [x29, #120]
+    udiv x19, x2, x5            // output height
+    udiv x20, x3, x5            // output width
+    mul x9, x19, x20
+    str x9, [x29, #112]
+    mov x21, x1                 // output pixel
+    mov x22, #0                 // oy
+.Lpooling_row:
+    cmp x22, x19
+    b.ge finish
+    mov x23, #0                 // ox
+.Lpooling_column:
+    cmp x23, x20
+    b.ge .Lpooling_next_row
+    mov w9, #0xff800000         // -inf
+    fmov s1, w9
+    mov x24, #0
+.Lpooling_start:
+    cmp x24, x4
+    b.ge .Lpooling_window
+    str s1, [x21, x24, lsl #2]
+    add x24, x24, #1
+    b .Lpooling_start
+.Lpooling_window:
+    mov x25, #0                 // py
+.Lpooling_window_row:
+    cmp x25, x5
+    b.ge .Lpooling_next_column
+    mov x26, #0                 // px
+.Lpooling_window_column:
+    cmp x26, x5
+    b.ge .Lpooling_next_window_row
+    madd x9, x22, x5, x25       // x[oy * size + py][ox * size + px]
+    mul x9, x9, x3
+    madd x9, x23, x5, x9
+    add x9, x9, x26
+    mul x9, x9, x4
+    add x9, x0, x9, lsl #2
+    mov x24, #0
+.Lpooling_channel:
+    cmp x24, x4
+    b.ge .Lpooling_next_window_column
+    ldr s1, [x21, x24, lsl #2]
+    ldr s2, [x9, x24, lsl #2]
+    fmaxnm s1, s1, s2
+    str s1, [x21, x24, lsl #2]
+    add x24, x24, #1
+    b .Lpooling_channel
+.Lpooling_next_window_column:
+    add x26, x26, #1
+    b .Lpooling_window_column
+.Lpooling_next_window_row:
+    add x25, x25, #1
+    b .Lpooling_window_row
+.Lpooling_next_column:
+    add x21, x21, x4, lsl #2
+    add x23, x23, #1
+    b .Lpooling_column
+.Lpooling_next_row:
+    add x22, x22, #1
+    b .Lpooling_row
+
+// void gaia_upsampling_<activation>(const float *x, float *y, int64 h, int64 width, int64 c, int64 factor)
+//   nearest neighbour, factor times the height and the width
+    .global gaia_upsampling_none
+    .global gaia_upsampling_relu
+    .global gaia_upsampling_sigmoid
+    .global gaia_upsampling_tanh
+    .global gaia_upsampling_softmax
+gaia_upsampling_none:
+    mov w9, #0
+    b upsampling
+gaia_upsampling_relu:
+    mov w9, #1
+    b upsampling
+gaia_upsampling_sigmoid:
+    mov w9, #2
+    b upsampling
+gaia_upsampling_tanh:
+    mov w9, #3
+    b upsampling
+gaia_upsampling_softmax:
+    mov w9, #4
+    b upsampling
+
+upsampling:
+    stp x29, x30, [sp, #-128]!
+    mov x29, sp
+    stp x19, x20, [sp, #16]
+    stp x21, x22, [sp, #32]
+    stp x23, x24, [sp, #48]
+    stp x25, x26, [sp, #64]
+    stp x27, x28, [sp, #80]
+    str w9, [x29, #96]
+    str x1, [x29, #104]
+    str x4, [x29, #120]
+    mul x19, x2, x5             // output height
+    mul x20, x3, x5             // output width
+    mul x9, x19, x20
+    str x9, [x29, #112]
+    mov x21, x1                 // output pixel
+    mov x22, #0                 // oy
+.Lupsampling_row:
+    cmp x22, x19
+    b.ge finish
+    mov x23, #0                 // ox
+.Lupsampling_column:
+    cmp x23, x20
+    b.ge .Lupsampling_next_row
+    udiv x9, x22, x5
+    udiv x24, x23, x5
+    madd x9, x9, x3, x24        // x[oy / factor][ox / factor]
+    mul x9, x9, x4
+    add x11, x0, x9, lsl #2
+    mov x10, x21
+    mov x12, x4
+    bl copy
+    add x21, x21, x4, lsl #2
+    add x23, x23, #1
+    b .Lupsampling_column
+.Lupsampling_next_row:
+    add x22, x22, #1
+    b .Lupsampling_row
+
+// void gaia_embedding_<activation>(const float *ids, float *y, const float *table, int64 n,
+//                                  int64 vocab, int64 dim, int64 positional)
+//   row ids[i] of table ([vocab, dim]) for each of the n ids, zeros for ids
+//   outside the vocabulary; positional adds the sinusoidal position encoding
+    .global gaia_embedding_none
+    .global gaia_embedding_relu
+    .global gaia_embedding_sigmoid
+    .global gaia_embedding_tanh
+    .global gaia_embedding_softmax
+gaia_embedding_none:
+    mov w9, #0
+    b embedding
+gaia_embedding_relu:
+    mov w9, #1
+    b embedding
+gaia_embedding_sigmoid:
+    mov w9, #2
+    b embedding
+gaia_embedding_tanh:
+    mov w9, #3
+    b embedding
+gaia_embedding_softmax:
+    mov w9, #4
+    b embedding
+
+embedding:
+    stp x29, x30, [sp, #-128]!
+    mov x29, sp
+    stp x19, x20, [sp, #16]
+    stp x21, x22, [sp, #32]
+    stp x23, x24, [sp, #48]
+    stp x25, x26, [sp, #64]
+    stp x27, x28, [sp, #80]
+    str w9, [x29, #96]
+    str x1, [x29, #104]
+    str x3, [x29, #112]
+    str x5, [x29, #120]
+    mov x19, x0                 // next id
+    mov x20, x1                 // its row of y
+    mov x21, x3                 // ids left
+.Lembedding_id:
+    cbz x21, .Lembedding_positional
+    ldr s0, [x19], #4
+    fcvtzs x9, s0
+    tbnz x9, #63, .Lembedding_zero
+    cmp x9, x4
+    b.ge .Lembedding_zero
+    mul x9, x9, x5
+    add x11, x2, x9, lsl #2
+    mov x10, x20
+    mov x12, x5
+    bl copy
+    b .Lembedding_next_id
+.Lembedding_zero:
+    mov x9, #0
+.Lembedding_zero_value:
+    cmp x9, x5
+    b.ge .Lembedding_next_id
+    str wzr, [x20, x9, lsl #2]
+    add x9, x9, #1
+    b .Lembedding_zero_value
+.Lembedding_next_id:
+    add x20, x20, x5, lsl #2
+    sub x21, x21, #1
+    b .Lembedding_id
+.Lembedding_positional:
+    cbz x6, finish
+    ldr x0, [x29, #104]
+    mov x1, x3
+    mov x2, x5
+    bl positional
+    b finish
+
+// void gaia_flatten_<activation>(const float *x, float *y, int64 rows, int64 n)
+//   copy of rows × n values; layers that only change the shape use it when
+//   they carry an activation, and x may be y
+    .global gaia_flatten_none
+    .global gaia_flatten_relu
+    .global gaia_flatten_sigmoid
+    .global gaia_flatten_tanh
+    .global gaia_flatten_softmax
+gaia_flatten_none:
+    mov w9, #0
+    b flatten
+gaia_flatten_relu:
+    mov w9, #1
+    b flatten
+gaia_flatten_sigmoid:
+    mov w9, #2
+    b flatten
+gaia_flatten_tanh:
+    mov w9, #3
+    b flatten
+gaia_flatten_softmax:
+    mov w9, #4
+    b flatten
+
+flatten:
+    stp x29, x30, [sp, #-128]!
+    mov x29, sp
+    stp x19, x20, [sp, #16]
+    stp x21, x22, [sp, #32]
+    stp x23, x24, [sp, #48]
+    stp x25, x26, [sp, #64]
+    stp x27, x28, [sp, #80]
+    str w9, [x29, #96]
+    str x1, [x29, #104]
+    str x2, [x29, #112]
+    str x3, [x29, #120]
+    mov x10, x1
+    mov x11, x0
+    mul x12, x2, x3
+    bl copy
+    b finish
+
+// Apply the activation chosen on entry to the output and return; every
+// kernel body ends here with its frame in place
+finish:
+    ldr x0, [x29, #104]
+    ldr x1, [x29, #112]
+    ldr x2, [x29, #120]
+    ldr w3, [x29, #96]
+    bl activate
+    ldp x19, x20, [sp, #16]
+    ldp x21, x22, [sp, #32]
+    ldp x23, x24, [sp, #48]
+    ldp x25, x26, [sp, #64]
+    ldp x27, x28, [sp, #80]
+    ldp x29, x30, [sp], #128
+    ret
+
+// Copy x12 floats from x11 to x10
+copy:
+    cmp x12, #4
+    b.lo .Lcopy_tail
+.Lcopy_vector:
+    ld1 {v1.4s}, [x11], #16
+    st1 {v1.4s}, [x10], #16
+    sub x12, x12, #4
+    cmp x12, #4
+    b.hs .Lcopy_vector
+.Lcopy_tail:
+    cbz x12, .Lcopy_done
+    ldr s1, [x11], #4
+    str s1, [x10], #4
+    sub x12, x12, #1
+    b .Lcopy_tail
+.Lcopy_done:
+    ret
+
+// y[o] += w[o] * s0 for the x12 floats of y at x10 and w at x11
+axpy:
+    cmp x12, #4
+    b.lo .Laxpy_tail
+.Laxpy_vector:
+    ld1 {v1.4s}, [x11], #16
+    fmul v1.4s, v1.4s, v0.s[0]
+    ld1 {v2.4s}, [x10]
+    fadd v2.4s, v2.4s, v1.4s
+    st1 {v2.4s}, [x10], #16
+    sub x12, x12, #4
+    cmp x12, #4
+    b.hs .Laxpy_vector
+.Laxpy_tail:
+    cbz x12, .Laxpy_done
+    ldr s1, [x11], #4
+    fmul s1, s1, s0
+    ldr s2, [x10]
+    fadd s2, s2, s1
+    str s2, [x10], #4
+    sub x12, x12, #1
+    b .Laxpy_tail
+.Laxpy_done:
+    ret
+
+// void activate(float *y, int64 rows, int64 n, int32 activation)
+activate:
+    stp x29, x30, [sp, #-64]!
+    mov x29, sp
+    stp x19, x20, [sp, #16]
+    stp x21, x22, [sp, #32]
+    stp d8, d9, [sp, #48]
+    mov x19, x0
+    mov x20, x1                 // rows left
+    mov x21, x2
+    mul x22, x1, x2             // values left
+    cmp w3, #1
+    b.eq .Lactivate_relu
+    cmp w3, #2
+    b.eq .Lactivate_sigmoid
+    cmp w3, #3
+    b.eq .Lactivate_tanh
+    cmp w3, #4
+    b.eq .Lactivate_softmax
+    b .Lactivate_done
+.Lactivate_relu:
+    fmov s1, wzr
+.Lactivate_relu_next:
+    cbz x22, .Lactivate_done
+    ldr s0, [x19]
+    fmaxnm s0, s0, s1
+    str s0, [x19], #4
+    sub x22, x22, #1
+    b .Lactivate_relu_next
+.Lactivate_sigmoid:
+    cbz x22, .Lactivate_done
+    ldr s0, [x19]
+    fneg s0, s0
+    bl expf
+    fmov s1, #1.0
+    fadd s0, s0, s1
+    fdiv s0, s1, s0
+    str s0, [x19], #4
+    sub x22, x22, #1
+    b .Lactivate_sigmoid
+.Lactivate_tanh:
+    cbz x22, .Lactivate_done
+    ldr s0, [x19]
+    bl tanhf
+    str s0, [x19], #4
+    sub x22, x22, #1
+    b .Lactivate_tanh
+.Lactivate_softmax:
+    cbz x20, .Lactivate_done
+    mov w9, #0xff800000         // -inf
+    fmov s8, w9                 // maximum of the row
+    mov x22, #0
+.Lactivate_maximum:
+    cmp x22, x21
+    b.ge .Lactivate_exponentials
+    ldr s0, [x19, x22, lsl #2]
+    fmaxnm s8, s8, s0
+    add x22, x22, #1
+    b .Lactivate_maximum
+.Lactivate_exponentials:
+    fmov s9, wzr                // sum of the exponentials
+    mov x22, #0
+.Lactivate_exponential:
+    cmp x22, x21
+    b.ge .Lactivate_normalise
+    ldr s0, [x19, x22, lsl #2]
+    fsub s0, s0, s8
+    bl expf
+    str s0, [x19, x22, lsl #2]
+    fadd s9, s9, s0
+    add x22, x22, #1
+    b .Lactivate_exponential
+.Lactivate_normalise:
+    mov x22, #0
+.Lactivate_divide:
+    cmp x22, x21
+    b.ge .Lactivate_next_row
+    ldr s0, [x19, x22, lsl #2]
+    fdiv s0, s0, s9
+    str s0, [x19, x22, lsl #2]
+    add x22, x22, #1
+    b .Lactivate_divide
+.Lactivate_next_row:
+    add x19, x19, x21, lsl #2
+    sub x20, x20, #1
+    b .Lactivate_softmax
+.Lactivate_done:
+    ldp d8, d9, [sp, #48]
+    ldp x21, x22, [sp, #32]
+    ldp x19, x20, [sp, #16]
+    ldp x29, x30, [sp], #64
+    ret
+
+// void positional(float *y, int64 steps, int64 dim)
+//   adds sin(p / 10000^(2i / dim)) to feature 2i of position p and the cosine to feature 2i + 1
+positional:
+    stp x29, x30, [sp, #-64]!
+    mov x29, sp
+    stp x19, x20, [sp, #16]
+    stp x21, x22, [sp, #32]
+    str x23, [sp, #48]
+    mov x19, x0
+    mov x20, x1
+    mov x23, x2
+    mov x21, #0                 // position
+.Lpositional_position:
+    cmp x21, x20
+    b.ge .Lpositional_done
+    mov x22, #0                 // feature
+.Lpositional_feature:
+    cmp x22, x23
+    b.ge .Lpositional_next_position
+    lsr x9, x22, #1
+    lsl x9, x9, #1
+    scvtf s1, x9
+    scvtf s2, x23
+    fdiv s1, s1, s2
+    mov w9, #0x4000
+    movk w9, #0x461c, lsl #16   // 10000.0
+    fmov s0, w9
+    bl powf
+    scvtf s1, x21
+    fdiv s0, s1, s0
+    tbnz x22, #0, .Lpositional_cosine
+    bl sinf
+    b .Lpositional_add
+.Lpositional_cosine:
+    bl cosf
+.Lpositional_add:
+    ldr s1, [x19]
+    fadd s0, s0, s1
+    str s0, [x19], #4
+    add x22, x22, #1
+    b .Lpositional_feature
+.Lpositional_next_position:
+    add x21, x21, #1
+    b .Lpositional_position
+.Lpositional_done:
+    ldr x23, [sp, #48]
+    ldp x21, x22, [sp, #32]
+    ldp x19, x20, [sp, #16]
+    ldp x29, x30, [sp], #64
+    ret
+
+// Losses: float gaia_loss_<name>(const float *prediction, const float *target, int64 n)
+
+// Mean squared error
+    .global gaia_loss_MSE
+gaia_loss_MSE:
+    fmov s0, wzr
+    mov x9, #0
+.Lmse_next:
+    cmp x9, x2
+    b.ge .Lmse_mean
+    ldr s1, [x0, x9, lsl #2]
+    ldr s2, [x1, x9, lsl #2]
+    fsub s1, s1, s2
+    fmul s1, s1, s1
+    fadd s0, s0, s1
+    add x9, x9, #1
+    b .Lmse_next
+.Lmse_mean:
+    scvtf s1, x2
+    fdiv s0, s0, s1
+    ret
+
+// Binary cross-entropy, with the prediction clamped to [1e-7, 1 - 1e-7]
+    .global gaia_loss_BCE
+gaia_loss_BCE:
+    stp x29, x30, [sp, #-80]!
+    mov x29, sp
+    stp x19, x20, [sp, #16]
+    stp x21, x22, [sp, #32]
+    stp d8, d9, [sp, #48]
+    str d10, [sp, #64]
+    mov x19, x0
+    mov x20, x1
+    mov x21, x2
+    mov x22, #0
+    fmov s8, wzr                // sum
+.Lbce_next:
+    cmp x22, x21
+    b.ge .Lbce_mean
+    ldr s0, [x19, x22, lsl #2]
+    mov w9, #0xbf95
+    movk w9, #0x33d6, lsl #16   // 1e-7
+    fmov s1, w9
+    fmaxnm s0, s0, s1
+    mov w9, #0xfffe
+    movk w9, #0x3f7f, lsl #16   // 1 - 1e-7
+    fmov s1, w9
+    fminnm s0, s0, s1
+    fmov s9, s0                 // p
+    bl logf
+    fmov s10, s0                // ln p
+    fmov s0, #1.0
+    fsub s0, s0, s9
+    bl logf                     // ln(1 - p)
+    ldr s2, [x20, x22, lsl #2]
+    fmov s1, #1.0
+    fsub s1, s1, s2
+    fmul s0, s0, s1
+    fmul s1, s2, s10
+    fadd s0, s0, s1
+    fsub s8, s8, s0
+    add x22, x22, #1
+    b .Lbce_next
+.Lbce_mean:
+    scvtf s1, x21
+    fdiv s0, s8, s1
+    ldr d10, [sp, #64]
+    ldp d8, d9, [sp, #48]
+    ldp x21, x22, [sp, #32]
+    ldp x19, x20, [sp, #16]
+    ldp x29, x30, [sp], #80
+    ret
+
+// Categorical cross-entropy of a probability distribution
+    .global gaia_loss_CE
+gaia_loss_CE:
+    stp x29, x30, [sp, #-64]!
+    mov x29, sp
+    stp x19, x20, [sp, #16]
+    stp x21, x22, [sp, #32]
+    str d8, [sp, #48]
+    mov x19, x0
+    mov x20, x1
+    mov x21, x2
+    mov x22, #0
+    fmov s8, wzr                // sum
+.Lce_next:
+    cmp x22, x21
+    b.ge .Lce_done
+    ldr s0, [x19, x22, lsl #2]
+    mov w9, #0xbf95
+    movk w9, #0x33d6, lsl #16   // 1e-7
+    fmov s1, w9
+    fmaxnm s0, s0, s1
+    bl logf
+    ldr s1, [x20, x22, lsl #2]
+    fmul s0, s0, s1
+    fsub s8, s8, s0
+    add x22, x22, #1
+    b .Lce_next
+.Lce_done:
+    fmov s0, s8
+    ldr d8, [sp, #48]
+    ldp x21, x22, [sp, #32]
+    ldp x19, x20, [sp, #16]
+    ldp x29, x30, [sp], #64
+    ret
+
+// Hinge loss; 0/1 targets are mapped to -1/1
+    .global gaia_loss_Hinge
+gaia_loss_Hinge:
+    fmov s0, wzr
+    mov x9, #0
+.Lhinge_next:
+    cmp x9, x2
+    b.ge .Lhinge_mean
+    ldr s1, [x1, x9, lsl #2]
+    fcmp s1, #0.0
+    b.ne .Lhinge_label
+    fmov s1, #-1.0
+.Lhinge_label:
+    ldr s2, [x0, x9, lsl #2]
+    fmul s1, s1, s2
+    fmov s2, #1.0
+    fsub s2, s2, s1
+    fmov s1, wzr
+    fmaxnm s2, s2, s1
+    fadd s0, s0, s2
+    add x9, x9, #1
+    b .Lhinge_next
+.Lhinge_mean:
+    scvtf s1, x2
+    fdiv s0, s0, s1
+    ret
+
+// Kullback-Leibler divergence of the prediction from the target distribution
+    .global gaia_loss_KL
+gaia_loss_KL:
+    stp x29, x30, [sp, #-64]!
+    mov x29, sp
+    stp x19, x20, [sp, #16]
+    stp x21, x22, [sp, #32]
+    str d8, [sp, #48]
+    mov x19, x0
+    mov x20, x1
+    mov x21, x2
+    mov x22, #0
+    fmov s8, wzr                // sum
+.Lkl_next:
+    cmp x22, x21
+    b.ge .Lkl_done
+    ldr s1, [x20, x22, lsl #2]
+    fcmp s1, #0.0
+    b.le .Lkl_skip
+    ldr s0, [x19, x22, lsl #2]
+    mov w9, #0xbf95
+    movk w9, #0x33d6, lsl #16   // 1e-7
+    fmov s2, w9
+    fmaxnm s0, s0, s2
+    fdiv s0, s1, s0
+    bl logf
+    ldr s1, [x20, x22, lsl #2]
+    fmul s0, s0, s1
+    fadd s8, s8, s0
+.Lkl_skip:
+    add x22, x22, #1
+    b .Lkl_next
+.Lkl_done:
+    fmov s0, s8
+    ldr d8, [sp, #48]
+    ldp x21, x22, [sp, #32]
+    ldp x19, x20, [sp, #16]
+    ldp x29, x30, [sp], #64
+    ret
+
+    .section .note.GNU-stack,"",%progbits
@@ -0,0 +1,705 @@
+  ;; GaiaScript WebAssembly runtime: float32 kernels for the generated components
+  ;;
+  ;; Spliced into every module of the WASM target, so the module needs no imports.
+  ;; Pointers are i32 byte offsets in the module's memory and sizes are i32
+  ;; counts. Tensors are row-major float32 with channels last, as in the
+  ;; interpreter, and the arithmetic follows the interpreter step by step, with
+  ;; a separate multiply and add. WebAssembly has no transcendental
+  ;; instructions, so expf, logf, tanhf, powf, sinf and cosf are computed below
+  ;; in float64 and rounded to float32.
+  ;;
+  ;; Layer kernels are named gaia_<layer>_<activation> and apply the activation
+  ;; to their output: none, relu, sigmoid, tanh or softmax (over the last axis).
+  ;; Their bodies take the activation as a last argument (0 none, 1 relu,
+  ;; 2 sigmoid, 3 tanh, 4 softmax).
+
+  ;; void gaia_dense_<activation>(const float *x, float *y, const float *w, const float *b,
+  ;;                              int32 rows, int32 n_in, int32 n_out)
+  ;;   y[r] = x[r] w + b for every row; w is [n_in, n_out]
+  (func $gaia_dense_none (param i32 i32 i32 i32 i32 i32 i32)
+    (call $dense (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (i32.const 0)))
+  (func $gaia_dense_relu (param i32 i32 i32 i32 i32 i32 i32)
+    (call $dense (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (i32.const 1)))
+  (func $gaia_dense_sigmoid (param i32 i32 i32 i32 i32 i32 i32)
+    (call $dense (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (i32.const 2)))
+  (func $gaia_dense_tanh (param i32 i32 i32 i32 i32 i32 i32)
+    (call $dense (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (i32.const 3)))
+  (func $gaia_dense_softmax (param i32 i32 i32 i32 i32 i32 i32)
+    (call $dense (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (i32.const 4)))
+
+  (func $dense (param $x i32) (param $y i32) (param $w i32) (param $b i32)
+    (param $rows i32) (param $n_in i32) (param $n_out i32) (param $activation i32)
+    (local $r i32) (local $i i32) (local $x_row i32) (local $y_row i32) (local $w_row i32)
+    (local.set $x_row (local.get $x))
+    (local.set $y_row (local.get $y))
+    (block $done
+      (loop $row
+        (br_if $done (i32.ge_u (local.get $r) (local.get $rows)))
+        (call $copy (local.get $y_row) (local.get $b) (local.get $n_out))
+        (local.set $w_row (local.get $w))
+        (local.set $i (i32.const 0))
+        (block $row_done
+          (loop $input
+            (br_if $row_done (i32.ge_u (local.get $i) (local.get $n_in)))
+            (call $axpy (local.get $y_row) (local.get $w_row)
+              (f32.load (i32.add (local.get $x_row) (i32.shl (local.get $i) (i32.const 2))))
+              (local.get $n_out))
+            (local.set $w_row (i32.add (local.get $w_row) (i32.shl (local.get $n_out) (i32.const 2))))
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            (br $input)))
+        (local.set $x_row (i32.add (local.get $x_row) (i32.shl (local.get $n_in) (i32.const 2))))
+        (local.set $y_row (i32.add (local.get $y_row) (i32.shl (local.get $n_out) (i32.const 2))))
+        (local.set $r (i32.add (local.get $r) (i32.const 1)))
+        (br $row)))
+    (call $activate (local.get $y) (local.get $rows) (local.get $n_out) (local.get $activation)))
+
+  ;; void gaia_conv_<activation>(const float *x, float *y, const float *w, const float *b,
+  ;;                             int32 h, int32 width, int32 c_in, int32 c_out, int32 k)
+  ;;   stride 1 with "same" padding; w is [k, k, c_in, c_out]
+  (func $gaia_conv_none (param i32 i32 i32 i32 i32 i32 i32 i32 i32)
+    (call $conv (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (local.get 7) (local.get 8) (i32.const 0)))
+  (func $gaia_conv_relu (param i32 i32 i32 i32 i32 i32 i32 i32 i32)
+    (call $conv (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (local.get 7) (local.get 8) (i32.const 1)))
+  (func $gaia_conv_sigmoid (param i32 i32 i32 i32 i32 i32 i32 i32 i32)
+    (call $conv (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (local.get 7) (local.get 8) (i32.const 2)))
+  (func $gaia_conv_tanh (param i32 i32 i32 i32 i32 i32 i32 i32 i32)
+    (call $conv (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (local.get 7) (local.get 8) (i32.const 3)))
+  (func $gaia_conv_softmax (param i32 i32 i32 i32 i32 i32 i32 i32 i32)
+    (call $conv (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (local.get 7) (local.get 8) (i32.const 4)))
+
+  (func $conv (param $x i32) (param $y i32) (param $w i32) (param $b i32)
+    (param $h i32) (param $width i32) (param $c_in i32) (param $c_out i32) (param $k i32) (param $activation i32)
+    (local $padding i32) (local $pixel i32) (local $oy i32) (local $ox i32) (local $ky i32) (local $kx i32)
+    (local $iy i32) (local $ix i32) (local $c i32) (local $x_pixel i32) (local $w_tap i32)
+    (local.set $padding (i32.shr_u (i32.sub (local.get $k) (i32.const 1)) (i32.const 1)))
+    (local.set $pixel (local.get $y))
+    (block $done
+      (loop $row
+        (br_if $done (i32.ge_u (local.get $oy) (local.get $h)))
+        (local.set $ox (i32.const 0))
+        (block $row_done
+          (loop $column
+            (br_if $row_done (i32.ge_u (local.get $ox) (local.get $width)))
+            (call $copy (local.get $pixel) (local.get $b) (local.get $c_out))
+            (local.set $ky (i32.const 0))
+            (block $window_done
+              (loop $kernel_row
+                (br_if $window_done (i32.ge_u (local.get $ky) (local.get $k)))
+                ;; Rows above the input wrap around to large unsigned values
+                (local.set $iy (i32.sub (i32.add (local.get $oy) (local.get $ky)) (local.get $padding)))
+                (if (i32.lt_u (local.get $iy) (local.get $h))
+                  (then
+                    (local.set $kx (i32.const 0))
+                    (block $kernel_row_done
+                      (loop $kernel_column
+                        (br_if $kernel_row_done (i32.ge_u (local.get $kx) (local.get $k)))
+                        (local.set $ix (i32.sub (i32.add (local.get $ox) (local.get $kx)) (local.get $padding)))
+                        (if (i32.lt_u (local.get $ix) (local.get $width))
+                          (then
+                            ;; x[iy][ix] and w[ky][kx]
+                            (local.set $x_pixel (i32.add (local.get $x) (i32.shl
+                              (i32.mul (i32.add (i32.mul (local.get $iy) (local.get $width)) (local.get $ix)) (local.get $c_in))
+                              (i32.const 2))))
+                            (local.set $w_tap (i32.add (local.get $w) (i32.shl
+                              (i32.mul (i32.mul (i32.add (i32.mul (local.get $ky) (local.get $k)) (local.get $kx)) (local.get $c_in))
+                                       (local.get $c_out))
+                              (i32.const 2))))
+                            (local.set $c (i32.const 0))
+                            (block $channels_done
+                              (loop $channel
+                                (br_if $channels_done (i32.ge_u (local.get $c) (local.get $c_in)))
+                                (call $axpy (local.get $pixel) (local.get $w_tap)
+                                  (f32.load (i32.add (local.get $x_pixel) (i32.shl (local.get $c) (i32.const 2))))
+                                  (local.get $c_out))
+                                (local.set $w_tap (i32.add (local.get $w_tap) (i32.shl (local.get $c_out) (i32.const 2))))
+                                (local.set $c (i32.add (local.get $c) (i32.const 1)))
+                                (br $channel)))))
+                        (local.set $kx (i32.add (local.get $kx) (i32.const 1)))
+                        (br $kernel_column)))))
+                (local.set $ky (i32.add (local.get $ky) (i32.const 1)))
+                (br $kernel_row)))
+            (local.set $pixel (i32.add (local.get $pixel) (i32.shl (local.get $c_out) (i32.const 2))))
+            (local.set $ox (i32.add (local.get $ox) (i32.const 1)))
+            (br $column)))
+        (local.set $oy (i32.add (local.get $oy) (i32.const 1)))
+        (br $row)))
+    (call $activate (local.get $y) (i32.mul (local.get $h) (local.get $width)) (local.get $c_out) (local.get $activation)))
+
+  ;; void gaia_transpose_conv_<activation>(const float *x, float *y, const float *w, const float *b,
+  ;;                                       int32 h, int32 width, int32 c_in, int32 c_out,
+  ;;                                       int32 k, int32 stride)
+  ;;   output stride times the input size with "same" padding; w is [k, k, c_in, c_out]
+  (func $gaia_transpose_conv_none (param i32 i32 i32 i32 i32 i32 i32 i32 i32 i32)
+    (call $transpose_conv (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (local.get 7) (local.get 8) (local.get 9) (i32.const 0)))
+  (func $gaia_transpose_conv_relu (param i32 i32 i32 i32 i32 i32 i32 i32 i32 i32)
+    (call $transpose_conv (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (local.get 7) (local.get 8) (local.get 9) (i32.const 1)))
+  (func $gaia_transpose_conv_sigmoid (param i32 i32 i32 i32 i32 i32 i32 i32 i32 i32)
+    (call $transpose_conv (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (local.get 7) (local.get 8) (local.get 9) (i32.const 2)))
+  (func $gaia_transpose_conv_tanh (param i32 i32 i32 i32 i32 i32 i32 i32 i32 i32)
+    (call $transpose_conv (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (local.get 7) (local.get 8) (local.get 9) (i32.const 3)))
+  (func $gaia_transpose_conv_softmax (param i32 i32 i32 i32 i32 i32 i32 i32 i32 i32)
+    (call $transpose_conv (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (local.get 7) (local.get 8) (local.get 9) (i32.const 4)))
+
+  (func $transpose_conv (param $x i32) (param $y i32) (param $w i32) (param $b i32)
+    (param $h i32) (param $width i32) (param $c_in i32) (param $c_out i32) (param $k i32) (param $stride i32)
+    (param $activation i32)
+    (local $out_h i32) (local $out_w i32) (local $padding i32) (local $pixel i32) (local $i i32)
+    (local $iy i32) (local $ix i32) (local $ky i32) (local $kx i32) (local $oy i32) (local $ox i32)
+    (local $c i32) (local $x_pixel i32) (local $w_tap i32)
+    (local.set $out_h (i32.mul (local.get $h) (local.get $stride)))
+    (local.set $out_w (i32.mul (local.get $width) (local.get $stride)))
+    (if (i32.gt_s (local.get $k) (local.get $stride))
+      (then (local.set $padding (i32.shr_u (i32.sub (local.get $k) (local.get $stride)) (i32.const 1)))))
+    ;; Every output pixel starts at the bias
+    (local.set $pixel (local.get $y))
+    (block $filled
+      (loop $fill
+        (br_if $filled (i32.ge_u (local.get $i) (i32.mul (local.get $out_h) (local.get $out_w))))
+        (call $copy (local.get $pixel) (local.get $b) (local.get $c_out))
+        (local.set $pixel (i32.add (local.get $pixel) (i32.shl (local.get $c_out) (i32.const 2))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $fill)))
+    (block $done
+      (loop $row
+        (br_if $done (i32.ge_u (local.get $iy) (local.get $h)))
+        (local.set $ix (i32.const 0))
+        (block $row_done
+          (loop $column
+            (br_if $row_done (i32.ge_u (local.get $ix) (local.get $width)))
+            ;; x[iy][ix]
+            (local.set $x_pixel (i32.add (local.get $x) (i32.shl
+              (i32.mul (i32.add (i32.mul (local.get $iy) (local.get $width)) (local.get $ix)) (local.get $c_in))
+              (i32.const 2))))
+            (local.set $ky (i32.const 0))
+            (block $window_done
+              (loop $kernel_row
+                (br_if $window_done (i32.ge_u (local.get $ky) (local.get $k)))
+                (local.set $oy (i32.sub (i32.add (i32.mul (local.get $iy) (local.get $stride)) (local.get $ky)) (local.get $padding)))
+                (if (i32.lt_u (local.get $oy) (local.get $out_h))
+                  (then
+                    (local.set $kx (i32.const 0))
+                    (block $kernel_row_done
+                      (loop $kernel_column
+                        (br_if $kernel_row_done (i32.ge_u (local.get $kx) (local.get $k)))
+                        (local.set $ox (i32.sub (i32.add (i32.mul (local.get $ix) (local.get $stride)) (local.get $kx)) (local.get $padding)))
+                        (if (i32.lt_u (local.get $ox) (local.get $out_w))
+                          (then
+                            ;; y[oy][ox] and w[ky][kx]
+                            (local.set $pixel (i32.add (local.get $y) (i32.shl
+                              (i32.mul (i32.add (i32.mul (local.get $oy) (local.get $out_w)) (local.get $ox)) (local.get $c_out))
+                              (i32.const 2))))
+                            (local.set $w_tap (i32.add (local.get $w) (i32.shl
+                              (i32.mul (i32.mul (i32.add (i32.mul (local.get $ky) (local.get $k)) (local.get $kx)) (local.get $c_in))
+                                       (local.get $c_out))
+                              (i32.const 2))))
+                            (local.set $c (i32.const 0))
+                            (block $channels_done
+                              (loop $channel
+                                (br_if $channels_done (i32.ge_u (local.get $c) (local.get $c_in)))
+                                (call $axpy (local.get $pixel) (local.get $w_tap)
+                                  (f32.load (i32.add (local.get $x_pixel) (i32.shl (local.get $c) (i32.const 2))))
+                                  (local.get $c_out))
+                                (local.set $w_tap (i32.add (local.get $w_tap) (i32.shl (local.get $c_out) (i32.const 2))))
+                                (local.set $c (i32.add (local.get $c) (i32.const 1)))
+                                (br $channel)))))
+                        (local.set $kx (i32.add (local.get $kx) (i32.const 1)))
+                        (br $kernel_column)))))
+                (local.set $ky (i32.add (local.get $ky) (i32.const 1)))
+                (br $kernel_row)))
+            (local.set $ix (i32.add (local.get $ix) (i32.const 1)))
+            (br $column)))
+        (local.set $iy (i32.add (local.get $iy) (i32.const 1)))
+        (br $row)))
+    (call $activate (local.get $y) (i32.mul (local.get $out_h) (local.get $out_w)) (local.get $c_out) (local.get $activation)))
+
+  ;; void gaia_pooling_<activation>(const float *x, float *y, int32 h, int32 width, int32 c, int32 size)
+  ;;   max over non-overlapping size × size windows
+  (func $gaia_pooling_none (param i32 i32 i32 i32 i32 i32)
+    (call $pooling (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (i32.const 0)))
+  (func $gaia_pooling_relu (param i32 i32 i32 i32 i32 i32)
+    (call $pooling (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (i32.const 1)))
+  (func $gaia_pooling_sigmoid (param i32 i32 i32 i32 i32 i32)
+    (call $pooling (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (i32.const 2)))
+  (func $gaia_pooling_tanh (param i32 i32 i32 i32 i32 i32)
+    (call $pooling (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (i32.const 3)))
+  (func $gaia_pooling_softmax (param i32 i32 i32 i32 i32 i32)
+    (call $pooling (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (i32.const 4)))
+
+  (func $pooling (param $x i32) (param $y i32) (param $h i32) (param $width i32) (param $c i32) (param $size i32)
+    (param $activation i32)
+    (local $out_h i32) (local $out_w i32) (local $pixel i32) (local $oy i32) (local $ox i32)
+    (local $py i32) (local $px i32) (local $i i32) (local $x_pixel i32) (local $at i32)
+    (local.set $out_h (i32.div_u (local.get $h) (local.get $size)))
+    (local.set $out_w (i32.div_u (local.get $width) (local.get $size)))
+    (local.set $pixel (local.get $y))
+    (block $done
+      (loop $row
+        (br_if $done (i32.ge_u (local.get $oy) (local.get $out_h)))
+        (local.set $ox (i32.const 0))
+        (block $row_done
+          (loop $column
+            (br_if $row_done (i32.ge_u (local.get $ox) (local.get $out_w)))
+            (local.set $i (i32.const 0))
+            (block $started
+              (loop $start
+                (br_if $started (i32.ge_u (local.get $i) (local.get $c)))
+                (f32.store (i32.add (local.get $pixel) (i32.shl (local.get $i) (i32.const 2))) (f32.const -inf))
+                (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                (br $start)))
+            (local.set $py (i32.const 0))
+            (block $window_done
+              (loop $window_row
+                (br_if $window_done (i32.ge_u (local.get $py) (local.get $size)))
+                (local.set $px (i32.const 0))
+                (block $window_row_done
+                  (loop $window_column
+                    (br_if $window_row_done (i32.ge_u (local.get $px) (local.get $size)))
+                    ;; x[oy * size + py][ox * size + px]
+                    (local.set $x_pixel (i32.add (local.get $x) (i32.shl
+                      (i32.mul
+                        (i32.add
+                          (i32.mul (i32.add (i32.mul (local.get $oy) (local.get $size)) (local.get $py)) (local.get $width))
+                          (i32.add (i32.mul (local.get $ox) (local.get $size)) (local.get $px)))
+                        (local.get $c))
+                      (i32.const 2))))
+                    (local.set $i (i32.const 0))
+                    (block $channels_done
+                      (loop $channel
+                        (br_if $channels_done (i32.ge_u (local.get $i) (local.get $c)))
+                        (local.set $at (i32.add (local.get $pixel) (i32.shl (local.get $i) (i32.const 2))))
+                        (f32.store (local.get $at) (f32.max (f32.load (local.get $at))
+                          (f32.load (i32.add (local.get $x_pixel) (i32.shl (local.get $i) (i32.const 2))))))
+                        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                        (br $channel)))
+                    (local.set $px (i32.add (local.get $px) (i32.const 1)))
+                    (br $window_column)))
+                (local.set $py (i32.add (local.get $py) (i32.const 1)))
+                (br $window_row)))
+            (local.set $pixel (i32.add (local.get $pixel) (i32.shl (local.get $c) (i32.const 2))))
+            (local.set $ox (i32.add (local.get $ox) (i32.const 1)))
+            (br $column)))
+        (local.set $oy (i32.add (local.get $oy) (i32.const 1)))
+        (br $row)))
+    (call $activate (local.get $y) (i32.mul (local.get $out_h) (local.get $out_w)) (local.get $c) (local.get $activation)))
+
+  ;; void gaia_upsampling_<activation>(const float *x, float *y, int32 h, int32 width, int32 c, int32 factor)
+  ;;   nearest neighbour, factor times the height and the width
+  (func $gaia_upsampling_none (param i32 i32 i32 i32 i32 i32)
+    (call $upsampling (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (i32.const 0)))
+  (func $gaia_upsampling_relu (param i32 i32 i32 i32 i32 i32)
+    (call $upsampling (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (i32.const 1)))
+  (func $gaia_upsampling_sigmoid (param i32 i32 i32 i32 i32 i32)
+    (call $upsampling (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (i32.const 2)))
+  (func $gaia_upsampling_tanh (param i32 i32 i32 i32 i32 i32)
+    (call $upsampling (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (i32.const 3)))
+  (func $gaia_upsampling_softmax (param i32 i32 i32 i32 i32 i32)
+    (call $upsampling (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (i32.const 4)))
+
+  (func $upsampling (param $x i32) (param $y i32) (param $h i32) (param $width i32) (param $c i32) (param $factor i32)
+    (param $activation i32)
+    (local $out_h i32) (local $out_w i32) (local $pixel i32) (local $oy i32) (local $ox i32)
+    (local.set $out_h (i32.mul (local.get $h) (local.get $factor)))
+    (local.set $out_w (i32.mul (local.get $width) (local.get $factor)))
+    (local.set $pixel (local.get $y))
+    (block $done
+      (loop $row
+        (br_if $done (i32.ge_u (local.get $oy) (local.get $out_h)))
+        (local.set $ox (i32.const 0))
+        (block $row_done
+          (loop $column
+            (br_if $row_done (i32.ge_u (local.get $ox) (local.get $out_w)))
+            ;; x[oy / factor][ox / factor]
+            (call $copy (local.get $pixel)
+              (i32.add (local.get $x) (i32.shl
+                (i32.mul
+                  (i32.add
+                    (i32.mul (i32.div_u (local.get $oy) (local.get $factor)) (local.get $width))
+                    (i32.div_u (local.get $ox) (local.get $factor)))
+                  (local.get $c))
+                (i32.const 2)))
+              (local.get $c))
+            (local.set $pixel (i32.add (local.get $pixel) (i32.shl (local.get $c) (i32.const 2))))
+            (local.set $ox (i32.add (local.get $ox) (i32.const 1)))
+            (br $column)))
+        (local.set $oy (i32.add (local.get $oy) (i32.const 1)))
+        (br $row)))
+    (call $activate (local.get $y) (i32.mul (local.get $out_h) (local.get $out_w)) (local.get $c) (local.get $activation)))
+
+  ;; void gaia_embedding_<activation>(const float *ids, float *y, const float *table, int32 n,
+  ;;                                  int32 vocab, int32 dim, int32 positional)
+  ;;   row ids[i] of table ([vocab, dim]) for each of the n ids, zeros for ids
+  ;;   outside the vocabulary; positional adds the sinusoidal position encoding
+  (func $gaia_embedding_none (param i32 i32 i32 i32 i32 i32 i32)
+    (call $embedding (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (i32.const 0)))
+  (func $gaia_embedding_relu (param i32 i32 i32 i32 i32 i32 i32)
+    (call $embedding (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (i32.const 1)))
+  (func $gaia_embedding_sigmoid (param i32 i32 i32 i32 i32 i32 i32)
+    (call $embedding (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (i32.const 2)))
+  (func $gaia_embedding_tanh (param i32 i32 i32 i32 i32 i32 i32)
+    (call $embedding (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (i32.const 3)))
+  (func $gaia_embedding_softmax (param i32 i32 i32 i32 i32 i32 i32)
+    (call $embedding (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4) (local.get 5) (local.get 6) (i32.const 4)))
+
+  (func $embedding (param $ids i32) (param $y i32) (param $table i32) (param $n i32)
+    (param $vocab i32) (param $dim i32) (param $positional i32) (param $activation i32)
+    (local $i i32) (local $id i32) (local $row i32) (local $j i32)
+    (local.set $row (local.get $y))
+    (block $done
+      (loop $next_id
+        (br_if $done (i32.ge_u (local.get $i) (local.get $n)))
+        (local.set $id (i32.trunc_sat_f32_s (f32.load (i32.add (local.get $ids) (i32.shl (local.get $i) (i32.const 2))))))
+        (if (i32.and (i32.ge_s (local.get $id) (i32.const 0)) (i32.lt_s (local.get $id) (local.get $vocab)))
+          (then
+            (call $copy (local.get $row)
+              (i32.add (local.get $table) (i32.shl (i32.mul (local.get $id) (local.get $dim)) (i32.const 2)))
+              (local.get $dim)))
+          (else
+            (local.set $j (i32.const 0))
+            (block $zeroed
+              (loop $zero
+                (br_if $zeroed (i32.ge_u (local.get $j) (local.get $dim)))
+                (f32.store (i32.add (local.get $row) (i32.shl (local.get $j) (i32.const 2))) (f32.const 0))
+                (local.set $j (i32.add (local.get $j) (i32.const 1)))
+                (br $zero)))))
+        (local.set $row (i32.add (local.get $row) (i32.shl (local.get $dim) (i32.const 2))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next_id)))
+    (if (local.get $positional)
+      (then (call $positional (local.get $y) (local.get $n) (local.get $dim))))
+    (call $activate (local.get $y) (local.get $n) (local.get $dim) (local.get $activation)))
+
+  ;; void gaia_flatten_<activation>(const float *x, float *y, int32 rows, int32 n)
+  ;;   copy of rows × n values; layers that only change the shape use it when
+  ;;   they carry an activation, and x may be y
+  (func $gaia_flatten_none (param i32 i32 i32 i32)
+    (call $flatten (local.get 0) (local.get 1) (local.get 2) (local.get 3) (i32.const 0)))
+  (func $gaia_flatten_relu (param i32 i32 i32 i32)
+    (call $flatten (local.get 0) (local.get 1) (local.get 2) (local.get 3) (i32.const 1)))
+  (func $gaia_flatten_sigmoid (param i32 i32 i32 i32)
+    (call $flatten (local.get 0) (local.get 1) (local.get 2) (local.get 3) (i32.const 2)))
+  (func $gaia_flatten_tanh (param i32 i32 i32 i32)
+    (call $flatten (local.get 0) (local.get 1) (local.get 2) (local.get 3) (i32.const 3)))
+  (func $gaia_flatten_softmax (param i32 i32 i32 i32)
+    (call $flatten (local.get 0) (local.get 1) (local.get 2) (local.get 3) (i32.const 4)))
+
+  (func $flatten (param $x i32) (param $y i32) (param $rows i32) (param $n i32) (param $activation i32)
+    (call $copy (local.get $y) (local.get $x) (i32.mul (local.get $rows) (local.get $n)))
+    (call $activate (local.get $y) (local.get $rows) (local.get $n) (local.get $activation)))
+
+  ;; Copy n floats from x to y
+  (func $copy (param $y i32) (param $x i32) (param $n i32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.eqz (local.get $n)))
+        (f32.store (local.get $y) (f32.load (local.get $x)))
+        (local.set $y (i32.add (local.get $y) (i32.const 4)))
+        (local.set $x (i32.add (local.get $x) (i32.const 4)))
+        (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+        (br $next))))
+
+  ;; y[o] += w[o] * s for the n floats of y and w
+  (func $axpy (param $y i32) (param $w i32) (param $s f32) (param $n i32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.eqz (local.get $n)))
+        (f32.store (local.get $y) (f32.add (f32.load (local.get $y)) (f32.mul (f32.load (local.get $w)) (local.get $s))))
+        (local.set $y (i32.add (local.get $y) (i32.const 4)))
+        (local.set $w (i32.add (local.get $w) (i32.const 4)))
+        (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+        (br $next))))
+
+  ;; Apply an activation to rows × n values at y, softmax over each row
+  (func $activate (param $y i32) (param $rows i32) (param $n i32) (param $activation i32)
+    (local $at i32) (local $end i32) (local $row_end i32) (local $value f32) (local $maximum f32) (local $sum f32)
+    (local.set $at (local.get $y))
+    (local.set $end (i32.add (local.get $y) (i32.shl (i32.mul (local.get $rows) (local.get $n)) (i32.const 2))))
+    (block $done
+      (br_if $done (i32.eqz (local.get $activation)))
+      (if (i32.eq (local.get $activation) (i32.const 4))
+        (then
+          (loop $row
+            (br_if $done (i32.ge_u (local.get $at) (local.get $end)))
+            (local.set $row_end (i32.add (local.get $at) (i32.shl (local.get $n) (i32.const 2))))
+            (local.set $maximum (f32.const -inf))
+            (local.set $y (local.get $at))
+            (block $maximum_done
+              (loop $maximum_next
+                (br_if $maximum_done (i32.ge_u (local.get $y) (local.get $row_end)))
+                (local.set $maximum (f32.max (local.get $maximum) (f32.load (local.get $y))))
+                (local.set $y (i32.add (local.get $y) (i32.const 4)))
+                (br $maximum_next)))
+            (local.set $sum (f32.const 0))
+            (local.set $y (local.get $at))
+            (block $exponentials_done
+              (loop $exponential
+                (br_if $exponentials_done (i32.ge_u (local.get $y) (local.get $row_end)))
+                (local.set $value (call $expf (f32.sub (f32.load (local.get $y)) (local.get $maximum))))
+                (f32.store (local.get $y) (local.get $value))
+                (local.set $sum (f32.add (local.get $sum) (local.get $value)))
+                (local.set $y (i32.add (local.get $y) (i32.const 4)))
+                (br $exponential)))
+            (block $normalised
+              (loop $divide
+                (br_if $normalised (i32.ge_u (local.get $at) (local.get $row_end)))
+                (f32.store (local.get $at) (f32.div (f32.load (local.get $at)) (local.get $sum)))
+                (local.set $at (i32.add (local.get $at) (i32.const 4)))
+                (br $divide)))
+            (br $row))))
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $at) (local.get $end)))
+        (local.set $value (f32.load (local.get $at)))
+        (f32.store (local.get $at)
+          (if (result f32) (i32.eq (local.get $activation) (i32.const 1))
+            (then (f32.max (local.get $value) (f32.const 0)))
+            (else
+              (if (result f32) (i32.eq (local.get $activation) (i32.const 2))
+                (then (f32.div (f32.const 1) (f32.add (f32.const 1) (call $expf (f32.neg (local.get $value))))))
+                (else (call $tanhf (local.get $value)))))))
+        (local.set $at (i32.add (local.get $at) (i32.const 4)))
+        (br $next))))
+
+  ;; Add sin(p / 10000^(2i / dim)) to feature 2i of position p and the cosine to feature 2i + 1
+  (func $positional (param $y i32) (param $steps i32) (param $dim i32)
+    (local $p i32) (local $f i32) (local $angle f32)
+    (block $done
+      (loop $position
+        (br_if $done (i32.ge_u (local.get $p) (local.get $steps)))
+        (local.set $f (i32.const 0))
+        (block $position_done
+          (loop $feature
+            (br_if $position_done (i32.ge_u (local.get $f) (local.get $dim)))
+            (local.set $angle (f32.div (f32.convert_i32_u (local.get $p))
+              (call $powf (f32.const 10000)
+                (f32.div (f32.convert_i32_u (i32.and (local.get $f) (i32.const -2))) (f32.convert_i32_u (local.get $dim))))))
+            (f32.store (local.get $y) (f32.add
+              (if (result f32) (i32.and (local.get $f) (i32.const 1))
+                (then (call $cosf (local.get $angle)))
+                (else (call $sinf (local.get $angle))))
+              (f32.load (local.get $y))))
+            (local.set $y (i32.add (local.get $y) (i32.const 4)))
+            (local.set $f (i32.add (local.get $f) (i32.const 1)))
+            (br $feature)))
+        (local.set $p (i32.add (local.get $p) (i32.const 1)))
+        (br $position))))
+
+  ;; Losses: float gaia_loss_<name>(const float *prediction, const float *target, int32 n)
+
+  ;; Mean squared error
+  (func $gaia_loss_MSE (param $p i32) (param $t i32) (param $n i32) (result f32)
+    (local $i i32) (local $sum f32) (local $d f32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (local.get $n)))
+        (local.set $d (f32.sub (f32.load (local.get $p)) (f32.load (local.get $t))))
+        (local.set $sum (f32.add (local.get $sum) (f32.mul (local.get $d) (local.get $d))))
+        (local.set $p (i32.add (local.get $p) (i32.const 4)))
+        (local.set $t (i32.add (local.get $t) (i32.const 4)))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (f32.div (local.get $sum) (f32.convert_i32_u (local.get $n))))
+
+  ;; Binary cross-entropy, with the prediction clamped to [1e-7, 1 - 1e-7]
+  (func $gaia_loss_BCE (param $p i32) (param $t i32) (param $n i32) (result f32)
+    (local $i i32) (local $sum f32) (local $q f32) (local $label f32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (local.get $n)))
+        (local.set $q (f32.min (f32.max (f32.load (local.get $p)) (f32.const 1e-7)) (f32.const 0x1.fffffcp-1)))
+        (local.set $label (f32.load (local.get $t)))
+        (local.set $sum (f32.sub (local.get $sum) (f32.add
+          (f32.mul (call $logf (f32.sub (f32.const 1) (local.get $q))) (f32.sub (f32.const 1) (local.get $label)))
+          (f32.mul (local.get $label) (call $logf (local.get $q))))))
+        (local.set $p (i32.add (local.get $p) (i32.const 4)))
+        (local.set $t (i32.add (local.get $t) (i32.const 4)))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (f32.div (local.get $sum) (f32.convert_i32_u (local.get $n))))
+
+  ;; Categorical cross-entropy of a probability distribution
+  (func $gaia_loss_CE (param $p i32) (param $t i32) (param $n i32) (result f32)
+    (local $i i32) (local $sum f32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (local.get $n)))
+        (local.set $sum (f32.sub (local.get $sum) (f32.mul
+          (call $logf (f32.max (f32.load (local.get $p)) (f32.const 1e-7)))
+          (f32.load (local.get $t)))))
+        (local.set $p (i32.add (local.get $p) (i32.const 4)))
+        (local.set $t (i32.add (local.get $t) (i32.const 4)))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (local.get $sum))
+
+  ;; Hinge loss; 0/1 targets are mapped to -1/1
+  (func $gaia_loss_Hinge (param $p i32) (param $t i32) (param $n i32) (result f32)
+    (local $i i32) (local $sum f32) (local $label f32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (local.get $n)))
+        (local.set $label (f32.load (local.get $t)))
+        (if (f32.eq (local.get $label) (f32.const 0))
+          (then (local.set $label (f32.const -1))))
+        (local.set $sum (f32.add (local.get $sum) (f32.max
+          (f32.sub (f32.const 1) (f32.mul (local.get $label) (f32.load (local.get $p))))
+          (f32.const 0))))
+        (local.set $p (i32.add (local.get $p) (i32.const 4)))
+        (local.set $t (i32.add (local.get $t) (i32.const 4)))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (f32.div (local.get $sum) (f32.convert_i32_u (local.get $n))))
+
+  ;; Kullback-Leibler divergence of the prediction from the target distribution
+  (func $gaia_loss_KL (param $p i32) (param $t i32) (param $n i32) (result f32)
+    (local $i i32) (local $sum f32) (local $label f32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (local.get $n)))
+        (local.set $label (f32.load (local.get $t)))
+        (if (f32.gt (local.get $label) (f32.const 0))
+          (then
+            (local.set $sum (f32.add (local.get $sum) (f32.mul
+              (call $logf (f32.div (local.get $label) (f32.max (f32.load (local.get $p)) (f32.const 1e-7))))
+              (local.get $label))))))
+        (local.set $p (i32.add (local.get $p) (i32.const 4)))
+        (local.set $t (i32.add (local.get $t) (i32.const 4)))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (local.get $sum))
+
+  ;; Elementary functions: float32 in and out, float64 in between
+
+  (func $expf (param $x f32) (result f32)
+    (f32.demote_f64 (call $exp (f64.promote_f32 (local.get $x)))))
+
+  (func $logf (param $x f32) (result f32)
+    (f32.demote_f64 (call $log (f64.promote_f32 (local.get $x)))))
+
+  (func $powf (param $x f32) (param $y f32) (result f32)
+    (f32.demote_f64 (call $exp (f64.mul (f64.promote_f32 (local.get $y)) (call $log (f64.promote_f32 (local.get $x)))))))
+
+  (func $sinf (param $x f32) (result f32)
+    (f32.demote_f64 (call $sine (f64.promote_f32 (local.get $x)) (i32.const 0))))
+
+  (func $cosf (param $x f32) (result f32)
+    (f32.demote_f64 (call $sine (f64.promote_f32 (local.get $x)) (i32.const 1))))
+
+  ;; tanh(x) = 1 - 2 / (e^2x + 1), with a series near zero where that cancels
+  (func $tanhf (param $x f32) (result f32)
+    (local $a f64) (local $a2 f64)
+    (local.set $a (f64.abs (f64.promote_f32 (local.get $x))))
+    (local.set $a2 (f64.mul (local.get $a) (local.get $a)))
+    (f32.copysign
+      (f32.demote_f64
+        (if (result f64) (f64.lt (local.get $a) (f64.const 0.1))
+          (then
+            ;; a - a^3/3 + 2a^5/15 - 17a^7/315 + 62a^9/2835
+            (f64.mul (local.get $a) (f64.add (f64.const 1) (f64.mul (local.get $a2)
+              (f64.add (f64.const -0.3333333333333333) (f64.mul (local.get $a2)
+                (f64.add (f64.const 0.13333333333333333) (f64.mul (local.get $a2)
+                  (f64.add (f64.const -0.05396825396825397) (f64.mul (local.get $a2) (f64.const 0.021869488536155203)))))))))))
+          (else
+            (if (result f64) (f64.gt (local.get $a) (f64.const 20))
+              (then (f64.const 1))
+              (else (f64.sub (f64.const 1) (f64.div (f64.const 2)
+                (f64.add (call $exp (f64.mul (f64.const 2) (local.get $a))) (f64.const 1)))))))))
+      (local.get $x)))
+
+  ;; e^x = 2^k e^r with |r| <= ln 2 / 2, e^r by its series
+  (func $exp (param $x f64) (result f64)
+    (local $k f64) (local $r f64) (local $term f64) (local $sum f64) (local $i i32) (local $half i64)
+    (if (f64.gt (local.get $x) (f64.const 709.8)) (then (return (f64.const inf))))
+    (if (f64.lt (local.get $x) (f64.const -745.2)) (then (return (f64.const 0))))
+    (local.set $k (f64.nearest (f64.mul (local.get $x) (f64.const 0x1.71547652b82fep0))))
+    ;; ln 2 in two parts, the first exact in k ln 2 for every k here
+    (local.set $r (f64.sub
+      (f64.sub (local.get $x) (f64.mul (local.get $k) (f64.const 0x1.62e42feep-1)))
+      (f64.mul (local.get $k) (f64.const 0x1.a39ef35793c76p-33))))
+    (local.set $term (f64.const 1))
+    (local.set $sum (f64.const 1))
+    (local.set $i (i32.const 1))
+    (block $done
+      (loop $next
+        (br_if $done (i32.gt_u (local.get $i) (i32.const 13)))
+        (local.set $term (f64.div (f64.mul (local.get $term) (local.get $r)) (f64.convert_i32_u (local.get $i))))
+        (local.set $sum (f64.add (local.get $sum) (local.get $term)))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    ;; 2^k in two factors, each a normal float64
+    (local.set $half (i64.trunc_sat_f64_s (f64.mul (local.get $k) (f64.const 0.5))))
+    (f64.mul
+      (f64.mul (local.get $sum)
+        (f64.reinterpret_i64 (i64.shl (i64.add (local.get $half) (i64.const 1023)) (i64.const 52))))
+      (f64.reinterpret_i64 (i64.shl
+        (i64.add (i64.sub (i64.trunc_sat_f64_s (local.get $k)) (local.get $half)) (i64.const 1023))
+        (i64.const 52)))))
+
+  ;; ln x = e ln 2 + ln m with m in [sqrt(1/2), sqrt(2)), ln m = 2 atanh((m - 1) / (m + 1))
+  (func $log (param $x f64) (result f64)
+    (local $bits i64) (local $e f64) (local $m f64) (local $s f64) (local $s2 f64) (local $term f64)
+    (local $sum f64) (local $i i32)
+    (if (i32.eqz (f64.gt (local.get $x) (f64.const 0)))
+      (then (return (select (f64.const -inf) (f64.const nan) (f64.eq (local.get $x) (f64.const 0))))))
+    (if (f64.eq (local.get $x) (f64.const inf)) (then (return (local.get $x))))
+    ;; Subnormals are scaled into the normal range first
+    (if (f64.lt (local.get $x) (f64.const 0x1p-1022))
+      (then
+        (local.set $x (f64.mul (local.get $x) (f64.const 0x1p54)))
+        (local.set $e (f64.const -54))))
+    (local.set $bits (i64.reinterpret_f64 (local.get $x)))
+    (local.set $e (f64.add (local.get $e) (f64.convert_i64_s
+      (i64.sub (i64.shr_u (local.get $bits) (i64.const 52)) (i64.const 1023)))))
+    (local.set $m (f64.reinterpret_i64 (i64.or
+      (i64.and (local.get $bits) (i64.const 0xfffffffffffff))
+      (i64.const 0x3ff0000000000000))))
+    (if (f64.gt (local.get $m) (f64.const 0x1.6a09e667f3bcdp0))
+      (then
+        (local.set $m (f64.mul (local.get $m) (f64.const 0.5)))
+        (local.set $e (f64.add (local.get $e) (f64.const 1)))))
+    (local.set $s (f64.div (f64.sub (local.get $m) (f64.const 1)) (f64.add (local.get $m) (f64.const 1))))
+    (local.set $s2 (f64.mul (local.get $s) (local.get $s)))
+    (local.set $term (local.get $s))
+    (local.set $sum (local.get $s))
+    (local.set $i (i32.const 1))
+    (block $done
+      (loop $next
+        (br_if $done (i32.gt_u (local.get $i) (i32.const 11)))
+        (local.set $term (f64.mul (local.get $term) (local.get $s2)))
+        (local.set $sum (f64.add (local.get $sum)
+          (f64.div (local.get $term) (f64.convert_i32_u (i32.add (i32.shl (local.get $i) (i32.const 1)) (i32.const 1))))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (f64.add
+      (f64.mul (local.get $e) (f64.const 0x1.62e42feep-1))
+      (f64.add (f64.mul (local.get $sum) (f64.const 2)) (f64.mul (local.get $e) (f64.const 0x1.a39ef35793c76p-33)))))
+
+  ;; sin x, or cos x = sin(x + pi/2) when cosine is set: x = k pi/2 + r with
+  ;; |r| <= pi/4, then the series of sin r or cos r by the quadrant
+  (func $sine (param $x f64) (param $cosine i32) (result f64)
+    (local $k f64) (local $r f64) (local $r2 f64) (local $quadrant i32) (local $term f64) (local $sum f64)
+    (local $i i32) (local $odd i32)
+    (local.set $k (f64.nearest (f64.mul (local.get $x) (f64.const 0x1.45f306dc9c883p-1))))
+    ;; pi/2 in two parts, as for ln 2 in exp
+    (local.set $r (f64.sub
+      (f64.sub (local.get $x) (f64.mul (local.get $k) (f64.const 0x1.921fb544p0)))
+      (f64.mul (local.get $k) (f64.const 0x1.0b4611a626331p-34))))
+    (local.set $quadrant (i32.and
+      (i32.add (i32.wrap_i64 (i64.trunc_sat_f64_s (local.get $k))) (local.get $cosine))
+      (i32.const 3)))
+    ;; Quadrants 0 and 2 take sin r, 1 and 3 cos r; 2 and 3 are negated
+    (local.set $odd (i32.and (local.get $quadrant) (i32.const 1)))
+    (local.set $r2 (f64.mul (local.get $r) (local.get $r)))
+    (local.set $term (select (f64.const 1) (local.get $r) (local.get $odd)))
+    (local.set $sum (local.get $term))
+    (local.set $i (i32.const 1))
+    (block $done
+      (loop $next
+        (br_if $done (i32.gt_u (local.get $i) (i32.const 9)))
+        ;; term *= -r^2 / ((2i - odd)(2i + 1 - odd))
+        (local.set $term (f64.div (f64.mul (f64.neg (local.get $term)) (local.get $r2))
+          (f64.convert_i32_u (i32.mul
+            (i32.sub (i32.shl (local.get $i) (i32.const 1)) (local.get $odd))
+            (i32.sub (i32.add (i32.shl (local.get $i) (i32.const 1)) (i32.const 1)) (local.get $odd))))))
+        (local.set $sum (f64.add (local.get $sum) (local.get $term)))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (select (f64.neg (local.get $sum)) (local.get $sum) (i32.ge_u (local.get $quadrant) (i32.const 2))))
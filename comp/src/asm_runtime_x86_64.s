@@ -1,152 +1,1054 @@
-; GaiaScript X86-64 Runtime Support
-; This file contains the runtime functions needed by GaiaScript assembly
-
-section .data
-    ; Error messages
-    err_memory_alloc_failed db "Failed to allocate memory", 0
-    
-    ; Constants
-    float_one dq 1.0
-    
+; GaiaScript x86-64 runtime: float32 kernels for the generated components
+;
+; Every public function follows the System V AMD64 ABI: integer and pointer
+; arguments in rdi, rsi, rdx, rcx, r8 and r9, then on the stack; float
+; results in xmm0; rbx, rbp and r12-r15 preserved; rsp 16-byte aligned at
+; every call. Tensors are row-major float32 with channels last, as in the
+; interpreter, and the arithmetic follows the interpreter step by step.
+;
+; Layer kernels are named gaia_<layer>_<activation> and apply the activation
+; to their output: none, relu, sigmoid, tanh or softmax (over the last axis).
+;
+;   nasm -f elf64 gaia_runtime_x86_64.s
+;   cc main.c app_x86_64.o gaia_runtime_x86_64.o -lm
+
+default rel
+
+extern expf
+extern logf
+extern tanhf
+extern powf
+extern sinf
+extern cosf
+
 section .text
-    global gaia_input_image
-    global gaia_input_text
-    global gaia_input_sequence
-    global gaia_input_latent
-    global gaia_conv_relu
-    global gaia_conv_sigmoid
-    global gaia_conv_tanh
-    global gaia_conv_softmax
-    global gaia_conv_none
-    global gaia_dense_relu
-    global gaia_dense_sigmoid
-    global gaia_dense_tanh
-    global gaia_dense_softmax
-    global gaia_dense_none
-    global gaia_pooling_none
-    global gaia_flatten_none
-    global gaia_loss_MSE
-    global gaia_loss_BCE
-
-; Input layer implementations
-gaia_input_image:
-    ; Allocate memory for image input
-    ; Parameters:
-    ;   r0 = width
-    ;   r1 = height
-    ;   r2 = channels
+
+; Kernel bodies are entered with the activation in eax (0 none, 1 relu,
+; 2 sigmoid, 3 tanh, 4 softmax) and share one frame:
+;   [rbp - 48] activation    [rbp - 56] output
+;   [rbp - 64] output rows   [rbp - 72] values per row
+;   [rbp - 80] to [rbp - 112] free for the kernel
+; Stack arguments start at [rbp + 16].
+
+; void gaia_dense_<activation>(const float *x, float *y, const float *w, const float *b,
+;                              int64 rows, int64 n_in, int64 n_out)
+;   y[r] = x[r] w + b for every row; w is [n_in, n_out]
+global gaia_dense_none
+global gaia_dense_relu
+global gaia_dense_sigmoid
+global gaia_dense_tanh
+global gaia_dense_softmax
+gaia_dense_none:
+    xor eax, eax
+    jmp dense
+gaia_dense_relu:
+    mov eax, 1
+    jmp dense
+gaia_dense_sigmoid:
+    mov eax, 2
+    jmp dense
+gaia_dense_tanh:
+    mov eax, 3
+    jmp dense
+gaia_dense_softmax:
+    mov eax, 4
+    jmp dense
+
+dense:
     push rbp
     mov rbp, rsp
-    
-    ; Calculate size needed (width * height * channels * sizeof(float))
-    imul r0, r1
-    imul r0, r2
-    imul r0, 4  ; 4 bytes per float
-    
-    ; Allocate memory
-    mov rdi, r0  ; Size
-    call malloc
-    
-    ; Check if allocation succeeded
-    test rax, rax
-    jz .alloc_failed
-    
-    ; Initialize with zeros
-    mov rdi, rax  ; Destination
-    xor rsi, rsi  ; Value (0)
-    mov rdx, r0   ; Size
-    call memset
-    
-    ; Return pointer to allocated memory
-    pop rbp
-    ret
-    
-.alloc_failed:
-    ; Print error message
-    mov rdi, err_memory_alloc_failed
-    call puts
-    
-    ; Return NULL
-    xor rax, rax
-    pop rbp
-    ret
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    sub rsp, 72
+    mov [rbp - 48], eax
+    mov [rbp - 56], rsi
+    mov [rbp - 64], r8
+    mov r10, [rbp + 16]         ; n_out
+    mov [rbp - 72], r10
+.row:
+    test r8, r8
+    jz finish
+    xor eax, eax
+.bias:
+    cmp rax, r10
+    jge .weights
+    movss xmm0, [rcx + rax * 4]
+    movss [rsi + rax * 4], xmm0
+    inc rax
+    jmp .bias
+.weights:
+    mov r12, rdx                ; row i of w
+    xor r11d, r11d              ; i
+.input:
+    cmp r11, r9
+    jge .next_row
+    movss xmm1, [rdi + r11 * 4]
+    xor eax, eax
+.output:
+    cmp rax, r10
+    jge .next_input
+    movss xmm0, [r12 + rax * 4]
+    mulss xmm0, xmm1
+    addss xmm0, [rsi + rax * 4]
+    movss [rsi + rax * 4], xmm0
+    inc rax
+    jmp .output
+.next_input:
+    lea r12, [r12 + r10 * 4]
+    inc r11
+    jmp .input
+.next_row:
+    lea rdi, [rdi + r9 * 4]
+    lea rsi, [rsi + r10 * 4]
+    dec r8
+    jmp .row
 
-gaia_input_text:
-    ; Similar to image input but for text
-    ret
+; void gaia_conv_<activation>(const float *x, float *y, const float *w, const float *b,
+;                             int64 h, int64 width, int64 c_in, int64 c_out, int64 k)
+;   stride 1 with "same" padding; w is [k, k, c_in, c_out]
+global gaia_conv_none
+global gaia_conv_relu
+global gaia_conv_sigmoid
+global gaia_conv_tanh
+global gaia_conv_softmax
+gaia_conv_none:
+    xor eax, eax
+    jmp conv
+gaia_conv_relu:
+    mov eax, 1
+    jmp conv
+gaia_conv_sigmoid:
+    mov eax, 2
+    jmp conv
+gaia_conv_tanh:
+    mov eax, 3
+    jmp conv
+gaia_conv_softmax:
+    mov eax, 4
+    jmp conv
 
-gaia_input_sequence:
-    ; For sequence data
-    ret
+conv:
+    push rbp
+    mov rbp, rsp
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    sub rsp, 72
+    mov [rbp - 48], eax
+    mov [rbp - 56], rsi
+    mov rax, r8
+    imul rax, r9
+    mov [rbp - 64], rax
+    mov rax, [rbp + 24]
+    mov [rbp - 72], rax
+    mov rax, [rbp + 32]
+    dec rax
+    shr rax, 1
+    mov [rbp - 80], rax         ; padding
+    xor r12d, r12d              ; oy
+.row:
+    cmp r12, r8
+    jge finish
+    xor r13d, r13d              ; ox
+.column:
+    cmp r13, r9
+    jge .next_row
+    xor eax, eax
+.bias:
+    cmp rax, [rbp + 24]
+    jge .kernel
+    movss xmm0, [rcx + rax * 4]
+    movss [rsi + rax * 4], xmm0
+    inc rax
+    jmp .bias
+.kernel:
+    xor r14d, r14d              ; ky
+.kernel_row:
+    cmp r14, [rbp + 32]
+    jge .next_column
+    mov rax, r12
+    add rax, r14
+    sub rax, [rbp - 80]         ; iy
+    js .next_kernel_row
+    cmp rax, r8
+    jge .next_kernel_row
+    mov [rbp - 88], rax
+    xor r15d, r15d              ; kx
+.kernel_column:
+    cmp r15, [rbp + 32]
+    jge .next_kernel_row
+    mov rax, r13
+    add rax, r15
+    sub rax, [rbp - 80]         ; ix
+    js .next_kernel_column
+    cmp rax, r9
+    jge .next_kernel_column
+    mov r10, [rbp - 88]         ; x[iy][ix]
+    imul r10, r9
+    add r10, rax
+    imul r10, [rbp + 16]
+    lea r10, [rdi + r10 * 4]
+    mov r11, r14                ; w[ky][kx]
+    imul r11, [rbp + 32]
+    add r11, r15
+    imul r11, [rbp + 16]
+    imul r11, [rbp + 24]
+    lea r11, [rdx + r11 * 4]
+    xor ebx, ebx                ; input channel
+.channel:
+    cmp rbx, [rbp + 16]
+    jge .next_kernel_column
+    movss xmm1, [r10 + rbx * 4]
+    xor eax, eax                ; output channel
+.output:
+    cmp rax, [rbp + 24]
+    jge .next_channel
+    movss xmm0, [r11 + rax * 4]
+    mulss xmm0, xmm1
+    addss xmm0, [rsi + rax * 4]
+    movss [rsi + rax * 4], xmm0
+    inc rax
+    jmp .output
+.next_channel:
+    mov rax, [rbp + 24]
+    lea r11, [r11 + rax * 4]
+    inc rbx
+    jmp .channel
+.next_kernel_column:
+    inc r15
+    jmp .kernel_column
+.next_kernel_row:
+    inc r14
+    jmp .kernel_row
+.next_column:
+    mov rax, [rbp + 24]
+    lea rsi, [rsi + rax * 4]
+    inc r13
+    jmp .column
+.next_row:
+    inc r12
+    jmp .row
 
-gaia_input_latent:
-    ; For latent/random inputs
-    ret
+; void gaia_transpose_conv_<activation>(const float *x, float *y, const float *w, const float *b,
+;                                       int64 h, int64 width, int64 c_in, int64 c_out,
+;                                       int64 k, int64 stride)
+;   output stride times the input size with "same" padding; w is [k, k, c_in, c_out]
+global gaia_transpose_conv_none
+global gaia_transpose_conv_relu
+global gaia_transpose_conv_sigmoid
+global gaia_transpose_conv_tanh
+global gaia_transpose_conv_softmax
+gaia_transpose_conv_none:
+    xor eax, eax
+    jmp transpose_conv
+gaia_transpose_conv_relu:
+    mov eax, 1
+    jmp transpose_conv
+gaia_transpose_conv_sigmoid:
+    mov eax, 2
+    jmp transpose_conv
+gaia_transpose_conv_tanh:
+    mov eax, 3
+    jmp transpose_conv
+gaia_transpose_conv_softmax:
+    mov eax, 4
+    jmp transpose_conv
 
-; Layer implementations
-gaia_conv_relu:
-    ; Convolutional layer with ReLU activation
-    ; Parameters:
-    ;   rdi = Input tensor
-    ;   rsi = Filters
-    ;   rdx = Other parameters
-    ret
+transpose_conv:
+    push rbp
+    mov rbp, rsp
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    sub rsp, 72
+    mov [rbp - 48], eax
+    mov [rbp - 56], rsi
+    mov rax, r8
+    imul rax, [rbp + 40]
+    mov [rbp - 80], rax         ; output height
+    mov rax, r9
+    imul rax, [rbp + 40]
+    mov [rbp - 88], rax         ; output width
+    imul rax, [rbp - 80]
+    mov [rbp - 64], rax
+    mov rax, [rbp + 24]
+    mov [rbp - 72], rax
+    mov rax, [rbp + 32]
+    sub rax, [rbp + 40]
+    jns .padding
+    xor eax, eax
+.padding:
+    shr rax, 1
+    mov [rbp - 96], rax
+    mov r10, rsi                ; every output pixel starts at the bias
+    mov r11, [rbp - 64]
+.fill:
+    test r11, r11
+    jz .filled
+    xor eax, eax
+.fill_channel:
+    cmp rax, [rbp + 24]
+    jge .next_fill
+    movss xmm0, [rcx + rax * 4]
+    movss [r10 + rax * 4], xmm0
+    inc rax
+    jmp .fill_channel
+.next_fill:
+    mov rax, [rbp + 24]
+    lea r10, [r10 + rax * 4]
+    dec r11
+    jmp .fill
+.filled:
+    xor r12d, r12d              ; iy
+.row:
+    cmp r12, r8
+    jge finish
+    xor r13d, r13d              ; ix
+.column:
+    cmp r13, r9
+    jge .next_row
+    mov rax, r12                ; x[iy][ix]
+    imul rax, r9
+    add rax, r13
+    imul rax, [rbp + 16]
+    lea rax, [rdi + rax * 4]
+    mov [rbp - 112], rax
+    xor r14d, r14d              ; ky
+.kernel_row:
+    cmp r14, [rbp + 32]
+    jge .next_column
+    mov rax, r12
+    imul rax, [rbp + 40]
+    add rax, r14
+    sub rax, [rbp - 96]         ; oy
+    js .next_kernel_row
+    cmp rax, [rbp - 80]
+    jge .next_kernel_row
+    mov [rbp - 104], rax
+    xor r15d, r15d              ; kx
+.kernel_column:
+    cmp r15, [rbp + 32]
+    jge .next_kernel_row
+    mov rax, r13
+    imul rax, [rbp + 40]
+    add rax, r15
+    sub rax, [rbp - 96]         ; ox
+    js .next_kernel_column
+    cmp rax, [rbp - 88]
+    jge .next_kernel_column
+    mov r10, [rbp - 104]        ; y[oy][ox]
+    imul r10, [rbp - 88]
+    add r10, rax
+    imul r10, [rbp + 24]
+    lea r10, [rsi + r10 * 4]
+    mov r11, r14                ; w[ky][kx]
+    imul r11, [rbp + 32]
+    add r11, r15
+    imul r11, [rbp + 16]
+    imul r11, [rbp + 24]
+    lea r11, [rdx + r11 * 4]
+    xor ebx, ebx                ; input channel
+.channel:
+    cmp rbx, [rbp + 16]
+    jge .next_kernel_column
+    mov rax, [rbp - 112]
+    movss xmm1, [rax + rbx * 4]
+    xor eax, eax                ; output channel
+.output:
+    cmp rax, [rbp + 24]
+    jge .next_channel
+    movss xmm0, [r11 + rax * 4]
+    mulss xmm0, xmm1
+    addss xmm0, [r10 + rax * 4]
+    movss [r10 + rax * 4], xmm0
+    inc rax
+    jmp .output
+.next_channel:
+    mov rax, [rbp + 24]
+    lea r11, [r11 + rax * 4]
+    inc rbx
+    jmp .channel
+.next_kernel_column:
+    inc r15
+    jmp .kernel_column
+.next_kernel_row:
+    inc r14
+    jmp .kernel_row
+.next_column:
+    inc r13
+    jmp .column
+.next_row:
+    inc r12
+    jmp .row
 
-gaia_conv_sigmoid:
-    ; Convolutional layer with sigmoid activation
-    ret
+; void gaia_pooling_<activation>(const float *x, float *y, int64 h, int64 width, int64 c, int64 size)
+;   max over non-overlapping size × size windows
+global gaia_pooling_none
+global gaia_pooling_relu
+global gaia_pooling_sigmoid
+global gaia_pooling_tanh
+global gaia_pooling_softmax
+gaia_pooling_none:
+    xor eax, eax
+    jmp pooling
+gaia_pooling_relu:
+    mov eax, 1
+    jmp pooling
+gaia_pooling_sigmoid:
+    mov eax, 2
+    jmp pooling
+gaia_pooling_tanh:
+    mov eax, 3
+    jmp pooling
+gaia_pooling_softmax:
+    mov eax, 4
+    jmp pooling
 
-gaia_conv_tanh:
-    ; Convolutional layer with tanh activation
-    ret
+pooling:
+    push rbp
+    mov rbp, rsp
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    sub rsp, 72
+    mov [rbp - 48], eax
+    mov [rbp - 56], rsi
+    mov [rbp - 72], r8
+    mov rax, rdx
+    xor edx, edx
+    div r9
+    mov r10, rax                ; output height
+    mov rax, rcx
+    xor edx, edx
+    div r9
+    mov r11, rax                ; output width
+    imul rax, r10
+    mov [rbp - 64], rax
+    xor r12d, r12d              ; oy
+.row:
+    cmp r12, r10
+    jge finish
+    xor r13d, r13d              ; ox
+.column:
+    cmp r13, r11
+    jge .next_row
+    xor eax, eax
+    mov edx, 0xff800000         ; -inf
+.start:
+    cmp rax, r8
+    jge .window
+    mov [rsi + rax * 4], edx
+    inc rax
+    jmp .start
+.window:
+    xor r14d, r14d              ; py
+.window_row:
+    cmp r14, r9
+    jge .next_column
+    xor r15d, r15d              ; px
+.window_column:
+    cmp r15, r9
+    jge .next_window_row
+    mov rax, r12                ; x[oy * size + py][ox * size + px]
+    imul rax, r9
+    add rax, r14
+    imul rax, rcx
+    mov rdx, r13
+    imul rdx, r9
+    add rax, rdx
+    add rax, r15
+    imul rax, r8
+    lea rdx, [rdi + rax * 4]
+    xor ebx, ebx
+.channel:
+    cmp rbx, r8
+    jge .next_window_column
+    movss xmm0, [rsi + rbx * 4]
+    maxss xmm0, [rdx + rbx * 4]
+    movss [rsi + rbx * 4], xmm0
+    inc rbx
+    jmp .channel
+.next_window_column:
+    inc r15
+    jmp .window_column
+.next_window_row:
+    inc r14
+    jmp .window_row
+.next_column:
+    lea rsi, [rsi + r8 * 4]
+    inc r13
+    jmp .column
+.next_row:
+    inc r12
+    jmp .row
 
-gaia_conv_softmax:
-    ; Convolutional layer with softmax activation
-    ret
+; void gaia_upsampling_<activation>(const float *x, float *y, int64 h, int64 width, int64 c, int64 factor)
+;   nearest neighbour, factor times the height and the width
+global gaia_upsampling_none
+global gaia_upsampling_relu
+global gaia_upsampling_sigmoid
+global gaia_upsampling_tanh
+global gaia_upsampling_softmax
+gaia_upsampling_none:
+    xor eax, eax
+    jmp upsampling
+gaia_upsampling_relu:
+    mov eax, 1
+    jmp upsampling
+gaia_upsampling_sigmoid:
+    mov eax, 2
+    jmp upsampling
+gaia_upsampling_tanh:
+    mov eax, 3
+    jmp upsampling
+gaia_upsampling_softmax:
+    mov eax, 4
+    jmp upsampling
 
-gaia_conv_none:
-    ; Convolutional layer without activation
-    ret
+upsampling:
+    push rbp
+    mov rbp, rsp
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    sub rsp, 72
+    mov [rbp - 48], eax
+    mov [rbp - 56], rsi
+    mov [rbp - 72], r8
+    mov r10, rdx
+    imul r10, r9                ; output height
+    mov r11, rcx
+    imul r11, r9                ; output width
+    mov rax, r10
+    imul rax, r11
+    mov [rbp - 64], rax
+    xor r12d, r12d              ; oy
+.row:
+    cmp r12, r10
+    jge finish
+    xor r13d, r13d              ; ox
+.column:
+    cmp r13, r11
+    jge .next_row
+    mov rax, r13
+    xor edx, edx
+    div r9
+    mov r14, rax                ; ox / factor
+    mov rax, r12
+    xor edx, edx
+    div r9
+    imul rax, rcx
+    add rax, r14
+    imul rax, r8
+    lea rdx, [rdi + rax * 4]
+    xor ebx, ebx
+.channel:
+    cmp rbx, r8
+    jge .next_column
+    mov eax, [rdx + rbx * 4]
+    mov [rsi + rbx * 4], eax
+    inc rbx
+    jmp .channel
+.next_column:
+    lea rsi, [rsi + r8 * 4]
+    inc r13
+    jmp .column
+.next_row:
+    inc r12
+    jmp .row
 
-gaia_dense_relu:
-    ; Dense layer with ReLU activation
-    ret
+; void gaia_embedding_<activation>(const float *ids, float *y, const float *table, int64 n,
+;                                  int64 vocab, int64 dim, int64 positional)
+;   row ids[i] of table ([vocab, dim]) for each of the n ids, zeros for ids
+;   outside the vocabulary; positional adds the sinusoidal position encoding
+global gaia_embedding_none
+global gaia_embedding_relu
+global gaia_embedding_sigmoid
+global gaia_embedding_tanh
+global gaia_embedding_softmax
+gaia_embedding_none:
+    xor eax, eax
+    jmp embedding
+gaia_embedding_relu:
+    mov eax, 1
+    jmp embedding
+gaia_embedding_sigmoid:
+    mov eax, 2
+    jmp embedding
+gaia_embedding_tanh:
+    mov eax, 3
+    jmp embedding
+gaia_embedding_softmax:
+    mov eax, 4
+    jmp embedding
 
-gaia_dense_sigmoid:
-    ; Dense layer with sigmoid activation
+embedding:
+    push rbp
+    mov rbp, rsp
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    sub rsp, 72
+    mov [rbp - 48], eax
+    mov [rbp - 56], rsi
+    mov [rbp - 64], rcx
+    mov [rbp - 72], r9
+.id:
+    test rcx, rcx
+    jz .positional
+    xor r10d, r10d
+    cvttss2si rax, dword [rdi]
+    test rax, rax
+    js .zero
+    cmp rax, r8
+    jge .zero
+    imul rax, r9
+    lea r11, [rdx + rax * 4]
+.copy:
+    cmp r10, r9
+    jge .next_id
+    mov eax, [r11 + r10 * 4]
+    mov [rsi + r10 * 4], eax
+    inc r10
+    jmp .copy
+.zero:
+    cmp r10, r9
+    jge .next_id
+    mov dword [rsi + r10 * 4], 0
+    inc r10
+    jmp .zero
+.next_id:
+    add rdi, 4
+    lea rsi, [rsi + r9 * 4]
+    dec rcx
+    jmp .id
+.positional:
+    cmp qword [rbp + 16], 0
+    je finish
+    mov rdi, [rbp - 56]
+    mov rsi, [rbp - 64]
+    mov rdx, [rbp - 72]
+    call positional
+    jmp finish
+
+; void gaia_flatten_<activation>(const float *x, float *y, int64 rows, int64 n)
+;   copy of rows × n values; layers that only change the shape use it when
+;   they carry an activation, and x may be y
+global gaia_flatten_none
+global gaia_flatten_relu
+global gaia_flatten_sigmoid
+global gaia_flatten_tanh
+global gaia_flatten_softmax
+gaia_flatten_none:
+    xor eax, eax
+    jmp flatten
+gaia_flatten_relu:
+    mov eax, 1
+    jmp flatten
+gaia_flatten_sigmoid:
+    mov eax, 2
+    jmp flatten
+gaia_flatten_tanh:
+    mov eax, 3
+    jmp flatten
+gaia_flatten_softmax:
+    mov eax, 4
+    jmp flatten
+
+flatten:
+    push rbp
+    mov rbp, rsp
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    sub rsp, 72
+    mov [rbp - 48], eax
+    mov [rbp - 56], rsi
+    mov [rbp - 64], rdx
+    mov [rbp - 72], rcx
+    imul rcx, rdx
+    xchg rdi, rsi
+    rep movsd
+    jmp finish
+
+; Apply the activation chosen on entry to the output and return; every
+; kernel body ends here with its frame in place
+finish:
+    mov rdi, [rbp - 56]
+    mov rsi, [rbp - 64]
+    mov rdx, [rbp - 72]
+    mov ecx, [rbp - 48]
+    call activate
+    add rsp, 72
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
+    pop rbp
     ret
 
-gaia_dense_tanh:
-    ; Dense layer with tanh activation
+; void activate(float *y, int64 rows, int64 n, int32 activation)
+activate:
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    sub rsp, 16                 ; softmax maximum and sum
+    mov rbx, rdi
+    mov r12, rsi
+    mov r13, rdx
+    mov r14, rsi
+    imul r14, rdx               ; values
+    cmp ecx, 1
+    je .relu
+    cmp ecx, 2
+    je .sigmoid
+    cmp ecx, 3
+    je .tanh
+    cmp ecx, 4
+    je .softmax
+    jmp .done
+.relu:
+    xorps xmm1, xmm1
+.relu_next:
+    test r14, r14
+    jz .done
+    movss xmm0, [rbx]
+    maxss xmm0, xmm1
+    movss [rbx], xmm0
+    add rbx, 4
+    dec r14
+    jmp .relu_next
+.sigmoid:
+    test r14, r14
+    jz .done
+    movss xmm0, [rbx]
+    mov eax, 0x80000000
+    movd xmm1, eax
+    xorps xmm0, xmm1
+    call expf wrt ..plt
+    mov eax, 0x3f800000         ; 1.0
+    movd xmm1, eax
+    addss xmm0, xmm1
+    divss xmm1, xmm0
+    movss [rbx], xmm1
+    add rbx, 4
+    dec r14
+    jmp .sigmoid
+.tanh:
+    test r14, r14
+    jz .done
+    movss xmm0, [rbx]
+    call tanhf wrt ..plt
+    movss [rbx], xmm0
+    add rbx, 4
+    dec r14
+    jmp .tanh
+.softmax:
+    test r12, r12
+    jz .done
+    mov eax, 0xff800000         ; -inf
+    movd xmm0, eax
+    xor r15d, r15d
+.maximum:
+    cmp r15, r13
+    jge .exponentials
+    maxss xmm0, [rbx + r15 * 4]
+    inc r15
+    jmp .maximum
+.exponentials:
+    movss [rsp], xmm0
+    mov dword [rsp + 4], 0
+    xor r15d, r15d
+.exponential:
+    cmp r15, r13
+    jge .normalise
+    movss xmm0, [rbx + r15 * 4]
+    subss xmm0, [rsp]
+    call expf wrt ..plt
+    movss [rbx + r15 * 4], xmm0
+    addss xmm0, [rsp + 4]
+    movss [rsp + 4], xmm0
+    inc r15
+    jmp .exponential
+.normalise:
+    xor r15d, r15d
+.divide:
+    cmp r15, r13
+    jge .next_row
+    movss xmm0, [rbx + r15 * 4]
+    divss xmm0, [rsp + 4]
+    movss [rbx + r15 * 4], xmm0
+    inc r15
+    jmp .divide
+.next_row:
+    lea rbx, [rbx + r13 * 4]
+    dec r12
+    jmp .softmax
+.done:
+    add rsp, 16
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
     ret
 
-gaia_dense_softmax:
-    ; Dense layer with softmax activation
+; void positional(float *y, int64 steps, int64 dim)
+;   adds sin(p / 10000^(2i / dim)) to feature 2i of position p and the cosine to feature 2i + 1
+positional:
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    mov rbx, rdi
+    mov r12, rsi
+    mov r13, rdx
+    xor r14d, r14d              ; position
+.position:
+    cmp r14, r12
+    jge .done
+    xor r15d, r15d              ; feature
+.feature:
+    cmp r15, r13
+    jge .next_position
+    mov rax, r15
+    and rax, -2
+    cvtsi2ss xmm1, rax
+    cvtsi2ss xmm2, r13
+    divss xmm1, xmm2
+    mov eax, 0x461c4000         ; 10000.0
+    movd xmm0, eax
+    call powf wrt ..plt
+    cvtsi2ss xmm1, r14
+    divss xmm1, xmm0
+    movaps xmm0, xmm1
+    test r15, 1
+    jnz .cosine
+    call sinf wrt ..plt
+    jmp .add
+.cosine:
+    call cosf wrt ..plt
+.add:
+    addss xmm0, [rbx]
+    movss [rbx], xmm0
+    add rbx, 4
+    inc r15
+    jmp .feature
+.next_position:
+    inc r14
+    jmp .position
+.done:
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
     ret
 
-gaia_dense_none:
-    ; Dense layer without activation
+; Losses: float gaia_loss_<name>(const float *prediction, const float *target, int64 n)
+
+; Mean squared error
+global gaia_loss_MSE
+gaia_loss_MSE:
+    xorps xmm0, xmm0
+    xor eax, eax
+.next:
+    cmp rax, rdx
+    jge .mean
+    movss xmm1, [rdi + rax * 4]
+    subss xmm1, [rsi + rax * 4]
+    mulss xmm1, xmm1
+    addss xmm0, xmm1
+    inc rax
+    jmp .next
+.mean:
+    cvtsi2ss xmm1, rdx
+    divss xmm0, xmm1
     ret
 
-gaia_pooling_none:
-    ; Pooling layer
+; Binary cross-entropy, with the prediction clamped to [1e-7, 1 - 1e-7]
+global gaia_loss_BCE
+gaia_loss_BCE:
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    sub rsp, 16                 ; sum, clamped prediction and its logarithm
+    mov rbx, rdi
+    mov r12, rsi
+    mov r13, rdx
+    xor r14d, r14d
+    mov dword [rsp], 0
+.next:
+    cmp r14, r13
+    jge .mean
+    movss xmm0, [rbx + r14 * 4]
+    mov eax, 0x33d6bf95         ; 1e-7
+    movd xmm1, eax
+    maxss xmm0, xmm1
+    mov eax, 0x3f7ffffe         ; 1 - 1e-7
+    movd xmm1, eax
+    minss xmm0, xmm1
+    movss [rsp + 4], xmm0
+    call logf wrt ..plt
+    movss [rsp + 8], xmm0
+    mov eax, 0x3f800000
+    movd xmm0, eax
+    subss xmm0, [rsp + 4]
+    call logf wrt ..plt         ; ln(1 - p)
+    mov eax, 0x3f800000
+    movd xmm1, eax
+    subss xmm1, [r12 + r14 * 4]
+    mulss xmm0, xmm1
+    movss xmm1, [r12 + r14 * 4]
+    mulss xmm1, [rsp + 8]
+    addss xmm0, xmm1
+    movss xmm1, [rsp]
+    subss xmm1, xmm0
+    movss [rsp], xmm1
+    inc r14
+    jmp .next
+.mean:
+    movss xmm0, [rsp]
+    cvtsi2ss xmm1, r13
+    divss xmm0, xmm1
+    add rsp, 16
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
     ret
 
-gaia_flatten_none:
-    ; Flatten layer
+; Categorical cross-entropy of a probability distribution
+global gaia_loss_CE
+gaia_loss_CE:
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    sub rsp, 16
+    mov rbx, rdi
+    mov r12, rsi
+    mov r13, rdx
+    xor r14d, r14d
+    mov dword [rsp], 0
+.next:
+    cmp r14, r13
+    jge .done
+    movss xmm0, [rbx + r14 * 4]
+    mov eax, 0x33d6bf95         ; 1e-7
+    movd xmm1, eax
+    maxss xmm0, xmm1
+    call logf wrt ..plt
+    mulss xmm0, [r12 + r14 * 4]
+    movss xmm1, [rsp]
+    subss xmm1, xmm0
+    movss [rsp], xmm1
+    inc r14
+    jmp .next
+.done:
+    movss xmm0, [rsp]
+    add rsp, 16
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
     ret
 
-; Loss functions
-gaia_loss_MSE:
-    ; Mean Squared Error loss function
+; Hinge loss; 0/1 targets are mapped to -1/1
+global gaia_loss_Hinge
+gaia_loss_Hinge:
+    xorps xmm0, xmm0
+    xor eax, eax
+.next:
+    cmp rax, rdx
+    jge .mean
+    movss xmm1, [rsi + rax * 4]
+    xorps xmm2, xmm2
+    ucomiss xmm1, xmm2
+    jne .label
+    mov ecx, 0xbf800000         ; -1.0
+    movd xmm1, ecx
+.label:
+    mulss xmm1, [rdi + rax * 4]
+    mov ecx, 0x3f800000
+    movd xmm2, ecx
+    subss xmm2, xmm1
+    xorps xmm1, xmm1
+    maxss xmm2, xmm1
+    addss xmm0, xmm2
+    inc rax
+    jmp .next
+.mean:
+    cvtsi2ss xmm1, rdx
+    divss xmm0, xmm1
     ret
 
-gaia_loss_BCE:
-    ; Binary Cross Entropy loss function
+; Kullback-Leibler divergence of the prediction from the target distribution
+global gaia_loss_KL
+gaia_loss_KL:
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    sub rsp, 16
+    mov rbx, rdi
+    mov r12, rsi
+    mov r13, rdx
+    xor r14d, r14d
+    mov dword [rsp], 0
+.next:
+    cmp r14, r13
+    jge .done
+    movss xmm1, [r12 + r14 * 4]
+    xorps xmm2, xmm2
+    comiss xmm1, xmm2
+    jbe .skip
+    movss xmm0, [rbx + r14 * 4]
+    mov eax, 0x33d6bf95         ; 1e-7
+    movd xmm2, eax
+    maxss xmm0, xmm2
+    divss xmm1, xmm0
+    movaps xmm0, xmm1
+    call logf wrt ..plt
+    mulss xmm0, [r12 + r14 * 4]
+    addss xmm0, [rsp]
+    movss [rsp], xmm0
+.skip:
+    inc r14
+    jmp .next
+.done:
+    movss xmm0, [rsp]
+    add rsp, 16
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
     ret
 
-; External functions that would be linked in
-extern malloc
-extern free
-extern memset
-extern puts
\ No newline at end of file
+section .note.GNU-stack noalloc noexec nowrite progbits
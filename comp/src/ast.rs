@@ -1,5 +1,9 @@
 use std::collections::HashMap;
 use std::fmt;
+use std::sync::Arc;
+use crate::custom_ops::CustomOp;
+use crate::diagnostics::Span;
+use crate::tokenizer::Tokenizer;
 
 // AST Node types for the AOPL language
 #[derive(Debug, Clone)]
@@ -42,7 +46,7 @@ impl fmt::Display for ASTNode {
                 }
                 Ok(())
             },
-            ASTNode::UIComponent(_) => write!(f, "UIComponent"),
+            ASTNode::UIComponent(ui) => write!(f, "{}", ui),
             ASTNode::EventHandler(_) => write!(f, "EventHandler"),
             ASTNode::DataBinding(_) => write!(f, "DataBinding"),
             ASTNode::ThreeDComponent(_) => write!(f, "ThreeDComponent"),
@@ -61,6 +65,22 @@ pub struct RawNode {
 pub struct NetworkNode {
     pub components: Option<Vec<String>>,
     pub body: Vec<ASTNode>,
+    // Parameter sharing declarations (`E≜D`)
+    pub shares: Vec<ShareNode>,
+}
+
+/// `component≜owner`: the component runs with the parameters of `owner`
+#[derive(Debug, Clone)]
+pub struct ShareNode {
+    pub component: String,
+    pub owner: String,
+    pub span: Span,
+}
+
+impl fmt::Display for ShareNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}≜{}", self.component, self.owner)
+    }
 }
 
 impl fmt::Display for NetworkNode {
@@ -79,6 +99,9 @@ impl fmt::Display for NetworkNode {
         for node in &self.body {
             write!(f, "\n{}", node)?;
         }
+        for share in &self.shares {
+            write!(f, "\n{}", share)?;
+        }
         Ok(())
     }
 }
@@ -87,6 +110,7 @@ impl fmt::Display for NetworkNode {
 pub struct ComponentNode {
     pub id: String,
     pub expr: Box<ASTNode>,
+    pub span: Span,
 }
 
 impl fmt::Display for ComponentNode {
@@ -118,34 +142,125 @@ pub enum LayerType {
     BatchSize,
     TransposeConv,
     Attention,
+    // `⇝name`, resolved by the parser, see `custom_ops`
+    Custom(Arc<dyn CustomOp>),
+}
+
+/// Shape of a single example, without the batch dimension (channels last)
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorShape {
+    pub dims: Vec<usize>,
+}
+
+impl TensorShape {
+    pub fn new(dims: Vec<usize>) -> Self {
+        TensorShape { dims }
+    }
+
+    pub fn rank(&self) -> usize {
+        self.dims.len()
+    }
+
+    /// Total number of elements
+    pub fn elements(&self) -> usize {
+        self.dims.iter().product()
+    }
+}
+
+impl fmt::Display for TensorShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, dim) in self.dims.iter().enumerate() {
+            if i > 0 {
+                write!(f, "×")?;
+            }
+            write!(f, "{}", dim)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct LayerNode {
     pub layer_type: LayerType,
+    // Positional values, see `layer_params::schema`
     pub params: Vec<usize>,
+    pub named_params: Vec<NamedParam>,
+    // `+P`: sinusoidal positional encoding added to the output of `E`
+    pub positional: bool,
     pub activation: ActivationFunction,
+    pub span: Span,
+    // Filled in by shape inference
+    pub input_shape: Option<TensorShape>,
+    pub output_shape: Option<TensorShape>,
 }
 
-impl fmt::Display for LayerNode {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+/// `name=value` override of a layer parameter, e.g. `kernel=5` in `C₁ 32 kernel=5`
+#[derive(Debug, Clone)]
+pub struct NamedParam {
+    pub name: String,
+    pub value: usize,
+    pub span: Span,
+}
+
+impl LayerNode {
+    /// Source symbol of the layer, including its subscript (e.g. `C₁`, `P`)
+    pub fn symbol(&self) -> String {
         match &self.layer_type {
-            LayerType::Convolutional(_idx) => write!(f, "C₁")?,
-            LayerType::Dense(_idx) => write!(f, "D₁")?,
-            LayerType::Pooling => write!(f, "P")?,
-            LayerType::Flatten => write!(f, "F")?,
-            LayerType::Upsampling => write!(f, "U")?,
-            LayerType::LSTM => write!(f, "L")?,
-            LayerType::AttentionHeads => write!(f, "H")?,
-            LayerType::Reshape => write!(f, "R")?,
-            LayerType::Embedding => write!(f, "E")?,
-            LayerType::BatchSize => write!(f, "B")?,
-            LayerType::TransposeConv => write!(f, "T")?,
-            LayerType::Attention => write!(f, "A")?,
+            LayerType::Convolutional(idx) => format!("C{}", subscript(*idx)),
+            LayerType::Dense(idx) => format!("D{}", subscript(*idx)),
+            LayerType::Pooling => "P".to_string(),
+            LayerType::Flatten => "F".to_string(),
+            LayerType::Upsampling => "U".to_string(),
+            LayerType::LSTM => "L".to_string(),
+            LayerType::AttentionHeads => "H".to_string(),
+            LayerType::Reshape => "R".to_string(),
+            LayerType::Embedding => "E".to_string(),
+            LayerType::BatchSize => "B".to_string(),
+            LayerType::TransposeConv => "Cᵀ".to_string(),
+            LayerType::Attention => "A".to_string(),
+            LayerType::Custom(op) => format!("⇝{}", op.symbol()),
+        }
+    }
+}
+
+// Subscript digits for a layer index; index 0 is written without a subscript
+fn subscript(idx: usize) -> String {
+    if idx == 0 {
+        return String::new();
+    }
+    idx.to_string().chars()
+        .map(|c| char::from_u32('₀' as u32 + c.to_digit(10).unwrap()).unwrap())
+        .collect()
+}
+
+/// Identifier for a GaiaScript name in generated code, e.g. `u00d1_C1` for
+/// `Ñ.C₁`: subscripts become digits, `.` becomes `_`, and characters that
+/// are not letters or digits (or not ASCII, with `ascii`) are spelled by code point
+pub fn identifier(name: &str, ascii: bool) -> String {
+    let mut out = String::new();
+    for c in name.chars() {
+        match c {
+            '.' => out.push('_'),
+            '₀'..='₉' => out.push(char::from_digit(c as u32 - '₀' as u32, 10).unwrap()),
+            c if c == '_' || if ascii { c.is_ascii_alphanumeric() } else { c.is_alphanumeric() } => out.push(c),
+            c => out.push_str(&format!("u{:04x}", c as u32)),
         }
+    }
+    out
+}
+
+impl fmt::Display for LayerNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.symbol())?;
         for param in &self.params {
             write!(f, " {}", param)?;
         }
+        for param in &self.named_params {
+            write!(f, " {}={}", param.name, param.value)?;
+        }
+        if self.positional {
+            write!(f, " +P")?;
+        }
         match self.activation {
             ActivationFunction::ReLU => write!(f, " ρ")?,
             ActivationFunction::Sigmoid => write!(f, " σ")?,
@@ -157,15 +272,61 @@ impl fmt::Display for LayerNode {
     }
 }
 
+/// `[…]×n`: the content applied `n` times, each repetition fed the output of
+/// the one before
+///
+/// Repetitions have their own parameters unless the block is marked `≜`, in
+/// which case every repetition applies the parameters of the first (`≠`
+/// spells out the default).
 #[derive(Debug, Clone)]
 pub struct BlockNode {
     pub content: Box<ASTNode>,
     pub repetitions: usize,
+    pub shared: bool,
+    // Whether the source wrote `≜` or `≠` at all, so the block prints back as written
+    pub marked: bool,
+    // Set by shape inference: the input of the block, then the output of each repetition
+    pub shapes: Vec<TensorShape>,
+    pub span: Span,
+}
+
+impl BlockNode {
+    /// Whether every repetition sees the same input shape, so a single copy
+    /// of the content can run in a loop
+    pub fn is_uniform(&self) -> bool {
+        self.shapes.len() == self.repetitions.max(1) + 1 && self.shapes.windows(2).all(|w| w[0] == w[1])
+    }
 }
 
 impl fmt::Display for BlockNode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "[{}]×{}", self.content, self.repetitions)
+        write!(f, "[{}]×{}", self.content, self.repetitions)?;
+        if self.shared {
+            write!(f, "≜")?;
+        } else if self.marked {
+            write!(f, "≠")?;
+        }
+        Ok(())
+    }
+}
+
+/// How backends write out a repeated block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockMode {
+    /// One copy of the content in a counted loop; blocks whose repetitions
+    /// change the shape are still unrolled
+    Loop,
+    /// One copy of the content per repetition
+    Unroll,
+}
+
+impl BlockMode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "loop" => Some(BlockMode::Loop),
+            "unroll" => Some(BlockMode::Unroll),
+            _ => None,
+        }
     }
 }
 
@@ -177,10 +338,65 @@ pub enum InputType {
     Latent,
 }
 
+/// Normalization of the data fed to an input, written `⍓` after its dimensions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Preprocess {
+    /// `⍓`: zero mean and unit variance per channel, with the statistics of
+    /// the training data
+    Standardize,
+    /// `⍓ 255`: every value divided by a constant
+    Scale(usize),
+}
+
+/// How a tokenizer splits text, written after `⌸`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenizerKind {
+    /// Byte-level BPE from a `vocab.json` and a `merges.txt`
+    Bpe,
+    /// WordPiece from a BERT-style `vocab.txt`
+    WordPiece,
+    /// One token per character from a file of characters, or per UTF-8 byte
+    /// without one
+    Char,
+}
+
+impl TokenizerKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "bpe" => Some(TokenizerKind::Bpe),
+            "wordpiece" => Some(TokenizerKind::WordPiece),
+            "char" => Some(TokenizerKind::Char),
+            _ => None,
+        }
+    }
+    
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenizerKind::Bpe => "bpe",
+            TokenizerKind::WordPiece => "wordpiece",
+            TokenizerKind::Char => "char",
+        }
+    }
+}
+
+/// Tokenizer of a text input, e.g. `T 64 ⌸ wordpiece "vocab.txt"`
+#[derive(Debug, Clone)]
+pub struct TokenizerSpec {
+    pub kind: TokenizerKind,
+    // Vocabulary files as written, relative to the source file
+    pub files: Vec<String>,
+    pub span: Span,
+    // Set by `tokenizer::attach`
+    pub loaded: Option<Arc<Tokenizer>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct InputNode {
     pub input_type: InputType,
     pub params: Vec<usize>,
+    pub preprocess: Option<Preprocess>,
+    pub tokenizer: Option<TokenizerSpec>,
+    pub span: Span,
 }
 
 impl fmt::Display for InputNode {
@@ -194,20 +410,197 @@ impl fmt::Display for InputNode {
         for param in &self.params {
             write!(f, " {}", param)?;
         }
+        match self.preprocess {
+            Some(Preprocess::Standardize) => write!(f, " ⍓")?,
+            Some(Preprocess::Scale(divisor)) => write!(f, " ⍓ {}", divisor)?,
+            None => {},
+        }
+        if let Some(tokenizer) = &self.tokenizer {
+            write!(f, " ⌸ {}", tokenizer.kind.name())?;
+            for file in &tokenizer.files {
+                write!(f, " {:?}", file)?;
+            }
+        }
         Ok(())
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct LossNode {
+    pub label: Option<String>,
     pub from: Box<ASTNode>,
-    pub to: String,
-    pub function: String,
+    // Components referenced by the source expression, in order
+    pub sources: Vec<String>,
+    // Components the source output is injected into, in order (`G⊳D` or `E⊳G⊳C`)
+    pub targets: Vec<String>,
+    // Components marked `⊘`, whose parameters are not trained by this loss
+    pub frozen: Vec<String>,
+    pub function: LossExpr,
+    pub span: Span,
+}
+
+impl LossNode {
+    /// The component whose output the loss is computed on
+    pub fn to(&self) -> &str {
+        self.targets.last().map(|t| t.as_str()).unwrap_or("")
+    }
+    
+    /// Every component of the composite in execution order: sources, then targets
+    pub fn chain(&self) -> Vec<&str> {
+        self.sources.iter().chain(&self.targets).map(|c| c.as_str()).collect()
+    }
+    
+    pub fn is_frozen(&self, component: &str) -> bool {
+        self.frozen.iter().any(|c| c == component)
+    }
 }
 
 impl fmt::Display for LossNode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}⊳{}⟿{}", self.from, self.to, self.function)
+        if let Some(label) = &self.label {
+            write!(f, "{}: ", label)?;
+        }
+        write!(f, "{}", self.from)?;
+        for target in &self.targets {
+            write!(f, "⊳{}", target)?;
+            if self.is_frozen(target) {
+                write!(f, "⊘")?;
+            }
+        }
+        write!(f, "⟿{}", self.function)
+    }
+}
+
+/// Named base losses that can appear after `⟿`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BaseLoss {
+    MeanSquaredError,
+    BinaryCrossEntropy,
+    CrossEntropy,
+    Hinge,
+    KullbackLeibler,
+}
+
+impl BaseLoss {
+    pub const NAMES: &'static [&'static str] = &["MSE", "BCE", "CE", "Hinge", "KL"];
+    
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "MSE" => Some(BaseLoss::MeanSquaredError),
+            "BCE" => Some(BaseLoss::BinaryCrossEntropy),
+            "CE" | "CCE" => Some(BaseLoss::CrossEntropy),
+            "HINGE" => Some(BaseLoss::Hinge),
+            "KL" => Some(BaseLoss::KullbackLeibler),
+            _ => None,
+        }
+    }
+    
+    pub fn name(&self) -> &'static str {
+        match self {
+            BaseLoss::MeanSquaredError => "MSE",
+            BaseLoss::BinaryCrossEntropy => "BCE",
+            BaseLoss::CrossEntropy => "CE",
+            BaseLoss::Hinge => "Hinge",
+            BaseLoss::KullbackLeibler => "KL",
+        }
+    }
+}
+
+/// Constant factor in front of a loss term, either a number or a symbol such as `λ`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Coefficient {
+    Value(f32),
+    Symbol(String),
+}
+
+impl fmt::Display for Coefficient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Coefficient::Value(value) => write!(f, "{}", value),
+            Coefficient::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+/// Vector norm used by regularizers and gradient penalties
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Norm {
+    L1,
+    L2,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LossTerm {
+    Base(BaseLoss),
+    // Weight regularizer: `L1`/`L2` over every trained weight, or `‖G‖₂` over one component
+    Regularizer { norm: Norm, component: Option<String> },
+    // Norm of the gradient of a component with respect to its input: `‖∇D‖`
+    GradientPenalty { component: String, norm: Norm },
+}
+
+impl fmt::Display for LossTerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let order = |norm: &Norm| if *norm == Norm::L1 { "₁" } else { "" };
+        match self {
+            LossTerm::Base(base) => write!(f, "{}", base.name()),
+            LossTerm::Regularizer { norm, component: None } => write!(f, "{:?}", norm),
+            LossTerm::Regularizer { norm, component: Some(id) } => write!(f, "‖{}‖{}", id, order(norm)),
+            LossTerm::GradientPenalty { component, norm } => write!(f, "‖∇{}‖{}", component, order(norm)),
+        }
+    }
+}
+
+/// One signed, weighted term of a loss function
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeightedTerm {
+    pub negated: bool,
+    pub coefficient: Option<Coefficient>,
+    pub term: LossTerm,
+    pub span: Span,
+}
+
+/// Structured loss function, e.g. `BCE+λ‖∇D‖`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LossExpr {
+    pub terms: Vec<WeightedTerm>,
+}
+
+impl LossExpr {
+    /// A loss made of a single base loss
+    pub fn single(base: BaseLoss) -> Self {
+        LossExpr {
+            terms: vec![WeightedTerm {
+                negated: false,
+                coefficient: None,
+                term: LossTerm::Base(base),
+                span: Span::default(),
+            }],
+        }
+    }
+    
+    /// Base losses of the expression, in order
+    pub fn base_losses(&self) -> impl Iterator<Item = BaseLoss> + '_ {
+        self.terms.iter().filter_map(|t| match t.term {
+            LossTerm::Base(base) => Some(base),
+            _ => None,
+        })
+    }
+}
+
+impl fmt::Display for LossExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, term) in self.terms.iter().enumerate() {
+            if term.negated {
+                write!(f, "-")?;
+            } else if i > 0 {
+                write!(f, "+")?;
+            }
+            if let Some(coefficient) = &term.coefficient {
+                write!(f, "{}", coefficient)?;
+            }
+            write!(f, "{}", term.term)?;
+        }
+        Ok(())
     }
 }
 
@@ -0,0 +1,490 @@
+use crate::ast::{ActivationFunction, Norm};
+use crate::interpreter::{self, Tensor};
+use crate::layer_params::Window;
+
+// Keeps logarithms finite for saturated probabilities
+const EPSILON: f32 = 1e-7;
+
+/// Handle to a value recorded on a `Tape`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Var(usize);
+
+#[derive(Debug, Clone)]
+enum Op {
+    Leaf,
+    Dense { x: Var, w: Var, b: Var },
+    Conv2d { x: Var, w: Var, b: Var, window: Window },
+    ConvTranspose { x: Var, w: Var, b: Var, stride: usize },
+    MaxPool { x: Var, argmax: Vec<usize> },
+    Upsample { x: Var, factor: usize },
+    Reshape { x: Var },
+    Activation { x: Var, activation: ActivationFunction },
+    Add { a: Var, b: Var },
+    Scale { x: Var, factor: f32 },
+    Mse { prediction: Var, target: Tensor },
+    Bce { prediction: Var, target: Tensor },
+    CrossEntropy { prediction: Var, target: Tensor },
+    Hinge { prediction: Var, target: Tensor },
+    KullbackLeibler { prediction: Var, target: Tensor },
+    Norm { x: Var, norm: Norm },
+}
+
+/// Reverse-mode automatic differentiation tape
+///
+/// Every operation evaluates eagerly with the interpreter kernels and records
+/// what it needs to propagate gradients back in `backward`.
+#[derive(Debug, Default)]
+pub struct Tape {
+    values: Vec<Tensor>,
+    ops: Vec<Op>,
+}
+
+/// Gradients of a scalar with respect to every value on the tape
+pub struct Gradients {
+    grads: Vec<Option<Tensor>>,
+}
+
+impl Gradients {
+    pub fn get(&self, var: Var) -> Option<&Tensor> {
+        self.grads.get(var.0).and_then(|g| g.as_ref())
+    }
+}
+
+fn accumulate(slot: &mut Option<Tensor>, grad: Tensor) {
+    match slot {
+        Some(existing) => {
+            for (a, b) in existing.data.iter_mut().zip(&grad.data) {
+                *a += b;
+            }
+        },
+        None => *slot = Some(grad),
+    }
+}
+
+fn scalar(value: f32) -> Tensor {
+    Tensor { shape: vec![1], data: vec![value] }
+}
+
+impl Tape {
+    pub fn new() -> Self {
+        Tape { values: Vec::new(), ops: Vec::new() }
+    }
+    
+    fn push(&mut self, value: Tensor, op: Op) -> Var {
+        self.values.push(value);
+        self.ops.push(op);
+        Var(self.values.len() - 1)
+    }
+    
+    pub fn value(&self, var: Var) -> &Tensor {
+        &self.values[var.0]
+    }
+    
+    /// Record an input or a parameter
+    pub fn leaf(&mut self, value: Tensor) -> Var {
+        self.push(value, Op::Leaf)
+    }
+    
+    pub fn dense(&mut self, x: Var, w: Var, b: Var) -> Var {
+        let y = interpreter::dense(self.value(x), self.value(w), self.value(b));
+        self.push(y, Op::Dense { x, w, b })
+    }
+    
+    pub fn conv2d(&mut self, x: Var, w: Var, b: Var, window: Window) -> Var {
+        let y = interpreter::conv2d(self.value(x), self.value(w), self.value(b), window);
+        self.push(y, Op::Conv2d { x, w, b, window })
+    }
+    
+    pub fn conv2d_transpose(&mut self, x: Var, w: Var, b: Var, stride: usize) -> Var {
+        let y = interpreter::conv2d_transpose(self.value(x), self.value(w), self.value(b), stride);
+        self.push(y, Op::ConvTranspose { x, w, b, stride })
+    }
+    
+    pub fn max_pool2d(&mut self, x: Var, window: Window) -> Var {
+        let input = self.value(x);
+        let (h, wd, c) = (input.shape[0], input.shape[1], input.shape[2]);
+        let y = interpreter::max_pool2d(input, window);
+        let (oh, ow) = (y.shape[0], y.shape[1]);
+        
+        // Remember which input produced each maximum
+        let mut argmax = vec![0; y.len()];
+        for oy in 0..oh {
+            for ox in 0..ow {
+                for ch in 0..c {
+                    let out = (oy * ow + ox) * c + ch;
+                    let inputs = interpreter::window_inputs(window, (h, wd), (oy, ox));
+                    if let Some(src) = inputs.map(|(iy, ix)| (iy * wd + ix) * c + ch).find(|&src| input.data[src] == y.data[out]) {
+                        argmax[out] = src;
+                    }
+                }
+            }
+        }
+        self.push(y, Op::MaxPool { x, argmax })
+    }
+    
+    pub fn upsample(&mut self, x: Var, factor: usize) -> Var {
+        let y = interpreter::upsample_nearest(self.value(x), factor);
+        self.push(y, Op::Upsample { x, factor })
+    }
+    
+    pub fn reshape(&mut self, x: Var, shape: Vec<usize>) -> Result<Var, String> {
+        let y = self.value(x).clone().reshape(shape)?;
+        Ok(self.push(y, Op::Reshape { x }))
+    }
+    
+    pub fn activation(&mut self, x: Var, activation: &ActivationFunction) -> Var {
+        if *activation == ActivationFunction::None {
+            return x;
+        }
+        let y = interpreter::activate(self.value(x).clone(), activation);
+        self.push(y, Op::Activation { x, activation: activation.clone() })
+    }
+    
+    /// Element-wise sum of two values of the same shape
+    pub fn add(&mut self, a: Var, b: Var) -> Var {
+        let mut y = self.value(a).clone();
+        for (v, w) in y.data.iter_mut().zip(&self.value(b).data) {
+            *v += w;
+        }
+        self.push(y, Op::Add { a, b })
+    }
+    
+    pub fn scale(&mut self, x: Var, factor: f32) -> Var {
+        let mut y = self.value(x).clone();
+        y.data.iter_mut().for_each(|v| *v *= factor);
+        self.push(y, Op::Scale { x, factor })
+    }
+    
+    /// Mean squared error
+    pub fn mse(&mut self, prediction: Var, target: &Tensor) -> Var {
+        let p = self.value(prediction);
+        let sum: f32 = p.data.iter().zip(&target.data).map(|(a, b)| (a - b) * (a - b)).sum();
+        let loss = sum / p.len() as f32;
+        self.push(scalar(loss), Op::Mse { prediction, target: target.clone() })
+    }
+    
+    /// Binary cross-entropy of probabilities, e.g. the output of a sigmoid
+    pub fn bce(&mut self, prediction: Var, target: &Tensor) -> Var {
+        let p = self.value(prediction);
+        let sum: f32 = p.data.iter().zip(&target.data).map(|(p, y)| {
+            let p = p.clamp(EPSILON, 1.0 - EPSILON);
+            -(y * p.ln() + (1.0 - y) * (1.0 - p).ln())
+        }).sum();
+        let loss = sum / p.len() as f32;
+        self.push(scalar(loss), Op::Bce { prediction, target: target.clone() })
+    }
+    
+    /// Categorical cross-entropy of a probability distribution, e.g. a softmax
+    pub fn cross_entropy(&mut self, prediction: Var, target: &Tensor) -> Var {
+        let p = self.value(prediction);
+        let loss: f32 = p.data.iter().zip(&target.data).map(|(p, y)| -y * p.max(EPSILON).ln()).sum();
+        self.push(scalar(loss), Op::CrossEntropy { prediction, target: target.clone() })
+    }
+    
+    /// Hinge loss; 0/1 targets are mapped to -1/1
+    pub fn hinge(&mut self, prediction: Var, target: &Tensor) -> Var {
+        let p = self.value(prediction);
+        let sum: f32 = p.data.iter().zip(&target.data)
+            .map(|(p, y)| (1.0 - hinge_label(*y) * p).max(0.0))
+            .sum();
+        let loss = sum / p.len() as f32;
+        self.push(scalar(loss), Op::Hinge { prediction, target: target.clone() })
+    }
+    
+    /// Kullback-Leibler divergence of the prediction from the target distribution
+    pub fn kl_divergence(&mut self, prediction: Var, target: &Tensor) -> Var {
+        let p = self.value(prediction);
+        let loss: f32 = p.data.iter().zip(&target.data)
+            .filter(|(_, y)| **y > 0.0)
+            .map(|(p, y)| y * (y / p.max(EPSILON)).ln())
+            .sum();
+        self.push(scalar(loss), Op::KullbackLeibler { prediction, target: target.clone() })
+    }
+    
+    /// Sum of absolute values (L1) or of squares (L2)
+    pub fn norm(&mut self, x: Var, norm: Norm) -> Var {
+        let v = self.value(x);
+        let value = match norm {
+            Norm::L1 => v.data.iter().map(|w| w.abs()).sum(),
+            Norm::L2 => v.data.iter().map(|w| w * w).sum(),
+        };
+        self.push(scalar(value), Op::Norm { x, norm })
+    }
+    
+    /// Propagate the gradient of the scalar `output` back to every value
+    pub fn backward(&self, output: Var) -> Gradients {
+        let mut grads: Vec<Option<Tensor>> = vec![None; self.values.len()];
+        let mut seed = Tensor::zeros(self.values[output.0].shape.clone());
+        seed.data.iter_mut().for_each(|v| *v = 1.0);
+        grads[output.0] = Some(seed);
+        
+        for index in (0..=output.0).rev() {
+            let grad = match grads[index].take() {
+                Some(grad) => grad,
+                None => continue,
+            };
+            self.backward_op(index, &grad, &mut grads);
+            grads[index] = Some(grad);
+        }
+        Gradients { grads }
+    }
+    
+    fn backward_op(&self, index: usize, g: &Tensor, grads: &mut [Option<Tensor>]) {
+        let y = &self.values[index];
+        match &self.ops[index] {
+            Op::Leaf => {},
+            Op::Dense { x, w, b } => {
+                let (xv, wv) = (self.value(*x), self.value(*w));
+                let (n_in, n_out) = (wv.shape[0], wv.shape[1]);
+                let mut gx = Tensor::zeros(xv.shape.clone());
+                let mut gw = Tensor::zeros(wv.shape.clone());
+                let mut gb = Tensor::zeros(vec![n_out]);
+                for r in 0..xv.len() / n_in {
+                    let g_row = &g.data[r * n_out..(r + 1) * n_out];
+                    for (o, go) in g_row.iter().enumerate() {
+                        gb.data[o] += go;
+                    }
+                    for i in 0..n_in {
+                        let xi = xv.data[r * n_in + i];
+                        let w_row = &wv.data[i * n_out..(i + 1) * n_out];
+                        let gw_row = &mut gw.data[i * n_out..(i + 1) * n_out];
+                        let mut acc = 0.0;
+                        for o in 0..n_out {
+                            gw_row[o] += xi * g_row[o];
+                            acc += w_row[o] * g_row[o];
+                        }
+                        gx.data[r * n_in + i] = acc;
+                    }
+                }
+                accumulate(&mut grads[x.0], gx);
+                accumulate(&mut grads[w.0], gw);
+                accumulate(&mut grads[b.0], gb);
+            },
+            Op::Conv2d { x, w, b, window } => {
+                let (xv, wv) = (self.value(*x), self.value(*w));
+                let (h, wd, c_in) = (xv.shape[0], xv.shape[1], xv.shape[2]);
+                let (k, c_out) = (wv.shape[0], wv.shape[3]);
+                let (oh, ow) = (g.shape[0], g.shape[1]);
+                let (pad_y, pad_x) = (window.padding(h).0 as isize, window.padding(wd).0 as isize);
+                let mut gx = Tensor::zeros(xv.shape.clone());
+                let mut gw = Tensor::zeros(wv.shape.clone());
+                let mut gb = Tensor::zeros(vec![c_out]);
+                for oy in 0..oh {
+                    for ox in 0..ow {
+                        let g_px = &g.data[(oy * ow + ox) * c_out..][..c_out];
+                        for (o, go) in g_px.iter().enumerate() {
+                            gb.data[o] += go;
+                        }
+                        for ky in 0..k {
+                            let iy = (oy * window.stride + ky) as isize - pad_y;
+                            if iy < 0 || iy >= h as isize {
+                                continue;
+                            }
+                            for kx in 0..k {
+                                let ix = (ox * window.stride + kx) as isize - pad_x;
+                                if ix < 0 || ix >= wd as isize {
+                                    continue;
+                                }
+                                let src = (iy as usize * wd + ix as usize) * c_in;
+                                for ci in 0..c_in {
+                                    let base = ((ky * k + kx) * c_in + ci) * c_out;
+                                    let xv_ci = xv.data[src + ci];
+                                    let mut acc = 0.0;
+                                    let gw_row = &mut gw.data[base..base + c_out];
+                                    let w_row = &wv.data[base..base + c_out];
+                                    for ((gwv, wv), go) in gw_row.iter_mut().zip(w_row).zip(g_px) {
+                                        *gwv += xv_ci * go;
+                                        acc += wv * go;
+                                    }
+                                    gx.data[src + ci] += acc;
+                                }
+                            }
+                        }
+                    }
+                }
+                accumulate(&mut grads[x.0], gx);
+                accumulate(&mut grads[w.0], gw);
+                accumulate(&mut grads[b.0], gb);
+            },
+            Op::ConvTranspose { x, w, b, stride } => {
+                let (xv, wv) = (self.value(*x), self.value(*w));
+                let (h, wd, c_in) = (xv.shape[0], xv.shape[1], xv.shape[2]);
+                let (k, c_out) = (wv.shape[0], wv.shape[3]);
+                let (oh, ow) = (h * stride, wd * stride);
+                let pad = (k.saturating_sub(*stride) / 2) as isize;
+                let mut gx = Tensor::zeros(xv.shape.clone());
+                let mut gw = Tensor::zeros(wv.shape.clone());
+                let mut gb = Tensor::zeros(vec![c_out]);
+                for px in g.data.chunks(c_out) {
+                    for (o, go) in px.iter().enumerate() {
+                        gb.data[o] += go;
+                    }
+                }
+                for iy in 0..h {
+                    for ix in 0..wd {
+                        let src = (iy * wd + ix) * c_in;
+                        for ky in 0..k {
+                            let oy = (iy * stride + ky) as isize - pad;
+                            if oy < 0 || oy >= oh as isize {
+                                continue;
+                            }
+                            for kx in 0..k {
+                                let ox = (ix * stride + kx) as isize - pad;
+                                if ox < 0 || ox >= ow as isize {
+                                    continue;
+                                }
+                                let g_px = &g.data[(oy as usize * ow + ox as usize) * c_out..][..c_out];
+                                for ci in 0..c_in {
+                                    let base = ((ky * k + kx) * c_in + ci) * c_out;
+                                    let xv_ci = xv.data[src + ci];
+                                    let mut acc = 0.0;
+                                    let gw_row = &mut gw.data[base..base + c_out];
+                                    let w_row = &wv.data[base..base + c_out];
+                                    for ((gwv, wv), go) in gw_row.iter_mut().zip(w_row).zip(g_px) {
+                                        *gwv += xv_ci * go;
+                                        acc += wv * go;
+                                    }
+                                    gx.data[src + ci] += acc;
+                                }
+                            }
+                        }
+                    }
+                }
+                accumulate(&mut grads[x.0], gx);
+                accumulate(&mut grads[w.0], gw);
+                accumulate(&mut grads[b.0], gb);
+            },
+            Op::MaxPool { x, argmax } => {
+                let mut gx = Tensor::zeros(self.value(*x).shape.clone());
+                for (out, src) in argmax.iter().enumerate() {
+                    gx.data[*src] += g.data[out];
+                }
+                accumulate(&mut grads[x.0], gx);
+            },
+            Op::Upsample { x, factor } => {
+                let xv = self.value(*x);
+                let (wd, c) = (xv.shape[1], xv.shape[2]);
+                let (oh, ow) = (y.shape[0], y.shape[1]);
+                let mut gx = Tensor::zeros(xv.shape.clone());
+                for oy in 0..oh {
+                    for ox in 0..ow {
+                        let src = ((oy / factor) * wd + ox / factor) * c;
+                        for ch in 0..c {
+                            gx.data[src + ch] += g.data[(oy * ow + ox) * c + ch];
+                        }
+                    }
+                }
+                accumulate(&mut grads[x.0], gx);
+            },
+            Op::Reshape { x } => {
+                let gx = Tensor { shape: self.value(*x).shape.clone(), data: g.data.clone() };
+                accumulate(&mut grads[x.0], gx);
+            },
+            Op::Activation { x, activation } => {
+                let mut gx = g.clone();
+                match activation {
+                    ActivationFunction::ReLU => {
+                        for (gv, yv) in gx.data.iter_mut().zip(&y.data) {
+                            if *yv <= 0.0 {
+                                *gv = 0.0;
+                            }
+                        }
+                    },
+                    ActivationFunction::Sigmoid => {
+                        for (gv, yv) in gx.data.iter_mut().zip(&y.data) {
+                            *gv *= yv * (1.0 - yv);
+                        }
+                    },
+                    ActivationFunction::Tanh => {
+                        for (gv, yv) in gx.data.iter_mut().zip(&y.data) {
+                            *gv *= 1.0 - yv * yv;
+                        }
+                    },
+                    ActivationFunction::Softmax => {
+                        let n = y.shape.last().copied().unwrap_or(1).max(1);
+                        for (g_row, y_row) in gx.data.chunks_mut(n).zip(y.data.chunks(n)) {
+                            let dot: f32 = g_row.iter().zip(y_row).map(|(a, b)| a * b).sum();
+                            for (gv, yv) in g_row.iter_mut().zip(y_row) {
+                                *gv = yv * (*gv - dot);
+                            }
+                        }
+                    },
+                    ActivationFunction::None => {},
+                }
+                accumulate(&mut grads[x.0], gx);
+            },
+            Op::Add { a, b } => {
+                accumulate(&mut grads[a.0], g.clone());
+                accumulate(&mut grads[b.0], g.clone());
+            },
+            Op::Scale { x, factor } => {
+                let mut gx = g.clone();
+                gx.data.iter_mut().for_each(|v| *v *= factor);
+                accumulate(&mut grads[x.0], gx);
+            },
+            Op::Mse { prediction, target } => {
+                let p = self.value(*prediction);
+                let scale = 2.0 * g.data[0] / p.len() as f32;
+                let mut gp = Tensor::zeros(p.shape.clone());
+                for ((gv, pv), tv) in gp.data.iter_mut().zip(&p.data).zip(&target.data) {
+                    *gv = scale * (pv - tv);
+                }
+                accumulate(&mut grads[prediction.0], gp);
+            },
+            Op::Bce { prediction, target } => {
+                let p = self.value(*prediction);
+                let scale = g.data[0] / p.len() as f32;
+                let mut gp = Tensor::zeros(p.shape.clone());
+                for ((gv, pv), tv) in gp.data.iter_mut().zip(&p.data).zip(&target.data) {
+                    let pv = pv.clamp(EPSILON, 1.0 - EPSILON);
+                    *gv = scale * (pv - tv) / (pv * (1.0 - pv));
+                }
+                accumulate(&mut grads[prediction.0], gp);
+            },
+            Op::CrossEntropy { prediction, target } => {
+                let p = self.value(*prediction);
+                let mut gp = Tensor::zeros(p.shape.clone());
+                for ((gv, pv), tv) in gp.data.iter_mut().zip(&p.data).zip(&target.data) {
+                    *gv = -g.data[0] * tv / pv.max(EPSILON);
+                }
+                accumulate(&mut grads[prediction.0], gp);
+            },
+            Op::Hinge { prediction, target } => {
+                let p = self.value(*prediction);
+                let scale = g.data[0] / p.len() as f32;
+                let mut gp = Tensor::zeros(p.shape.clone());
+                for ((gv, pv), tv) in gp.data.iter_mut().zip(&p.data).zip(&target.data) {
+                    let label = hinge_label(*tv);
+                    if label * pv < 1.0 {
+                        *gv = -scale * label;
+                    }
+                }
+                accumulate(&mut grads[prediction.0], gp);
+            },
+            Op::KullbackLeibler { prediction, target } => {
+                let p = self.value(*prediction);
+                let mut gp = Tensor::zeros(p.shape.clone());
+                for ((gv, pv), tv) in gp.data.iter_mut().zip(&p.data).zip(&target.data) {
+                    if *tv > 0.0 {
+                        *gv = -g.data[0] * tv / pv.max(EPSILON);
+                    }
+                }
+                accumulate(&mut grads[prediction.0], gp);
+            },
+            Op::Norm { x, norm } => {
+                let mut gx = self.value(*x).clone();
+                for v in gx.data.iter_mut() {
+                    *v = match norm {
+                        Norm::L1 => g.data[0] * v.signum(),
+                        Norm::L2 => g.data[0] * 2.0 * *v,
+                    };
+                }
+                accumulate(&mut grads[x.0], gx);
+            },
+        }
+    }
+}
+
+fn hinge_label(target: f32) -> f32 {
+    if target == 0.0 { -1.0 } else { target }
+}
@@ -0,0 +1,735 @@
+use crate::ast::{ASTNode, BlockMode};
+use crate::asm_compiler::{AsmCompiler, AsmTarget};
+use crate::compiler::JsCompiler;
+use crate::compilers::android_compiler::AndroidCompiler;
+use crate::compilers::kotlin_compiler::{self, KotlinCompiler};
+use crate::compilers::pytorch_compiler::compile_to_pytorch;
+use crate::compilers::react_compiler::ReactCompiler;
+use crate::compilers::tfjs_compiler::{self, compile_to_tfjs};
+use crate::diagnostics::{codes, Diagnostic};
+use crate::interpreter::{plan_network, Weights};
+use crate::onnx;
+use crate::quantization::QuantizedModel;
+use crate::safetensors;
+use crate::tokenizer::{self, text_inputs};
+use crate::platform_detector::Platform;
+use crate::universal_compiler::WebFramework;
+use std::fmt;
+use std::path::PathBuf;
+
+/// A feature a backend is able to translate from GaiaScript
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capability {
+    UserInterface,
+    NeuralNetwork,
+    ThreeD,
+    NativeCode,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Capability::UserInterface => write!(f, "ui"),
+            Capability::NeuralNetwork => write!(f, "nn"),
+            Capability::ThreeD => write!(f, "3d"),
+            Capability::NativeCode => write!(f, "native"),
+        }
+    }
+}
+
+/// A single file produced by a backend, relative to the output directory
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    pub path: PathBuf,
+    pub contents: Vec<u8>,
+}
+
+impl Artifact {
+    pub fn new(path: impl Into<PathBuf>, contents: Vec<u8>) -> Self {
+        Artifact {
+            path: path.into(),
+            contents,
+        }
+    }
+
+    pub fn text(path: impl Into<PathBuf>, contents: impl Into<String>) -> Self {
+        Artifact::new(path, contents.into().into_bytes())
+    }
+}
+
+/// Settings shared by every backend for one compilation
+#[derive(Debug, Clone)]
+pub struct BackendOptions {
+    pub app_name: String,
+    pub web_framework: WebFramework,
+    // Trained parameters for backends that embed weights
+    pub weights: Option<Weights>,
+    // Int8 components for backends with quantized code paths
+    pub quantized: Vec<QuantizedModel>,
+    // Loops or unrolled copies for repeated blocks
+    pub blocks: BlockMode,
+}
+
+impl BackendOptions {
+    pub fn new(app_name: &str) -> Self {
+        BackendOptions {
+            app_name: app_name.to_string(),
+            web_framework: WebFramework::PureJs,
+            weights: None,
+            quantized: Vec::new(),
+            blocks: BlockMode::Loop,
+        }
+    }
+}
+
+/// A compilation target that turns a parsed program into output files
+pub trait Backend {
+    /// Unique name used to select the backend (e.g. `--backend=react`)
+    fn name(&self) -> &'static str;
+
+    /// One-line description shown by `gaia targets`
+    fn description(&self) -> &'static str;
+
+    /// Platforms this backend is the default choice for
+    fn platforms(&self) -> &'static [Platform] {
+        &[]
+    }
+
+    /// Language features the backend can translate
+    fn capabilities(&self) -> &'static [Capability];
+
+    /// Compile the AST into a set of artifacts
+    fn compile(&self, ast: &ASTNode, options: &BackendOptions) -> Result<Vec<Artifact>, Diagnostic>;
+
+    /// Instructions printed after the artifacts have been written
+    fn next_steps(&self, _output_dir: &str, _options: &BackendOptions) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn supports(&self, capability: Capability) -> bool {
+        self.capabilities().contains(&capability)
+    }
+}
+
+/// Registry of available backends, queried by the universal compiler
+pub struct BackendRegistry {
+    backends: Vec<Box<dyn Backend>>,
+}
+
+impl BackendRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        BackendRegistry {
+            backends: Vec::new(),
+        }
+    }
+
+    /// Create a registry containing every built-in backend
+    pub fn with_builtin() -> Self {
+        let mut registry = BackendRegistry::new();
+        registry.register(Box::new(WebJsBackend));
+        registry.register(Box::new(ReactBackend));
+        registry.register(Box::new(AndroidBackend));
+        registry.register(Box::new(KotlinBackend));
+        registry.register(Box::new(AsmBackend::new(AsmTarget::X86_64)));
+        registry.register(Box::new(AsmBackend::new(AsmTarget::ARM64)));
+        registry.register(Box::new(AsmBackend::new(AsmTarget::WASM)));
+        registry.register(Box::new(AsmBackend::new(AsmTarget::WASMUI)));
+        registry.register(Box::new(OnnxBackend));
+        registry.register(Box::new(PyTorchBackend));
+        registry
+    }
+
+    /// Add a backend, replacing any existing backend with the same name
+    pub fn register(&mut self, backend: Box<dyn Backend>) {
+        self.backends.retain(|b| b.name() != backend.name());
+        self.backends.push(backend);
+    }
+
+    /// Look up a backend by name
+    pub fn get(&self, name: &str) -> Option<&dyn Backend> {
+        self.backends.iter()
+            .find(|b| b.name() == name)
+            .map(|b| b.as_ref())
+    }
+
+    /// Find the first backend registered as the default for a platform
+    pub fn default_for_platform(&self, platform: Platform) -> Option<&dyn Backend> {
+        self.backends.iter()
+            .find(|b| b.platforms().contains(&platform))
+            .map(|b| b.as_ref())
+    }
+
+    /// Iterate over all registered backends in registration order
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Backend> {
+        self.backends.iter().map(|b| b.as_ref())
+    }
+
+    /// Names of all registered backends
+    pub fn names(&self) -> Vec<&'static str> {
+        self.backends.iter().map(|b| b.name()).collect()
+    }
+}
+
+impl Default for BackendRegistry {
+    fn default() -> Self {
+        BackendRegistry::with_builtin()
+    }
+}
+
+// Weights shipped with generated code: the trained ones from the options,
+// seeded for the rest; `None` when the program has no layers
+fn shipped_weights(ast: &ASTNode, options: &BackendOptions) -> Result<Option<Weights>, Diagnostic> {
+    let plans = plan_network(ast)?;
+    if plans.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Weights::for_export(&plans, options.weights.as_ref())))
+}
+
+// The safetensors file and the Kotlin object that loads it
+fn kotlin_weights(ast: &ASTNode, options: &BackendOptions) -> Result<Vec<Artifact>, Diagnostic> {
+    let weights = match shipped_weights(ast, options)? {
+        Some(weights) => weights,
+        None => return Ok(Vec::new()),
+    };
+    Ok(vec![
+        Artifact::new(format!("{}.safetensors", options.app_name), safetensors::encode(&weights)),
+        Artifact::text("GaiaWeights.kt", kotlin_compiler::weights_loader(&weights)),
+    ])
+}
+
+// The Kotlin tokenizer of the text inputs and the vocabulary files it reads as assets
+fn kotlin_tokenizers(ast: &ASTNode) -> Vec<Artifact> {
+    let inputs = text_inputs(ast);
+    if inputs.is_empty() {
+        return Vec::new();
+    }
+    let mut artifacts = vec![Artifact::text("GaiaTokenizer.kt", tokenizer::tokenizer_kotlin(&inputs))];
+    for input in &inputs {
+        artifacts.extend(input.files().into_iter().map(|(path, contents)| Artifact::text(path, contents)));
+    }
+    artifacts
+}
+
+/// Pure JavaScript web application
+pub struct WebJsBackend;
+
+impl Backend for WebJsBackend {
+    fn name(&self) -> &'static str {
+        "web"
+    }
+
+    fn description(&self) -> &'static str {
+        "Pure JavaScript web application"
+    }
+
+    fn platforms(&self) -> &'static [Platform] {
+        &[Platform::Web]
+    }
+
+    fn capabilities(&self) -> &'static [Capability] {
+        &[Capability::UserInterface, Capability::NeuralNetwork, Capability::ThreeD]
+    }
+
+    fn compile(&self, ast: &ASTNode, options: &BackendOptions) -> Result<Vec<Artifact>, Diagnostic> {
+        let app_name = &options.app_name;
+
+        // JavaScript is the primary target for web
+        let mut js_compiler = JsCompiler::new();
+        let js_code = match js_compiler.compile(ast) {
+            Ok(code) => code,
+            Err(e) => return Err(Diagnostic::error(codes::BACKEND_FAILURE, format!("JavaScript compilation error: {}", e)))
+        };
+
+        let web_dir = PathBuf::from(format!("{}_web", app_name));
+
+        // Network components run with TensorFlow.js, loaded after the application
+        let models = compile_to_tfjs(ast, options.weights.as_ref())?;
+        let (tfjs_script, models_script) = if models.is_empty() {
+            (String::new(), String::new())
+        } else {
+            (
+                "\n    <script src=\"https://cdn.jsdelivr.net/npm/@tensorflow/tfjs@4/dist/tf.min.js\"></script>".to_string(),
+                format!("\n    <script src=\"{}_models.js\"></script>", app_name),
+            )
+        };
+        // Text inputs are tokenized in the browser as the compiler does
+        let text_inputs = text_inputs(ast);
+        let models_script = if text_inputs.is_empty() {
+            models_script
+        } else {
+            format!("{}\n    <script src=\"{}_tokenizer.js\"></script>", models_script, app_name)
+        };
+
+        // Create a basic HTML file
+        let html_content = format!(r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>{} - GaiaScript Web App</title>
+    <style>
+        body {{ font-family: Arial, sans-serif; margin: 0; padding: 20px; }}
+        .container {{ max-width: 1200px; margin: 0 auto; padding: 1rem; }}
+        header {{ background-color: #f0f0f0; padding: 1rem; border-radius: 4px; margin-bottom: 1rem; }}
+        h1 {{ margin-top: 0; }}
+    </style>
+</head>
+<body>
+    <div class="container">
+        <header>
+            <h1>{} - GaiaScript App</h1>
+        </header>
+        <div id="app"></div>
+    </div>{}
+    <script src="{}.js"></script>{}
+</body>
+</html>"#, app_name, app_name, tfjs_script, app_name, models_script);
+
+        let mut artifacts = vec![
+            Artifact::text(web_dir.join(format!("{}.js", app_name)), js_code),
+            Artifact::text(web_dir.join("index.html"), html_content),
+        ];
+        if !models.is_empty() {
+            artifacts.push(Artifact::text(web_dir.join(format!("{}_models.js", app_name)), tfjs_compiler::loader_js(&models, app_name)));
+            artifacts.push(Artifact::new(web_dir.join(format!("{}.safetensors", app_name)), safetensors::encode(&models.weights)));
+        }
+        for model in models.models {
+            let model_dir = web_dir.join("models").join(&model.directory);
+            let model_json = serde_json::to_string(&model.model_json).map_err(|e| e.to_string())?;
+            artifacts.push(Artifact::text(model_dir.join("model.json"), model_json));
+            artifacts.push(Artifact::new(model_dir.join(tfjs_compiler::WEIGHTS_FILE), model.weights));
+        }
+        if !text_inputs.is_empty() {
+            artifacts.push(Artifact::text(web_dir.join(format!("{}_tokenizer.js", app_name)), tokenizer::tokenizer_js(&text_inputs, app_name)));
+        }
+        for (path, contents) in text_inputs.iter().flat_map(|input| input.files()) {
+            artifacts.push(Artifact::text(web_dir.join(path), contents));
+        }
+        Ok(artifacts)
+    }
+
+    fn next_steps(&self, output_dir: &str, options: &BackendOptions) -> Vec<String> {
+        let web_dir = format!("{}/{}_web", output_dir, options.app_name);
+        vec![
+            format!("Generated Pure JavaScript Web application at {}", web_dir),
+            "To serve the application:".to_string(),
+            format!("  cd {}", web_dir),
+            "  python -m http.server 8000  # or any other web server".to_string(),
+            "  Open http://localhost:8000 in your browser".to_string(),
+            "Network components load from models/ with TensorFlow.js; pass --weights=FILE to ship trained weights".to_string(),
+            "Text inputs with a tokenizer: gaiaTokenizer.encode(component, text) gives the ids to feed them".to_string(),
+        ]
+    }
+}
+
+/// React single-page application
+pub struct ReactBackend;
+
+impl Backend for ReactBackend {
+    fn name(&self) -> &'static str {
+        "react"
+    }
+
+    fn description(&self) -> &'static str {
+        "React.js single-page application"
+    }
+
+    fn capabilities(&self) -> &'static [Capability] {
+        &[Capability::UserInterface]
+    }
+
+    fn compile(&self, ast: &ASTNode, options: &BackendOptions) -> Result<Vec<Artifact>, Diagnostic> {
+        let app_name = &options.app_name;
+
+        // Use the React compiler
+        let mut react_compiler = ReactCompiler::new();
+        let react_code = match react_compiler.compile(ast) {
+            Ok(code) => code,
+            Err(e) => return Err(Diagnostic::error(codes::BACKEND_FAILURE, format!("React compilation error: {}", e)))
+        };
+
+        let react_dir = PathBuf::from(format!("{}_react", app_name));
+        let src_dir = react_dir.join("src");
+        let public_dir = react_dir.join("public");
+
+        let app_css_content = r#"
+.App {
+  text-align: center;
+}
+
+.App-header {
+  background-color: #282c34;
+  min-height: 100vh;
+  display: flex;
+  flex-direction: column;
+  align-items: center;
+  justify-content: center;
+  font-size: calc(10px + 2vmin);
+  color: white;
+}
+
+.App-link {
+  color: #61dafb;
+}
+"#;
+
+        let index_js_content = r#"
+import React from 'react';
+import ReactDOM from 'react-dom/client';
+import './index.css';
+import App from './App';
+
+const root = ReactDOM.createRoot(document.getElementById('root'));
+root.render(
+  <React.StrictMode>
+    <App />
+  </React.StrictMode>
+);
+"#;
+
+        let index_css_content = r#"
+body {
+  margin: 0;
+  font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', 'Roboto', 'Oxygen',
+    'Ubuntu', 'Cantarell', 'Fira Sans', 'Droid Sans', 'Helvetica Neue',
+    sans-serif;
+  -webkit-font-smoothing: antialiased;
+  -moz-osx-font-smoothing: grayscale;
+}
+
+code {
+  font-family: source-code-pro, Menlo, Monaco, Consolas, 'Courier New',
+    monospace;
+}
+"#;
+
+        let index_html_content = format!(r##"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8" />
+    <meta name="viewport" content="width=device-width, initial-scale=1" />
+    <meta name="theme-color" content="#000000" />
+    <meta name="description" content="Web app created using GaiaScript" />
+    <title>{} - GaiaScript React App</title>
+  </head>
+  <body>
+    <noscript>You need to enable JavaScript to run this app.</noscript>
+    <div id="root"></div>
+  </body>
+</html>"##, app_name);
+
+        let package_json_content = format!(r#"{{
+  "name": "{}",
+  "version": "0.1.0",
+  "private": true,
+  "dependencies": {{
+    "react": "^18.2.0",
+    "react-dom": "^18.2.0",
+    "react-scripts": "5.0.1"
+  }},
+  "scripts": {{
+    "start": "react-scripts start",
+    "build": "react-scripts build",
+    "test": "react-scripts test",
+    "eject": "react-scripts eject"
+  }},
+  "eslintConfig": {{
+    "extends": [
+      "react-app"
+    ]
+  }},
+  "browserslist": {{
+    "production": [
+      ">0.2%",
+      "not dead",
+      "not op_mini all"
+    ],
+    "development": [
+      "last 1 chrome version",
+      "last 1 firefox version",
+      "last 1 safari version"
+    ]
+  }}
+}}"#, app_name.to_lowercase().replace(" ", "-"));
+
+        let readme_content = format!(r#"# {} React App
+
+This is a React application generated from GaiaScript.
+
+## Available Scripts
+
+In the project directory, you can run:
+
+### `npm start`
+
+Runs the app in the development mode.\
+Open [http://localhost:3000](http://localhost:3000) to view it in your browser.
+
+### `npm run build`
+
+Builds the app for production to the `build` folder.
+
+## Learn More
+
+This app was generated using GaiaScript's Universal Compiler with React support.
+"#, app_name);
+
+        Ok(vec![
+            Artifact::text(src_dir.join("App.js"), react_code),
+            Artifact::text(src_dir.join("App.css"), app_css_content),
+            Artifact::text(src_dir.join("index.js"), index_js_content),
+            Artifact::text(src_dir.join("index.css"), index_css_content),
+            Artifact::text(public_dir.join("index.html"), index_html_content),
+            Artifact::text(react_dir.join("package.json"), package_json_content),
+            Artifact::text(react_dir.join("README.md"), readme_content),
+        ])
+    }
+
+    fn next_steps(&self, output_dir: &str, options: &BackendOptions) -> Vec<String> {
+        let react_dir = format!("{}/{}_react", output_dir, options.app_name);
+        vec![
+            format!("Generated React application at {}", react_dir),
+            "To run the application:".to_string(),
+            format!("  cd {}", react_dir),
+            "  npm install".to_string(),
+            "  npm start".to_string(),
+        ]
+    }
+}
+
+/// Android application written in Kotlin
+pub struct AndroidBackend;
+
+impl Backend for AndroidBackend {
+    fn name(&self) -> &'static str {
+        "android"
+    }
+
+    fn description(&self) -> &'static str {
+        "Android application (Kotlin, Android SDK views)"
+    }
+
+    fn platforms(&self) -> &'static [Platform] {
+        &[Platform::Android]
+    }
+
+    fn capabilities(&self) -> &'static [Capability] {
+        &[Capability::UserInterface, Capability::NeuralNetwork]
+    }
+
+    fn compile(&self, ast: &ASTNode, options: &BackendOptions) -> Result<Vec<Artifact>, Diagnostic> {
+        let mut compiler = AndroidCompiler::new();
+
+        // Compile the AST to Kotlin code
+        let kotlin_code = compiler.compile(ast)?;
+
+        let mut artifacts = vec![Artifact::text(format!("{}.kt", options.app_name), kotlin_code)];
+        artifacts.extend(kotlin_weights(ast, options)?);
+        artifacts.extend(kotlin_tokenizers(ast));
+        Ok(artifacts)
+    }
+
+    fn next_steps(&self, output_dir: &str, options: &BackendOptions) -> Vec<String> {
+        vec![
+            format!("Generated Kotlin code at {}/{}.kt", output_dir, options.app_name),
+            "You can build this into an Android app using Android Studio.".to_string(),
+            format!("Network weights: copy {}.safetensors into app/src/main/assets and read it with GaiaWeights.load", options.app_name),
+            "Text inputs with a tokenizer: copy tokenizers/ into the assets too and encode text with GaiaTokenizer.load".to_string(),
+        ]
+    }
+}
+
+/// Jetpack Compose Kotlin source
+pub struct KotlinBackend;
+
+impl Backend for KotlinBackend {
+    fn name(&self) -> &'static str {
+        "kotlin"
+    }
+
+    fn description(&self) -> &'static str {
+        "Kotlin source using Jetpack Compose"
+    }
+
+    fn capabilities(&self) -> &'static [Capability] {
+        &[Capability::UserInterface, Capability::NeuralNetwork, Capability::ThreeD]
+    }
+
+    fn compile(&self, ast: &ASTNode, options: &BackendOptions) -> Result<Vec<Artifact>, Diagnostic> {
+        let mut compiler = KotlinCompiler::new();
+        compiler.set_block_mode(options.blocks);
+        let kotlin_code = compiler.compile(ast)?;
+
+        let mut artifacts = vec![Artifact::text(format!("{}.kt", options.app_name), kotlin_code)];
+        artifacts.extend(kotlin_weights(ast, options)?);
+        artifacts.extend(kotlin_tokenizers(ast));
+        Ok(artifacts)
+    }
+}
+
+/// Assembly output through the `AsmCompiler`
+pub struct AsmBackend {
+    target: AsmTarget,
+}
+
+impl AsmBackend {
+    pub fn new(target: AsmTarget) -> Self {
+        AsmBackend { target }
+    }
+}
+
+impl Backend for AsmBackend {
+    fn name(&self) -> &'static str {
+        self.target.name()
+    }
+
+    fn description(&self) -> &'static str {
+        match self.target {
+            AsmTarget::X86_64 => "x86-64 NASM assembly with runtime support file",
+            AsmTarget::ARM64 => "AArch64 GNU assembly with NEON runtime support file",
+            AsmTarget::WASM => "WebAssembly text format with float32 runtime kernels",
+            AsmTarget::WASMUI => "WebAssembly text format (UI components)",
+        }
+    }
+
+    fn capabilities(&self) -> &'static [Capability] {
+        match self.target {
+            AsmTarget::WASMUI => &[Capability::UserInterface],
+            _ => &[Capability::NeuralNetwork, Capability::NativeCode],
+        }
+    }
+
+    fn compile(&self, ast: &ASTNode, options: &BackendOptions) -> Result<Vec<Artifact>, Diagnostic> {
+        // WASMUI imports its memory, so only the other targets embed weights;
+        // quantized components take the place of the float weights
+        let mut compiler = match self.target {
+            AsmTarget::WASMUI => AsmCompiler::new(self.target),
+            _ if !options.quantized.is_empty() => AsmCompiler::with_quantized(self.target, &options.quantized),
+            _ => match shipped_weights(ast, options)? {
+                Some(weights) => AsmCompiler::with_weights(self.target, &weights),
+                None => AsmCompiler::new(self.target),
+            },
+        };
+        compiler.set_block_mode(options.blocks);
+        let code = compiler.compile(ast);
+
+        let mut artifacts = Vec::new();
+        match self.target {
+            AsmTarget::X86_64 => {
+                artifacts.push(Artifact::text(format!("{}_x86_64.s", options.app_name), code));
+                artifacts.push(Artifact::text(
+                    "gaia_runtime_x86_64.s",
+                    include_str!("asm_runtime_x86_64.s"),
+                ));
+            },
+            AsmTarget::ARM64 => {
+                artifacts.push(Artifact::text(format!("{}_arm64.s", options.app_name), code));
+                artifacts.push(Artifact::text(
+                    "gaia_runtime_arm64.s",
+                    include_str!("asm_runtime_arm64.s"),
+                ));
+            },
+            AsmTarget::WASM | AsmTarget::WASMUI => {
+                artifacts.push(Artifact::text(format!("{}.wat", options.app_name), code));
+            },
+        }
+
+        Ok(artifacts)
+    }
+
+    fn next_steps(&self, output_dir: &str, options: &BackendOptions) -> Vec<String> {
+        match self.target {
+            AsmTarget::X86_64 => vec![
+                format!("Generated x86-64 assembly in {}", output_dir),
+                "To build a program calling the network:".to_string(),
+                format!("  nasm -f elf64 {}_x86_64.s && nasm -f elf64 gaia_runtime_x86_64.s", options.app_name),
+                format!("  cc main.c {}_x86_64.o gaia_runtime_x86_64.o -lm", options.app_name),
+                "Each component is void component_<id>(const float *in, float *out), System V ABI".to_string(),
+            ],
+            AsmTarget::ARM64 => vec![
+                format!("Generated AArch64 assembly in {}", output_dir),
+                "To build a program calling the network:".to_string(),
+                format!("  as {}_arm64.s -o {}_arm64.o && as gaia_runtime_arm64.s -o gaia_runtime_arm64.o", options.app_name, options.app_name),
+                format!("  cc main.c {}_arm64.o gaia_runtime_arm64.o -lm", options.app_name),
+                "Each component is void component_<id>(const float *in, float *out), AAPCS64".to_string(),
+            ],
+            AsmTarget::WASM => vec![
+                format!("Generated WebAssembly in {}", output_dir),
+                "To build a module for the browser or any WASM engine:".to_string(),
+                format!("  wat2wasm {}.wat -o {}.wasm", options.app_name, options.app_name),
+                "forward(in, out) takes byte offsets of float32 tensors in the exported memory".to_string(),
+            ],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// ONNX models of the network, one file per component and composite
+pub struct OnnxBackend;
+
+impl Backend for OnnxBackend {
+    fn name(&self) -> &'static str {
+        "onnx"
+    }
+
+    fn description(&self) -> &'static str {
+        "ONNX models with inferred shapes and initial weights"
+    }
+
+    fn capabilities(&self) -> &'static [Capability] {
+        &[Capability::NeuralNetwork]
+    }
+
+    fn compile(&self, ast: &ASTNode, options: &BackendOptions) -> Result<Vec<Artifact>, Diagnostic> {
+        let models = onnx::export_network(ast, options.weights.as_ref())?;
+        if models.is_empty() {
+            return Err(Diagnostic::error(codes::BACKEND_FAILURE, "the program declares no network layers to export"));
+        }
+
+        let artifacts = models.iter()
+            .map(|(name, model)| {
+                let file_name: String = name.chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect();
+                Artifact::new(format!("{}_{}.onnx", options.app_name, file_name), model.encode())
+            })
+            .collect();
+        Ok(artifacts)
+    }
+
+    fn next_steps(&self, output_dir: &str, options: &BackendOptions) -> Vec<String> {
+        vec![
+            format!("Generated ONNX models at {}/{}_*.onnx", output_dir, options.app_name),
+            "Run them with onnxruntime or inspect them with Netron.".to_string(),
+        ]
+    }
+}
+
+/// PyTorch modules and loss functions for the network, as plain Python
+pub struct PyTorchBackend;
+
+impl Backend for PyTorchBackend {
+    fn name(&self) -> &'static str {
+        "pytorch"
+    }
+
+    fn description(&self) -> &'static str {
+        "PyTorch nn.Module per component and loss functions"
+    }
+
+    fn capabilities(&self) -> &'static [Capability] {
+        &[Capability::NeuralNetwork]
+    }
+
+    fn compile(&self, ast: &ASTNode, options: &BackendOptions) -> Result<Vec<Artifact>, Diagnostic> {
+        let code = compile_to_pytorch(ast, &options.app_name)?;
+        Ok(vec![Artifact::text(format!("{}_model.py", options.app_name), code)])
+    }
+
+    fn next_steps(&self, output_dir: &str, options: &BackendOptions) -> Vec<String> {
+        vec![
+            format!("Generated PyTorch modules at {}/{}_model.py", output_dir, options.app_name),
+            format!("Create them with `from {}_model import build; models = build()`", options.app_name),
+        ]
+    }
+}
@@ -1,4 +1,3 @@
-use gaiascript::ast::*;
 use gaiascript::asm_compiler::*;
 use gaiascript::parser::parse as parse_gaiascript;
 use std::env;
@@ -20,9 +19,7 @@ fn main() -> io::Result<()> {
         args[2].clone()
     } else {
         // Default output file is in web directory
-        let input_path = Path::new(input_file);
-        let file_stem = input_path.file_stem().unwrap_or_default().to_string_lossy();
-        format!("web/gaiaui.wasm")
+        "web/gaiaui.wasm".to_string()
     };
     
     println!("Building GaiaUI WebAssembly module from: {}", input_file);
@@ -75,7 +72,7 @@ fn main() -> io::Result<()> {
     if !output.status.success() {
         eprintln!("Error converting WAT to WASM:");
         io::stderr().write_all(&output.stderr)?;
-        return Err(io::Error::new(io::ErrorKind::Other, "wat2wasm failed"));
+        return Err(io::Error::other("wat2wasm failed"));
     }
     
     // Try to optimize with wasm-opt if available
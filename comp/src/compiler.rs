@@ -0,0 +1,46 @@
+use crate::ast::ASTNode;
+use std::fmt::Write;
+
+/// Pure JavaScript for the web target: the application script mounts into
+/// `#app` and lists the components of the network, which run with the
+/// TensorFlow.js models loaded after it
+#[derive(Default)]
+pub struct JsCompiler {
+    output_code: String,
+}
+
+impl JsCompiler {
+    pub fn new() -> Self {
+        JsCompiler::default()
+    }
+
+    pub fn compile(&mut self, ast: &ASTNode) -> Result<String, String> {
+        let components: Vec<String> = match ast {
+            ASTNode::Network(network) => network.body.iter()
+                .filter_map(|node| match node {
+                    ASTNode::Component(component) => Some(component.to_string()),
+                    _ => None,
+                })
+                .collect(),
+            ASTNode::Component(component) => vec![component.to_string()],
+            _ => Vec::new(),
+        };
+        let components = serde_json::to_string(&components).map_err(|e| e.to_string())?;
+
+        self.output_code.clear();
+        writeln!(self.output_code, "// GaiaScript web application").unwrap();
+        writeln!(self.output_code, "const gaiaComponents = {};", components).unwrap();
+        writeln!(self.output_code).unwrap();
+        writeln!(self.output_code, "document.addEventListener('DOMContentLoaded', () => {{").unwrap();
+        writeln!(self.output_code, "  const app = document.getElementById('app');").unwrap();
+        writeln!(self.output_code, "  const list = document.createElement('ul');").unwrap();
+        writeln!(self.output_code, "  for (const component of gaiaComponents) {{").unwrap();
+        writeln!(self.output_code, "    const item = document.createElement('li');").unwrap();
+        writeln!(self.output_code, "    item.textContent = component;").unwrap();
+        writeln!(self.output_code, "    list.appendChild(item);").unwrap();
+        writeln!(self.output_code, "  }}").unwrap();
+        writeln!(self.output_code, "  app.appendChild(list);").unwrap();
+        writeln!(self.output_code, "}});").unwrap();
+        Ok(self.output_code.clone())
+    }
+}
@@ -1,4 +1,4 @@
-use crate::ast::{ASTNode, LayerType, SymbolTable, NetworkNode};
+use crate::ast::{ASTNode, NetworkNode};
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
@@ -13,19 +13,25 @@ pub struct AndroidCompiler {
     imports: Vec<String>,
     state_variables: HashMap<String, String>,
     event_handlers: Vec<String>,
-    indentation_level: usize,
+}
+
+impl Default for AndroidCompiler {
+    fn default() -> Self {
+        AndroidCompiler::new()
+    }
 }
 
 impl AndroidCompiler {
     pub fn new() -> Self {
-        let mut imports = Vec::new();
         // Core imports required for Android applications
-        imports.push("import android.app.Activity".to_string());
-        imports.push("import android.os.Bundle".to_string());
-        imports.push("import android.view.View".to_string());
-        imports.push("import android.widget.*".to_string());
-        imports.push("import androidx.appcompat.app.AppCompatActivity".to_string());
-        imports.push("import androidx.constraintlayout.widget.ConstraintLayout".to_string());
+        let imports = vec![
+            "import android.app.Activity".to_string(),
+            "import android.os.Bundle".to_string(),
+            "import android.view.View".to_string(),
+            "import android.widget.*".to_string(),
+            "import androidx.appcompat.app.AppCompatActivity".to_string(),
+            "import androidx.constraintlayout.widget.ConstraintLayout".to_string(),
+        ];
         
         AndroidCompiler {
             output_code: String::new(),
@@ -34,7 +40,6 @@ impl AndroidCompiler {
             imports,
             state_variables: HashMap::new(),
             event_handlers: Vec::new(),
-            indentation_level: 0,
         }
     }
     
@@ -86,7 +91,7 @@ impl AndroidCompiler {
     }
     
     /// Compile a network node to Kotlin code
-    fn compile_network(&mut self, network: &NetworkNode) -> Result<(), String> {
+    fn compile_network(&mut self, _network: &NetworkNode) -> Result<(), String> {
         // First, compile a model we can use
         self.compile_model()?;
         
@@ -170,18 +175,6 @@ impl AndroidCompiler {
         Ok(component_code)
     }
     
-    /// Simplified statement compilation method
-    fn compile_statement(&mut self, name: &str) -> Result<(), String> {
-        // Add a simple function implementation
-        let func_code = format!("\n    private fun {}() {{\n        // Placeholder implementation\n    }}\n", 
-            self.kotlin_variable_name(name));
-        
-        // Add to event handlers
-        self.event_handlers.push(func_code);
-        
-        Ok(())
-    }
-    
     /// Assemble the final application combining all components
     fn assemble_application(&self) -> Result<String, String> {
         let mut app_code = String::new();
@@ -193,18 +186,18 @@ impl AndroidCompiler {
         for import in &self.imports {
             app_code.push_str(&format!("{}\n", import));
         }
-        app_code.push_str("\n");
+        app_code.push('\n');
         
         // Add model implementations
         for model in &self.model_implementations {
             app_code.push_str(model);
-            app_code.push_str("\n");
+            app_code.push('\n');
         }
         
         // Add UI components
         for component in &self.ui_components {
             app_code.push_str(component);
-            app_code.push_str("\n");
+            app_code.push('\n');
         }
         
         Ok(app_code)
@@ -253,7 +246,7 @@ impl AndroidCompiler {
         fs::write(&kotlin_path, &self.output_code).map_err(|e| format!("Failed to write Kotlin file: {}", e))?;
         
         // Create AndroidManifest.xml
-        let manifest_content = format!(r#"<?xml version="1.0" encoding="utf-8"?>
+        let manifest_content = r#"<?xml version="1.0" encoding="utf-8"?>
 <manifest xmlns:android="http://schemas.android.com/apk/res/android"
     package="com.gaiascript.app">
 
@@ -274,7 +267,7 @@ impl AndroidCompiler {
         </activity>
     </application>
 
-</manifest>"#);
+</manifest>"#;
         
         let manifest_path = app_dir.join("src/main/AndroidManifest.xml");
         fs::write(&manifest_path, manifest_content).map_err(|e| format!("Failed to write AndroidManifest.xml: {}", e))?;
@@ -445,67 +438,4 @@ This Android application was generated by GaiaScript Android Compiler.
         // Return success
         Ok(())
     }
-    
-    /// Helper functions for Kotlin naming conventions
-    fn kotlin_class_name(&self, name: &str) -> String {
-        let mut class_name = String::new();
-        let mut capitalize_next = true;
-        
-        for c in name.chars() {
-            if !c.is_alphanumeric() {
-                capitalize_next = true;
-                continue;
-            }
-            
-            if capitalize_next {
-                class_name.push(c.to_ascii_uppercase());
-                capitalize_next = false;
-            } else {
-                class_name.push(c);
-            }
-        }
-        
-        // Ensure the class name starts with an uppercase letter
-        if !class_name.is_empty() && !class_name.chars().next().unwrap().is_uppercase() {
-            class_name = format!("{}{}", 
-                class_name.chars().next().unwrap().to_ascii_uppercase(),
-                &class_name[1..]);
-        }
-        
-        // If the class name is empty, use a default
-        if class_name.is_empty() {
-            class_name = "GaiaModel".to_string();
-        }
-        
-        class_name
-    }
-    
-    fn kotlin_variable_name(&self, name: &str) -> String {
-        let class_name = self.kotlin_class_name(name);
-        
-        // Convert to camelCase (first letter lowercase)
-        if !class_name.is_empty() {
-            format!("{}{}", 
-                class_name.chars().next().unwrap().to_ascii_lowercase(),
-                &class_name[1..])
-        } else {
-            "gaiaVariable".to_string()
-        }
-    }
-    
-    /// Simplified helper for Kotlin type names
-    fn value_to_kotlin_type(&self, value_name: &str) -> String {
-        match value_name {
-            "integer" => "Int".to_string(),
-            "float" => "Float".to_string(),
-            "boolean" => "Boolean".to_string(),
-            "string" => "String".to_string(),
-            _ => "Any".to_string(),
-        }
-    }
-    
-    /// Simplified helper for Kotlin literal values
-    fn value_to_kotlin_string(&self, value: &str) -> String {
-        format!("\"{}\"", value)
-    }
 }
\ No newline at end of file
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use crate::ast::*;
 use crate::extensions::ui_extensions::*;
 use crate::extensions::three_extensions::*;
+use crate::layer_params::{FILTERS, KERNEL, SIZE, UNITS};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -196,25 +197,25 @@ class _{}State extends State<{}> {{
         // Convert layer type and parameters to Flutter/TensorFlow
         let (layer_class, layer_params) = match &layer.layer_type {
             LayerType::Convolutional(idx) => {
-                let filters = if !layer.params.is_empty() { layer.params[0] } else { 32 };
-                let kernel_size = if layer.params.len() > 1 { layer.params[1] } else { 3 };
+                let filters = layer.param(FILTERS);
+                let kernel_size = layer.param(KERNEL);
                 
                 ("Conv2D", format!("filters: {}, kernelSize: {}, index: {}", 
                     filters, kernel_size, idx))
             },
             LayerType::Dense(idx) => {
-                let units = if !layer.params.is_empty() { layer.params[0] } else { 128 };
+                let units = layer.param(UNITS);
                 
                 ("Dense", format!("units: {}, index: {}", units, idx))
             },
             LayerType::Pooling => {
-                let size = if !layer.params.is_empty() { layer.params[0] } else { 2 };
+                let size = layer.param(SIZE);
                 
                 ("MaxPooling2D", format!("poolSize: {}", size))
             },
             LayerType::Flatten => ("Flatten", "".to_string()),
             LayerType::LSTM => {
-                let units = if !layer.params.is_empty() { layer.params[0] } else { 128 };
+                let units = layer.param(UNITS);
                 
                 ("LSTM", format!("units: {}", units))
             },
@@ -338,7 +339,7 @@ connectLayers({}, {});
 
 // Loss function
 final {} = createLoss('{}', '{}');
-        ", from_dart, loss_var, loss.to, loss.function);
+        ", from_dart, loss_var, loss.to(), loss.function);
         
         Ok(dart_code)
     }
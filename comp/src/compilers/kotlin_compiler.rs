@@ -2,6 +2,11 @@ use std::collections::HashMap;
 use crate::ast::*;
 use crate::extensions::ui_extensions::*;
 use crate::extensions::three_extensions::*;
+use crate::diagnostics::{codes, Diagnostic};
+use crate::interpreter::Weights;
+use crate::safetensors;
+use crate::shape_inference::input_shape;
+use crate::layer_params::{FILTERS, KERNEL, SIZE, UNITS};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -13,11 +18,27 @@ pub enum KotlinCompilerError {
     CompilationError(String),
 }
 
+impl From<KotlinCompilerError> for Diagnostic {
+    fn from(error: KotlinCompilerError) -> Self {
+        match &error {
+            KotlinCompilerError::UndefinedComponent(_) => Diagnostic::error(codes::UNKNOWN_COMPONENT, error.to_string()),
+            KotlinCompilerError::CompilationError(_) => Diagnostic::error(codes::BACKEND_FAILURE, error.to_string()),
+        }
+        .with_note("reported by the Kotlin backend")
+    }
+}
+
 pub struct KotlinCompiler {
     symbol_table: SymbolTable,
     kotlin_classes: HashMap<String, String>,
-    kotlin_functions: HashMap<String, String>,
     unique_id_counter: usize,
+    blocks: BlockMode,
+}
+
+impl Default for KotlinCompiler {
+    fn default() -> Self {
+        KotlinCompiler::new()
+    }
 }
 
 impl KotlinCompiler {
@@ -25,11 +46,16 @@ impl KotlinCompiler {
         KotlinCompiler {
             symbol_table: SymbolTable::new(),
             kotlin_classes: HashMap::new(),
-            kotlin_functions: HashMap::new(),
             unique_id_counter: 0,
+            blocks: BlockMode::Loop,
         }
     }
     
+    /// Choose between a loop and unrolled copies for blocks
+    pub fn set_block_mode(&mut self, mode: BlockMode) {
+        self.blocks = mode;
+    }
+    
     pub fn compile(&mut self, node: &ASTNode) -> Result<String, KotlinCompilerError> {
         match node {
             ASTNode::Network(network) => self.compile_network(network),
@@ -77,7 +103,7 @@ impl KotlinCompiler {
         for node in &network.body {
             let node_kt = self.compile(node)?;
             body_kt.push_str(&node_kt);
-            body_kt.push_str("\n");
+            body_kt.push('\n');
         }
         
         // Create a Kotlin class with all compiled components
@@ -159,25 +185,25 @@ class {} {{
         // Convert layer type and parameters to Kotlin
         let (layer_type, layer_params) = match &layer.layer_type {
             LayerType::Convolutional(idx) => {
-                let filters = if !layer.params.is_empty() { layer.params[0] } else { 32 };
-                let kernel_size = if layer.params.len() > 1 { layer.params[1] } else { 3 };
+                let filters = layer.param(FILTERS);
+                let kernel_size = layer.param(KERNEL);
                 
                 ("Conv2D", format!("filters = {}, kernelSize = {}, index = {}", 
                     filters, kernel_size, idx))
             },
             LayerType::Dense(idx) => {
-                let units = if !layer.params.is_empty() { layer.params[0] } else { 128 };
+                let units = layer.param(UNITS);
                 
                 ("Dense", format!("units = {}, index = {}", units, idx))
             },
             LayerType::Pooling => {
-                let size = if !layer.params.is_empty() { layer.params[0] } else { 2 };
+                let size = layer.param(SIZE);
                 
                 ("MaxPooling", format!("size = {}", size))
             },
             LayerType::Flatten => ("Flatten", "".to_string()),
             LayerType::LSTM => {
-                let units = if !layer.params.is_empty() { layer.params[0] } else { 128 };
+                let units = layer.param(UNITS);
                 
                 ("LSTM", format!("units = {}", units))
             },
@@ -204,6 +230,36 @@ class {} {{
     }
     
     fn compile_block(&mut self, block: &BlockNode) -> Result<String, KotlinCompilerError> {
+        // Layers of a shared block are created once, and every later repetition
+        // feeds the output of the previous one back through them
+        if block.shared {
+            let content_kt = self.compile(&block.content)?;
+            let shared_id = self.get_unique_id("shared");
+            let output_id = self.get_unique_id("repeated");
+            return Ok(format!("
+// Repeat the block {} times with shared weights; the first repetition is the block itself
+val {} = run {{
+    {}
+    lastCreated()
+}}
+var {} = {}
+for (repetition in 2..{}) {{
+    {} = applyShared({}, {})
+}}
+        ", block.repetitions, shared_id, content_kt, output_id, shared_id,
+               block.repetitions, output_id, shared_id, output_id));
+        }
+        
+        // Otherwise every repetition creates its own layers
+        if self.blocks == BlockMode::Unroll {
+            let mut unrolled = String::new();
+            for iteration in 0..block.repetitions.max(1) {
+                let content_kt = self.compile(&block.content)?;
+                unrolled.push_str(&format!("\n// Repetition {} of {}\n{}\n", iteration + 1, block.repetitions, content_kt));
+            }
+            return Ok(unrolled);
+        }
+        
         // Compile the block content
         let content_kt = self.compile(&block.content)?;
         
@@ -223,18 +279,9 @@ val {} = (1..{}).map {{ {}() }}
     }
     
     fn compile_input(&mut self, input: &InputNode) -> Result<String, KotlinCompilerError> {
-        // Determine input shape based on type
-        let shape = if input.params.is_empty() {
-            match input.input_type {
-                InputType::Image => "[1, 224, 224, 3]".to_string(),
-                InputType::Text => "[1, 128]".to_string(),
-                InputType::Sequence => "[1, 100]".to_string(),
-                InputType::Latent => "[1, 100]".to_string(),
-            }
-        } else {
-            let shape_str: Vec<String> = input.params.iter().map(|p| p.to_string()).collect();
-            format!("[{}]", shape_str.join(", "))
-        };
+        // A batch of one example of the declared shape, or the default one
+        let (shape, _) = input_shape(input);
+        let dims: Vec<String> = std::iter::once(1).chain(shape.dims).map(|d| d.to_string()).collect();
         
         // Determine input type
         let input_type = match input.input_type {
@@ -248,8 +295,8 @@ val {} = (1..{}).map {{ {}() }}
         let input_var = self.get_unique_id("input");
         
         // Generate the Kotlin code
-        let kt_code = format!("val {} = {}(shape = intArrayOf{})", 
-            input_var, input_type, shape);
+        let kt_code = format!("val {} = {}(shape = intArrayOf({}))", 
+            input_var, input_type, dims.join(", "));
         
         Ok(kt_code)
     }
@@ -299,7 +346,7 @@ connectLayers({}, {})
 
 // Loss function
 val {} = createLoss(\"{}\", \"{}\")
-        ", from_kt, loss_var, loss.to, loss.function);
+        ", from_kt, loss_var, loss.to(), loss.function);
         
         Ok(kt_code)
     }
@@ -310,7 +357,7 @@ val {} = createLoss(\"{}\", \"{}\")
         for node in nodes {
             let node_kt = self.compile(node)?;
             kt_code.push_str(&node_kt);
-            kt_code.push_str("\n");
+            kt_code.push('\n');
         }
         
         Ok(kt_code)
@@ -428,4 +475,61 @@ val {} = loadAsset(
         
         Ok(kt_code)
     }
-}
\ No newline at end of file
+}
+const WEIGHTS_READER: &str = r#"// Generated by the GaiaScript compiler: reads the network weights from a
+// safetensors container, e.g. GaiaWeights.load(assets.open("app.safetensors"))
+import java.io.InputStream
+import java.nio.ByteBuffer
+import java.nio.ByteOrder
+import org.json.JSONObject
+
+class GaiaTensor(val shape: IntArray, val data: FloatArray)
+
+object GaiaWeights {
+    fun load(stream: InputStream): Map<String, GaiaTensor> = parse(stream.use { it.readBytes() })
+
+    fun parse(bytes: ByteArray): Map<String, GaiaTensor> {
+        val buffer = ByteBuffer.wrap(bytes).order(ByteOrder.LITTLE_ENDIAN)
+        val headerSize = buffer.getLong(0).toInt()
+        val header = JSONObject(String(bytes, 8, headerSize, Charsets.UTF_8))
+        val start = 8 + headerSize
+        val tensors = mutableMapOf<String, GaiaTensor>()
+        for (name in header.keys()) {
+            if (name == "__metadata__") continue
+            val entry = header.getJSONObject(name)
+            require(entry.getString("dtype") == "F32") { "weight $name is not F32" }
+            val dims = entry.getJSONArray("shape")
+            val shape = IntArray(dims.length()) { dims.getInt(it) }
+            val offsets = entry.getJSONArray("data_offsets")
+            val begin = start + offsets.getLong(0).toInt()
+            val count = (offsets.getLong(1) - offsets.getLong(0)).toInt() / 4
+            tensors[name] = GaiaTensor(shape, FloatArray(count) { buffer.getFloat(begin + 4 * it) })
+        }
+        validate(tensors)
+        return tensors
+    }
+
+    /** Check every parameter of the network against its inferred shape */
+    fun validate(tensors: Map<String, GaiaTensor>) {
+        for ((name, shape) in SHAPES) {
+            val tensor = tensors[name] ?: throw IllegalArgumentException("missing weight $name")
+            require(tensor.shape.contentEquals(shape)) {
+                "weight $name has shape ${tensor.shape.contentToString()}, expected ${shape.contentToString()}"
+            }
+        }
+    }
+"#;
+
+/// Kotlin `GaiaWeights` object that loads and validates the network weights
+/// written by `safetensors::encode`
+pub fn weights_loader(weights: &Weights) -> String {
+    let mut code = WEIGHTS_READER.to_string();
+    code.push_str("\n    /** Parameters of the network and their shapes */\n");
+    code.push_str("    val SHAPES: Map<String, IntArray> = mapOf(\n");
+    for (key, tensor) in weights.iter() {
+        let dims: Vec<String> = tensor.shape.iter().map(|d| d.to_string()).collect();
+        code.push_str(&format!("        \"{}\" to intArrayOf({}),\n", safetensors::qualified_name(key), dims.join(", ")));
+    }
+    code.push_str("    )\n}\n");
+    code
+}
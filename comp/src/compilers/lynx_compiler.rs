@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use crate::ast::*;
 use crate::extensions::ui_extensions::*;
 use crate::extensions::three_extensions::*;
+use crate::layer_params::{FILTERS, KERNEL, SIZE, UNITS};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -326,26 +327,26 @@ export class {} {{{{
         // Convert layer type and parameters to LynxJS neural - optimized
         let (layer_type, layer_params) = match &layer.layer_type {
             LayerType::Convolutional(_idx) => {
-                let filters = if !layer.params.is_empty() { layer.params[0] } else { 32 };
-                let _kernel_size = if layer.params.len() > 1 { layer.params[1] } else { 3 };
+                let filters = layer.param(FILTERS);
+                let _kernel_size = layer.param(KERNEL);
                 
                 ("N.Conv2D", format!("{{f:{},k:[3,3],a:'{}'}}", 
                     filters, self.get_activation_name(&layer.activation)))
             },
             LayerType::Dense(_idx) => {
-                let units = if !layer.params.is_empty() { layer.params[0] } else { 128 };
+                let units = layer.param(UNITS);
                 
                 ("N.Dense", format!("{{u:{},a:'{}'}}", 
                     units, self.get_activation_name(&layer.activation)))
             },
             LayerType::Pooling => {
-                let size = if !layer.params.is_empty() { layer.params[0] } else { 2 };
+                let size = layer.param(SIZE);
                 
                 ("N.MaxPooling2D", format!("{{p:{}}}", size))
             },
             LayerType::Flatten => ("N.Flatten", "{}".to_string()),
             LayerType::LSTM => {
-                let units = if !layer.params.is_empty() { layer.params[0] } else { 128 };
+                let units = layer.param(UNITS);
                 
                 ("N.LSTM", format!("{{u:{},r:1}}", units))
             },
@@ -442,7 +443,7 @@ N.connect({},{});", from_var, from_lynx, to_var, to_lynx, from_var, to_var);
         
         // Generate the LynxJS code - optimized
         let lynx_code = format!("{}
-const {}=N.loss('{}','{}')", from_lynx, loss_var, loss.to, loss.function);
+const {}=N.loss('{}','{}')", from_lynx, loss_var, loss.to(), loss.function);
         
         Ok(lynx_code)
     }
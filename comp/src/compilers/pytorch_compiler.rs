@@ -0,0 +1,437 @@
+use crate::ast::*;
+use crate::composite::{self, Composite};
+use crate::custom_ops::{self, SnippetContext};
+use crate::interpreter::{plan_network, Plan, Step};
+use crate::layer_params::{CAUSAL, FACTOR, HEADS, KERNEL, STRIDE, target_shape, VOCAB, Window};
+use std::collections::{HashMap, HashSet};
+
+const HEADER: &str = "\
+import torch
+import torch.nn as nn
+import torch.nn.functional as F
+
+
+def gradient_norm(output, input, p=2):
+    \"\"\"Mean norm of the gradient of `output` with respect to `input`\"\"\"
+    grad, = torch.autograd.grad(output.sum(), input, create_graph=True)
+    return grad.flatten(1).norm(p, dim=1).mean()
+
+
+def weight_norm(modules, p=2):
+    \"\"\"Sum of absolute values (p=1) or squares (p=2) of the weights; biases are not regularized\"\"\"
+    weights = [w for m in modules for name, w in m.named_parameters() if not name.endswith(\"bias\")]
+    return sum(w.abs().sum() if p == 1 else w.pow(2).sum() for w in weights)
+
+
+def positional_encoding(steps, dim):
+    \"\"\"Sinusoidal encoding added by `+P`: sin at even features, cos at odd ones\"\"\"
+    angle = torch.arange(steps).unsqueeze(1) / 10000 ** (torch.arange(0, dim, 2) / dim)
+    encoding = torch.zeros(steps, dim)
+    encoding[:, 0::2] = torch.sin(angle)
+    encoding[:, 1::2] = torch.cos(angle[:, :dim // 2])
+    return encoding
+";
+
+/// Translate every network component into a PyTorch `nn.Module` and every
+/// declared loss into a loss function, as one Python file
+///
+/// Images are NCHW as usual in PyTorch; dense layers act on the last axis, so
+/// they see channels-last images like in the interpreter, and images are
+/// flattened and reshaped in channels-last order to keep the interpreter's
+/// weight layout. Components joined by
+/// `≜` share modules in `build()`.
+pub fn compile_to_pytorch(ast: &ASTNode, app_name: &str) -> Result<String, String> {
+    let composites = composite::composites(ast)?;
+    // A component fed by another one is sized for the injected input
+    let mut injected: HashMap<String, Plan> = HashMap::new();
+    for composite in &composites {
+        for stage in &composite.stages {
+            injected.entry(stage.component.clone()).or_insert_with(|| stage.plan.clone());
+        }
+    }
+    let plans: Vec<Plan> = plan_network(ast)?.into_iter()
+        .map(|plan| injected.get(&plan.component).cloned().unwrap_or(plan))
+        .collect();
+    
+    let mut out = format!("# Generated by the GaiaScript compiler from `{}`; do not edit.\n{}", app_name, HEADER);
+    for plan in &plans {
+        let component = composite::find_component(ast, &plan.component)?;
+        out.push_str("\n\n");
+        out.push_str(&ModuleWriter::new(plan).write(component)?);
+    }
+    for composite in &composites {
+        out.push_str("\n\n");
+        out.push_str(&write_loss(composite)?);
+    }
+    out.push_str("\n\n");
+    out.push_str(&write_build(ast, &plans));
+    Ok(out)
+}
+
+// Module attribute of a step: its parameter key without the component, e.g.
+// `c1_1`, or `swish` for `⇝swish`
+fn attribute(step: &Step) -> String {
+    let local = step.key.split_once('.').map(|(_, rest)| rest).unwrap_or(&step.key);
+    identifier(&local.trim_start_matches(custom_ops::MARKER).to_lowercase(), false)
+}
+
+// `padding` of a PyTorch convolution or pooling module for `window`, and its
+// input: even padding goes to the module, uneven padding is added with
+// `F.pad` first, filled with `fill`
+fn torch_padding(window: &Window, input: &TensorShape, fill: &str) -> (String, String) {
+    let ((top, bottom), (left, right)) = (window.padding(input.dims[0]), window.padding(input.dims[1]));
+    if top == bottom && left == right {
+        (format!("({}, {})", top, left), "x".to_string())
+    } else {
+        ("0".to_string(), format!("F.pad(x, ({}, {}, {}, {}), value={})", left, right, top, bottom, fill))
+    }
+}
+
+// Batched PyTorch shape of a per-example shape, e.g. `(N, 32, 28, 28)`
+fn torch_shape(shape: &TensorShape) -> String {
+    let dims: Vec<String> = match shape.dims.as_slice() {
+        [h, w, c] => vec![c, h, w].into_iter().map(|d| d.to_string()).collect(),
+        dims => dims.iter().map(|d| d.to_string()).collect(),
+    };
+    format!("(N, {})", dims.join(", "))
+}
+
+// `x` flattened per example in the interpreter's channels-last order
+fn flattened(input: &TensorShape) -> &'static str {
+    if input.rank() == 3 { "torch.flatten(x.permute(0, 2, 3, 1), 1)" } else { "torch.flatten(x, 1)" }
+}
+
+// Flat examples `y` viewed as `output`, filling images channels-last
+fn unflattened(y: &str, output: &TensorShape) -> String {
+    match output.dims.as_slice() {
+        [h, w, c] => format!("{}.view(-1, {}, {}, {}).permute(0, 3, 1, 2)", y, h, w, c),
+        _ => format!("{}.view{}", y, torch_shape(output).replace("N", "-1")),
+    }
+}
+
+struct ModuleWriter<'a> {
+    plan: &'a Plan,
+    // Next step of the plan, in the order the expression is visited
+    cursor: usize,
+    init: Vec<String>,
+    forward: Vec<String>,
+    // Heads of the last `H`, used by the following attention layer
+    heads: usize,
+    // Attributes already declared in `__init__`
+    declared: HashSet<String>,
+}
+
+impl<'a> ModuleWriter<'a> {
+    fn new(plan: &'a Plan) -> Self {
+        ModuleWriter { plan, cursor: 0, init: Vec::new(), forward: Vec::new(), heads: 1, declared: HashSet::new() }
+    }
+    
+    fn write(mut self, component: &ComponentNode) -> Result<String, String> {
+        self.visit(&component.expr)?;
+        let name = identifier(&self.plan.component, false);
+        let mut out = format!("class {}(nn.Module):\n", name);
+        out.push_str(&format!("    \"\"\"{}: {} → {}\"\"\"\n\n", self.plan.component, self.plan.input, self.plan.output()));
+        out.push_str("    def __init__(self):\n        super().__init__()\n");
+        for line in &self.init {
+            out.push_str(&format!("        {}\n", line));
+        }
+        out.push_str(&format!("\n    def forward(self, x):\n        # x: {}\n", torch_shape(&self.plan.input)));
+        for line in &self.forward {
+            out.push_str(&format!("        {}\n", line));
+        }
+        out.push_str("        return x\n");
+        Ok(out)
+    }
+    
+    // Mirrors the order in which the plan builder visits the expression
+    fn visit(&mut self, node: &ASTNode) -> Result<(), String> {
+        match node {
+            ASTNode::Layer(_) => {
+                let plan = self.plan;
+                let step = plan.steps.get(self.cursor)
+                    .ok_or_else(|| format!("`{}` has more layers than its plan", self.plan.component))?;
+                self.cursor += 1;
+                self.step(step)?;
+            },
+            ASTNode::DataFlow(from, to) => {
+                self.visit(from)?;
+                self.visit(to)?;
+            },
+            ASTNode::Expression(nodes) => {
+                for child in nodes {
+                    self.visit(child)?;
+                }
+            },
+            ASTNode::Block(block) => {
+                self.forward.push(format!("# {}", block));
+                for _ in 0..block.repetitions.max(1) {
+                    self.visit(&block.content)?;
+                }
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+    
+    fn step(&mut self, step: &Step) -> Result<(), String> {
+        let layer = &step.layer;
+        let (input, output) = (&step.input, &step.output);
+        let name = attribute(step);
+        let module = format!("self.{}", name);
+        let init = self.init.len();
+        
+        let y = match layer.layer_type {
+            LayerType::Convolutional(_) => {
+                let window = layer.window().unwrap();
+                if window.stride == 1 && window.same {
+                    self.init.push(format!("{} = nn.Conv2d({}, {}, {}, padding=\"same\")", module, input.dims[2], output.dims[2], window.size));
+                    format!("{}(x)", module)
+                } else {
+                    let (padding, x) = torch_padding(&window, input, "0.0");
+                    self.init.push(format!("{} = nn.Conv2d({}, {}, {}, stride={}, padding={})",
+                                           module, input.dims[2], output.dims[2], window.size, window.stride, padding));
+                    format!("{}({})", module, x)
+                }
+            },
+            LayerType::TransposeConv => {
+                let k = layer.param(KERNEL);
+                let s = layer.param(STRIDE);
+                // Output exactly `s` times the input
+                let padding = k.saturating_sub(s).div_ceil(2);
+                let output_padding = s + 2 * padding - k.min(s + 2 * padding);
+                self.init.push(format!("{} = nn.ConvTranspose2d({}, {}, {}, stride={}, padding={}, output_padding={})",
+                                       module, input.dims[2], output.dims[2], k, s, padding, output_padding));
+                format!("{}(x)", module)
+            },
+            LayerType::Pooling => {
+                let window = layer.window().unwrap();
+                if window.stride == window.size && !window.same {
+                    self.init.push(format!("{} = nn.MaxPool2d({})", module, window.size));
+                    format!("{}(x)", module)
+                } else {
+                    let (padding, x) = torch_padding(&window, input, "float(\"-inf\")");
+                    self.init.push(format!("{} = nn.MaxPool2d({}, stride={}, padding={})", module, window.size, window.stride, padding));
+                    format!("{}({})", module, x)
+                }
+            },
+            LayerType::Flatten => flattened(input).to_string(),
+            LayerType::Dense(_) => {
+                self.init.push(format!("{} = nn.Linear({}, {})", module, input.dims[input.rank() - 1], output.dims[output.rank() - 1]));
+                if input.rank() == 3 {
+                    format!("{}(x.permute(0, 2, 3, 1)).permute(0, 3, 1, 2)", module)
+                } else {
+                    format!("{}(x)", module)
+                }
+            },
+            LayerType::Upsampling if target_shape(layer).is_some() => {
+                self.init.push(format!("{} = nn.Linear({}, {})", module, input.elements(), output.elements()));
+                unflattened(&format!("{}({})", module, flattened(input)), output)
+            },
+            LayerType::Upsampling => {
+                let factor = layer.param(FACTOR);
+                self.init.push(format!("{} = nn.Upsample(scale_factor={}, mode=\"nearest\")", module, factor));
+                format!("{}(x)", module)
+            },
+            LayerType::Reshape if target_shape(layer).is_some() && (input.rank() == 3 || output.rank() == 3) => {
+                unflattened(flattened(input), output)
+            },
+            LayerType::Reshape if target_shape(layer).is_some() => {
+                format!("x.reshape{}", torch_shape(output).replace("N", "-1"))
+            },
+            LayerType::LSTM => {
+                self.init.push(format!("{} = nn.LSTM({}, {}, batch_first=True)", module, input.dims[1], output.dims[0]));
+                // The last hidden state
+                self.forward.push(format!("_, (h, _) = {}(x)", module));
+                "h[-1]".to_string()
+            },
+            LayerType::Embedding => {
+                let vocabulary = layer.param(VOCAB);
+                let dim = output.dims[output.rank() - 1];
+                self.init.push(format!("{} = nn.Embedding({}, {})", module, vocabulary, dim));
+                if layer.positional {
+                    self.init.push(format!("self.register_buffer(\"{}_encoding\", positional_encoding({}, {}))", name, input.dims[0], dim));
+                    format!("{}(x) + {}_encoding", module, module)
+                } else {
+                    format!("{}(x)", module)
+                }
+            },
+            LayerType::AttentionHeads => {
+                self.heads = layer.param(HEADS).max(1);
+                "x".to_string()
+            },
+            LayerType::Attention => {
+                let dim = input.dims[input.rank() - 1];
+                let heads = if dim % self.heads == 0 { self.heads } else { 1 };
+                self.init.push(format!("{} = nn.MultiheadAttention({}, {}, batch_first=True)", module, dim, heads));
+                if layer.param(CAUSAL) == 1 {
+                    // True above the diagonal hides later positions
+                    self.forward.push(format!("mask = torch.ones({0}, {0}, dtype=torch.bool, device=x.device).triu(1)", input.dims[0]));
+                    self.forward.push(format!("x, _ = {}(x, x, x, attn_mask=mask)", module));
+                } else {
+                    self.forward.push(format!("x, _ = {}(x, x, x)", module));
+                }
+                "x".to_string()
+            },
+            LayerType::Reshape | LayerType::BatchSize => "x".to_string(),
+            LayerType::Custom(ref op) => {
+                let params = layer.resolved_params();
+                for (param, shape) in op.parameter_shapes(input, output, &params) {
+                    let dims: Vec<String> = shape.iter().map(|d| d.to_string()).collect();
+                    self.init.push(format!("{}_{} = nn.Parameter(torch.zeros({}))", module, param, dims.join(", ")));
+                }
+                let context = SnippetContext { backend: "pytorch", params: &params, input, output, name: &module };
+                op.snippet(&context).ok_or_else(|| format!("`{}` has no PyTorch snippet", layer.symbol()))?
+            },
+        };
+        // Repetitions of a shared block call the module of the first
+        if !self.declared.insert(name) {
+            self.init.truncate(init);
+        }
+        
+        let y = match layer.activation {
+            ActivationFunction::ReLU => format!("torch.relu({})", y),
+            ActivationFunction::Sigmoid => format!("torch.sigmoid({})", y),
+            ActivationFunction::Tanh => format!("torch.tanh({})", y),
+            ActivationFunction::Softmax => {
+                format!("torch.softmax({}, dim={})", y, if output.rank() == 3 { 1 } else { -1 })
+            },
+            ActivationFunction::None => y,
+        };
+        if y != "x" {
+            self.forward.push(format!("x = {}  # {}", y, torch_shape(output)));
+        }
+        Ok(())
+    }
+}
+
+// Loss function of a composite: runs the chain on `x` and compares the result with `target`
+fn write_loss(composite: &Composite) -> Result<String, String> {
+    let modules: Vec<String> = composite.stages.iter().map(|s| identifier(&s.component, false)).collect();
+    let mut coefficients: Vec<String> = Vec::new();
+    for term in &composite.loss.terms {
+        if let Some(Coefficient::Symbol(symbol)) = &term.coefficient {
+            let symbol = identifier(symbol, false);
+            if !coefficients.contains(&symbol) {
+                coefficients.push(symbol);
+            }
+        }
+    }
+    // Inputs of components whose gradient is penalized must track gradients
+    let penalized: Vec<usize> = composite.loss.terms.iter()
+        .filter_map(|t| match &t.term {
+            LossTerm::GradientPenalty { component, .. } => composite.stages.iter().position(|s| &s.component == component),
+            _ => None,
+        })
+        .collect();
+    
+    // A component can appear twice in a chain but is passed once
+    let mut unique: Vec<String> = Vec::new();
+    for module in &modules {
+        if !unique.contains(module) {
+            unique.push(module.clone());
+        }
+    }
+    
+    let name = identifier(&composite.name, false);
+    let mut args = unique.clone();
+    args.extend(["x".to_string(), "target".to_string()]);
+    args.extend(coefficients.iter().cloned());
+    let mut out = format!("def loss_{}({}):\n", name, args.join(", "));
+    out.push_str(&format!("    \"\"\"{}\"\"\"\n", composite));
+    let mut x = "x".to_string();
+    for (i, module) in modules.iter().enumerate() {
+        if penalized.contains(&i) && i == 0 {
+            out.push_str("    x = x.requires_grad_()\n");
+        }
+        out.push_str(&format!("    h{} = {}({})\n", i + 1, module, x));
+        x = format!("h{}", i + 1);
+    }
+    out.push_str(&format!("    y = {}\n", x));
+    
+    let mut sum = String::new();
+    for (i, term) in composite.loss.terms.iter().enumerate() {
+        let value = match &term.term {
+            LossTerm::Base(BaseLoss::MeanSquaredError) => "F.mse_loss(y, target)".to_string(),
+            LossTerm::Base(BaseLoss::BinaryCrossEntropy) => "F.binary_cross_entropy(y, target)".to_string(),
+            LossTerm::Base(BaseLoss::CrossEntropy) => "-(target * y.clamp_min(1e-7).log()).sum(dim=-1).mean()".to_string(),
+            // 0/1 targets are mapped to -1/1
+            LossTerm::Base(BaseLoss::Hinge) => "(1 - torch.where(target == 0, -1.0, target) * y).clamp_min(0).mean()".to_string(),
+            LossTerm::Base(BaseLoss::KullbackLeibler) => "F.kl_div(y.clamp_min(1e-7).log(), target, reduction=\"batchmean\")".to_string(),
+            LossTerm::Regularizer { norm, component } => {
+                let targets = match component {
+                    Some(id) => identifier(id, false),
+                    None => unique.join(", "),
+                };
+                format!("weight_norm([{}], p={})", targets, norm_order(norm))
+            },
+            LossTerm::GradientPenalty { component, norm } => {
+                let stage = composite.stages.iter().position(|s| &s.component == component)
+                    .ok_or_else(|| format!("`{}` penalizes the gradient of `{}`, which is not part of the chain", composite.name, component))?;
+                let input = if stage == 0 { "x".to_string() } else { format!("h{}", stage) };
+                format!("gradient_norm(h{}, {}, p={})", stage + 1, input, norm_order(norm))
+            },
+        };
+        let value = match &term.coefficient {
+            Some(coefficient) => format!("{} * {}", python_coefficient(coefficient), value),
+            None => value,
+        };
+        sum.push_str(&match (i, term.negated) {
+            (0, false) => value,
+            (0, true) => format!("-{}", value),
+            (_, false) => format!(" + {}", value),
+            (_, true) => format!(" - {}", value),
+        });
+    }
+    out.push_str(&format!("    return {}\n", sum));
+    
+    // Frozen components still run, but their parameters are left to the caller
+    let trained: Vec<&String> = unique.iter()
+        .filter(|module| !modules.iter().zip(&composite.stages).any(|(m, stage)| m == *module && stage.frozen))
+        .collect();
+    out.push_str(&format!("\n\ndef parameters_{}({}):\n", name, unique.join(", ")));
+    out.push_str(&format!("    \"\"\"Parameters trained by `loss_{}`; frozen (⊘) components are left out\"\"\"\n", name));
+    let lists: Vec<String> = trained.iter().map(|m| format!("list({}.parameters())", m)).collect();
+    out.push_str(&format!("    return {}\n", if lists.is_empty() { "[]".to_string() } else { lists.join(" + ") }));
+    Ok(out)
+}
+
+fn norm_order(norm: &Norm) -> u8 {
+    match norm {
+        Norm::L1 => 1,
+        Norm::L2 => 2,
+    }
+}
+
+fn python_coefficient(coefficient: &Coefficient) -> String {
+    match coefficient {
+        Coefficient::Value(value) => format!("{:?}", value),
+        Coefficient::Symbol(symbol) => identifier(symbol, false),
+    }
+}
+
+// `build()` creates one module per component and ties shared parameters (`E≜D`)
+fn write_build(ast: &ASTNode, plans: &[Plan]) -> String {
+    let mut out = String::from("def build():\n    \"\"\"One module per component\"\"\"\n    models = {\n");
+    for plan in plans {
+        out.push_str(&format!("        \"{}\": {}(),\n", plan.component, identifier(&plan.component, false)));
+    }
+    out.push_str("    }\n");
+    for plan in plans {
+        let owner = composite::parameter_owner(ast, &plan.component);
+        let Some(owner_plan) = plans.iter().find(|p| p.component == owner && owner != plan.component) else {
+            continue;
+        };
+        // Adaptive inputs can size the two components differently
+        if owner_plan.parameter_shapes() != plan.parameter_shapes() {
+            out.push_str(&format!("    # {}≜{} is not tied: the injected input gives them different sizes\n", plan.component, owner));
+            continue;
+        }
+        out.push_str(&format!("    # {}≜{}\n", plan.component, owner));
+        for step in plan.steps.iter().filter(|s| !s.parameter_shapes().is_empty()) {
+            let name = attribute(step);
+            out.push_str(&format!("    models[\"{}\"].{} = models[\"{}\"].{}\n", plan.component, name, owner, name));
+        }
+    }
+    out.push_str("    return models\n");
+    out
+}
@@ -1,4 +1,5 @@
-use crate::ast::{ASTNode, SymbolTable};
+use crate::ast::ASTNode;
+use crate::diagnostics::{codes, Diagnostic};
 use std::fmt;
 
 #[derive(Debug)]
@@ -16,11 +17,27 @@ impl fmt::Display for CompilerError {
     }
 }
 
+impl From<CompilerError> for Diagnostic {
+    fn from(error: CompilerError) -> Self {
+        match &error {
+            CompilerError::UnsupportedNode(_) => Diagnostic::error(codes::UNSUPPORTED_NODE, error.to_string()),
+            CompilerError::InternalError(_) => Diagnostic::error(codes::BACKEND_FAILURE, error.to_string()),
+        }
+        .with_note("reported by the React backend")
+    }
+}
+
 pub struct ReactCompiler {
     pub imports: Vec<String>,
     pub output_code: String,
 }
 
+impl Default for ReactCompiler {
+    fn default() -> Self {
+        ReactCompiler::new()
+    }
+}
+
 impl ReactCompiler {
     pub fn new() -> Self {
         ReactCompiler {
@@ -32,7 +49,7 @@ impl ReactCompiler {
         }
     }
     
-    pub fn compile(&mut self, ast: &ASTNode) -> Result<String, String> {
+    pub fn compile(&mut self, _ast: &ASTNode) -> Result<String, String> {
         let mut output = String::new();
         
         // Add imports
@@ -61,7 +78,7 @@ impl ReactCompiler {
         Ok(output)
     }
     
-    pub fn generate_project(&self, app_name: &str, output_dir: &str) -> Result<(), String> {
+    pub fn generate_project(&self, _app_name: &str, _output_dir: &str) -> Result<(), String> {
         // Stub implementation
         Ok(())
     }
@@ -0,0 +1,389 @@
+use crate::ast::*;
+use crate::interpreter::{plan_network, Plan, Step, Tensor, Weights};
+use crate::safetensors;
+use crate::layer_params::{FACTOR, KERNEL, STRIDE, target_shape, Window};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// File name of the single weight shard next to each `model.json`
+pub const WEIGHTS_FILE: &str = "group1-shard1of1.bin";
+
+/// One network component as a TensorFlow.js layers model
+#[derive(Debug, Clone)]
+pub struct TfjsModel {
+    pub component: String,
+    // Directory of the model, an ASCII spelling of the component id
+    pub directory: String,
+    pub input: Vec<usize>,
+    pub output: Vec<usize>,
+    /// `model.json`: the Keras topology and the weights manifest
+    pub model_json: Value,
+    /// Little-endian float32 weights in manifest order
+    pub weights: Vec<u8>,
+}
+
+/// Models of every network component; components using layers that
+/// TensorFlow.js cannot express are listed in `skipped` with the reason
+#[derive(Debug, Clone, Default)]
+pub struct TfjsExport {
+    pub models: Vec<TfjsModel>,
+    pub skipped: Vec<(String, String)>,
+    /// Parameters of every component, for the safetensors file next to the models
+    pub weights: Weights,
+}
+
+impl TfjsExport {
+    pub fn is_empty(&self) -> bool {
+        self.models.is_empty() && self.skipped.is_empty()
+    }
+}
+
+/// Translate every network component into a Keras `Sequential` topology that
+/// `tf.loadLayersModel` reads, with its weights in one binary shard
+///
+/// TensorFlow.js is channels-last like the interpreter, so convolution and
+/// dense kernels are stored as they are; only transposed convolution kernels
+/// are swapped to `[k, k, out, in]`. Parameters missing from `weights` are seeded.
+pub fn compile_to_tfjs(ast: &ASTNode, weights: Option<&Weights>) -> Result<TfjsExport, String> {
+    let plans = plan_network(ast)?;
+    let merged = Weights::for_export(&plans, weights);
+    
+    let mut export = TfjsExport::default();
+    for plan in &plans {
+        match export_plan(plan, &merged) {
+            Ok(model) => export.models.push(model),
+            Err(reason) => export.skipped.push((plan.component.clone(), reason)),
+        }
+    }
+    export.weights = merged;
+    Ok(export)
+}
+
+/// Export one component as a layers model
+pub fn export_plan(plan: &Plan, weights: &Weights) -> Result<TfjsModel, String> {
+    let directory = identifier(&plan.component, true);
+    let mut builder = TopologyBuilder { weights, layers: Vec::new(), manifest: Vec::new(), data: Vec::new(), uses: HashMap::new() };
+    
+    let mut batch_shape = vec![Value::Null];
+    batch_shape.extend(plan.input.dims.iter().map(|&d| json!(d)));
+    builder.layers.push(json!({
+        "class_name": "InputLayer",
+        "config": { "name": "input", "batch_input_shape": batch_shape, "dtype": "float32", "sparse": false },
+    }));
+    for step in &plan.steps {
+        builder.step(step)?;
+    }
+    
+    let model_json = json!({
+        "format": "layers-model",
+        "generatedBy": "GaiaScript",
+        "convertedBy": null,
+        "modelTopology": {
+            "class_name": "Sequential",
+            "config": { "name": directory, "layers": builder.layers },
+            "keras_version": "tfjs-layers 4.0.0",
+            "backend": "tensor_flow.js",
+        },
+        "weightsManifest": [{ "paths": [WEIGHTS_FILE], "weights": builder.manifest }],
+    });
+    Ok(TfjsModel {
+        component: plan.component.clone(),
+        directory,
+        input: plan.input.dims.clone(),
+        output: plan.output().dims.clone(),
+        model_json,
+        weights: builder.data,
+    })
+}
+
+fn keras_activation(activation: &ActivationFunction) -> &'static str {
+    match activation {
+        ActivationFunction::ReLU => "relu",
+        ActivationFunction::Sigmoid => "sigmoid",
+        ActivationFunction::Tanh => "tanh",
+        ActivationFunction::Softmax => "softmax",
+        ActivationFunction::None => "linear",
+    }
+}
+
+fn keras_padding(window: &Window) -> &'static str {
+    if window.same { "same" } else { "valid" }
+}
+
+struct TopologyBuilder<'a> {
+    weights: &'a Weights,
+    layers: Vec<Value>,
+    manifest: Vec<Value>,
+    data: Vec<u8>,
+    // Steps seen per layer name
+    uses: HashMap<String, usize>,
+}
+
+impl<'a> TopologyBuilder<'a> {
+    fn layer(&mut self, class_name: &str, name: &str, mut config: Value) {
+        config["name"] = json!(name);
+        config["trainable"] = json!(true);
+        self.layers.push(json!({ "class_name": class_name, "config": config }));
+    }
+    
+    fn tensor(&self, key: &str) -> Result<&'a Tensor, String> {
+        self.weights.get(key).ok_or_else(|| format!("missing weight `{}`", key))
+    }
+    
+    // Append values to the shard under a Keras weight name
+    fn push(&mut self, name: String, key: &str, values: &[f32], shape: Vec<usize>) -> Result<(), String> {
+        if values.len() != shape.iter().product::<usize>() {
+            return Err(format!("weight `{}` has {} values, expected shape {:?}", key, values.len(), shape));
+        }
+        self.data.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+        self.manifest.push(json!({ "name": name, "shape": shape, "dtype": "float32" }));
+        Ok(())
+    }
+    
+    fn weight(&mut self, name: String, key: &str, shape: Vec<usize>) -> Result<(), String> {
+        let tensor = self.tensor(key)?;
+        self.push(name, key, &tensor.data, shape)
+    }
+    
+    fn kernel_and_bias(&mut self, name: &str, step: &Step, kernel: Vec<usize>, units: usize) -> Result<(), String> {
+        self.weight(format!("{}/kernel", name), &format!("{}.weight", step.key), kernel)?;
+        self.weight(format!("{}/bias", name), &format!("{}.bias", step.key), vec![units])
+    }
+    
+    // Flatten or reshape to `dims`, or nothing when the shape is unchanged
+    fn reshape(&mut self, name: &str, from: &[usize], dims: &[usize]) {
+        if from == dims {
+            return;
+        }
+        if dims.len() == 1 {
+            self.layer("Flatten", name, json!({}));
+        } else {
+            self.layer("Reshape", name, json!({ "target_shape": dims }));
+        }
+    }
+    
+    fn step(&mut self, step: &Step) -> Result<(), String> {
+        let layer = &step.layer;
+        let local = step.key.split_once('.').map(|(_, rest)| rest).unwrap_or(&step.key);
+        let mut name = identifier(&local.to_lowercase(), true);
+        // A sequential model cannot call a layer twice, so repetitions of a
+        // shared block become copies holding the same weights
+        let uses = self.uses.entry(name.clone()).or_insert(0);
+        *uses += 1;
+        if *uses > 1 {
+            name = format!("{}_r{}", name, uses);
+        }
+        let c_in = step.input.dims.last().copied().unwrap_or(1);
+        let c_out = step.output.dims.last().copied().unwrap_or(1);
+        let activation = keras_activation(&layer.activation);
+        
+        // Dense and convolution layers apply the activation themselves
+        let fused = match layer.layer_type {
+            LayerType::Dense(_) => {
+                self.layer("Dense", &name, json!({ "units": c_out, "activation": activation, "use_bias": true }));
+                self.kernel_and_bias(&name, step, vec![c_in, c_out], c_out)?;
+                true
+            },
+            LayerType::Convolutional(_) => {
+                let window = layer.window().unwrap();
+                let k = window.size;
+                self.layer("Conv2D", &name, json!({
+                    "filters": c_out, "kernel_size": [k, k], "strides": [window.stride, window.stride], "padding": keras_padding(&window),
+                    "data_format": "channels_last", "dilation_rate": [1, 1], "activation": activation, "use_bias": true,
+                }));
+                self.kernel_and_bias(&name, step, vec![k, k, c_in, c_out], c_out)?;
+                true
+            },
+            LayerType::TransposeConv => {
+                let k = layer.param(KERNEL);
+                let stride = layer.param(STRIDE);
+                self.layer("Conv2DTranspose", &name, json!({
+                    "filters": c_out, "kernel_size": [k, k], "strides": [stride, stride], "padding": "same",
+                    "data_format": "channels_last", "activation": activation, "use_bias": true,
+                }));
+                // `[k, k, in, out]` to `[k, k, out, in]`
+                let key = format!("{}.weight", step.key);
+                let kernel = self.tensor(&key)?;
+                let mut swapped = vec![0.0; kernel.len()];
+                for (i, v) in kernel.data.iter().enumerate() {
+                    let (kk, ci, co) = (i / (c_in * c_out), i / c_out % c_in, i % c_out);
+                    swapped[(kk * c_out + co) * c_in + ci] = *v;
+                }
+                self.push(format!("{}/kernel", name), &key, &swapped, vec![k, k, c_out, c_in])?;
+                self.weight(format!("{}/bias", name), &format!("{}.bias", step.key), vec![c_out])?;
+                true
+            },
+            LayerType::Pooling => {
+                let window = layer.window().unwrap();
+                self.layer("MaxPooling2D", &name, json!({
+                    "pool_size": [window.size, window.size], "strides": [window.stride, window.stride],
+                    "padding": keras_padding(&window), "data_format": "channels_last",
+                }));
+                false
+            },
+            LayerType::Upsampling if target_shape(layer).is_some() => {
+                // Projection: a dense layer on the flattened input, then the target shape
+                let (n_in, n_out) = (step.input.elements(), step.output.elements());
+                self.reshape(&format!("{}_flatten", name), &step.input.dims, &[n_in]);
+                self.layer("Dense", &name, json!({ "units": n_out, "activation": activation, "use_bias": true }));
+                self.kernel_and_bias(&name, step, vec![n_in, n_out], n_out)?;
+                self.reshape(&format!("{}_reshape", name), &[n_out], &step.output.dims);
+                true
+            },
+            LayerType::Upsampling => {
+                let factor = layer.param(FACTOR);
+                self.layer("UpSampling2D", &name, json!({
+                    "size": [factor, factor], "interpolation": "nearest", "data_format": "channels_last",
+                }));
+                false
+            },
+            LayerType::Flatten | LayerType::Reshape => {
+                self.reshape(&name, &step.input.dims, &step.output.dims);
+                false
+            },
+            LayerType::LSTM => {
+                // Keras stacks the gates input, forget, cell, output like the interpreter
+                let gates = 4 * c_out;
+                self.layer("LSTM", &name, json!({
+                    "units": c_out, "activation": "tanh", "recurrent_activation": "sigmoid",
+                    "use_bias": true, "return_sequences": false, "return_state": false,
+                }));
+                self.weight(format!("{}/kernel", name), &format!("{}.weight", step.key), vec![c_in, gates])?;
+                self.weight(format!("{}/recurrent_kernel", name), &format!("{}.recurrent", step.key), vec![c_out, gates])?;
+                self.weight(format!("{}/bias", name), &format!("{}.bias", step.key), vec![gates])?;
+                false
+            },
+            LayerType::AttentionHeads | LayerType::BatchSize => false,
+            LayerType::Embedding | LayerType::Attention | LayerType::Custom(_) => {
+                return Err(format!("`{}` has no TensorFlow.js equivalent yet", layer.symbol()));
+            },
+        };
+        if !fused && layer.activation != ActivationFunction::None {
+            self.layer("Activation", &format!("{}_{}", name, activation), json!({ "activation": activation }));
+        }
+        Ok(())
+    }
+}
+
+/// JavaScript that loads the models with TensorFlow.js and runs inference;
+/// when loaded after the application script it replaces the
+/// `gaiaRuntime.network` stub with one that loads every model
+pub fn loader_js(export: &TfjsExport, app_name: &str) -> String {
+    let mut entries = String::new();
+    for model in &export.models {
+        entries.push_str(&format!("  {}: {{ url: \"models/{}/model.json\", input: {:?}, output: {:?} }},\n",
+            json!(model.component), model.directory, model.input, model.output));
+    }
+    for (component, reason) in &export.skipped {
+        entries.push_str(&format!("  {}: {{ unsupported: {} }},\n", json!(component), json!(reason)));
+    }
+    let mut shapes = String::new();
+    for (key, tensor) in export.weights.iter() {
+        shapes.push_str(&format!("  {}: {:?},\n", json!(safetensors::qualified_name(key)), tensor.shape));
+    }
+    
+    format!(r#"// Generated by the GaiaScript compiler for {app}; do not edit
+// Runs the network components with TensorFlow.js, which must be loaded first
+const gaiaModels = {{
+{entries}}};
+const gaiaLoadedModels = {{}};
+
+// Parameters of the network and their shapes, as named in {app}.safetensors
+const gaiaWeightShapes = {{
+{shapes}}};
+
+const gaiaTf = typeof tf !== 'undefined' ? tf : require('@tensorflow/tfjs');
+
+// Load a component once; weights come from the shard next to its model.json
+function gaiaLoadModel(component) {{
+  const entry = gaiaModels[component];
+  if (!entry) {{
+    return Promise.reject(new Error(`unknown network component ${{component}}`));
+  }}
+  if (entry.unsupported) {{
+    return Promise.reject(new Error(`component ${{component}}: ${{entry.unsupported}}`));
+  }}
+  if (!gaiaLoadedModels[component]) {{
+    gaiaLoadedModels[component] = gaiaTf.loadLayersModel(entry.url);
+  }}
+  return gaiaLoadedModels[component];
+}}
+
+// Run one example given as a flat channels-last array; resolves to a flat array
+async function gaiaPredict(component, data) {{
+  const model = await gaiaLoadModel(component);
+  const shape = [1, ...gaiaModels[component].input];
+  const output = gaiaTf.tidy(() => model.predict(gaiaTf.tensor(Array.from(data), shape)));
+  const values = await output.data();
+  output.dispose();
+  return Array.from(values);
+}}
+
+// Pixels of a canvas resized to the input of an image component, scaled to [0, 1]
+function gaiaCanvasInput(component, canvas) {{
+  const [height, width, channels] = gaiaModels[component].input;
+  return gaiaTf.tidy(() => {{
+    const pixels = gaiaTf.browser.fromPixels(canvas, channels === 1 ? 1 : 3);
+    const resized = gaiaTf.image.resizeBilinear(pixels.toFloat(), [height, width]).div(255);
+    return Array.from(resized.dataSync());
+  }});
+}}
+
+// Read a safetensors file into {{ name: {{ shape, data: Float32Array }} }},
+// checking every parameter against the shapes of the network
+async function gaiaLoadWeights(url) {{
+  const response = await fetch(url);
+  const bytes = await response.arrayBuffer();
+  const view = new DataView(bytes);
+  const headerSize = Number(view.getBigUint64(0, true));
+  const header = JSON.parse(new TextDecoder().decode(new Uint8Array(bytes, 8, headerSize)));
+  const tensors = {{}};
+  for (const [name, entry] of Object.entries(header)) {{
+    if (name === '__metadata__') continue;
+    if (entry.dtype !== 'F32') throw new Error(`weight ${{name}} is ${{entry.dtype}}, not F32`);
+    const [begin, end] = entry.data_offsets;
+    const data = new Float32Array(bytes.slice(8 + headerSize + begin, 8 + headerSize + end));
+    tensors[name] = {{ shape: entry.shape, data }};
+  }}
+  for (const [name, shape] of Object.entries(gaiaWeightShapes)) {{
+    const tensor = tensors[name];
+    if (!tensor) throw new Error(`missing weight ${{name}}`);
+    if (tensor.shape.join() !== shape.join()) {{
+      throw new Error(`weight ${{name}} has shape [${{tensor.shape}}], expected [${{shape}}]`);
+    }}
+  }}
+  return tensors;
+}}
+
+// Index of the largest output, e.g. the recognised digit
+function gaiaArgmax(values) {{
+  return values.reduce((best, v, i) => (v > values[best] ? i : best), 0);
+}}
+
+const gaiaNetwork = {{
+  models: gaiaModels,
+  load: gaiaLoadModel,
+  predict: gaiaPredict,
+  canvasInput: gaiaCanvasInput,
+  loadWeights: gaiaLoadWeights,
+  argmax: gaiaArgmax,
+}};
+
+if (typeof gaiaRuntime !== 'undefined') {{
+  gaiaRuntime.models = gaiaNetwork;
+  gaiaRuntime.network = function() {{
+    const ready = Object.keys(gaiaModels).filter(c => !gaiaModels[c].unsupported);
+    return Promise.all(ready.map(gaiaLoadModel))
+      .then(() => console.log(`GaiaScript network loaded: ${{ready.join(', ')}}`))
+      .catch(error => console.error('GaiaScript network failed to load', error));
+  }};
+  gaiaRuntime.network.call(gaiaRuntime);
+}}
+if (typeof window !== 'undefined') {{
+  window.gaiaNetwork = gaiaNetwork;
+}}
+if (typeof module !== 'undefined') {{
+  module.exports = gaiaNetwork;
+}}
+"#, app = app_name, entries = entries, shapes = shapes)
+}
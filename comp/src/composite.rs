@@ -0,0 +1,217 @@
+use crate::ast::*;
+use crate::diagnostics::{codes, Diagnostic};
+use crate::interpreter::Plan;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// One component of a composite model, in execution order
+#[derive(Debug, Clone)]
+pub struct Stage {
+    pub component: String,
+    // Run in the forward pass, but its parameters are not trained (`⊘`)
+    pub frozen: bool,
+    pub plan: Plan,
+}
+
+/// Components wired into a single graph with `⊳`, such as a generator feeding
+/// a discriminator (`L:G(Z)⊳D⊘⟿BCE`) or an encoder feeding a decoder
+///
+/// Each stage receives the output of the previous one. Parameters belong to
+/// components, not to composites, so a component used by several composites
+/// trains the same weights in all of them.
+#[derive(Debug, Clone)]
+pub struct Composite {
+    // Loss label, or the chain itself such as `G⊳D`
+    pub name: String,
+    pub stages: Vec<Stage>,
+    pub loss: LossExpr,
+}
+
+impl Composite {
+    /// Build the composite a loss declaration describes
+    pub fn from_loss(ast: &ASTNode, loss: &LossNode) -> Result<Self, String> {
+        let mut stages: Vec<Stage> = Vec::new();
+        for id in loss.chain() {
+            let input = stages.last().map(|s| s.plan.output().clone());
+            stages.push(Stage {
+                component: id.to_string(),
+                frozen: loss.is_frozen(id),
+                plan: component_plan(ast, id, input)?,
+            });
+        }
+        if stages.is_empty() {
+            return Err(format!("loss `{}` chains no components", loss.function));
+        }
+        
+        let name = match &loss.label {
+            Some(label) => label.clone(),
+            None => loss.chain().join("⊳"),
+        };
+        Ok(Composite { name, stages, loss: loss.function.clone() })
+    }
+    
+    pub fn input(&self) -> &TensorShape {
+        &self.stages[0].plan.input
+    }
+    
+    pub fn output(&self) -> &TensorShape {
+        self.stages.last().unwrap().plan.output()
+    }
+    
+    pub fn plans(&self) -> Vec<Plan> {
+        self.stages.iter().map(|s| s.plan.clone()).collect()
+    }
+    
+    /// Names and shapes of every parameter, listed once even when stages share them
+    pub fn parameter_shapes(&self) -> Vec<(String, Vec<usize>)> {
+        let mut seen = BTreeSet::new();
+        self.stages.iter()
+            .flat_map(|s| s.plan.parameter_shapes())
+            .filter(|(name, _)| seen.insert(name.clone()))
+            .collect()
+    }
+    
+    /// Parameters used by a frozen stage; a parameter shared with a trained
+    /// stage stays frozen
+    pub fn frozen_parameters(&self) -> BTreeSet<String> {
+        self.stages.iter()
+            .filter(|s| s.frozen)
+            .flat_map(|s| s.plan.parameter_shapes())
+            .map(|(name, _)| name)
+            .collect()
+    }
+}
+
+impl fmt::Display for Composite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.name)?;
+        for (i, stage) in self.stages.iter().enumerate() {
+            if i > 0 {
+                write!(f, "⊳")?;
+            }
+            write!(f, "{}{}", stage.component, if stage.frozen { "⊘" } else { "" })?;
+        }
+        write!(f, "⟿{}", self.loss)
+    }
+}
+
+/// Every composite declared by the program, in source order
+pub fn composites(ast: &ASTNode) -> Result<Vec<Composite>, String> {
+    losses(ast).map(|loss| Composite::from_loss(ast, loss)).collect()
+}
+
+/// The composite of the loss labelled `label`, or of the first loss
+pub fn find(ast: &ASTNode, label: Option<&str>) -> Result<Composite, String> {
+    match losses(ast).find(|loss| label.is_none() || loss.label.as_deref() == label) {
+        Some(loss) => Composite::from_loss(ast, loss),
+        None => match label {
+            Some(label) => Err(format!("no loss labelled `{}`", label)),
+            None => Err("the program declares no loss (`…⊳…⟿…`)".to_string()),
+        },
+    }
+}
+
+/// Component whose parameters `component` runs with, following `≜` declarations
+pub fn parameter_owner(ast: &ASTNode, component: &str) -> String {
+    let mut owner = component.to_string();
+    if let ASTNode::Network(network) = ast {
+        // Bounded so that a cycle such as `A≜B`, `B≜A` terminates
+        for _ in 0..network.shares.len() {
+            match network.shares.iter().find(|s| s.component == owner && s.owner != owner) {
+                Some(share) => owner = share.owner.clone(),
+                None => break,
+            }
+        }
+    }
+    owner
+}
+
+/// Execution plan of a component with shared parameters renamed to their owner;
+/// `input` is the shape injected by a previous stage
+pub fn component_plan(ast: &ASTNode, id: &str, input: Option<TensorShape>) -> Result<Plan, String> {
+    let component = find_component(ast, id)?;
+    let mut plan = match input {
+        Some(input) => Plan::with_input(component, input)?,
+        None => Plan::new(component)?,
+    };
+    let owner = parameter_owner(ast, id);
+    if owner != id {
+        plan.share_parameters(&owner);
+    }
+    Ok(plan)
+}
+
+/// The definition of a component; the last one wins when it is redefined
+pub fn find_component<'a>(ast: &'a ASTNode, id: &str) -> Result<&'a ComponentNode, String> {
+    let mut found = None;
+    if let ASTNode::Network(network) = ast {
+        for node in &network.body {
+            match node {
+                ASTNode::Component(component) if component.id == id => found = Some(component),
+                _ => {}
+            }
+        }
+    }
+    found.ok_or_else(|| format!("unknown component `{}`", id))
+}
+
+/// Check that components joined by `≜` have identical parameters
+pub fn check_shares(ast: &ASTNode) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let network = match ast {
+        ASTNode::Network(network) => network,
+        _ => return diagnostics,
+    };
+    
+    for share in &network.shares {
+        if share.component == share.owner {
+            diagnostics.push(
+                Diagnostic::error(codes::SHAPE_SHARE_MISMATCH, format!("`{}` cannot share parameters with itself", share.component))
+                    .with_primary(share.span, "")
+            );
+            continue;
+        }
+        // Undefined components and shape errors are reported elsewhere
+        let (Ok(component), Ok(owner)) = (find_component(ast, &share.component), find_component(ast, &share.owner)) else {
+            continue;
+        };
+        let (Ok(component), Ok(owner)) = (Plan::new(component), Plan::new(owner)) else {
+            continue;
+        };
+        
+        let strip = |plan: &Plan| -> Vec<(String, Vec<usize>)> {
+            let prefix = format!("{}.", plan.component);
+            plan.parameter_shapes().into_iter()
+                .map(|(name, shape)| (name.strip_prefix(&prefix).unwrap_or(&name).to_string(), shape))
+                .collect()
+        };
+        let (ours, theirs) = (strip(&component), strip(&owner));
+        if ours == theirs {
+            continue;
+        }
+        
+        let mut diagnostic = Diagnostic::error(codes::SHAPE_SHARE_MISMATCH,
+                format!("`{}` cannot share the parameters of `{}`", share.component, share.owner))
+            .with_primary(share.span, "parameters differ");
+        let difference = ours.iter().zip(&theirs).find(|(a, b)| a != b);
+        diagnostic = match difference {
+            Some(((name, shape), (other, other_shape))) => diagnostic.with_note(format!(
+                "`{}.{}` is {:?} but `{}.{}` is {:?}", share.component, name, shape, share.owner, other, other_shape)),
+            None => diagnostic.with_note(format!(
+                "`{}` has {} parameters but `{}` has {}", share.component, ours.len(), share.owner, theirs.len())),
+        };
+        diagnostics.push(diagnostic);
+    }
+    diagnostics
+}
+
+fn losses(ast: &ASTNode) -> impl Iterator<Item = &LossNode> {
+    let body = match ast {
+        ASTNode::Network(network) => network.body.as_slice(),
+        _ => &[],
+    };
+    body.iter().filter_map(|node| match node {
+        ASTNode::Loss(loss) => Some(loss),
+        _ => None,
+    })
+}
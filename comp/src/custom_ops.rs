@@ -0,0 +1,111 @@
+// User-defined layers: `⇝name` in source resolves to a `CustomOp` in the
+// `CustomOpRegistry` given to the parser, so new operations need no change to
+// the grammar
+
+use crate::ast::TensorShape;
+use crate::diagnostics::{codes, Diagnostic, Span};
+use crate::interpreter::Tensor;
+use crate::layer_params::ParamSpec;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Marker written before the symbol of a custom operation
+pub const MARKER: char = '⇝';
+
+/// One use of a custom operation, passed to it when a backend asks for code
+#[derive(Debug, Clone)]
+pub struct SnippetContext<'a> {
+    /// Backend name as given to `--backend`, e.g. `pytorch` or `asm-x86_64`
+    pub backend: &'a str,
+    /// Resolved parameter values in schema order
+    pub params: &'a [(&'static str, usize)],
+    pub input: &'a TensorShape,
+    pub output: &'a TensorShape,
+    /// Name of this use in the generated code, e.g. `self.swish` in PyTorch;
+    /// trainable parameters are `{name}_{parameter}`
+    pub name: &'a str,
+}
+
+/// A layer that is not part of the language, written `⇝symbol`
+///
+/// Only the symbol and the shape function are required. Without `forward` the
+/// interpreter (and so training, quantization and weight checks that run it)
+/// reports the operation as unsupported, and a backend without a snippet
+/// refuses to compile it.
+pub trait CustomOp: Send + Sync {
+    /// Name written after `⇝`, in ASCII letters, digits and `_`
+    fn symbol(&self) -> &'static str;
+    
+    /// Parameters in the order positional values fill them
+    fn params(&self) -> &'static [ParamSpec] {
+        &[]
+    }
+    
+    /// Output shape for an input shape, or why the input is not accepted
+    fn output_shape(&self, input: &TensorShape, params: &[(&'static str, usize)]) -> Result<TensorShape, String>;
+    
+    /// Trainable tensors, named relative to the layer (e.g. `weight`)
+    fn parameter_shapes(&self, _input: &TensorShape, _output: &TensorShape,
+                        _params: &[(&'static str, usize)]) -> Vec<(String, Vec<usize>)> {
+        Vec::new()
+    }
+    
+    /// CPU reference implementation of a single example; `weights` follow
+    /// the order of `parameter_shapes`
+    fn forward(&self, _x: &Tensor, _params: &[(&'static str, usize)], _weights: &[&Tensor]) -> Option<Result<Tensor, String>> {
+        None
+    }
+    
+    /// Code computing the operation in a backend's language: a Python
+    /// expression of `x` for PyTorch, instructions for the assembly targets
+    fn snippet(&self, _context: &SnippetContext) -> Option<String> {
+        None
+    }
+}
+
+impl fmt::Debug for dyn CustomOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", MARKER, self.symbol())
+    }
+}
+
+/// Custom operations a program may use; the parser resolves each `⇝symbol`
+/// against it and stores the operation on the layer for later stages
+#[derive(Clone, Default)]
+pub struct CustomOpRegistry {
+    ops: BTreeMap<&'static str, Arc<dyn CustomOp>>,
+}
+
+impl CustomOpRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        CustomOpRegistry::default()
+    }
+    
+    /// Add an operation, replacing any operation registered with the same symbol
+    pub fn register(&mut self, op: impl CustomOp + 'static) {
+        self.ops.insert(op.symbol(), Arc::new(op));
+    }
+    
+    /// The operation registered as `symbol`
+    pub fn get(&self, symbol: &str) -> Option<Arc<dyn CustomOp>> {
+        self.ops.get(symbol).cloned()
+    }
+    
+    /// Symbols of every registered operation, sorted
+    pub fn symbols(&self) -> Vec<&'static str> {
+        self.ops.keys().copied().collect()
+    }
+    
+    /// Error for a `⇝symbol` that no registered operation answers to
+    pub fn unknown(&self, symbol: &str, span: Span) -> Diagnostic {
+        let diagnostic = Diagnostic::error(codes::UNKNOWN_CUSTOM_OP, format!("unknown custom operation `{}{}`", MARKER, symbol))
+            .with_primary(span, "not registered");
+        match self.symbols().as_slice() {
+            [] => diagnostic.with_note("no custom operations are registered"),
+            registered => diagnostic.with_note(format!("registered operations: {}",
+                registered.iter().map(|s| format!("`{}{}`", MARKER, s)).collect::<Vec<_>>().join(", "))),
+        }
+    }
+}
@@ -0,0 +1,967 @@
+// Training and evaluation data read from files: NumPy arrays (`.npy`, `.npz`),
+// IDX files such as MNIST, CSV, folders of PPM or PNG images and text for
+// tokenized inputs
+
+use crate::ast::{Preprocess, TensorShape};
+use crate::interpreter::{Rng, Tensor};
+use crate::tokenizer::Tokenizer;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Input/target pairs for supervised training
+#[derive(Debug, Clone, Default)]
+pub struct Dataset {
+    pub examples: Vec<(Tensor, Tensor)>,
+}
+
+/// Where the targets come from for formats that do not carry them
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Array of labels for `.npy` and IDX inputs, e.g. `train-labels-idx1-ubyte`
+    pub labels: Option<String>,
+    /// CSV column holding the class index or target, by header name or
+    /// zero-based position
+    pub label_column: Option<String>,
+    /// Tokenizer of a text input, which reads `.tsv` files of `text<TAB>label`
+    pub tokenizer: Option<Arc<Tokenizer>>,
+}
+
+impl Dataset {
+    /// Read a CSV file where every row holds the input values followed by the
+    /// target values. A single target column with a multi-class output is
+    /// treated as a class index and one-hot encoded. Non-numeric rows, such as
+    /// a header, are skipped.
+    pub fn from_csv(text: &str, input: &TensorShape, output: &TensorShape) -> Result<Self, String> {
+        let (n_in, n_out) = (input.elements(), output.elements());
+        let mut examples = Vec::new();
+        
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values: Result<Vec<f32>, _> = line.split(',').map(|v| v.trim().parse::<f32>()).collect();
+            let values = match values {
+                Ok(values) => values,
+                Err(_) if examples.is_empty() => continue,
+                Err(e) => return Err(format!("line {}: {}", line_no + 1, e)),
+            };
+            
+            let target = if values.len() == n_in + n_out {
+                values[n_in..].to_vec()
+            } else if values.len() == n_in + 1 && n_out > 1 {
+                let class = values[n_in] as usize;
+                if class >= n_out {
+                    return Err(format!("line {}: class {} is out of range for {} outputs", line_no + 1, class, n_out));
+                }
+                let mut one_hot = vec![0.0; n_out];
+                one_hot[class] = 1.0;
+                one_hot
+            } else {
+                return Err(format!("line {}: expected {} inputs and {} targets, found {} values",
+                                   line_no + 1, n_in, n_out, values.len()));
+            };
+            
+            examples.push((
+                Tensor::new(input.dims.clone(), values[..n_in].to_vec())?,
+                Tensor::new(output.dims.clone(), target)?,
+            ));
+        }
+        Ok(Dataset { examples })
+    }
+    
+    pub fn len(&self) -> usize {
+        self.examples.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.examples.is_empty()
+    }
+    
+    /// Inputs of a CSV file whose rows may leave out the targets, such as a
+    /// calibration set; extra columns are ignored
+    pub fn inputs_from_csv(text: &str, input: &TensorShape) -> Result<Vec<Tensor>, String> {
+        let n_in = input.elements();
+        let mut inputs = Vec::new();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values: Result<Vec<f32>, _> = line.split(',').map(|v| v.trim().parse::<f32>()).collect();
+            let values = match values {
+                Ok(values) => values,
+                Err(_) if inputs.is_empty() => continue,
+                Err(e) => return Err(format!("line {}: {}", line_no + 1, e)),
+            };
+            if values.len() < n_in {
+                return Err(format!("line {}: expected {} inputs, found {} values", line_no + 1, n_in, values.len()));
+            }
+            inputs.push(Tensor::new(input.dims.clone(), values[..n_in].to_vec())?);
+        }
+        Ok(inputs)
+    }
+    
+    /// Read a dataset for a network with the given input and output shapes
+    ///
+    /// A folder holds images in one subfolder per class. Files are read by
+    /// extension: `.csv`, `.npz` (arrays `x` and `y`, or `x_train` and
+    /// `y_train`), `.npy`, and IDX files (`.idx`, or names containing
+    /// `-ubyte`); `.npy` and IDX inputs take their targets from
+    /// `options.labels`. A `.gz` suffix is decompressed first.
+    pub fn load(path: &str, input: &TensorShape, output: &TensorShape, options: &LoadOptions) -> Result<Self, String> {
+        if Path::new(path).is_dir() {
+            let (inputs, labels, _) = read_image_folder(Path::new(path))?;
+            return Self::from_arrays(&inputs, &labels, input, output);
+        }
+        let name = path.strip_suffix(".gz").unwrap_or(path);
+        let bytes = read_file(path)?;
+        
+        if let (Some(tokenizer), true) = (&options.tokenizer, name.ends_with(".tsv")) {
+            let text = String::from_utf8(bytes).map_err(|_| format!("'{}' is not UTF-8 text", path))?;
+            let (inputs, labels) = tokenize_tsv(&text, tokenizer, input.dims[0])?;
+            return Self::from_arrays(&inputs, &labels, input, output);
+        }
+        if name.ends_with(".csv") {
+            let text = String::from_utf8(bytes).map_err(|_| format!("'{}' is not UTF-8 text", path))?;
+            return match &options.label_column {
+                Some(column) => {
+                    let (inputs, labels) = csv_with_label(&text, column)?;
+                    Self::from_arrays(&inputs, &labels, input, output)
+                },
+                None => Self::from_csv(&text, input, output),
+            };
+        }
+        if name.ends_with(".npz") {
+            let arrays = read_npz(&bytes)?;
+            let pick = |names: &[&str]| names.iter().find_map(|n| arrays.get(*n));
+            let inputs = pick(&["x", "x_train"])
+                .ok_or_else(|| format!("'{}' has no array named `x` or `x_train`", path))?;
+            let labels = match &options.labels {
+                Some(labels) => read_array(labels)?,
+                None => pick(&["y", "y_train"])
+                    .ok_or_else(|| format!("'{}' has no array named `y` or `y_train`", path))?
+                    .clone(),
+            };
+            return Self::from_arrays(inputs, &labels, input, output);
+        }
+        
+        let inputs = parse_array(name, &bytes).map_err(|e| format!("'{}': {}", path, e))?;
+        let labels = options.labels.as_deref()
+            .ok_or_else(|| format!("'{}' holds no targets (use --labels=FILE)", path))?;
+        Self::from_arrays(&inputs, &read_array(labels)?, input, output)
+    }
+    
+    /// Pair every example of `inputs` with the same row of `targets`, both
+    /// indexed by their first axis
+    ///
+    /// Examples must have the input's shape, may leave out a trailing channel
+    /// of 1 (an MNIST digit for `I 28×28×1`), or may be flat rows with as many
+    /// values. A target with a single value for a multi-class output is a
+    /// class index and is one-hot encoded.
+    pub fn from_arrays(inputs: &Tensor, targets: &Tensor, input: &TensorShape, output: &TensorShape) -> Result<Self, String> {
+        let count = inputs.shape.first().copied().unwrap_or(0);
+        let example = TensorShape::new(inputs.shape.get(1..).unwrap_or_default().to_vec());
+        let fits = example.dims == input.dims
+            || (input.dims.last() == Some(&1) && example.dims == input.dims[..input.rank() - 1])
+            || (example.rank() == 1 && example.elements() == input.elements());
+        if !fits {
+            return Err(format!("examples are {} but the input is declared {}", example, input));
+        }
+        if targets.shape.first() != Some(&count) {
+            return Err(format!("found {} inputs but {} targets", count, targets.shape.first().copied().unwrap_or(0)));
+        }
+        
+        let (n_in, n_out) = (input.elements(), output.elements());
+        let per_target = targets.len() / count.max(1);
+        let mut examples = Vec::with_capacity(count);
+        for i in 0..count {
+            let target = &targets.data[i * per_target..(i + 1) * per_target];
+            let target = if per_target == n_out {
+                target.to_vec()
+            } else if per_target == 1 && n_out > 1 {
+                let class = target[0];
+                if class < 0.0 || class.fract() != 0.0 || class as usize >= n_out {
+                    return Err(format!("example {}: class {} is out of range for {} outputs", i + 1, class, n_out));
+                }
+                let mut one_hot = vec![0.0; n_out];
+                one_hot[class as usize] = 1.0;
+                one_hot
+            } else {
+                return Err(format!("targets have {} values per example but the output is {}", per_target, output));
+            };
+            examples.push((
+                Tensor::new(input.dims.clone(), inputs.data[i * n_in..(i + 1) * n_in].to_vec())?,
+                Tensor::new(output.dims.clone(), target)?,
+            ));
+        }
+        Ok(Dataset { examples })
+    }
+    
+    /// Shuffle the examples; the same seed gives the same order
+    pub fn shuffle(&mut self, seed: u64) {
+        shuffle(&mut self.examples, &mut Rng::new(seed));
+    }
+    
+    /// Consecutive batches of `size` examples, the last one possibly shorter
+    pub fn batches(&self, size: usize) -> std::slice::Chunks<'_, (Tensor, Tensor)> {
+        self.examples.chunks(size.max(1))
+    }
+    
+    /// Normalize the inputs as declared by `⍓`, returning the statistics used
+    /// so that other data can be normalized the same way
+    pub fn normalize(&mut self, preprocess: Preprocess) -> Normalization {
+        let normalization = Normalization::fit(preprocess, self);
+        for (input, _) in &mut self.examples {
+            normalization.apply(input);
+        }
+        normalization
+    }
+}
+
+/// Fisher-Yates shuffle drawing from `rng`
+pub fn shuffle<T>(items: &mut [T], rng: &mut Rng) {
+    for i in (1..items.len()).rev() {
+        let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+/// Per-channel shift and divisor applied to inputs, channels being the last axis
+#[derive(Debug, Clone, PartialEq)]
+pub struct Normalization {
+    pub mean: Vec<f32>,
+    pub scale: Vec<f32>,
+}
+
+impl Normalization {
+    /// Statistics for `preprocess`; standardization measures them on `dataset`
+    pub fn fit(preprocess: Preprocess, dataset: &Dataset) -> Self {
+        if let Preprocess::Scale(divisor) = preprocess {
+            return Normalization { mean: vec![0.0], scale: vec![divisor.max(1) as f32] };
+        }
+        let channels = dataset.examples.first().and_then(|(x, _)| x.shape.last().copied()).unwrap_or(1).max(1);
+        let (mut sum, mut squares, mut count) = (vec![0.0f64; channels], vec![0.0f64; channels], vec![0usize; channels]);
+        for (input, _) in &dataset.examples {
+            for (i, &value) in input.data.iter().enumerate() {
+                sum[i % channels] += value as f64;
+                squares[i % channels] += (value as f64).powi(2);
+                count[i % channels] += 1;
+            }
+        }
+        let mut normalization = Normalization { mean: Vec::new(), scale: Vec::new() };
+        for c in 0..channels {
+            let n = count[c].max(1) as f64;
+            let mean = sum[c] / n;
+            let std = (squares[c] / n - mean * mean).max(0.0).sqrt();
+            normalization.mean.push(mean as f32);
+            // A constant channel is only centred
+            normalization.scale.push(if std > 1e-6 { std as f32 } else { 1.0 });
+        }
+        normalization
+    }
+    
+    pub fn apply(&self, input: &mut Tensor) {
+        let channels = self.mean.len();
+        for (i, value) in input.data.iter_mut().enumerate() {
+            *value = (*value - self.mean[i % channels]) / self.scale[i % channels];
+        }
+    }
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("failed to read '{}': {}", path, e))?;
+    if path.ends_with(".gz") {
+        return gunzip(&bytes).map_err(|e| format!("'{}': {}", path, e));
+    }
+    Ok(bytes)
+}
+
+/// Read a `.npy` or IDX file, optionally gzipped, as one array
+pub fn read_array(path: &str) -> Result<Tensor, String> {
+    let bytes = read_file(path)?;
+    parse_array(path.strip_suffix(".gz").unwrap_or(path), &bytes).map_err(|e| format!("'{}': {}", path, e))
+}
+
+fn parse_array(name: &str, bytes: &[u8]) -> Result<Tensor, String> {
+    if name.ends_with(".npy") {
+        read_npy(bytes)
+    } else if name.ends_with(".idx") || name.contains("-ubyte") || name.contains(".idx") {
+        read_idx(bytes)
+    } else {
+        Err("unknown array format (expected .npy, .npz, .csv or an IDX file)".to_string())
+    }
+}
+
+// Element type of an array file
+#[derive(Debug, Clone, Copy)]
+struct Dtype {
+    // `f`, `i`, `u` or `b` as in NumPy type strings
+    kind: char,
+    size: usize,
+    big_endian: bool,
+}
+
+impl Dtype {
+    // NumPy type string such as `<f4` or `|u1`
+    fn parse(descr: &str) -> Result<Self, String> {
+        let mut chars = descr.chars();
+        let big_endian = match chars.next() {
+            Some('>') => true,
+            Some('<') | Some('|') | Some('=') => false,
+            _ => return Err(format!("unsupported dtype '{}'", descr)),
+        };
+        let kind = chars.next().unwrap_or(' ');
+        let size = chars.as_str().parse::<usize>().unwrap_or(0);
+        match (kind, size) {
+            ('f', 4) | ('f', 8) | ('i', 1..=8) | ('u', 1..=8) | ('b', 1) if size.is_power_of_two() => {
+                Ok(Dtype { kind, size, big_endian })
+            },
+            _ => Err(format!("unsupported dtype '{}'", descr)),
+        }
+    }
+    
+    fn value(&self, raw: &[u8]) -> f32 {
+        let bits = if self.big_endian {
+            raw.iter().fold(0u64, |bits, &b| bits << 8 | b as u64)
+        } else {
+            raw.iter().rev().fold(0u64, |bits, &b| bits << 8 | b as u64)
+        };
+        match (self.kind, self.size) {
+            ('f', 4) => f32::from_bits(bits as u32),
+            ('f', 8) => f64::from_bits(bits) as f32,
+            ('i', size) => {
+                let shift = 64 - 8 * size as u32;
+                ((bits << shift) as i64 >> shift) as f32
+            },
+            _ => bits as f32,
+        }
+    }
+    
+    fn decode(&self, bytes: &[u8], count: usize) -> Result<Vec<f32>, String> {
+        let needed = count * self.size;
+        let bytes = bytes.get(..needed)
+            .ok_or_else(|| format!("array data is truncated: expected {} bytes, found {}", needed, bytes.len()))?;
+        Ok(bytes.chunks(self.size).map(|raw| self.value(raw)).collect())
+    }
+}
+
+fn u16_le(bytes: &[u8], at: usize) -> Result<usize, String> {
+    bytes.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize).ok_or_else(|| "file is truncated".to_string())
+}
+
+fn u32_le(bytes: &[u8], at: usize) -> Result<usize, String> {
+    bytes.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize).ok_or_else(|| "file is truncated".to_string())
+}
+
+fn u32_be(bytes: &[u8], at: usize) -> Result<usize, String> {
+    bytes.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize).ok_or_else(|| "file is truncated".to_string())
+}
+
+/// Parse a NumPy `.npy` array of booleans, integers or floats
+pub fn read_npy(bytes: &[u8]) -> Result<Tensor, String> {
+    if !bytes.starts_with(b"\x93NUMPY") {
+        return Err("not a NumPy array (missing magic string)".to_string());
+    }
+    let (length, start) = match bytes.get(6) {
+        Some(1) => (u16_le(bytes, 8)?, 10),
+        Some(2) | Some(3) => (u32_le(bytes, 8)?, 12),
+        version => return Err(format!("unsupported .npy version {:?}", version)),
+    };
+    let header = bytes.get(start..start + length)
+        .and_then(|h| std::str::from_utf8(h).ok())
+        .ok_or("invalid .npy header")?;
+    
+    let dtype = Dtype::parse(header_value(header, "descr")?.trim_matches(|c| c == '\'' || c == '"'))?;
+    let fortran_order = header_value(header, "fortran_order")? == "True";
+    let shape = header_value(header, "shape")?
+        .trim_matches(|c| c == '(' || c == ')')
+        .split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| dim.parse::<usize>().map_err(|_| format!("invalid dimension '{}' in .npy shape", dim)))
+        .collect::<Result<Vec<_>, _>>()?;
+    
+    let data = dtype.decode(&bytes[start + length..], shape.iter().product())?;
+    let data = if fortran_order { from_fortran_order(&shape, data) } else { data };
+    Tensor::new(shape, data)
+}
+
+// Value of `key` in the Python dict literal of a `.npy` header
+fn header_value<'a>(header: &'a str, key: &str) -> Result<&'a str, String> {
+    let at = header.find(&format!("'{}':", key)).ok_or_else(|| format!(".npy header has no '{}'", key))?;
+    let rest = header[at + key.len() + 3..].trim_start();
+    let end = if rest.starts_with('(') {
+        rest.find(')').map(|i| i + 1)
+    } else {
+        rest.find([',', '}'])
+    };
+    Ok(rest[..end.unwrap_or(rest.len())].trim())
+}
+
+// Reorder column-major data into the row-major order tensors use
+fn from_fortran_order(shape: &[usize], data: Vec<f32>) -> Vec<f32> {
+    let mut strides = vec![1; shape.len()];
+    for axis in 1..shape.len() {
+        strides[axis] = strides[axis - 1] * shape[axis - 1];
+    }
+    (0..data.len()).map(|index| {
+        let (mut rest, mut offset) = (index, 0);
+        for axis in (0..shape.len()).rev() {
+            offset += rest % shape[axis] * strides[axis];
+            rest /= shape[axis];
+        }
+        data[offset]
+    }).collect()
+}
+
+/// Arrays of a NumPy `.npz` archive by name, stored or deflated
+pub fn read_npz(bytes: &[u8]) -> Result<BTreeMap<String, Tensor>, String> {
+    let mut arrays = BTreeMap::new();
+    for (name, data) in unzip(bytes)? {
+        let array = read_npy(&data).map_err(|e| format!("`{}`: {}", name, e))?;
+        arrays.insert(name.trim_end_matches(".npy").to_string(), array);
+    }
+    Ok(arrays)
+}
+
+// Files of a zip archive, found through its central directory
+fn unzip(bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let end = (0..bytes.len().saturating_sub(21)).rev()
+        .find(|&i| bytes[i..].starts_with(b"PK\x05\x06"))
+        .ok_or("not a zip archive")?;
+    let count = u16_le(bytes, end + 10)?;
+    let mut at = u32_le(bytes, end + 16)?;
+    
+    let mut files = Vec::new();
+    for _ in 0..count {
+        if !bytes.get(at..).is_some_and(|b| b.starts_with(b"PK\x01\x02")) {
+            return Err("corrupt zip central directory".to_string());
+        }
+        let method = u16_le(bytes, at + 10)?;
+        let mut compressed = u32_le(bytes, at + 20)?;
+        let mut size = u32_le(bytes, at + 24)?;
+        let (name_len, extra_len, comment_len) = (u16_le(bytes, at + 28)?, u16_le(bytes, at + 30)?, u16_le(bytes, at + 32)?);
+        let mut local = u32_le(bytes, at + 42)?;
+        let name = bytes.get(at + 46..at + 46 + name_len).ok_or("file is truncated")?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        
+        // Zip64 extra field: 64-bit values for the fields saturated above
+        let mut extra = at + 46 + name_len;
+        while extra + 4 <= at + 46 + name_len + extra_len {
+            let (id, len) = (u16_le(bytes, extra)?, u16_le(bytes, extra + 2)?);
+            if id == 1 {
+                let mut field = extra + 4;
+                for value in [&mut size, &mut compressed, &mut local] {
+                    if *value == 0xFFFF_FFFF {
+                        *value = u32_le(bytes, field)? | u32_le(bytes, field + 4)? << 32;
+                        field += 8;
+                    }
+                }
+            }
+            extra += 4 + len;
+        }
+        
+        if !bytes.get(local..).is_some_and(|b| b.starts_with(b"PK\x03\x04")) {
+            return Err(format!("corrupt zip entry `{}`", name));
+        }
+        let start = local + 30 + u16_le(bytes, local + 26)? + u16_le(bytes, local + 28)?;
+        let raw = bytes.get(start..start + compressed).ok_or("file is truncated")?;
+        let data = match method {
+            0 => raw.to_vec(),
+            8 => inflate(raw)?,
+            method => return Err(format!("`{}` uses unsupported zip compression method {}", name, method)),
+        };
+        if data.len() != size {
+            return Err(format!("`{}` decompressed to {} bytes instead of {}", name, data.len(), size));
+        }
+        files.push((name, data));
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(files)
+}
+
+/// Parse an IDX file, the format of the MNIST distribution
+pub fn read_idx(bytes: &[u8]) -> Result<Tensor, String> {
+    if bytes.len() < 4 || bytes[0] != 0 || bytes[1] != 0 {
+        return Err("not an IDX file (bad magic number)".to_string());
+    }
+    let (kind, size) = match bytes[2] {
+        0x08 => ('u', 1),
+        0x09 => ('i', 1),
+        0x0B => ('i', 2),
+        0x0C => ('i', 4),
+        0x0D => ('f', 4),
+        0x0E => ('f', 8),
+        code => return Err(format!("unknown IDX element type 0x{:02X}", code)),
+    };
+    let rank = bytes[3] as usize;
+    let shape = (0..rank).map(|axis| u32_be(bytes, 4 + 4 * axis)).collect::<Result<Vec<_>, _>>()?;
+    let data = Dtype { kind, size, big_endian: true }.decode(&bytes[4 + 4 * rank..], shape.iter().product())?;
+    Tensor::new(shape, data)
+}
+
+/// Parse a PPM or PGM image (`P2`, `P3`, `P5`, `P6`) into height×width×channels
+pub fn read_ppm(bytes: &[u8]) -> Result<Tensor, String> {
+    let channels = match bytes.get(..2) {
+        Some(b"P2") | Some(b"P5") => 1,
+        Some(b"P3") | Some(b"P6") => 3,
+        _ => return Err("not a PPM or PGM image".to_string()),
+    };
+    let binary = bytes[1] == b'5' || bytes[1] == b'6';
+    
+    // Width, height and maximum value, separated by whitespace and comments
+    let mut header = Vec::new();
+    let mut at = 2;
+    while header.len() < 3 {
+        match bytes.get(at) {
+            Some(b'#') => while bytes.get(at).is_some_and(|&b| b != b'\n') { at += 1 },
+            Some(b) if b.is_ascii_whitespace() => at += 1,
+            Some(b) if b.is_ascii_digit() => {
+                let start = at;
+                while bytes.get(at).is_some_and(u8::is_ascii_digit) {
+                    at += 1;
+                }
+                header.push(std::str::from_utf8(&bytes[start..at]).unwrap().parse::<usize>().map_err(|e| e.to_string())?);
+            },
+            _ => return Err("invalid PPM header".to_string()),
+        }
+    }
+    let (width, height, max) = (header[0], header[1], header[2]);
+    let count = width * height * channels;
+    
+    let data = if binary {
+        // A single whitespace byte ends the header
+        let size = if max > 255 { 2 } else { 1 };
+        Dtype { kind: 'u', size, big_endian: true }.decode(&bytes[at + 1..], count)?
+    } else {
+        let text = std::str::from_utf8(&bytes[at..]).map_err(|_| "invalid PPM data".to_string())?;
+        let data = text.split_ascii_whitespace()
+            .take(count)
+            .map(|v| v.parse::<f32>().map_err(|_| format!("invalid PPM value '{}'", v)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if data.len() < count {
+            return Err(format!("PPM data is truncated: expected {} values, found {}", count, data.len()));
+        }
+        data
+    };
+    Tensor::new(vec![height, width, channels], data)
+}
+
+/// Decode a PNG image into height×width×channels, dropping any alpha channel;
+/// palette images become RGB
+pub fn read_png(bytes: &[u8]) -> Result<Tensor, String> {
+    if !bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Err("not a PNG image".to_string());
+    }
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut compressed = Vec::new();
+    let mut at = 8;
+    while at + 8 <= bytes.len() {
+        let length = u32_be(bytes, at)?;
+        let data = bytes.get(at + 8..at + 8 + length).ok_or("PNG chunk is truncated")?;
+        match &bytes[at + 4..at + 8] {
+            b"IHDR" => header = Some(data.to_vec()),
+            b"PLTE" => palette = data.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {},
+        }
+        at += 12 + length;
+    }
+    let header = header.filter(|h| h.len() == 13).ok_or("PNG has no header")?;
+    let (width, height) = (u32_be(&header, 0)?, u32_be(&header, 4)?);
+    let (depth, color, interlace) = (header[8] as usize, header[9], header[12]);
+    
+    let samples = match color {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => return Err(format!("unknown PNG color type {}", color)),
+    };
+    if depth != 8 && depth != 16 {
+        return Err(format!("PNG bit depth {} is not supported (use 8 or 16)", depth));
+    }
+    if interlace != 0 {
+        return Err("interlaced PNG images are not supported".to_string());
+    }
+    
+    let pixel = samples * depth / 8;
+    let stride = width * pixel;
+    let raw = zlib(&compressed)?;
+    if raw.len() < height * (stride + 1) {
+        return Err("PNG image data is truncated".to_string());
+    }
+    let mut rows = vec![0u8; height * stride];
+    for y in 0..height {
+        let filter = raw[y * (stride + 1)];
+        let line = &raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (done, current) = rows.split_at_mut(y * stride);
+        let previous = if y > 0 { &done[(y - 1) * stride..] } else { &[][..] };
+        let current = &mut current[..stride];
+        for x in 0..stride {
+            let a = if x >= pixel { current[x - pixel] } else { 0 };
+            let b = previous.get(x).copied().unwrap_or(0);
+            let c = if x >= pixel { previous.get(x - pixel).copied().unwrap_or(0) } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                filter => return Err(format!("unknown PNG filter {}", filter)),
+            };
+            current[x] = line[x].wrapping_add(predicted);
+        }
+    }
+    
+    let values = Dtype { kind: 'u', size: depth / 8, big_endian: true }.decode(&rows, width * height * samples)?;
+    let (channels, data) = match color {
+        3 => {
+            let mut data = Vec::with_capacity(width * height * 3);
+            for &index in &values {
+                let rgb = palette.get(index as usize * 3..index as usize * 3 + 3)
+                    .ok_or_else(|| format!("PNG palette has no entry {}", index))?;
+                data.extend(rgb.iter().map(|&v| v as f32));
+            }
+            (3, data)
+        },
+        // Gray and RGB without their alpha channel
+        4 | 6 => {
+            let kept = samples - 1;
+            (kept, values.chunks(samples).flat_map(|p| p[..kept].to_vec()).collect())
+        },
+        _ => (samples, values),
+    };
+    Tensor::new(vec![height, width, channels], data)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Read a PPM, PGM or PNG image by extension
+pub fn read_image(path: &Path) -> Result<Tensor, String> {
+    let bytes = fs::read(path).map_err(|e| format!("failed to read '{}': {}", path.display(), e))?;
+    let image = match path.extension().and_then(|e| e.to_str()) {
+        Some("png") => read_png(&bytes),
+        _ => read_ppm(&bytes),
+    };
+    image.map_err(|e| format!("'{}': {}", path.display(), e))
+}
+
+/// Images stored in one subfolder per class, e.g. `train/cat/1.png`
+///
+/// Classes are numbered in name order; returns the stacked images, their
+/// class indices and the class names.
+pub fn read_image_folder(path: &Path) -> Result<(Tensor, Tensor, Vec<String>), String> {
+    let entries = |dir: &Path| -> Result<Vec<std::path::PathBuf>, String> {
+        let mut paths = fs::read_dir(dir)
+            .map_err(|e| format!("failed to read '{}': {}", dir.display(), e))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .collect::<Vec<_>>();
+        paths.sort();
+        Ok(paths)
+    };
+    
+    let mut classes = Vec::new();
+    let mut images: Vec<(std::path::PathBuf, Tensor)> = Vec::new();
+    let mut labels = Vec::new();
+    for class_dir in entries(path)?.into_iter().filter(|p| p.is_dir()) {
+        for file in entries(&class_dir)? {
+            let extension = file.extension().and_then(|e| e.to_str()).unwrap_or("");
+            if !matches!(extension, "png" | "ppm" | "pgm" | "pnm") {
+                continue;
+            }
+            let image = read_image(&file)?;
+            if let Some((first, shape)) = images.first().map(|(p, t)| (p, &t.shape)) {
+                if *shape != image.shape {
+                    return Err(format!("'{}' is {} but '{}' is {}", file.display(), TensorShape::new(image.shape.clone()),
+                                       first.display(), TensorShape::new(shape.clone())));
+                }
+            }
+            images.push((file, image));
+            labels.push(classes.len() as f32);
+        }
+        classes.push(class_dir.file_name().unwrap_or_default().to_string_lossy().into_owned());
+    }
+    
+    let shape = match images.first() {
+        Some((_, image)) => image.shape.clone(),
+        None => return Err(format!("no images found in the class folders of '{}'", path.display())),
+    };
+    let mut dims = vec![images.len()];
+    dims.extend(shape);
+    let data = images.into_iter().flat_map(|(_, image)| image.data).collect();
+    Ok((Tensor::new(dims, data)?, Tensor::new(vec![labels.len()], labels)?, classes))
+}
+
+// Split the label column off a CSV file: inputs as rows, labels as a vector.
+// A header row lets the column be named.
+// Token ids of the text before the last tab of every line, and the label after it
+fn tokenize_tsv(text: &str, tokenizer: &Tokenizer, length: usize) -> Result<(Tensor, Tensor), String> {
+    let (mut inputs, mut labels) = (Vec::new(), Vec::new());
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (sentence, label) = line.rsplit_once('\t')
+            .ok_or_else(|| format!("line {}: expected `text<TAB>label`", line_no + 1))?;
+        let label = label.trim().parse::<f32>().map_err(|e| format!("line {}: {}", line_no + 1, e))?;
+        inputs.extend(tokenizer.encode(sentence, length).into_iter().map(|id| id as f32));
+        labels.push(label);
+    }
+    let rows = labels.len();
+    Ok((Tensor::new(vec![rows, length], inputs)?, Tensor::new(vec![rows], labels)?))
+}
+
+fn csv_with_label(text: &str, column: &str) -> Result<(Tensor, Tensor), String> {
+    let mut header: Option<Vec<&str>> = None;
+    let mut index = column.parse::<usize>().ok();
+    let (mut inputs, mut labels, mut width) = (Vec::new(), Vec::new(), None);
+    
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let values: Result<Vec<f32>, _> = fields.iter().map(|v| v.parse::<f32>()).collect();
+        let values = match values {
+            Ok(values) => values,
+            Err(_) if header.is_none() && width.is_none() => {
+                header = Some(fields);
+                continue;
+            },
+            Err(e) => return Err(format!("line {}: {}", line_no + 1, e)),
+        };
+        
+        if index.is_none() {
+            index = header.as_ref().and_then(|h| h.iter().position(|name| *name == column));
+        }
+        let index = index.ok_or_else(|| format!("no column named `{}`", column))?;
+        if *width.get_or_insert(values.len()) != values.len() {
+            return Err(format!("line {}: expected {} values, found {}", line_no + 1, width.unwrap(), values.len()));
+        }
+        if index >= values.len() {
+            return Err(format!("label column {} is out of range for {} columns", index, values.len()));
+        }
+        for (i, value) in values.into_iter().enumerate() {
+            if i == index {
+                labels.push(value);
+            } else {
+                inputs.push(value);
+            }
+        }
+    }
+    let rows = labels.len();
+    Ok((Tensor::new(vec![rows, width.unwrap_or(1) - 1], inputs)?, Tensor::new(vec![rows], labels)?))
+}
+
+// Decompress a gzip member
+fn gunzip(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    if bytes.len() < 18 || bytes[0] != 0x1f || bytes[1] != 0x8b || bytes[2] != 8 {
+        return Err("not a gzip file".to_string());
+    }
+    let flags = bytes[3];
+    let mut at = 10;
+    if flags & 4 != 0 {
+        at += 2 + u16_le(bytes, at)?;
+    }
+    // File name and comment are zero-terminated
+    for flag in [8, 16] {
+        if flags & flag != 0 {
+            at += bytes.get(at..).and_then(|b| b.iter().position(|&c| c == 0)).ok_or("gzip header is truncated")? + 1;
+        }
+    }
+    if flags & 2 != 0 {
+        at += 2;
+    }
+    inflate(bytes.get(at..).ok_or("gzip header is truncated")?)
+}
+
+// Decompress a zlib stream, as in PNG image data
+fn zlib(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    match bytes {
+        [cmf, _, rest @ ..] if cmf & 0x0F == 8 => inflate(rest),
+        _ => Err("invalid zlib stream".to_string()),
+    }
+}
+
+// Bits of a deflate stream, least significant first
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, n: u32) -> Result<u32, String> {
+        while self.count < n {
+            let byte = *self.bytes.get(self.at).ok_or("compressed data is truncated")?;
+            self.buffer |= (byte as u32) << self.count;
+            self.at += 1;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1u64 << n) - 1) as u32;
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+}
+
+// Canonical Huffman code given by the length of every symbol's code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+    
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code in compressed data".to_string())
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073,
+                                  4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// Decompress raw deflate data (RFC 1951), as used by zip, gzip and PNG
+pub fn inflate(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = BitReader { bytes, at: 0, buffer: 0, count: 0 };
+    let mut out = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                // Stored block, starting at the next byte boundary
+                reader.buffer = 0;
+                reader.count = 0;
+                let length = u16_le(bytes, reader.at)?;
+                let data = bytes.get(reader.at + 4..reader.at + 4 + length).ok_or("compressed data is truncated")?;
+                out.extend_from_slice(data);
+                reader.at += 4 + length;
+            },
+            1 => {
+                let mut lengths = [8u8; 288];
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                inflate_block(&mut reader, &mut out, &Huffman::new(&lengths), &Huffman::new(&[5; 30]))?;
+            },
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut out, &literals, &distances)?;
+            },
+            _ => return Err("invalid deflate block type".to_string()),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+// Code lengths of a dynamic block, themselves Huffman coded
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    const ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+    let literals = reader.bits(5)? as usize + 257;
+    let distances = reader.bits(5)? as usize + 1;
+    let code_lengths = reader.bits(4)? as usize + 4;
+    
+    let mut lengths = [0u8; 19];
+    for &symbol in &ORDER[..code_lengths] {
+        lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code = Huffman::new(&lengths);
+    
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let (value, repeat) = match code.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or("invalid code lengths in compressed data")?, 3 + reader.bits(2)?),
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.resize(lengths.len() + repeat as usize, value);
+    }
+    if lengths.len() > literals + distances {
+        return Err("invalid code lengths in compressed data".to_string());
+    }
+    Ok((Huffman::new(&lengths[..literals]), Huffman::new(&lengths[literals..])))
+}
+
+fn inflate_block(reader: &mut BitReader, out: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> Result<(), String> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASE.len() {
+                    return Err("invalid length in compressed data".to_string());
+                }
+                let length = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+                let index = distances.decode(reader)? as usize;
+                if index >= DISTANCE_BASE.len() {
+                    return Err("invalid distance in compressed data".to_string());
+                }
+                let distance = DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                if distance > out.len() {
+                    return Err("distance too far back in compressed data".to_string());
+                }
+                // Copies may overlap their own output
+                let start = out.len() - distance;
+                for i in 0..length {
+                    out.push(out[start + i]);
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,634 @@
+use serde::Serialize;
+use std::fmt;
+use std::fmt::Write;
+
+/// Byte range into the source text
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl From<pest::Span<'_>> for Span {
+    fn from(span: pest::Span<'_>) -> Self {
+        Span::new(span.start(), span.end())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+            Severity::Help => write!(f, "help"),
+        }
+    }
+}
+
+/// A span with an optional message attached to it
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A single message from the parser, resolver, an analysis pass or a backend
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<&'static str>,
+    pub message: String,
+    // Boxed so that results carrying a diagnostic stay small
+    pub primary: Option<Box<Label>>,
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            code: Some(code),
+            message: message.into(),
+            primary: None,
+            secondary: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Warning, code, message)
+    }
+
+    /// Attach the main location of the problem
+    pub fn with_primary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.primary = Some(Box::new(Label { span, message: message.into() }));
+        self
+    }
+
+    /// Attach a related location
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.secondary.push(Label { span, message: message.into() });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    fn header(&self) -> String {
+        match self.code {
+            Some(code) => format!("{}[{}]: {}", self.severity, code, self.message),
+            None => format!("{}: {}", self.severity, self.message),
+        }
+    }
+
+    /// Render in rustc style, with source snippets for every labelled span
+    pub fn render(&self, file_name: &str, source: &str) -> String {
+        let mut out = String::new();
+        writeln!(out, "{}", self.header()).unwrap();
+
+        let mut labels: Vec<(&Label, bool)> = Vec::new();
+        if let Some(primary) = &self.primary {
+            labels.push((primary, true));
+        }
+        for label in &self.secondary {
+            labels.push((label, false));
+        }
+
+        let located: Vec<(LineCol, &Label, bool)> = labels.iter()
+            .map(|(label, primary)| (line_col(source, label.span.start), *label, *primary))
+            .collect();
+        let gutter = located.iter()
+            .map(|(pos, _, _)| pos.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad = " ".repeat(gutter);
+
+        if let Some((pos, _, _)) = located.first() {
+            writeln!(out, "{}--> {}:{}:{}", pad, file_name, pos.line, pos.column).unwrap();
+            writeln!(out, "{} |", pad).unwrap();
+        }
+
+        let mut by_line = located.clone();
+        by_line.sort_by_key(|(pos, _, primary)| (pos.line, !*primary));
+
+        let mut last_line = None;
+        for (pos, label, primary) in by_line {
+            let line_text = source.lines().nth(pos.line - 1).unwrap_or("");
+            if last_line != Some(pos.line) {
+                if let Some(last) = last_line {
+                    if pos.line > last + 1 {
+                        writeln!(out, "{} |", pad).unwrap();
+                    }
+                }
+                writeln!(out, "{:>width$} | {}", pos.line, line_text, width = gutter).unwrap();
+                last_line = Some(pos.line);
+            }
+
+            // Underline the span, clamped to the end of its first line
+            let line_chars = line_text.chars().count();
+            let start_col = pos.column - 1;
+            let span_chars = source.get(label.span.start..label.span.end)
+                .map(|s| s.lines().next().unwrap_or("").chars().count())
+                .unwrap_or(0);
+            let width = span_chars.min(line_chars.saturating_sub(start_col)).max(1);
+            let marker = if primary { "^" } else { "-" };
+            let mut underline = format!("{}{}", " ".repeat(start_col), marker.repeat(width));
+            if !label.message.is_empty() {
+                underline.push(' ');
+                underline.push_str(&label.message);
+            }
+            writeln!(out, "{} | {}", pad, underline).unwrap();
+        }
+
+        if !located.is_empty() && !self.notes.is_empty() {
+            writeln!(out, "{} |", pad).unwrap();
+        }
+        for note in &self.notes {
+            writeln!(out, "{}= note: {}", pad, note).unwrap();
+        }
+
+        out
+    }
+
+    /// Machine-readable form for editors and AI agents
+    pub fn to_json(&self, file_name: &str, source: &str) -> serde_json::Value {
+        let mut spans = Vec::new();
+        let labels = self.primary.iter().map(|l| (&**l, true))
+            .chain(self.secondary.iter().map(|l| (l, false)));
+        for (label, is_primary) in labels {
+            let start = line_col(source, label.span.start);
+            let end = line_col(source, label.span.end);
+            spans.push(JsonSpan {
+                file_name: file_name.to_string(),
+                byte_start: label.span.start,
+                byte_end: label.span.end,
+                line_start: start.line,
+                column_start: start.column,
+                line_end: end.line,
+                column_end: end.column,
+                is_primary,
+                label: if label.message.is_empty() { None } else { Some(label.message.clone()) },
+            });
+        }
+
+        let json = JsonDiagnostic {
+            severity: self.severity,
+            code: self.code,
+            message: self.message.clone(),
+            spans,
+            notes: self.notes.clone(),
+            rendered: self.render(file_name, source),
+        };
+        serde_json::to_value(json).unwrap()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.header())
+    }
+}
+
+impl From<String> for Diagnostic {
+    fn from(message: String) -> Self {
+        Diagnostic::error(codes::BACKEND_FAILURE, message)
+    }
+}
+
+#[derive(Serialize)]
+struct JsonSpan {
+    file_name: String,
+    byte_start: usize,
+    byte_end: usize,
+    line_start: usize,
+    column_start: usize,
+    line_end: usize,
+    column_end: usize,
+    is_primary: bool,
+    label: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JsonDiagnostic {
+    severity: Severity,
+    code: Option<&'static str>,
+    message: String,
+    spans: Vec<JsonSpan>,
+    notes: Vec<String>,
+    rendered: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LineCol {
+    line: usize,
+    column: usize,
+}
+
+// 1-based line and column (in characters) of a byte offset
+fn line_col(source: &str, offset: usize) -> LineCol {
+    let offset = offset.min(source.len());
+    let mut line = 1;
+    let mut line_start = 0;
+    for (i, c) in source.char_indices() {
+        if i >= offset {
+            break;
+        }
+        if c == '\n' {
+            line += 1;
+            line_start = i + 1;
+        }
+    }
+    let column = source.get(line_start..offset).map(|s| s.chars().count()).unwrap_or(0) + 1;
+    LineCol { line, column }
+}
+
+/// Output style for diagnostics on the command line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageFormat {
+    Human,
+    Json,
+}
+
+/// Render a batch of diagnostics in the requested format
+pub fn render_all(diagnostics: &[Diagnostic], file_name: &str, source: &str, format: MessageFormat) -> String {
+    let mut out = String::new();
+    for diagnostic in diagnostics {
+        match format {
+            MessageFormat::Human => {
+                out.push_str(&diagnostic.render(file_name, source));
+                out.push('\n');
+            },
+            MessageFormat::Json => {
+                out.push_str(&diagnostic.to_json(file_name, source).to_string());
+                out.push('\n');
+            },
+        }
+    }
+    out
+}
+
+/// Diagnostic codes; each one has a long explanation available through `gaia explain`
+pub mod codes {
+    pub const SYNTAX_ERROR: &str = "E0001";
+    pub const UNKNOWN_COMPONENT: &str = "E0002";
+    pub const EMPTY_EXPRESSION: &str = "E0003";
+    pub const BACKEND_FAILURE: &str = "E0004";
+    pub const UNSUPPORTED_NODE: &str = "E0005";
+    pub const UNKNOWN_BACKEND: &str = "E0006";
+    pub const IO_ERROR: &str = "E0007";
+    pub const UNKNOWN_LOSS: &str = "E0008";
+    pub const COMPONENT_REDEFINED: &str = "W0001";
+    pub const SHAPE_UNKNOWN_INPUT: &str = "E0101";
+    pub const SHAPE_RANK_MISMATCH: &str = "E0102";
+    pub const SHAPE_INJECTION_MISMATCH: &str = "E0103";
+    pub const SHAPE_ELEMENT_MISMATCH: &str = "E0104";
+    pub const SHAPE_TOO_SMALL: &str = "E0105";
+    pub const SHAPE_SHARE_MISMATCH: &str = "E0106";
+    pub const INVALID_LAYER_PARAM: &str = "E0107";
+    pub const UNKNOWN_CUSTOM_OP: &str = "E0108";
+    pub const CUSTOM_OP_REJECTED: &str = "E0109";
+    pub const SHAPE_SHARED_BLOCK: &str = "E0110";
+    pub const TOKENIZER_ERROR: &str = "E0111";
+    pub const TOKENIZER_VOCAB_MISMATCH: &str = "E0112";
+    pub const SHAPE_HEADS_MISMATCH: &str = "E0113";
+    pub const SHAPE_DEFAULTED: &str = "W0101";
+}
+
+/// Long-form explanation of a diagnostic code
+pub struct Explanation {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub text: &'static str,
+}
+
+pub const EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: codes::SYNTAX_ERROR,
+        title: "syntax error",
+        text: "The source does not match the GaiaScript grammar at the marked position.
+
+A program starts with a network declaration (`N` or `Ψ`), optionally followed by a
+component group such as `〈Ñ⊕Ŧ〉`, and then component definitions of the form
+`id:expr` where `expr` is a chain of inputs, layers and blocks joined by `→`:
+
+    N〈Ñ〉
+    Ñ:I 28×28×1→C₁ 32 3 ρ→P→F→D₀ 10→S
+
+Check the symbol at the marked position against the list of expected tokens.",
+    },
+    Explanation {
+        code: codes::UNKNOWN_COMPONENT,
+        title: "unknown component",
+        text: "A component was referenced before it was defined.
+
+Components are resolved in source order, so a reference such as `G(Z)` or the
+target of an injection `…⊳D` must come after the definition `G:…` / `D:…`:
+
+    G:Z 100→D₁ 256 ρ→D₀ 784 τ
+    D:I 784→D₁ 256 ρ→D₀ 1 σ
+    L:G(Z)⊳D⟿BCE
+
+Define the component first, or fix the spelling of the reference.",
+    },
+    Explanation {
+        code: codes::EMPTY_EXPRESSION,
+        title: "empty expression",
+        text: "A component, block or loss contains no construct the compiler understands.
+
+This happens when a definition only contains symbols that are parsed but not yet
+lowered to the AST. Add at least one input, layer, block or component reference.",
+    },
+    Explanation {
+        code: codes::BACKEND_FAILURE,
+        title: "backend failure",
+        text: "The selected backend could not translate the program.
+
+The message names the construct or the operation that failed. Try a different
+backend with `--backend=NAME`; `gaia targets` lists every backend together with
+the features (ui, nn, 3d, native) it supports.",
+    },
+    Explanation {
+        code: codes::UNSUPPORTED_NODE,
+        title: "unsupported construct",
+        text: "The backend does not support a construct used in the program.
+
+Not every backend implements every part of the language. For example the React
+backend only emits user interfaces, while the assembly backends only emit neural
+networks. Use `gaia targets` to find a backend with the required capability.",
+    },
+    Explanation {
+        code: codes::UNKNOWN_BACKEND,
+        title: "unknown backend",
+        text: "The name given to `--backend` is not registered.
+
+Run `gaia targets` for the list of available backends. Additional backends can be
+registered from Rust through `UniversalCompiler::register_backend`.",
+    },
+    Explanation {
+        code: codes::IO_ERROR,
+        title: "input/output error",
+        text: "A source file could not be read, or an output file could not be written.
+
+Check that the path exists and that the output directory (`--output=DIR`) is
+writable.",
+    },
+    Explanation {
+        code: codes::UNKNOWN_LOSS,
+        title: "unknown loss function",
+        text: "A loss expression after `⟿` names a loss that does not exist.
+
+A loss function is a sum of terms, each optionally preceded by a coefficient
+(a number or a symbol such as `λ`):
+
+    MSE, BCE, CE, Hinge, KL     base losses
+    L1, L2                      regularizers over every trained weight
+    ‖G‖ or ‖G‖₁                 regularizer over the weights of component G
+    ‖∇D‖                        gradient penalty of component D
+
+For example:
+
+    L:G(Z)⊳D⟿BCE+λ‖∇D‖
+    C:M⊳G⟿MSE+0.01·L2",
+    },
+    Explanation {
+        code: codes::COMPONENT_REDEFINED,
+        title: "component redefined",
+        text: "A component identifier was defined more than once.
+
+Later references resolve to the most recent definition. This is allowed, since
+different component groups may reuse short identifiers, but it is often a typo:
+
+    G:Z→U→C τ
+    G:I→C ρ→F→D₀ 10    // shadows the generator above
+
+Rename one of the components if both are meant to be used.",
+    },
+    Explanation {
+        code: codes::SHAPE_UNKNOWN_INPUT,
+        title: "unknown input shape",
+        text: "A layer is not fed by anything whose shape is known.
+
+Shapes flow from the input at the start of a component through every layer. A
+component that starts directly with a layer has nothing to infer from:
+
+    Ñ:C₁ 32 3 ρ→P→F→D₀ 10          // no input
+    Ñ:I 28×28×1→C₁ 32 3 ρ→P→F→D₀ 10
+
+Start the component with an input (`I`, `T`, `S` or `Z`) followed by its dimensions.",
+    },
+    Explanation {
+        code: codes::SHAPE_RANK_MISMATCH,
+        title: "layer received a tensor of the wrong rank",
+        text: "A layer was given a tensor with the wrong number of dimensions.
+
+Convolution, pooling and upsampling expect height×width×channels, LSTM expects
+steps×features and embedding expects a sequence of token ids. The most common
+cause is a missing flatten or reshape between a dense and a convolutional part:
+
+    Ñ:I 28×28×1→F→C₁ 32 3 ρ     // convolution after flatten
+    Ñ:I 28×28×1→C₁ 32 3 ρ→F     // flatten after convolution",
+    },
+    Explanation {
+        code: codes::SHAPE_INJECTION_MISMATCH,
+        title: "injected tensor does not match the receiving input",
+        text: "A tensor is fed into a component whose input has a different shape.
+
+In `L:G(Z)⊳D⟿BCE` the output of `G` becomes the input of `D`, so the last layer of
+`G` must produce exactly the shape declared by the first input of `D`:
+
+    G:Z 100→D₁ 256 ρ→D₀ 784 τ
+    D:I 784→D₁ 256 ρ→D₀ 1 σ
+    L:G(Z)⊳D⟿BCE
+
+The same check applies when a component with declared dimensions is inlined
+after another layer.",
+    },
+    Explanation {
+        code: codes::SHAPE_ELEMENT_MISMATCH,
+        title: "reshape changes the number of elements",
+        text: "A reshape must keep the total number of elements.
+
+The product of the target dimensions has to equal the product of the incoming
+dimensions. For example a 784-element vector can become `R 28×28×1`, but not
+`R 28×28×3`.",
+    },
+    Explanation {
+        code: codes::SHAPE_TOO_SMALL,
+        title: "window larger than the input",
+        text: "A convolution kernel or pooling window is larger than the spatial size of its input.
+
+This usually happens after too many pooling steps, for example when a block
+such as `[C→P]×⋱` is repeated more often than the image can be halved. Reduce the
+repetitions, the pooling size, or use a larger input.",
+    },
+    Explanation {
+        code: codes::SHAPE_SHARE_MISMATCH,
+        title: "shared components have different parameters",
+        text: "Two components joined by `≜` do not have identical parameters.
+
+`E≜D` makes `E` run with the weights of `D`, so both components must contain the
+same layers with the same parameter shapes, in the same order:
+
+    D:I 784→D₁ 256 ρ→D₀ 1 σ
+    E:I 784→D₁ 256 ρ→D₀ 1 σ
+    E≜D
+
+A component cannot share with itself, and sharing chains (`F≜E`, `E≜D`) resolve to
+the final owner.",
+    },
+    Explanation {
+        code: codes::INVALID_LAYER_PARAM,
+        title: "invalid layer parameter",
+        text: "A layer was given values its parameter schema does not accept.
+
+Positional values fill the parameters of a layer in order, and `name=value`
+overrides one by name. Omitted parameters take their defaults:
+
+    C   filters (32), kernel (3), stride (1), padding (1)
+    Cᵀ  filters (32), kernel (3), stride (2)
+    P   size (2), stride (0), padding (0)
+    D   units (128)
+    L   units (128)
+    U   factor (2), or a target shape such as `U 4×4×512`
+    E   dim (128), vocab (10000)
+    H   heads (1)
+    A   causal (0)
+    B   batch (32)
+    R   a target shape such as `R 7×7×64`
+    F   no parameters
+
+Padding is 1 (\"same\", every position starts a window) or 0 (\"valid\", only
+windows that fit), and a pooling stride of 0 steps by the window size. Every
+other value must be at least 1 (`causal` is 0 or 1), a parameter can only be
+given once, and a layer cannot take more positional values than it has
+parameters. Blocks repeat at least once, so `[…]×0` is rejected too. Only `E`
+can be followed by a positional encoding `+P`:
+
+    Ñ:I 28×28×1→C₁ 32 kernel=5 ρ→P size=2→F→D₀ 10",
+    },
+    Explanation {
+        code: codes::UNKNOWN_CUSTOM_OP,
+        title: "unknown custom operation",
+        text: "A layer written `⇝name` does not match any registered custom operation.
+
+Custom operations are implemented in Rust with the `CustomOp` trait and made
+added to the `CustomOpRegistry` the program is parsed with, e.g. through
+`UniversalCompiler::register_custom_op`. The symbol after `⇝` must match
+`CustomOp::symbol` exactly:
+
+    compiler.register_custom_op(Swish);
+    Ñ:I 28×28×1→C₁ 32 3→⇝swish→P→F→D₀ 10",
+    },
+    Explanation {
+        code: codes::CUSTOM_OP_REJECTED,
+        title: "custom operation rejected its input",
+        text: "The shape function of a custom operation did not accept its input shape.
+
+The label gives the reason reported by `CustomOp::output_shape`. Reshape the
+input before the operation or change its parameters.",
+    },
+    Explanation {
+        code: codes::SHAPE_SHARED_BLOCK,
+        title: "block with shared parameters changes the shape",
+        text: "A block marked `≜` does not return the shape it was given.
+
+`[…]×n≜` applies the same parameters in every repetition, so each repetition
+must see the same input shape, which means the content has to map its input
+shape to itself:
+
+    Ñ:I 28×28×16→[C₁ 16 3 ρ]×4≜→F→D₀ 10
+
+Match the filters or units of the last layer to the input of the block, or drop
+`≜` to give every repetition its own parameters. Without `≜` (or with `≠`) each
+repetition only has to accept the output of the one before.",
+    },
+    Explanation {
+        code: codes::TOKENIZER_ERROR,
+        title: "invalid tokenizer",
+        text: "The tokenizer of an input could not be set up.
+
+Only text inputs (`T`) take a tokenizer, written after the sequence length with
+its vocabulary files, which are read relative to the source file:
+
+    Ñ:T 64 ⌸ wordpiece \"vocab.txt\"→E 128→L 64→D₀ 2 S
+    Ñ:T 64 ⌸ bpe \"vocab.json\" \"merges.txt\"→E 128→L 64→D₀ 2 S
+    Ñ:T 64 ⌸ char→E 32→L 64→D₀ 2 S
+
+WordPiece takes one file with a token per line, BPE takes a JSON map of tokens to
+ids and a file of merges in priority order, and `char` takes an optional file
+with a character per line. Without a file `char` uses the UTF-8 bytes of the
+text as tokens 1 to 256. Check that the files exist and match the format.",
+    },
+    Explanation {
+        code: codes::TOKENIZER_VOCAB_MISMATCH,
+        title: "embedding too small for the tokenizer",
+        text: "An embedding layer has fewer rows than its tokenizer has tokens.
+
+The `E` that embeds the ids of a tokenized text input takes its vocabulary size
+from the tokenizer. When `E` sets a vocabulary itself, it must have a row for
+every token id the tokenizer produces:
+
+    Ñ:T 64 ⌸ char→E 32 257→L 64
+
+Leave out the vocabulary of `E` to take it from the tokenizer.",
+    },
+    Explanation {
+        code: codes::SHAPE_HEADS_MISMATCH,
+        title: "attention heads do not divide the features",
+        text: "An attention layer cannot split its features evenly between its heads.
+
+The heads declared by the last `H` each attend over `features / heads` of the
+features, so the number of heads must divide the feature count of the `A`
+that follows:
+
+    Ñ:T 4→E 8 16→H 4→A",
+    },
+    Explanation {
+        code: codes::SHAPE_DEFAULTED,
+        title: "input shape assumed",
+        text: "An input was declared without dimensions, so a default shape was assumed.
+
+The defaults are 224×224×3 for images (`I`), 128 for text (`T`), 100×1 for
+sequences (`S`) and 100 for latent vectors (`Z`). Declare the dimensions
+explicitly to silence this warning:
+
+    Ñ:I 28×28×1→C₁ 32 3 ρ→P→F→D₀ 10",
+    },
+];
+
+/// Look up the explanation for a code such as `E0002`
+pub fn explain(code: &str) -> Option<&'static Explanation> {
+    let code = code.trim().to_uppercase();
+    EXPLANATIONS.iter().find(|e| e.code == code)
+}
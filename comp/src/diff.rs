@@ -0,0 +1,474 @@
+// Structural diff of two programs: components are matched by name and the
+// layers, blocks and interface elements inside them are aligned in order, so a
+// revised one-line network reads as a list of edits rather than a text diff.
+
+use crate::ast::*;
+use crate::shape_inference::input_shape;
+use crate::summary::{self, layer_kind, thousands};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// One difference inside a component; `at` is the 1-based position of the
+/// node, with `.` stepping into a block (`2.1` is the first node of the
+/// block at position 2)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    Added { at: String, node: String, kind: &'static str },
+    Removed { at: String, node: String, kind: &'static str },
+    /// A resolved layer parameter, an input setting, a block's repetitions or
+    /// sharing, or an interface element's text or size
+    Parameter { at: String, node: String, name: String, from: String, to: String },
+    Activation { at: String, node: String, from: String, to: String },
+    /// Output shape of a node whose own settings did not change
+    Shape { at: String, node: String, from: String, to: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl Status {
+    fn sign(&self) -> char {
+        match self {
+            Status::Added => '+',
+            Status::Removed => '-',
+            Status::Changed => '~',
+        }
+    }
+}
+
+/// Differences of a component present in either program
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentDiff {
+    pub id: String,
+    pub status: Status,
+    /// The component as written, for added and removed ones
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Trainable parameters before and after, when they differ
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<(usize, usize)>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<Change>,
+}
+
+/// A loss or `≜` declaration added, removed or rewritten
+#[derive(Debug, Clone, Serialize)]
+pub struct DeclarationDiff {
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+}
+
+/// Everything that differs between two programs
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgramDiff {
+    pub components: Vec<ComponentDiff>,
+    pub losses: Vec<DeclarationDiff>,
+    pub shares: Vec<DeclarationDiff>,
+}
+
+// A node of a component as compared: nodes align when their keys match
+struct Node {
+    key: String,
+    // Symbol of the node, and the node as written for additions and removals
+    label: String,
+    text: String,
+    kind: &'static str,
+    fields: Vec<(String, String)>,
+    activation: Option<String>,
+    shape: Option<String>,
+    children: Vec<Node>,
+}
+
+/// Compare two analyzed programs
+pub fn diff(before: &ASTNode, after: &ASTNode) -> ProgramDiff {
+    let (old, new) = (components(before), components(after));
+    let (old_params, new_params) = (parameter_counts(before), parameter_counts(after));
+    let mut result = Vec::new();
+    
+    for (id, component) in &old {
+        let Some((_, revised)) = new.iter().find(|(other, _)| other == id) else {
+            result.push(ComponentDiff {
+                id: id.clone(),
+                status: Status::Removed,
+                source: Some(component.to_string()),
+                parameters: None,
+                changes: Vec::new(),
+            });
+            continue;
+        };
+        let mut changes = Vec::new();
+        align(&nodes(&component.expr), &nodes(&revised.expr), "", &mut changes);
+        let counts = (old_params.get(id).copied().unwrap_or(0), new_params.get(id).copied().unwrap_or(0));
+        if !changes.is_empty() || counts.0 != counts.1 {
+            result.push(ComponentDiff {
+                id: id.clone(),
+                status: Status::Changed,
+                source: None,
+                parameters: Some(counts).filter(|(a, b)| a != b),
+                changes,
+            });
+        }
+    }
+    for (id, component) in &new {
+        if !old.iter().any(|(other, _)| other == id) {
+            result.push(ComponentDiff {
+                id: id.clone(),
+                status: Status::Added,
+                source: Some(component.to_string()),
+                parameters: None,
+                changes: Vec::new(),
+            });
+        }
+    }
+    
+    ProgramDiff {
+        components: result,
+        losses: declarations(&losses(before), &losses(after)),
+        shares: declarations(&shares(before), &shares(after)),
+    }
+}
+
+// Components by name in order of definition; a redefinition replaces the
+// earlier one, as in `find_component`
+fn components(ast: &ASTNode) -> Vec<(String, &ComponentNode)> {
+    let mut found: Vec<(String, &ComponentNode)> = Vec::new();
+    if let ASTNode::Network(network) = ast {
+        for node in &network.body {
+            if let ASTNode::Component(component) = node {
+                match found.iter_mut().find(|(id, _)| *id == component.id) {
+                    Some(entry) => entry.1 = component,
+                    None => found.push((component.id.clone(), component)),
+                }
+            }
+        }
+    }
+    found
+}
+
+fn parameter_counts(ast: &ASTNode) -> HashMap<String, usize> {
+    summary::summarize(ast).components.into_iter().map(|c| (c.id, c.total_params)).collect()
+}
+
+// Losses keyed by their label, or by their text when unlabeled
+fn losses(ast: &ASTNode) -> Vec<(String, String)> {
+    let ASTNode::Network(network) = ast else {
+        return Vec::new();
+    };
+    network.body.iter().filter_map(|node| match node {
+        ASTNode::Loss(loss) => Some((loss.label.clone().unwrap_or_else(|| loss_text(loss)), loss_text(loss))),
+        _ => None,
+    }).collect()
+}
+
+// A loss written with the names of its components rather than their inlined
+// definitions, which are compared on their own
+fn loss_text(loss: &LossNode) -> String {
+    let mut text = loss.label.as_ref().map(|label| format!("{}: ", label)).unwrap_or_default();
+    match loss.sources.as_slice() {
+        [source] => text.push_str(source),
+        _ => text.push_str(&loss.from.to_string()),
+    }
+    for target in &loss.targets {
+        text.push_str(&format!("⊳{}{}", target, if loss.is_frozen(target) { "⊘" } else { "" }));
+    }
+    format!("{}⟿{}", text, loss.function)
+}
+
+fn shares(ast: &ASTNode) -> Vec<(String, String)> {
+    let ASTNode::Network(network) = ast else {
+        return Vec::new();
+    };
+    network.shares.iter().map(|share| (share.component.clone(), share.to_string())).collect()
+}
+
+fn declarations(old: &[(String, String)], new: &[(String, String)]) -> Vec<DeclarationDiff> {
+    let mut result = Vec::new();
+    for (key, text) in old {
+        match new.iter().find(|(other, _)| other == key) {
+            None => result.push(DeclarationDiff { status: Status::Removed, from: Some(text.clone()), to: None }),
+            Some((_, revised)) if revised != text => result.push(DeclarationDiff {
+                status: Status::Changed,
+                from: Some(text.clone()),
+                to: Some(revised.clone()),
+            }),
+            Some(_) => {},
+        }
+    }
+    for (key, text) in new {
+        if !old.iter().any(|(other, _)| other == key) {
+            result.push(DeclarationDiff { status: Status::Added, from: None, to: Some(text.clone()) });
+        }
+    }
+    result
+}
+
+// The nodes of an expression in data flow order
+fn nodes(expr: &ASTNode) -> Vec<Node> {
+    let mut out = Vec::new();
+    collect(expr, &mut out);
+    out
+}
+
+fn collect(node: &ASTNode, out: &mut Vec<Node>) {
+    match node {
+        ASTNode::DataFlow(from, to) => {
+            collect(from, out);
+            collect(to, out);
+        },
+        ASTNode::Expression(nodes) => {
+            for node in nodes {
+                collect(node, out);
+            }
+        },
+        ASTNode::Input(input) => {
+            let mut fields = vec![("shape".to_string(), input_shape(input).0.to_string())];
+            if let Some(preprocess) = input.preprocess {
+                fields.push(("normalization".to_string(), match preprocess {
+                    Preprocess::Standardize => "⍓".to_string(),
+                    Preprocess::Scale(divisor) => format!("⍓ {}", divisor),
+                }));
+            }
+            if let Some(tokenizer) = &input.tokenizer {
+                let files: Vec<String> = tokenizer.files.iter().map(|f| format!(" \"{}\"", f)).collect();
+                fields.push(("tokenizer".to_string(), format!("{}{}", tokenizer.kind.name(), files.concat())));
+            }
+            let text = input.to_string();
+            let label = text.split(' ').next().unwrap_or_default().to_string();
+            out.push(Node {
+                key: format!("input {}", label),
+                label,
+                text,
+                kind: "Input",
+                fields,
+                activation: None,
+                shape: None,
+                children: Vec::new(),
+            });
+        },
+        ASTNode::Layer(layer) => {
+            let mut fields = vec![("layer".to_string(), layer.symbol())];
+            fields.extend(layer.resolved_params().into_iter().map(|(name, value)| (name.to_string(), value.to_string())));
+            if layer.positional {
+                fields.push(("positional".to_string(), "+P".to_string()));
+            }
+            out.push(Node {
+                key: layer_kind(layer).to_string(),
+                label: layer.symbol(),
+                text: layer.to_string(),
+                kind: layer_kind(layer),
+                fields,
+                activation: Some(activation_symbol(&layer.activation).to_string()),
+                shape: layer.output_shape.as_ref().map(TensorShape::to_string),
+                children: Vec::new(),
+            });
+        },
+        ASTNode::Block(block) => {
+            let sharing = if block.shared { "≜" } else { "≠" };
+            out.push(Node {
+                key: "block".to_string(),
+                label: format!("[…]×{}", block.repetitions),
+                text: block.to_string(),
+                kind: "Block",
+                fields: vec![
+                    ("repetitions".to_string(), block.repetitions.to_string()),
+                    ("sharing".to_string(), sharing.to_string()),
+                ],
+                activation: None,
+                shape: block.shapes.last().map(TensorShape::to_string),
+                children: nodes(&block.content),
+            });
+        },
+        ASTNode::UIComponent(element) => {
+            let mut fields = Vec::new();
+            if let Some((width, height)) = element.dimensions {
+                fields.push(("size".to_string(), format!("{}×{}", width, height)));
+            }
+            if let Some(text) = element.properties.get("text") {
+                fields.push(("text".to_string(), format!("\"{}\"", text)));
+            }
+            out.push(Node {
+                key: element.component_type.name().to_string(),
+                label: element.component_type.symbol().to_string(),
+                text: element.to_string(),
+                kind: element.component_type.name(),
+                fields,
+                activation: None,
+                shape: None,
+                children: Vec::new(),
+            });
+        },
+        // A referenced component is compared on its own
+        ASTNode::Component(component) => out.push(Node {
+            key: format!("component {}", component.id),
+            label: component.id.clone(),
+            text: component.id.clone(),
+            kind: "Component",
+            fields: Vec::new(),
+            activation: None,
+            shape: None,
+            children: Vec::new(),
+        }),
+        other => out.push(Node {
+            key: "other".to_string(),
+            label: other.to_string(),
+            text: other.to_string(),
+            kind: "Expression",
+            fields: vec![("source".to_string(), other.to_string())],
+            activation: None,
+            shape: None,
+            children: Vec::new(),
+        }),
+    }
+}
+
+fn activation_symbol(activation: &ActivationFunction) -> &'static str {
+    match activation {
+        ActivationFunction::ReLU => "ρ",
+        ActivationFunction::Sigmoid => "σ",
+        ActivationFunction::Tanh => "τ",
+        ActivationFunction::Softmax => "S",
+        ActivationFunction::None => "none",
+    }
+}
+
+// Align two node lists on a longest common subsequence of their keys, then
+// compare the pairs that line up
+fn align(old: &[Node], new: &[Node], prefix: &str, changes: &mut Vec<Change>) {
+    let (n, m) = (old.len(), new.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old[i].key == new[j].key {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    
+    let at = |index: usize| format!("{}{}", prefix, index + 1);
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old[i].key == new[j].key {
+            compare(&old[i], &new[j], &at(j), changes);
+            i += 1;
+            j += 1;
+        } else if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            changes.push(Change::Added { at: at(j), node: new[j].text.clone(), kind: new[j].kind });
+            j += 1;
+        } else {
+            changes.push(Change::Removed { at: at(i), node: old[i].text.clone(), kind: old[i].kind });
+            i += 1;
+        }
+    }
+}
+
+fn compare(old: &Node, new: &Node, at: &str, changes: &mut Vec<Change>) {
+    let start = changes.len();
+    for (name, value) in &new.fields {
+        let before = old.fields.iter().find(|(other, _)| other == name).map(|(_, v)| v.as_str()).unwrap_or("none");
+        if before != value {
+            changes.push(Change::Parameter {
+                at: at.to_string(),
+                node: new.label.clone(),
+                name: name.clone(),
+                from: before.to_string(),
+                to: value.clone(),
+            });
+        }
+    }
+    for (name, value) in &old.fields {
+        if !new.fields.iter().any(|(other, _)| other == name) {
+            changes.push(Change::Parameter {
+                at: at.to_string(),
+                node: new.label.clone(),
+                name: name.clone(),
+                from: value.clone(),
+                to: "none".to_string(),
+            });
+        }
+    }
+    if let (Some(from), Some(to)) = (&old.activation, &new.activation) {
+        if from != to {
+            changes.push(Change::Activation { at: at.to_string(), node: new.label.clone(), from: from.clone(), to: to.clone() });
+        }
+    }
+    if changes.len() == start {
+        if let (Some(from), Some(to)) = (&old.shape, &new.shape) {
+            if from != to {
+                changes.push(Change::Shape { at: at.to_string(), node: new.label.clone(), from: from.clone(), to: to.clone() });
+            }
+        }
+    }
+    align(&old.children, &new.children, &format!("{}.", at), changes);
+}
+
+impl ProgramDiff {
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty() && self.losses.is_empty() && self.shares.is_empty()
+    }
+    
+    /// Render the differences as text: `+` added, `-` removed, `~` changed,
+    /// with one line per changed node
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for component in &self.components {
+            match (&component.source, component.parameters) {
+                (Some(source), _) => out.push_str(&format!("{} {}\n", component.status.sign(), source)),
+                (None, Some((from, to))) => out.push_str(&format!("~ {} (parameters {} → {})\n", component.id, thousands(from), thousands(to))),
+                (None, None) => out.push_str(&format!("~ {}\n", component.id)),
+            }
+            
+            // Changes to the same node share a line
+            let mut lines: Vec<(String, Vec<String>)> = Vec::new();
+            for change in &component.changes {
+                let (head, detail) = match change {
+                    Change::Added { at, node, kind } => (format!("+ {} {} ({})", at, node, kind), None),
+                    Change::Removed { at, node, kind } => (format!("- {} {} ({})", at, node, kind), None),
+                    Change::Parameter { at, node, name, from, to } => (format!("~ {} {}", at, node), Some(format!("{} {} → {}", name, from, to))),
+                    Change::Activation { at, node, from, to } => (format!("~ {} {}", at, node), Some(format!("activation {} → {}", from, to))),
+                    Change::Shape { at, node, from, to } => (format!("~ {} {}", at, node), Some(format!("output {} → {}", from, to))),
+                };
+                match lines.last_mut() {
+                    Some((last, details)) if *last == head && detail.is_some() => details.extend(detail),
+                    _ => lines.push((head, detail.into_iter().collect())),
+                }
+            }
+            for (head, details) in lines {
+                if details.is_empty() {
+                    out.push_str(&format!("    {}\n", head));
+                } else {
+                    out.push_str(&format!("    {}: {}\n", head, details.join(", ")));
+                }
+            }
+        }
+        for (kind, declarations) in [("loss", &self.losses), ("share", &self.shares)] {
+            for declaration in declarations {
+                match (&declaration.from, &declaration.to) {
+                    (Some(from), Some(to)) => out.push_str(&format!("~ {} {}\n    → {}\n", kind, from, to)),
+                    (Some(text), None) | (None, Some(text)) => out.push_str(&format!("{} {} {}\n", declaration.status.sign(), kind, text)),
+                    (None, None) => {},
+                }
+            }
+        }
+        if self.is_empty() {
+            out.push_str("No structural differences\n");
+        }
+        out
+    }
+    
+    /// Render the differences as JSON
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+}
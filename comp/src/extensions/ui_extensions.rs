@@ -1,7 +1,8 @@
-use crate::ast::{ASTNode, SymbolTable};
+use crate::ast::ASTNode;
 use std::collections::HashMap;
+use std::fmt;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum UIComponentType {
     Canvas,
     Panel,
@@ -10,13 +11,49 @@ pub enum UIComponentType {
     Label,
 }
 
+impl UIComponentType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            UIComponentType::Canvas => "Canvas",
+            UIComponentType::Panel => "Panel",
+            UIComponentType::Layout => "Layout",
+            UIComponentType::Button => "Button",
+            UIComponentType::Label => "Label",
+        }
+    }
+    
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UIComponentType::Canvas => "∮",
+            UIComponentType::Panel => "П",
+            UIComponentType::Layout => "⊞",
+            UIComponentType::Button => "⌘",
+            UIComponentType::Label => "⌑",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct UIComponentNode {
     pub component_type: UIComponentType,
     pub dimensions: Option<(usize, usize)>,
+    // `text` holds the caption of buttons and labels
     pub properties: HashMap<String, String>,
 }
 
+impl fmt::Display for UIComponentNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.component_type.symbol())?;
+        if let Some((width, height)) = self.dimensions {
+            write!(f, " {}×{}", width, height)?;
+        }
+        if let Some(text) = self.properties.get("text") {
+            write!(f, "\"{}\"", text)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct EventHandlerNode {
     pub event_type: String,
@@ -0,0 +1,433 @@
+// Architecture diagrams: Graphviz DOT, and SVG from a built-in layered layout
+// so that no `dot` binary is needed
+
+use crate::ast::*;
+use crate::shape_inference::{input_shape, layer_output_shape};
+use crate::summary::layer_kind;
+use crate::layer_params::target_shape;
+
+/// What a node of the diagram stands for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeKind {
+    Input,
+    Layer,
+    Loss,
+}
+
+/// A box of the diagram; the first line is its title
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub lines: Vec<String>,
+}
+
+/// A frame around the consecutive nodes `first..=last`: a component, or a
+/// repeated block inside one (`depth` 1 for a top-level block)
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub label: String,
+    pub first: usize,
+    pub last: usize,
+    pub depth: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    // `⊳` edges carry the output of one component into another
+    pub injection: bool,
+}
+
+/// Diagram of the components of a program, with the losses wiring them
+#[derive(Debug, Clone, Default)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub components: Vec<Frame>,
+    pub blocks: Vec<Frame>,
+    pub edges: Vec<Edge>,
+    // Component index a loss node is drawn under
+    loss_columns: Vec<(usize, usize)>,
+}
+
+/// Build the diagram of every component with layers, or of `component` only
+///
+/// Shapes are computed from each component's own input, as in the summary; a
+/// layer whose shape cannot be inferred shows `?`. Losses connect the
+/// components they chain with `⊳` edges and are left out for a single
+/// component.
+pub fn build(ast: &ASTNode, component: Option<&str>) -> Result<Graph, String> {
+    let mut graph = Graph::default();
+    let ASTNode::Network(network) = ast else {
+        return Err("expected a network".to_string());
+    };
+    
+    for node in &network.body {
+        if let ASTNode::Component(c) = node {
+            if component.is_some_and(|id| id != c.id) {
+                continue;
+            }
+            let first = graph.nodes.len();
+            let mut previous = None;
+            graph.walk(&c.expr, None, &mut previous, 1);
+            if !graph.nodes[first..].iter().any(|n| n.kind == NodeKind::Layer) {
+                graph.truncate(first);
+                continue;
+            }
+            graph.components.push(Frame { label: c.id.clone(), first, last: graph.nodes.len() - 1, depth: 0 });
+        }
+    }
+    if let Some(id) = component {
+        if graph.components.is_empty() {
+            return Err(format!("component `{}` has no layers", id));
+        }
+        return Ok(graph);
+    }
+    
+    for node in &network.body {
+        let ASTNode::Loss(loss) = node else { continue };
+        let chain: Vec<usize> = loss.chain().iter()
+            .filter_map(|id| graph.components.iter().position(|f| f.label == *id))
+            .collect();
+        for pair in chain.windows(2) {
+            let (from, to) = (graph.components[pair[0]].last, graph.components[pair[1]].first);
+            // Losses sharing a chain (e.g. both sides of a GAN) draw it once
+            if !graph.edges.iter().any(|e| e.injection && e.from == from && e.to == to) {
+                graph.edges.push(Edge { from, to, injection: true });
+            }
+        }
+        if let Some(&last) = chain.last() {
+            let title = match &loss.label {
+                Some(label) => format!("{} ⟿ {}", label, loss.function),
+                None => format!("⟿ {}", loss.function),
+            };
+            let from = graph.components[last].last;
+            graph.nodes.push(Node { kind: NodeKind::Loss, lines: vec![title, loss.chain().join("⊳")] });
+            graph.edges.push(Edge { from, to: graph.nodes.len() - 1, injection: false });
+            graph.loss_columns.push((graph.nodes.len() - 1, last));
+        }
+    }
+    Ok(graph)
+}
+
+impl Graph {
+    // Add the nodes of an expression, linking each to the one before it;
+    // returns the output shape
+    fn walk(&mut self, node: &ASTNode, input: Option<TensorShape>, previous: &mut Option<usize>, depth: usize) -> Option<TensorShape> {
+        match node {
+            ASTNode::Input(input_node) => {
+                let (declared, defaulted) = input_shape(input_node);
+                let shape = match input {
+                    Some(incoming) if defaulted => incoming,
+                    _ => declared,
+                };
+                let title = match input_node.input_type {
+                    InputType::Image => "I image",
+                    InputType::Text => "T text",
+                    InputType::Sequence => "S sequence",
+                    InputType::Latent => "Z latent",
+                };
+                self.push(Node { kind: NodeKind::Input, lines: vec![title.to_string(), shape.to_string()] }, previous);
+                Some(shape)
+            },
+            ASTNode::Layer(layer) => {
+                let output = input.as_ref().and_then(|input| layer_output_shape(layer, input).ok());
+                let mut lines = vec![format!("{} {}", layer.symbol(), layer_kind(layer))];
+                let settings = layer_settings(layer);
+                if !settings.is_empty() {
+                    lines.push(settings);
+                }
+                lines.push(output.as_ref().map(|s| s.to_string()).unwrap_or_else(|| "?".to_string()));
+                self.push(Node { kind: NodeKind::Layer, lines }, previous);
+                output
+            },
+            ASTNode::DataFlow(from, to) => {
+                let shape = self.walk(from, input, previous, depth);
+                self.walk(to, shape, previous, depth)
+            },
+            ASTNode::Expression(nodes) => {
+                let mut shape = input;
+                for child in nodes {
+                    shape = self.walk(child, shape, previous, depth);
+                }
+                shape
+            },
+            ASTNode::Block(block) => {
+                // The content is drawn once; later repetitions only carry the shape on
+                let first = self.nodes.len();
+                let mut shape = self.walk(&block.content, input, previous, depth + 1);
+                if self.nodes.len() > first {
+                    self.blocks.push(Frame {
+                        label: format!("×{}{}", block.repetitions, if block.shared { " ≜" } else { "" }),
+                        first,
+                        last: self.nodes.len() - 1,
+                        depth,
+                    });
+                }
+                for _ in 1..block.repetitions.max(1) {
+                    shape = shape.and_then(|s| repeat_shape(&block.content, s));
+                }
+                shape
+            },
+            _ => input,
+        }
+    }
+    
+    fn push(&mut self, node: Node, previous: &mut Option<usize>) {
+        let index = self.nodes.len();
+        self.nodes.push(node);
+        if let Some(from) = previous.replace(index) {
+            self.edges.push(Edge { from, to: index, injection: false });
+        }
+    }
+    
+    // Drop every node from `len` on, with the edges and blocks that use them
+    fn truncate(&mut self, len: usize) {
+        self.nodes.truncate(len);
+        self.edges.retain(|e| e.from < len && e.to < len);
+        self.blocks.retain(|b| b.last < len);
+    }
+    
+    // Column of a node: its component, or the last component of a loss
+    fn column(&self, node: usize) -> usize {
+        self.components.iter().position(|f| (f.first..=f.last).contains(&node))
+            .or_else(|| self.loss_columns.iter().find(|(n, _)| *n == node).map(|(_, c)| *c))
+            .unwrap_or(0)
+    }
+    
+    /// Graphviz source of the diagram; components become clusters, blocks
+    /// nested dashed clusters labeled with their repetitions
+    pub fn to_dot(&self, title: &str) -> String {
+        let mut out = format!("digraph \"{}\" {{\n", dot_escape(title));
+        out.push_str("    rankdir=TB;\n");
+        out.push_str("    node [shape=box, style=\"rounded,filled\", fillcolor=\"#eef2f7\", fontname=\"Helvetica\", fontsize=11];\n");
+        out.push_str("    edge [fontname=\"Helvetica\", fontsize=10];\n");
+        
+        for (c, component) in self.components.iter().enumerate() {
+            out.push_str(&format!("    subgraph cluster_{} {{\n        label=\"{}\";\n", c, dot_escape(&component.label)));
+            let mut open: Vec<&Frame> = Vec::new();
+            for n in component.first..=component.last {
+                let mut starting: Vec<(usize, &Frame)> = self.blocks.iter().enumerate().filter(|(_, b)| b.first == n).collect();
+                starting.sort_by_key(|(_, b)| b.depth);
+                for (b, block) in starting {
+                    let indent = "    ".repeat(open.len() + 2);
+                    out.push_str(&format!("{}subgraph cluster_block_{} {{\n{}    label=\"{}\"; style=dashed;\n",
+                                          indent, b, indent, dot_escape(&block.label)));
+                    open.push(block);
+                }
+                out.push_str(&format!("{}{};\n", "    ".repeat(open.len() + 2), self.dot_node(n)));
+                while open.last().is_some_and(|b| b.last == n) {
+                    open.pop();
+                    out.push_str(&format!("{}}}\n", "    ".repeat(open.len() + 2)));
+                }
+            }
+            out.push_str("    }\n");
+        }
+        for (n, node) in self.nodes.iter().enumerate() {
+            if node.kind == NodeKind::Loss {
+                out.push_str(&format!("    {};\n", self.dot_node(n)));
+            }
+        }
+        for edge in &self.edges {
+            let style = if edge.injection { " [label=\"⊳\", style=dashed, color=\"#c0392b\"]" } else { "" };
+            out.push_str(&format!("    n{} -> n{}{};\n", edge.from, edge.to, style));
+        }
+        out.push_str("}\n");
+        out
+    }
+    
+    fn dot_node(&self, n: usize) -> String {
+        let node = &self.nodes[n];
+        let label: Vec<String> = node.lines.iter().map(|l| dot_escape(l)).collect();
+        let shape = match node.kind {
+            NodeKind::Input => ", shape=ellipse",
+            NodeKind::Layer => "",
+            NodeKind::Loss => ", shape=octagon, fillcolor=\"#fdecea\"",
+        };
+        format!("n{} [label=\"{}\"{}]", n, label.join("\\n"), shape)
+    }
+    
+    /// Standalone SVG of the diagram: one column per component, nodes top to
+    /// bottom in data-flow order
+    pub fn to_svg(&self, title: &str) -> String {
+        let layout = Layout::new(self);
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" font-family=\"Helvetica, Arial, sans-serif\" font-size=\"{f}\">\n",
+            w = layout.width, h = layout.height, f = FONT_SIZE);
+        out.push_str(&format!("<title>{}</title>\n", xml_escape(title)));
+        out.push_str("<defs><marker id=\"arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"7\" markerHeight=\"7\" orient=\"auto\">\
+                      <path d=\"M0,0 L10,5 L0,10 z\" fill=\"#555\"/></marker>\
+                      <marker id=\"inject\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"7\" markerHeight=\"7\" orient=\"auto\">\
+                      <path d=\"M0,0 L10,5 L0,10 z\" fill=\"#c0392b\"/></marker></defs>\n");
+        out.push_str(&format!("<rect width=\"{}\" height=\"{}\" fill=\"white\"/>\n", layout.width, layout.height));
+        
+        for (c, frame) in self.components.iter().enumerate() {
+            let (x, width) = (layout.columns[c].0, layout.columns[c].1);
+            let top = layout.boxes[frame.first].y - FRAME_PADDING - TITLE_HEIGHT;
+            let bottom = layout.boxes[frame.last].y + layout.boxes[frame.last].height + FRAME_PADDING;
+            out.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"8\" fill=\"#fafafa\" stroke=\"#999\"/>\n",
+                                  x, top, width, bottom - top));
+            out.push_str(&format!("<text x=\"{}\" y=\"{}\" font-weight=\"bold\">{}</text>\n",
+                                  x + 10.0, top + 17.0, xml_escape(&frame.label)));
+        }
+        for block in &self.blocks {
+            let c = self.column(block.first);
+            let inset = BLOCK_INSET * block.depth as f64;
+            let (x, width) = (layout.columns[c].0 + inset, layout.columns[c].1 - 2.0 * inset);
+            let margin = BLOCK_MARGIN * (layout.max_depth + 1 - block.depth) as f64;
+            let top = layout.boxes[block.first].y - margin;
+            let bottom = layout.boxes[block.last].y + layout.boxes[block.last].height + margin;
+            out.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"6\" fill=\"none\" stroke=\"#7f8c8d\" stroke-dasharray=\"5,3\"/>\n",
+                                  x, top, width, bottom - top));
+            out.push_str(&format!("<text x=\"{}\" y=\"{}\" text-anchor=\"end\" fill=\"#555\">{}</text>\n",
+                                  x + width - 6.0, top + 15.0, xml_escape(&block.label)));
+        }
+        for edge in &self.edges {
+            out.push_str(&layout.edge(self, edge));
+        }
+        for (n, node) in self.nodes.iter().enumerate() {
+            let b = &layout.boxes[n];
+            let (fill, rx) = match node.kind {
+                NodeKind::Input => ("#e8f6ef", b.height / 2.0),
+                NodeKind::Layer => ("#eef2f7", 6.0),
+                NodeKind::Loss => ("#fdecea", 0.0),
+            };
+            out.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{}\" fill=\"{}\" stroke=\"#34495e\"/>\n",
+                                  b.x, b.y, b.width, b.height, rx, fill));
+            for (i, line) in node.lines.iter().enumerate() {
+                let weight = if i == 0 { " font-weight=\"bold\"" } else { "" };
+                out.push_str(&format!("<text x=\"{}\" y=\"{}\" text-anchor=\"middle\"{}>{}</text>\n",
+                                      b.x + b.width / 2.0, b.y + NODE_PADDING + LINE_HEIGHT * (i as f64 + 0.8), weight, xml_escape(line)));
+            }
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+}
+
+// Parameters, activation and positional encoding of a layer on one line
+fn layer_settings(layer: &LayerNode) -> String {
+    let mut parts: Vec<String> = match target_shape(layer) {
+        Some(shape) => vec![format!("target {}", TensorShape::new(shape.to_vec()))],
+        None => layer.resolved_params().iter().map(|(name, value)| format!("{}={}", name, value)).collect(),
+    };
+    if layer.positional {
+        parts.push("+P".to_string());
+    }
+    match layer.activation {
+        ActivationFunction::ReLU => parts.push("relu".to_string()),
+        ActivationFunction::Sigmoid => parts.push("sigmoid".to_string()),
+        ActivationFunction::Tanh => parts.push("tanh".to_string()),
+        ActivationFunction::Softmax => parts.push("softmax".to_string()),
+        ActivationFunction::None => {},
+    }
+    parts.join(" ")
+}
+
+// Output shape of one more repetition of a block's content
+fn repeat_shape(node: &ASTNode, input: TensorShape) -> Option<TensorShape> {
+    match node {
+        ASTNode::Layer(layer) => layer_output_shape(layer, &input).ok(),
+        ASTNode::DataFlow(from, to) => repeat_shape(to, repeat_shape(from, input)?),
+        ASTNode::Expression(nodes) => nodes.iter().try_fold(input, |shape, child| repeat_shape(child, shape)),
+        ASTNode::Block(block) => (0..block.repetitions.max(1)).try_fold(input, |shape, _| repeat_shape(&block.content, shape)),
+        _ => Some(input),
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// Layout metrics in pixels
+const FONT_SIZE: f64 = 12.0;
+const CHAR_WIDTH: f64 = 7.2;
+const LINE_HEIGHT: f64 = 15.0;
+const NODE_PADDING: f64 = 8.0;
+const NODE_GAP: f64 = 28.0;
+const FRAME_PADDING: f64 = 14.0;
+const TITLE_HEIGHT: f64 = 22.0;
+const BLOCK_INSET: f64 = 8.0;
+const BLOCK_MARGIN: f64 = 6.0;
+// Room for the `×n` labels beside the widest node
+const LABEL_SPACE: f64 = 36.0;
+const COLUMN_GAP: f64 = 70.0;
+const MARGIN: f64 = 20.0;
+
+#[derive(Debug, Clone, Copy)]
+struct Box2 {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+struct Layout {
+    boxes: Vec<Box2>,
+    // Left edge and width of each component column
+    columns: Vec<(f64, f64)>,
+    max_depth: usize,
+    width: f64,
+    height: f64,
+}
+
+impl Layout {
+    fn new(graph: &Graph) -> Self {
+        let max_depth = graph.blocks.iter().map(|b| b.depth).max().unwrap_or(0);
+        let size = |node: &Node| {
+            let chars = node.lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+            ((chars as f64 * CHAR_WIDTH).ceil() + 2.0 * NODE_PADDING + 8.0, node.lines.len() as f64 * LINE_HEIGHT + 2.0 * NODE_PADDING)
+        };
+        let mut boxes = vec![Box2 { x: 0.0, y: 0.0, width: 0.0, height: 0.0 }; graph.nodes.len()];
+        let mut columns = Vec::new();
+        let mut x = MARGIN;
+        let mut height: f64 = 0.0;
+        
+        for (c, frame) in graph.components.iter().enumerate() {
+            let losses: Vec<usize> = graph.loss_columns.iter().filter(|(_, col)| *col == c).map(|(n, _)| *n).collect();
+            let members: Vec<usize> = (frame.first..=frame.last).chain(losses.iter().copied()).collect();
+            let widest = members.iter().map(|&n| size(&graph.nodes[n]).0).fold(0.0, f64::max);
+            let inner = widest + 2.0 * BLOCK_INSET * max_depth as f64 + if max_depth > 0 { 2.0 * LABEL_SPACE } else { 0.0 };
+            let width = inner + 2.0 * FRAME_PADDING;
+            
+            let mut y = MARGIN + TITLE_HEIGHT + FRAME_PADDING + BLOCK_MARGIN * max_depth as f64;
+            for &n in &members {
+                if graph.nodes[n].kind == NodeKind::Loss {
+                    y += FRAME_PADDING + BLOCK_MARGIN * max_depth as f64;
+                }
+                let (w, h) = size(&graph.nodes[n]);
+                boxes[n] = Box2 { x: x + ((width - w) / 2.0).floor(), y, width: w, height: h };
+                y += h + NODE_GAP + 2.0 * BLOCK_MARGIN * max_depth as f64;
+            }
+            height = height.max(y);
+            columns.push((x, width));
+            x += width + COLUMN_GAP;
+        }
+        Layout { boxes, columns, max_depth, width: x - COLUMN_GAP + MARGIN, height: height + MARGIN }
+    }
+    
+    fn edge(&self, graph: &Graph, edge: &Edge) -> String {
+        let (a, b) = (&self.boxes[edge.from], &self.boxes[edge.to]);
+        let (x1, y1) = (a.x + a.width / 2.0, a.y + a.height);
+        let y2 = b.y;
+        if !edge.injection {
+            // Nodes of a column share its center, up to rounding
+            return format!("<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#555\" marker-end=\"url(#arrow)\"/>\n", x1, y1, x1, y2);
+        }
+        // Leave the source on its right and enter the target from the left
+        let (sx, sy) = (a.x + a.width, a.y + a.height / 2.0);
+        let (tx, ty) = (b.x, b.y + b.height / 2.0);
+        let (c1, c2) = if graph.column(edge.from) < graph.column(edge.to) {
+            (sx + COLUMN_GAP, tx - COLUMN_GAP)
+        } else {
+            (sx + COLUMN_GAP, tx - COLUMN_GAP * 2.0)
+        };
+        format!("<path d=\"M{},{} C{},{} {},{} {},{}\" fill=\"none\" stroke=\"#c0392b\" stroke-dasharray=\"6,3\" marker-end=\"url(#inject)\"/>\n\
+                 <text x=\"{}\" y=\"{}\" fill=\"#c0392b\" text-anchor=\"middle\">⊳</text>\n",
+                sx, sy, c1, sy, c2, ty, tx, ty, (sx + tx) / 2.0, (sy + ty) / 2.0 - 4.0)
+    }
+}
@@ -0,0 +1,545 @@
+use crate::ast::*;
+use crate::diagnostics::Span;
+use crate::layer_params::Window;
+use crate::onnx::{self, AttributeValue, Dim, Graph, Node};
+use crate::shape_inference::{self, layer_output_shape};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// Read an ONNX model or a Keras `model.json` and rebuild it as a network with
+/// a single component `component`
+///
+/// Only the architecture is imported. Operations without a GaiaScript
+/// equivalent are all listed in the error.
+pub fn import_file(path: &Path, component: &str) -> Result<NetworkNode, String> {
+    let bytes = fs::read(path).map_err(|e| format!("failed to read '{}': {}", path.display(), e))?;
+    let is_json = path.extension().is_some_and(|ext| ext == "json")
+        || bytes.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{');
+    if is_json {
+        let text = String::from_utf8(bytes).map_err(|_| format!("'{}' is not valid UTF-8", path.display()))?;
+        import_keras(&text, component)
+    } else {
+        import_onnx(&bytes, component)
+    }
+}
+
+/// Import the main path of an ONNX graph, from its input to its output
+pub fn import_onnx(bytes: &[u8], component: &str) -> Result<NetworkNode, String> {
+    let model = onnx::Model::decode(bytes)?;
+    let graph = &model.graph;
+    let is_constant = |name: &str| graph.initializer(name).is_some();
+    
+    let input = graph.inputs.iter().find(|i| !is_constant(&i.name)).ok_or("the ONNX graph has no input")?;
+    let mut dims = Vec::new();
+    for dim in input.dims.iter().skip(1) {
+        match dim {
+            Dim::Value(v) if *v > 0 => dims.push(*v as usize),
+            _ => return Err(format!("input `{}` has a dimension that is not fixed", input.name)),
+        }
+    }
+    let mut chain = Chain::new(component);
+    match dims.as_slice() {
+        [c, h, w] => chain.input(InputType::Image, vec![*h, *w, *c]),
+        [steps, features] => chain.input(InputType::Sequence, vec![*steps, *features]),
+        _ => chain.input(InputType::Text, dims),
+    }
+    
+    let mut value = input.name.clone();
+    // Each node is visited at most once, even in a malformed graph with a cycle
+    for _ in 0..graph.nodes.len() {
+        if value.is_empty() {
+            break;
+        }
+        let consumers: Vec<&Node> = graph.nodes.iter().filter(|n| n.inputs.contains(&value)).collect();
+        let node = match consumers.as_slice() {
+            [] => break,
+            [node] => *node,
+            _ => {
+                chain.unsupported(format!("`{}` feeds {} operations; only chains can be imported", value, consumers.len()));
+                break;
+            },
+        };
+        let constant = |index: usize| node.inputs.get(index).and_then(|name| graph.initializer(name));
+        let described = describe(node);
+        
+        match node.op_type.as_str() {
+            "Conv" => match (constant(1), onnx_window(node, graph, chain.shape.as_ref(), true)) {
+                (Some(w), Some(window)) if w.dims.len() == 4 && is_unit(node.ints("dilations")) && node.int("group").unwrap_or(1) == 1 => {
+                    chain.conv(w.dims[0] as usize, window);
+                },
+                _ => chain.unsupported(format!("{} with dilation, groups or padding other than none or \"same\"", described)),
+            },
+            "ConvTranspose" => {
+                let stride = node.ints("strides").and_then(|s| s.first().copied()).unwrap_or(1);
+                match (constant(1), square_kernel(node, graph)) {
+                    (Some(w), Some(k)) if w.dims.len() == 4 && node.int("group").unwrap_or(1) == 1 => {
+                        chain.layer(LayerType::TransposeConv, vec![w.dims[1] as usize, k, stride as usize]);
+                    },
+                    _ => chain.unsupported(format!("{} without constant weights", described)),
+                }
+            },
+            "Gemm" => match constant(1) {
+                Some(w) if w.dims.len() == 2 => {
+                    let units = if node.int("transB") == Some(1) { w.dims[0] } else { w.dims[1] };
+                    chain.layer(LayerType::Dense(1), vec![units as usize]);
+                },
+                _ => chain.unsupported(format!("{} without constant weights", described)),
+            },
+            "MatMul" => match constant(1) {
+                Some(w) if w.dims.len() == 2 => chain.layer(LayerType::Dense(1), vec![w.dims[1] as usize]),
+                _ => chain.unsupported(format!("{} of two computed values", described)),
+            },
+            // Bias of the preceding MatMul or convolution
+            "Add" if chain.follows_weights() && node.inputs.iter().any(|i| is_constant(i)) => {},
+            "MaxPool" => match onnx_window(node, graph, chain.shape.as_ref(), false) {
+                Some(window) if is_unit(node.ints("dilations")) && node.int("ceil_mode").unwrap_or(0) == 0 => chain.pool(window),
+                _ => chain.unsupported(format!("{} with dilation, ceil mode or padding other than none or \"same\"", described)),
+            },
+            "Flatten" => chain.reshape(None),
+            "Reshape" => match constant(1) {
+                Some(shape) => chain.reshape(Some(onnx_shape(&shape.ints))),
+                None => chain.unsupported(format!("{} to a computed shape", described)),
+            },
+            "Resize" | "Upsample" => {
+                let scales = node.inputs.iter().skip(1).rev()
+                    .filter_map(|name| graph.initializer(name))
+                    .find(|t| t.floats.len() == 4);
+                let nearest = !matches!(node.attribute("mode"), Some(AttributeValue::String(m)) if m != "nearest");
+                match scales {
+                    Some(t) if nearest && t.floats[2] == t.floats[3] && t.floats[2].fract() == 0.0 && t.floats[2] >= 1.0 => {
+                        chain.upsample(t.floats[2] as usize);
+                    },
+                    _ => chain.unsupported(format!("{} other than nearest-neighbour by an integer factor", described)),
+                }
+            },
+            "LSTM" => {
+                let bidirectional = matches!(node.attribute("direction"), Some(AttributeValue::String(d)) if d != "forward");
+                let sequences = node.outputs.first().is_some_and(|y| !y.is_empty()
+                    && graph.nodes.iter().any(|n| n.inputs.contains(y)));
+                match node.int("hidden_size") {
+                    Some(units) if !bidirectional && !sequences => {
+                        chain.layer(LayerType::LSTM, vec![units as usize]);
+                        // Continue from the last hidden state
+                        value = node.outputs.get(1).cloned().unwrap_or_default();
+                        continue;
+                    },
+                    _ => chain.unsupported(format!("{} returning sequences or running backwards", described)),
+                }
+            },
+            "Gather" if node.inputs.get(1) == Some(&value) => match constant(0) {
+                Some(table) if table.dims.len() == 2 => {
+                    chain.layer(LayerType::Embedding, vec![table.dims[1] as usize, table.dims[0] as usize]);
+                },
+                _ => chain.unsupported(format!("{} from a computed table", described)),
+            },
+            "Relu" => chain.activation(ActivationFunction::ReLU, &described),
+            "Sigmoid" => chain.activation(ActivationFunction::Sigmoid, &described),
+            "Tanh" => chain.activation(ActivationFunction::Tanh, &described),
+            "Softmax" => chain.activation(ActivationFunction::Softmax, &described),
+            // Layout changes between NCHW and our channels-last order, and no-ops at inference
+            "Transpose" | "Squeeze" | "Unsqueeze" | "Identity" | "Dropout" => {},
+            _ => chain.unsupported(described),
+        }
+        value = node.outputs.first().cloned().unwrap_or_default();
+    }
+    chain.finish()
+}
+
+/// Import a Keras `Sequential` (or linear functional) model saved as JSON,
+/// including the `modelTopology` of a TensorFlow.js `model.json`
+pub fn import_keras(text: &str, component: &str) -> Result<NetworkNode, String> {
+    let json: Value = serde_json::from_str(text).map_err(|e| format!("invalid Keras JSON: {}", e))?;
+    let mut model = &json;
+    for key in ["modelTopology", "model_config"] {
+        if let Some(inner) = model.get(key) {
+            model = inner;
+        }
+    }
+    let config = model.get("config").ok_or("the Keras JSON has no model `config`")?;
+    let layers = config.get("layers").unwrap_or(config).as_array().ok_or("the Keras model config has no `layers`")?;
+    
+    let mut chain = Chain::new(component);
+    for layer in layers {
+        let class = layer["class_name"].as_str().unwrap_or("?");
+        let config = &layer["config"];
+        let name = config["name"].as_str().unwrap_or("?");
+        let described = format!("{} `{}`", class, name);
+        
+        if !chain.has_input() {
+            let shape = config.get("batch_input_shape").or_else(|| config.get("batch_shape"));
+            if let Some(dims) = shape.and_then(|s| s.as_array()) {
+                let dims: Option<Vec<usize>> = dims.iter().skip(1).map(|d| d.as_u64().map(|d| d as usize)).collect();
+                let dims = dims.ok_or_else(|| format!("{} has a dimension that is not fixed", described))?;
+                let input_type = match (dims.len(), class) {
+                    (_, "Embedding") => InputType::Text,
+                    (3, _) => InputType::Image,
+                    (2, _) => InputType::Sequence,
+                    _ => InputType::Text,
+                };
+                chain.input(input_type, dims);
+            }
+        }
+        let int = |key: &str| config[key].as_u64().map(|v| v as usize);
+        // `kernel_size`, `pool_size` and `strides` are written as a pair or a single number
+        let square = |key: &str| match &config[key] {
+            Value::Array(pair) if pair.len() == 2 && pair[0] == pair[1] => pair[0].as_u64().map(|v| v as usize),
+            Value::Number(n) => n.as_u64().map(|v| v as usize),
+            _ => None,
+        };
+        let same = config["padding"].as_str().unwrap_or("valid") == "same";
+        // Strides default to 1 for convolutions and to the window for pooling
+        let strides = |default: usize| if config["strides"].is_null() { Some(default) } else { square("strides") };
+        
+        match class {
+            "InputLayer" | "Dropout" | "SpatialDropout1D" | "SpatialDropout2D" => {},
+            "Conv2D" => match (int("filters"), square("kernel_size"), strides(1)) {
+                (Some(filters), Some(size), Some(stride)) if square("dilation_rate").unwrap_or(1) == 1 => {
+                    chain.conv(filters, Window { size, stride, same });
+                },
+                _ => chain.unsupported(format!("{} with dilation or windows that are not square", described)),
+            },
+            "Conv2DTranspose" => match (int("filters"), square("kernel_size"), square("strides")) {
+                (Some(filters), Some(k), Some(stride)) if same => {
+                    chain.layer(LayerType::TransposeConv, vec![filters, k, stride]);
+                },
+                _ => chain.unsupported(format!("{} with padding other than \"same\"", described)),
+            },
+            "Dense" => match int("units") {
+                Some(units) => chain.layer(LayerType::Dense(1), vec![units]),
+                None => chain.unsupported(format!("{} without `units`", described)),
+            },
+            "MaxPooling2D" => {
+                let size = square("pool_size").unwrap_or(shape_inference::DEFAULT_POOL);
+                match strides(size) {
+                    Some(stride) => chain.pool(Window { size, stride, same }),
+                    None => chain.unsupported(format!("{} with windows that are not square", described)),
+                }
+            },
+            "Flatten" => chain.reshape(None),
+            "Reshape" => match config["target_shape"].as_array() {
+                Some(dims) => chain.reshape(Some(dims.iter().map(|d| d.as_i64().unwrap_or(-1)).collect())),
+                None => chain.unsupported(format!("{} without `target_shape`", described)),
+            },
+            "UpSampling2D" => match square("size") {
+                Some(factor) if config["interpolation"].as_str().unwrap_or("nearest") == "nearest" => chain.upsample(factor),
+                _ => chain.unsupported(format!("{} other than nearest-neighbour by an equal factor", described)),
+            },
+            "LSTM" => match int("units") {
+                Some(units) if !config["return_sequences"].as_bool().unwrap_or(false) => {
+                    chain.layer(LayerType::LSTM, vec![units]);
+                },
+                _ => chain.unsupported(format!("{} returning sequences", described)),
+            },
+            "Embedding" => match (int("output_dim"), int("input_dim")) {
+                (Some(dim), Some(vocabulary)) => chain.layer(LayerType::Embedding, vec![dim, vocabulary]),
+                _ => chain.unsupported(format!("{} without `input_dim` and `output_dim`", described)),
+            },
+            "Activation" => {},
+            "ReLU" => chain.activation(ActivationFunction::ReLU, &described),
+            "Softmax" => chain.activation(ActivationFunction::Softmax, &described),
+            _ => {
+                chain.unsupported(described);
+                continue;
+            },
+        }
+        // Fused activation of Dense, Conv2D, … or a standalone `Activation` layer;
+        // the cell activation of an LSTM is part of the layer
+        match config["activation"].as_str() {
+            _ if class == "LSTM" => {},
+            None | Some("linear") => {},
+            Some("relu") => chain.activation(ActivationFunction::ReLU, &described),
+            Some("sigmoid") => chain.activation(ActivationFunction::Sigmoid, &described),
+            Some("tanh") => chain.activation(ActivationFunction::Tanh, &described),
+            Some("softmax") => chain.activation(ActivationFunction::Softmax, &described),
+            Some(other) => chain.unsupported(format!("activation `{}` of {}", other, described)),
+        }
+    }
+    chain.finish()
+}
+
+/// Compact GaiaScript source for a network, as it would be written by hand
+/// (e.g. `Ñ:I 28×28×1→C₁ 32 3 ρ→P→F→D₁ 128 ρ→D₀ 10→S`)
+pub fn to_source(network: &NetworkNode) -> String {
+    let mut out = String::from("N");
+    if let Some(components) = &network.components {
+        out.push_str(&format!("〈{}〉", components.join("⊕")));
+    }
+    for node in &network.body {
+        out.push('\n');
+        match node {
+            ASTNode::Component(component) => {
+                out.push_str(&format!("{}:", component.id));
+                write_expr(&component.expr, &mut out);
+            },
+            other => out.push_str(&other.to_string()),
+        }
+    }
+    for share in &network.shares {
+        out.push_str(&format!("\n{}", share));
+    }
+    out.push('\n');
+    out
+}
+
+fn write_expr(node: &ASTNode, out: &mut String) {
+    match node {
+        ASTNode::DataFlow(from, to) => {
+            write_expr(from, out);
+            out.push('→');
+            write_expr(to, out);
+        },
+        ASTNode::Input(input) => {
+            out.push_str(&InputNode { params: Vec::new(), ..input.clone() }.to_string());
+            if !input.params.is_empty() {
+                out.push(' ');
+                out.push_str(&TensorShape::new(input.params.clone()).to_string());
+            }
+        },
+        ASTNode::Layer(layer) => {
+            let symbol = match layer.layer_type {
+                LayerType::Dense(0) => "D₀".to_string(),
+                _ => layer.symbol(),
+            };
+            out.push_str(&symbol);
+            // Target shapes are written as dimensions, other parameters one by one
+            let is_shape = matches!(layer.layer_type, LayerType::Upsampling | LayerType::Reshape) && layer.params.len() >= 2;
+            if is_shape {
+                out.push_str(&format!(" {}", TensorShape::new(layer.params.clone())));
+            } else {
+                for param in &layer.params {
+                    out.push_str(&format!(" {}", param));
+                }
+            }
+            match layer.activation {
+                ActivationFunction::ReLU => out.push_str(" ρ"),
+                ActivationFunction::Sigmoid => out.push_str(" σ"),
+                ActivationFunction::Tanh => out.push_str(" τ"),
+                ActivationFunction::Softmax => out.push_str("→S"),
+                ActivationFunction::None => {},
+            }
+        },
+        ASTNode::Block(block) => {
+            out.push('[');
+            write_expr(&block.content, out);
+            out.push_str(&format!("]×{}", block.repetitions));
+            if block.shared {
+                out.push('≜');
+            }
+        },
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+// "`Op` node `name`" for error messages
+fn describe(node: &Node) -> String {
+    if node.name.is_empty() {
+        format!("`{}`", node.op_type)
+    } else {
+        format!("`{}` node `{}`", node.op_type, node.name)
+    }
+}
+
+fn is_unit(values: Option<&[i64]>) -> bool {
+    values.is_none_or(|v| v.iter().all(|&x| x == 1))
+}
+
+// Kernel size of a square 2-D window, from `kernel_shape` or the weight dimensions
+fn square_kernel(node: &Node, graph: &Graph) -> Option<usize> {
+    let from_weights = node.inputs.get(1).and_then(|w| graph.initializer(w)).map(|w| w.dims[2..].to_vec());
+    let kernel = node.ints("kernel_shape").map(|k| k.to_vec()).or(from_weights)?;
+    match kernel.as_slice() {
+        [h, w] if h == w && *h > 0 => Some(*h as usize),
+        _ => None,
+    }
+}
+
+// Window of a Conv or MaxPool node on an image of shape `input`: a square
+// kernel and stride, and either no padding or the "same" padding we use.
+// Where "same" pads nothing either, the layer's default padding is kept
+fn onnx_window(node: &Node, graph: &Graph, input: Option<&TensorShape>, default_same: bool) -> Option<Window> {
+    let size = square_kernel(node, graph)?;
+    let stride = match node.ints("strides") {
+        None => 1,
+        Some(&[h, w]) if h == w && h > 0 => h as usize,
+        _ => return None,
+    };
+    let input = input.filter(|shape| shape.rank() == 3)?;
+    let same_pads = onnx::pads(&Window { size, stride, same: true }, input);
+    let same = match node.attribute("auto_pad") {
+        Some(AttributeValue::String(mode)) if mode == "SAME_UPPER" => true,
+        // The odd padding goes before, so only even padding matches ours
+        Some(AttributeValue::String(mode)) if mode == "SAME_LOWER" && same_pads[..2] == same_pads[2..] => true,
+        Some(AttributeValue::String(mode)) if mode == "VALID" => false,
+        Some(AttributeValue::String(mode)) if mode != "NOTSET" => return None,
+        _ => match node.ints("pads") {
+            Some(pads) if pads == same_pads => true,
+            pads if pads.is_none_or(|pads| pads.iter().all(|&p| p == 0)) => false,
+            _ => return None,
+        },
+    };
+    let pads_nothing = same_pads.iter().all(|&p| p == 0);
+    Some(Window { size, stride, same: if pads_nothing { default_same } else { same } })
+}
+
+// Per-example target of an ONNX reshape in our channels-last order
+fn onnx_shape(shape: &[i64]) -> Vec<i64> {
+    match shape.get(1..).unwrap_or_default() {
+        [c, h, w] => vec![*h, *w, *c],
+        rest => rest.to_vec(),
+    }
+}
+
+// Builds the layer chain of the imported component, tracking its shape
+struct Chain {
+    component: String,
+    input: Option<InputNode>,
+    layers: Vec<LayerNode>,
+    shape: Option<TensorShape>,
+    unsupported: Vec<String>,
+}
+
+impl Chain {
+    fn new(component: &str) -> Self {
+        Chain {
+            component: component.to_string(),
+            input: None,
+            layers: Vec::new(),
+            shape: None,
+            unsupported: Vec::new(),
+        }
+    }
+    
+    fn has_input(&self) -> bool {
+        self.input.is_some()
+    }
+    
+    fn input(&mut self, input_type: InputType, dims: Vec<usize>) {
+        let input = InputNode { input_type, params: dims, preprocess: None, tokenizer: None, span: Span::default() };
+        self.shape = Some(shape_inference::input_shape(&input).0);
+        self.input = Some(input);
+    }
+    
+    fn layer(&mut self, layer_type: LayerType, params: Vec<usize>) {
+        let layer = LayerNode {
+            layer_type,
+            params,
+            named_params: Vec::new(),
+            positional: false,
+            activation: ActivationFunction::None,
+            span: Span::default(),
+            input_shape: None,
+            output_shape: None,
+        };
+        // Once a shape is unknown, later layers are still collected for the report
+        if let Some(shape) = &self.shape {
+            match layer_output_shape(&layer, shape) {
+                Ok(output) => self.shape = Some(output),
+                Err(diagnostic) => {
+                    self.unsupported.push(diagnostic.message);
+                    self.shape = None;
+                },
+            }
+        }
+        self.layers.push(layer);
+    }
+    
+    // Stride and padding are written only when they differ from the defaults
+    fn conv(&mut self, filters: usize, window: Window) {
+        let mut params = vec![filters, window.size];
+        if window.stride != 1 || !window.same {
+            params.extend([window.stride, window.same as usize]);
+        }
+        self.layer(LayerType::Convolutional(1), params);
+    }
+    
+    fn pool(&mut self, window: Window) {
+        let params = match window {
+            Window { size, stride, same: false } if stride == size && size == shape_inference::DEFAULT_POOL => Vec::new(),
+            Window { size, stride, same: false } if stride == size => vec![size],
+            Window { size, stride, same } => vec![size, stride, same as usize],
+        };
+        self.layer(LayerType::Pooling, params);
+    }
+    
+    fn upsample(&mut self, factor: usize) {
+        let params = if factor == shape_inference::DEFAULT_UPSAMPLE { Vec::new() } else { vec![factor] };
+        self.layer(LayerType::Upsampling, params);
+    }
+    
+    // The previous layer has weights, so a following constant addition is its bias
+    fn follows_weights(&self) -> bool {
+        matches!(self.layers.last().map(|l| &l.layer_type),
+                 Some(LayerType::Dense(_) | LayerType::Convolutional(_) | LayerType::TransposeConv))
+    }
+    
+    /// Flatten, or reshape to `target` (`-1` for the inferred axis); a reshape
+    /// that keeps the current shape is dropped
+    fn reshape(&mut self, target: Option<Vec<i64>>) {
+        let Some(shape) = self.shape.clone() else {
+            return self.layer(LayerType::Flatten, Vec::new());
+        };
+        let dims = match target {
+            None => vec![shape.elements()],
+            Some(target) => {
+                let known: i64 = target.iter().filter(|&&d| d > 0).product();
+                target.iter()
+                    .map(|&d| if d > 0 { d as usize } else { shape.elements() / known.max(1) as usize })
+                    .collect()
+            },
+        };
+        if dims == shape.dims {
+            return;
+        }
+        if dims.len() == 1 {
+            self.layer(LayerType::Flatten, Vec::new());
+        } else {
+            self.layer(LayerType::Reshape, dims);
+        }
+    }
+    
+    // Fuse an activation into the layer before it
+    fn activation(&mut self, activation: ActivationFunction, described: &str) {
+        match self.layers.last_mut() {
+            Some(layer) if layer.activation == ActivationFunction::None => layer.activation = activation,
+            _ => self.unsupported.push(format!("{} does not follow a layer", described)),
+        }
+    }
+    
+    fn unsupported(&mut self, described: String) {
+        self.unsupported.push(described);
+    }
+    
+    fn finish(mut self) -> Result<NetworkNode, String> {
+        if !self.unsupported.is_empty() {
+            let count = self.unsupported.len();
+            return Err(format!("{} operation{} without a GaiaScript equivalent:\n  {}",
+                               count, if count == 1 { "" } else { "s" }, self.unsupported.join("\n  ")));
+        }
+        let input = self.input.take().ok_or("the model does not declare an input shape")?;
+        
+        // A final dense layer is the output layer (`D₀`), as written by hand
+        if let Some(layer) = self.layers.last_mut() {
+            if matches!(layer.layer_type, LayerType::Dense(_)) {
+                layer.layer_type = LayerType::Dense(0);
+            }
+        }
+        let mut expr = ASTNode::Input(input);
+        let mut rest: Vec<ASTNode> = self.layers.into_iter().map(ASTNode::Layer).collect();
+        // Right-nested like the parser: `I→(C→(P→F))`
+        if let Some(last) = rest.pop() {
+            let tail = rest.into_iter().rev().fold(last, |to, from| ASTNode::DataFlow(Box::new(from), Box::new(to)));
+            expr = ASTNode::DataFlow(Box::new(expr), Box::new(tail));
+        }
+        
+        Ok(NetworkNode {
+            components: None,
+            body: vec![ASTNode::Component(ComponentNode {
+                id: self.component,
+                expr: Box::new(expr),
+                span: Span::default(),
+            })],
+            shares: Vec::new(),
+        })
+    }
+}
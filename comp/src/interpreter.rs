@@ -0,0 +1,894 @@
+use crate::ast::*;
+use crate::composite;
+use crate::layer_params::{CAUSAL, FACTOR, HEADS, KERNEL, STRIDE, target_shape, VOCAB, Window};
+use crate::shape_inference::{input_shape, layer_output_shape};
+use crate::tokenizer::Tokenizer;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Arc;
+use serde_json::{json, Value};
+
+/// Dense row-major f32 tensor of a single example (channels last)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tensor {
+    pub shape: Vec<usize>,
+    pub data: Vec<f32>,
+}
+
+impl Tensor {
+    pub fn new(shape: Vec<usize>, data: Vec<f32>) -> Result<Self, String> {
+        let expected: usize = shape.iter().product();
+        if expected != data.len() {
+            return Err(format!("tensor of shape {:?} needs {} values, got {}", shape, expected, data.len()));
+        }
+        Ok(Tensor { shape, data })
+    }
+    
+    pub fn zeros(shape: Vec<usize>) -> Self {
+        let len = shape.iter().product();
+        Tensor { shape, data: vec![0.0; len] }
+    }
+    
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    
+    pub fn reshape(mut self, shape: Vec<usize>) -> Result<Self, String> {
+        if shape.iter().product::<usize>() != self.data.len() {
+            return Err(format!("cannot reshape {:?} into {:?}", self.shape, shape));
+        }
+        self.shape = shape;
+        Ok(self)
+    }
+    
+    /// Index of the largest value
+    pub fn argmax(&self) -> usize {
+        let mut best = 0;
+        for (i, v) in self.data.iter().enumerate() {
+            if *v > self.data[best] {
+                best = i;
+            }
+        }
+        best
+    }
+    
+    /// Largest absolute element-wise difference, used to compare backends
+    pub fn max_abs_diff(&self, other: &Tensor) -> f32 {
+        self.data.iter().zip(&other.data).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max)
+    }
+}
+
+/// Deterministic pseudo-random generator (SplitMix64), so seeded weights are
+/// identical on every platform
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+    
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    
+    /// Uniform value in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+    
+    pub fn uniform(&mut self, low: f32, high: f32) -> f32 {
+        low + (high - low) * self.next_f32()
+    }
+}
+
+/// One layer application in a component, with its resolved shapes
+#[derive(Debug, Clone)]
+pub struct Step {
+    // Parameter prefix, e.g. `Ñ.C₁` or `G.D₁_1` for the second `D₁`
+    pub key: String,
+    pub layer: LayerNode,
+    pub input: TensorShape,
+    pub output: TensorShape,
+    // Heads of an attention layer: those of the last `H`, or 1 when they do
+    // not divide the features
+    pub heads: usize,
+}
+
+impl Step {
+    /// Names and shapes of the trainable parameters of this step
+    pub fn parameter_shapes(&self) -> Vec<(String, Vec<usize>)> {
+        let c_in = self.input.dims.last().copied().unwrap_or(1);
+        let c_out = self.output.dims.last().copied().unwrap_or(1);
+        let weight = |dims: Vec<usize>, bias: usize| vec![
+            (format!("{}.weight", self.key), dims),
+            (format!("{}.bias", self.key), vec![bias]),
+        ];
+        match self.layer.layer_type {
+            LayerType::Convolutional(_) | LayerType::TransposeConv => {
+                let kernel = self.layer.param(KERNEL);
+                weight(vec![kernel, kernel, c_in, c_out], c_out)
+            },
+            LayerType::Dense(_) => weight(vec![c_in, c_out], c_out),
+            LayerType::Upsampling if target_shape(&self.layer).is_some() => {
+                weight(vec![self.input.elements(), self.output.elements()], self.output.elements())
+            },
+            LayerType::LSTM => {
+                // Gates stacked input, forget, cell, output along the last axis
+                let gates = 4 * c_out;
+                vec![
+                    (format!("{}.weight", self.key), vec![c_in, gates]),
+                    (format!("{}.recurrent", self.key), vec![c_out, gates]),
+                    (format!("{}.bias", self.key), vec![gates]),
+                ]
+            },
+            LayerType::Embedding => vec![(format!("{}.weight", self.key), vec![self.layer.param(VOCAB), c_out])],
+            LayerType::Attention => {
+                // Query, key and value projections stacked along the last axis
+                let mut shapes = weight(vec![c_in, 3 * c_in], 3 * c_in);
+                shapes.push((format!("{}.output", self.key), vec![c_in, c_in]));
+                shapes.push((format!("{}.output_bias", self.key), vec![c_in]));
+                shapes
+            },
+            LayerType::Custom(ref op) => op.parameter_shapes(&self.input, &self.output, &self.layer.resolved_params())
+                .into_iter()
+                .map(|(name, shape)| (format!("{}.{}", self.key, name), shape))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+    
+    /// Fan-in and fan-out used for Glorot initialisation
+    fn fans(&self, shape: &[usize]) -> (usize, usize) {
+        match shape.len() {
+            4 => (shape[0] * shape[1] * shape[2], shape[0] * shape[1] * shape[3]),
+            2 => (shape[0], shape[1]),
+            _ => (1, 1),
+        }
+    }
+}
+
+/// Flattened execution plan of a component: an input followed by layers
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub component: String,
+    pub input: TensorShape,
+    // Normalization declared on the input with `⍓`
+    pub preprocess: Option<Preprocess>,
+    // Tokenizer declared on a text input with `⌸`, once loaded
+    pub tokenizer: Option<Arc<Tokenizer>>,
+    pub steps: Vec<Step>,
+}
+
+impl Plan {
+    /// Build the plan of a component; blocks are unrolled and every repetition
+    /// gets its own parameters, unless the block is marked `≜`
+    pub fn new(component: &ComponentNode) -> Result<Self, String> {
+        Self::build(component, None)
+    }
+    
+    /// Plan of a component that is fed the output of another component
+    pub fn with_input(component: &ComponentNode, input: TensorShape) -> Result<Self, String> {
+        Self::build(component, Some(input))
+    }
+    
+    fn build(component: &ComponentNode, input: Option<TensorShape>) -> Result<Self, String> {
+        let mut builder = PlanBuilder {
+            component: &component.id,
+            input: input.clone(),
+            preprocess: None,
+            tokenizer: None,
+            steps: Vec::new(),
+            seen: HashMap::new(),
+            heads: 1,
+        };
+        builder.visit(&component.expr, input)?;
+        match builder.input {
+            Some(input) => Ok(Plan {
+                component: component.id.clone(),
+                input,
+                preprocess: builder.preprocess,
+                tokenizer: builder.tokenizer,
+                steps: builder.steps,
+            }),
+            None => Err(format!("component `{}` has no input", component.id)),
+        }
+    }
+    
+    pub fn output(&self) -> &TensorShape {
+        self.steps.last().map(|s| &s.output).unwrap_or(&self.input)
+    }
+    
+    pub fn parameter_shapes(&self) -> Vec<(String, Vec<usize>)> {
+        self.steps.iter().flat_map(|s| s.parameter_shapes()).collect()
+    }
+    
+    /// Use the parameters of `owner` instead of this component's own (`E≜D`)
+    pub fn share_parameters(&mut self, owner: &str) {
+        let prefix = format!("{}.", self.component);
+        for step in &mut self.steps {
+            if let Some(rest) = step.key.strip_prefix(&prefix) {
+                step.key = format!("{}.{}", owner, rest);
+            }
+        }
+    }
+}
+
+struct PlanBuilder<'a> {
+    component: &'a str,
+    input: Option<TensorShape>,
+    preprocess: Option<Preprocess>,
+    tokenizer: Option<Arc<Tokenizer>>,
+    steps: Vec<Step>,
+    // Occurrences of each layer symbol, for unique parameter names
+    seen: HashMap<String, usize>,
+    // Heads of the last `H`, used by the following attention layers
+    heads: usize,
+}
+
+impl<'a> PlanBuilder<'a> {
+    fn visit(&mut self, node: &ASTNode, shape: Option<TensorShape>) -> Result<Option<TensorShape>, String> {
+        match node {
+            ASTNode::Input(input_node) => {
+                let (declared, defaulted) = input_shape(input_node);
+                match shape {
+                    Some(incoming) if defaulted => {
+                        // A trailing `→S` is the softmax output of the previous layer
+                        if matches!(input_node.input_type, InputType::Sequence) {
+                            if let Some(last) = self.steps.last_mut() {
+                                if last.layer.activation == ActivationFunction::None {
+                                    last.layer.activation = ActivationFunction::Softmax;
+                                }
+                            }
+                        }
+                        Ok(Some(incoming))
+                    },
+                    Some(_) => Ok(Some(declared)),
+                    None => {
+                        self.input = Some(declared.clone());
+                        self.preprocess = input_node.preprocess;
+                        self.tokenizer = input_node.tokenizer.as_ref().and_then(|spec| spec.loaded.clone());
+                        Ok(Some(declared))
+                    },
+                }
+            },
+            ASTNode::Layer(layer) => {
+                let input = shape.ok_or_else(|| format!("`{}` in `{}` has no input", layer.symbol(), self.component))?;
+                let output = layer_output_shape(layer, &input).map_err(|d| d.message)?;
+                
+                let symbol = layer.symbol();
+                let count = self.seen.entry(symbol.clone()).or_insert(0);
+                let key = if *count == 0 {
+                    format!("{}.{}", self.component, symbol)
+                } else {
+                    format!("{}.{}_{}", self.component, symbol, count)
+                };
+                *count += 1;
+                
+                let heads = match layer.layer_type {
+                    LayerType::AttentionHeads => {
+                        self.heads = layer.param(HEADS).max(1);
+                        1
+                    },
+                    LayerType::Attention if input.dims[1] % self.heads != 0 => {
+                        return Err(format!("`{}` in `{}` cannot split {} features between {} heads",
+                                           layer.symbol(), self.component, input.dims[1], self.heads));
+                    },
+                    LayerType::Attention => self.heads,
+                    _ => 1,
+                };
+                self.steps.push(Step { key, layer: layer.clone(), input, output: output.clone(), heads });
+                Ok(Some(output))
+            },
+            ASTNode::DataFlow(from, to) => {
+                let shape = self.visit(from, shape)?;
+                self.visit(to, shape)
+            },
+            ASTNode::Expression(nodes) => {
+                let mut shape = shape;
+                for child in nodes {
+                    shape = self.visit(child, shape)?;
+                }
+                Ok(shape)
+            },
+            ASTNode::Block(block) => {
+                // Repetitions of a shared block name their steps like the first,
+                // so they read the same parameters
+                let seen = self.seen.clone();
+                if block.repetitions == 0 {
+                    return Err(format!("`{}` in `{}` repeats 0 times", block, self.component));
+                }
+                let input = shape.clone();
+                let mut shape = shape;
+                for iteration in 0..block.repetitions {
+                    if block.shared && iteration > 0 {
+                        self.seen = seen.clone();
+                    }
+                    shape = self.visit(&block.content, shape)?;
+                    if let (true, Some(input), Some(output)) = (block.shared && block.repetitions > 1, &input, &shape) {
+                        if input != output {
+                            return Err(format!("`{}` in `{}` shares parameters but turns {} into {}", block, self.component, input, output));
+                        }
+                    }
+                }
+                Ok(shape)
+            },
+            _ => Ok(shape),
+        }
+    }
+}
+
+/// Named parameters of a network, keyed like `Ñ.C₁.weight`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Weights {
+    tensors: BTreeMap<String, Tensor>,
+}
+
+impl Weights {
+    pub fn new() -> Self {
+        Weights { tensors: BTreeMap::new() }
+    }
+    
+    /// Glorot-uniform weights and zero biases drawn from a seeded generator
+    pub fn seeded(plans: &[Plan], seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let mut weights = Weights::new();
+        for plan in plans {
+            for step in &plan.steps {
+                for (name, shape) in step.parameter_shapes() {
+                    let mut tensor = Tensor::zeros(shape.clone());
+                    if shape.len() > 1 {
+                        let (fan_in, fan_out) = step.fans(&shape);
+                        let limit = (6.0 / (fan_in + fan_out) as f32).sqrt();
+                        for v in tensor.data.iter_mut() {
+                            *v = rng.uniform(-limit, limit);
+                        }
+                    }
+                    weights.insert(name, tensor);
+                }
+            }
+        }
+        weights
+    }
+    
+    pub fn insert(&mut self, name: impl Into<String>, tensor: Tensor) {
+        self.tensors.insert(name.into(), tensor);
+    }
+    
+    pub fn get(&self, name: &str) -> Option<&Tensor> {
+        self.tensors.get(name)
+    }
+    
+    /// Add every tensor of `other`, replacing tensors with the same name
+    pub fn extend(&mut self, other: &Weights) {
+        for (name, tensor) in &other.tensors {
+            self.tensors.insert(name.clone(), tensor.clone());
+        }
+    }
+    
+    /// Weights to ship with generated code: `trained` where given, seeded
+    /// (seed 0) for every other parameter of `plans`
+    pub fn for_export(plans: &[Plan], trained: Option<&Weights>) -> Self {
+        let mut weights = Weights::seeded(plans, 0);
+        if let Some(trained) = trained {
+            weights.extend(trained);
+        }
+        weights
+    }
+    
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Tensor> {
+        self.tensors.get_mut(name)
+    }
+    
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Tensor)> {
+        self.tensors.iter()
+    }
+    
+    pub fn len(&self) -> usize {
+        self.tensors.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.tensors.is_empty()
+    }
+    
+    /// Serialize as `{"name": {"shape": [...], "data": [...]}}`
+    pub fn to_json(&self) -> Value {
+        let mut map = serde_json::Map::new();
+        for (name, tensor) in &self.tensors {
+            map.insert(name.clone(), json!({ "shape": tensor.shape, "data": tensor.data }));
+        }
+        Value::Object(map)
+    }
+    
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let map = value.as_object().ok_or("weights must be a JSON object")?;
+        let mut weights = Weights::new();
+        for (name, entry) in map {
+            let shape: Vec<usize> = serde_json::from_value(entry["shape"].clone())
+                .map_err(|e| format!("weight `{}`: invalid shape: {}", name, e))?;
+            let data: Vec<f32> = serde_json::from_value(entry["data"].clone())
+                .map_err(|e| format!("weight `{}`: invalid data: {}", name, e))?;
+            weights.insert(name.clone(), Tensor::new(shape, data)?);
+        }
+        Ok(weights)
+    }
+    
+    fn require(&self, name: &str) -> Result<&Tensor, String> {
+        self.get(name).ok_or_else(|| format!("missing weight `{}`", name))
+    }
+}
+
+impl fmt::Display for Weights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, tensor) in &self.tensors {
+            writeln!(f, "{} {:?}", name, tensor.shape)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reference interpreter that executes network components on the CPU
+///
+/// The results are the numerical ground truth the code generators are
+/// checked against.
+pub struct Interpreter {
+    plans: Vec<Plan>,
+    weights: Weights,
+}
+
+impl Interpreter {
+    /// Create an interpreter with the given weights, checking that every
+    /// parameter is present with the expected shape
+    pub fn new(ast: &ASTNode, weights: Weights) -> Result<Self, String> {
+        let plans = plan_network(ast)?;
+        for plan in &plans {
+            for (name, shape) in plan.parameter_shapes() {
+                let tensor = weights.require(&name)?;
+                if tensor.shape != shape {
+                    return Err(format!("weight `{}` has shape {:?}, expected {:?}", name, tensor.shape, shape));
+                }
+            }
+        }
+        Ok(Interpreter { plans, weights })
+    }
+    
+    /// Create an interpreter with deterministic weights derived from `seed`
+    pub fn seeded(ast: &ASTNode, seed: u64) -> Result<Self, String> {
+        let plans = plan_network(ast)?;
+        let weights = Weights::seeded(&plans, seed);
+        Ok(Interpreter { plans, weights })
+    }
+    
+    pub fn weights(&self) -> &Weights {
+        &self.weights
+    }
+    
+    pub fn weights_mut(&mut self) -> &mut Weights {
+        &mut self.weights
+    }
+    
+    pub fn plans(&self) -> &[Plan] {
+        &self.plans
+    }
+    
+    pub fn plan(&self, component: &str) -> Option<&Plan> {
+        self.plans.iter().find(|p| p.component == component)
+    }
+    
+    /// Run a single example through a component
+    pub fn forward(&self, component: &str, input: &Tensor) -> Result<Tensor, String> {
+        let mut outputs = self.trace(component, input)?;
+        Ok(outputs.pop().unwrap())
+    }
+    
+    /// Run a single example and keep the input and the output of every step
+    pub fn trace(&self, component: &str, input: &Tensor) -> Result<Vec<Tensor>, String> {
+        let plan = self.plan(component).ok_or_else(|| format!("unknown component `{}`", component))?;
+        if input.len() != plan.input.elements() {
+            return Err(format!("component `{}` expects input {}, got {:?}", component, plan.input, input.shape));
+        }
+        
+        let mut outputs = vec![input.clone().reshape(plan.input.dims.clone())?];
+        for step in &plan.steps {
+            let y = self.apply(step, outputs.last().unwrap())?;
+            outputs.push(y);
+        }
+        Ok(outputs)
+    }
+    
+    fn apply(&self, step: &Step, x: &Tensor) -> Result<Tensor, String> {
+        let layer = &step.layer;
+        let param = |name: &str| self.weights.require(&format!("{}.{}", step.key, name));
+        let weight = || param("weight");
+        let bias = || param("bias");
+        
+        let y = match layer.layer_type {
+            LayerType::Dense(_) => dense(x, weight()?, bias()?),
+            LayerType::Convolutional(_) => conv2d(x, weight()?, bias()?, layer.window().unwrap()),
+            LayerType::TransposeConv => {
+                conv2d_transpose(x, weight()?, bias()?, layer.param(STRIDE))
+            },
+            LayerType::Pooling => max_pool2d(x, layer.window().unwrap()),
+            LayerType::Upsampling if target_shape(layer).is_some() => {
+                let flat = x.clone().reshape(vec![x.len()])?;
+                dense(&flat, weight()?, bias()?)
+            },
+            LayerType::Upsampling => upsample_nearest(x, layer.param(FACTOR)),
+            LayerType::Flatten | LayerType::Reshape | LayerType::AttentionHeads | LayerType::BatchSize => x.clone(),
+            LayerType::LSTM => lstm(x, weight()?, param("recurrent")?, bias()?),
+            LayerType::Embedding => {
+                let y = embedding(x, weight()?).map_err(|e| format!("`{}` in `{}`: {}", layer.symbol(), step.key, e))?;
+                if layer.positional {
+                    add_positional_encoding(y)
+                } else {
+                    y
+                }
+            },
+            LayerType::Attention => {
+                let mask = (layer.param(CAUSAL) == 1).then(|| causal_mask(x.shape[0]));
+                multi_head_attention(x, weight()?, bias()?, param("output")?, param("output_bias")?, step.heads, mask.as_deref())
+            },
+            LayerType::Custom(ref op) => {
+                let weights = step.parameter_shapes().iter()
+                    .map(|(name, _)| self.weights.require(name))
+                    .collect::<Result<Vec<_>, _>>()?;
+                op.forward(x, &layer.resolved_params(), &weights)
+                    .ok_or_else(|| format!("`{}` has no CPU implementation", layer.symbol()))??
+            },
+        };
+        let y = y.reshape(step.output.dims.clone())?;
+        Ok(activate(y, &layer.activation))
+    }
+}
+
+/// Build execution plans for every component that contains layers
+pub fn plan_network(ast: &ASTNode) -> Result<Vec<Plan>, String> {
+    let mut plans = Vec::new();
+    if let ASTNode::Network(network) = ast {
+        for node in &network.body {
+            if let ASTNode::Component(component) = node {
+                if is_interface(&component.expr) {
+                    continue;
+                }
+                let plan = composite::component_plan(ast, &component.id, None)?;
+                if !plan.steps.is_empty() {
+                    // A redefined component replaces the earlier plan
+                    plans.retain(|p: &Plan| p.component != plan.component);
+                    plans.push(plan);
+                }
+            }
+        }
+    }
+    Ok(plans)
+}
+
+// Components made only of interface elements, such as `∮ 600×400→⌑"…"`, run no network
+fn is_interface(node: &ASTNode) -> bool {
+    match node {
+        ASTNode::UIComponent(_) => true,
+        ASTNode::DataFlow(from, to) => is_interface(from) && is_interface(to),
+        _ => false,
+    }
+}
+
+/// Fully connected layer on the last axis; `w` is `[in, out]`
+pub fn dense(x: &Tensor, w: &Tensor, b: &Tensor) -> Tensor {
+    let (n_in, n_out) = (w.shape[0], w.shape[1]);
+    let rows = x.len() / n_in;
+    let mut out = vec![0.0; rows * n_out];
+    for r in 0..rows {
+        let row = &x.data[r * n_in..(r + 1) * n_in];
+        let dst = &mut out[r * n_out..(r + 1) * n_out];
+        dst.copy_from_slice(&b.data);
+        for (i, xi) in row.iter().enumerate() {
+            let w_row = &w.data[i * n_out..(i + 1) * n_out];
+            for (o, wo) in dst.iter_mut().zip(w_row) {
+                *o += xi * wo;
+            }
+        }
+    }
+    let mut shape = x.shape.clone();
+    *shape.last_mut().unwrap() = n_out;
+    Tensor { shape, data: out }
+}
+
+/// Convolution sliding `window` over the height and width; `w` is `[k, k, in, out]`
+pub fn conv2d(x: &Tensor, w: &Tensor, b: &Tensor, window: Window) -> Tensor {
+    let (h, wd, c_in) = (x.shape[0], x.shape[1], x.shape[2]);
+    let (k, c_out) = (w.shape[0], w.shape[3]);
+    let (oh, ow) = (window.positions(h).unwrap_or(0), window.positions(wd).unwrap_or(0));
+    let (pad_y, pad_x) = (window.padding(h).0 as isize, window.padding(wd).0 as isize);
+    let mut out = vec![0.0; oh * ow * c_out];
+    
+    for oy in 0..oh {
+        for ox in 0..ow {
+            let dst = &mut out[(oy * ow + ox) * c_out..(oy * ow + ox + 1) * c_out];
+            dst.copy_from_slice(&b.data);
+            for ky in 0..k {
+                let iy = (oy * window.stride + ky) as isize - pad_y;
+                if iy < 0 || iy >= h as isize {
+                    continue;
+                }
+                for kx in 0..k {
+                    let ix = (ox * window.stride + kx) as isize - pad_x;
+                    if ix < 0 || ix >= wd as isize {
+                        continue;
+                    }
+                    let src = &x.data[(iy as usize * wd + ix as usize) * c_in..][..c_in];
+                    for (ci, xv) in src.iter().enumerate() {
+                        let w_row = &w.data[((ky * k + kx) * c_in + ci) * c_out..][..c_out];
+                        for (o, wv) in dst.iter_mut().zip(w_row) {
+                            *o += xv * wv;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Tensor { shape: vec![oh, ow, c_out], data: out }
+}
+
+/// Transposed convolution with "same" padding, producing `stride` times the
+/// input size; `w` is `[k, k, in, out]`
+pub fn conv2d_transpose(x: &Tensor, w: &Tensor, b: &Tensor, stride: usize) -> Tensor {
+    let (h, wd, c_in) = (x.shape[0], x.shape[1], x.shape[2]);
+    let (k, c_out) = (w.shape[0], w.shape[3]);
+    let (oh, ow) = (h * stride, wd * stride);
+    let pad = (k.saturating_sub(stride) / 2) as isize;
+    let mut out = vec![0.0; oh * ow * c_out];
+    for pixel in out.chunks_mut(c_out) {
+        pixel.copy_from_slice(&b.data);
+    }
+    
+    for iy in 0..h {
+        for ix in 0..wd {
+            let src = &x.data[(iy * wd + ix) * c_in..][..c_in];
+            for ky in 0..k {
+                let oy = (iy * stride + ky) as isize - pad;
+                if oy < 0 || oy >= oh as isize {
+                    continue;
+                }
+                for kx in 0..k {
+                    let ox = (ix * stride + kx) as isize - pad;
+                    if ox < 0 || ox >= ow as isize {
+                        continue;
+                    }
+                    let dst = &mut out[(oy as usize * ow + ox as usize) * c_out..][..c_out];
+                    for (ci, xv) in src.iter().enumerate() {
+                        let w_row = &w.data[((ky * k + kx) * c_in + ci) * c_out..][..c_out];
+                        for (o, wv) in dst.iter_mut().zip(w_row) {
+                            *o += xv * wv;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Tensor { shape: vec![oh, ow, c_out], data: out }
+}
+
+/// Max pooling over `window`; padding never wins the maximum
+pub fn max_pool2d(x: &Tensor, window: Window) -> Tensor {
+    let (h, wd, c) = (x.shape[0], x.shape[1], x.shape[2]);
+    let (oh, ow) = (window.positions(h).unwrap_or(0), window.positions(wd).unwrap_or(0));
+    let mut out = vec![f32::NEG_INFINITY; oh * ow * c];
+    for oy in 0..oh {
+        for ox in 0..ow {
+            for (iy, ix) in window_inputs(window, (h, wd), (oy, ox)) {
+                let src = (iy * wd + ix) * c;
+                for ch in 0..c {
+                    let dst = &mut out[(oy * ow + ox) * c + ch];
+                    *dst = dst.max(x.data[src + ch]);
+                }
+            }
+        }
+    }
+    Tensor { shape: vec![oh, ow, c], data: out }
+}
+
+/// Input positions of the window at output position `at` that fall inside an
+/// input of `size`, row by row
+pub fn window_inputs(window: Window, size: (usize, usize), at: (usize, usize)) -> impl Iterator<Item = (usize, usize)> {
+    let (pad_y, pad_x) = (window.padding(size.0).0, window.padding(size.1).0);
+    let rows = (0..window.size).filter_map(move |ky| (at.0 * window.stride + ky).checked_sub(pad_y).filter(|&iy| iy < size.0));
+    rows.flat_map(move |iy| {
+        (0..window.size).filter_map(move |kx| (at.1 * window.stride + kx).checked_sub(pad_x).filter(|&ix| ix < size.1))
+            .map(move |ix| (iy, ix))
+    })
+}
+
+/// Nearest-neighbour upsampling by an integer factor
+pub fn upsample_nearest(x: &Tensor, factor: usize) -> Tensor {
+    let (h, wd, c) = (x.shape[0], x.shape[1], x.shape[2]);
+    let (oh, ow) = (h * factor, wd * factor);
+    let mut out = Vec::with_capacity(oh * ow * c);
+    for oy in 0..oh {
+        for ox in 0..ow {
+            let src = ((oy / factor) * wd + ox / factor) * c;
+            out.extend_from_slice(&x.data[src..src + c]);
+        }
+    }
+    Tensor { shape: vec![oh, ow, c], data: out }
+}
+
+/// Rows of `table` (`[vocab, dim]`) for the token ids in `ids`; the output has
+/// the shape of `ids` with `dim` appended
+pub fn embedding(ids: &Tensor, table: &Tensor) -> Result<Tensor, String> {
+    let (vocab, dim) = (table.shape[0], table.shape[1]);
+    let mut data = Vec::with_capacity(ids.len() * dim);
+    for &id in &ids.data {
+        if id < 0.0 || id.fract() != 0.0 || id as usize >= vocab {
+            return Err(format!("token id {} is outside the vocabulary of {}", id, vocab));
+        }
+        data.extend_from_slice(&table.data[id as usize * dim..][..dim]);
+    }
+    let mut shape = ids.shape.clone();
+    shape.push(dim);
+    Ok(Tensor { shape, data })
+}
+
+/// Sinusoidal positional encoding `[steps, dim]`: feature `2i` of position `p`
+/// is `sin(p / 10000^(2i / dim))` and feature `2i + 1` its cosine
+pub fn positional_encoding(steps: usize, dim: usize) -> Tensor {
+    let mut data = Vec::with_capacity(steps * dim);
+    for p in 0..steps {
+        for f in 0..dim {
+            let angle = p as f32 / 10000f32.powf((f - f % 2) as f32 / dim as f32);
+            data.push(if f % 2 == 0 { angle.sin() } else { angle.cos() });
+        }
+    }
+    Tensor { shape: vec![steps, dim], data }
+}
+
+/// `+P`: add the positional encoding to a `[steps, dim]` sequence
+pub fn add_positional_encoding(mut x: Tensor) -> Tensor {
+    let encoding = positional_encoding(x.shape[0], x.shape[1]);
+    x.data.iter_mut().zip(&encoding.data).for_each(|(v, p)| *v += p);
+    x
+}
+
+/// Mask letting query `i` attend to keys `0..=i` only
+pub fn causal_mask(tokens: usize) -> Vec<bool> {
+    (0..tokens * tokens).map(|i| i % tokens <= i / tokens).collect()
+}
+
+/// `softmax(q kᵀ / √d) v` for `q` `[n_q, d]`, `k` `[n_k, d]` and `v` `[n_k, d_v]`
+///
+/// `mask[i * n_k + j] == false` hides key `j` from query `i`; a query that sees
+/// no key at all yields zeros.
+pub fn scaled_dot_product_attention(q: &Tensor, k: &Tensor, v: &Tensor, mask: Option<&[bool]>) -> Tensor {
+    let (n_q, d) = (q.shape[0], q.shape[1]);
+    let (n_k, d_v) = (k.shape[0], v.shape[1]);
+    let scale = 1.0 / (d as f32).sqrt();
+    let mut out = vec![0.0; n_q * d_v];
+    let mut scores = vec![0.0; n_k];
+    
+    for (i, dst) in out.chunks_mut(d_v).enumerate() {
+        let query = &q.data[i * d..][..d];
+        for (j, score) in scores.iter_mut().enumerate() {
+            *score = if mask.is_none_or(|m| m[i * n_k + j]) {
+                let key = &k.data[j * d..][..d];
+                query.iter().zip(key).map(|(a, b)| a * b).sum::<f32>() * scale
+            } else {
+                f32::NEG_INFINITY
+            };
+        }
+        let max = scores.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        if max == f32::NEG_INFINITY {
+            continue;
+        }
+        let sum: f32 = scores.iter().map(|s| (s - max).exp()).sum();
+        for (j, score) in scores.iter().enumerate() {
+            let weight = (score - max).exp() / sum;
+            for (o, vv) in dst.iter_mut().zip(&v.data[j * d_v..][..d_v]) {
+                *o += weight * vv;
+            }
+        }
+    }
+    Tensor { shape: vec![n_q, d_v], data: out }
+}
+
+/// Multi-head self-attention over `[tokens, d]`
+///
+/// `w` (`[d, 3d]`) and `b` hold the query, key and value projections stacked
+/// along the last axis. Each projection is split into `heads` slices of
+/// `d / heads` features that attend independently, and the concatenated heads
+/// go through the output projection `w_out`.
+pub fn multi_head_attention(x: &Tensor, w: &Tensor, b: &Tensor, w_out: &Tensor, b_out: &Tensor,
+                            heads: usize, mask: Option<&[bool]>) -> Tensor {
+    let (tokens, d) = (x.shape[0], x.shape[1]);
+    let head_dim = d / heads;
+    let projected = dense(x, w, b);
+    let mut concat = vec![0.0; tokens * d];
+    
+    for h in 0..heads {
+        // Columns of head `h` in the query (0), key (1) or value (2) projection
+        let slice = |part: usize| {
+            let offset = part * d + h * head_dim;
+            let data = (0..tokens)
+                .flat_map(|t| projected.data[t * 3 * d + offset..][..head_dim].iter().copied())
+                .collect();
+            Tensor { shape: vec![tokens, head_dim], data }
+        };
+        let y = scaled_dot_product_attention(&slice(0), &slice(1), &slice(2), mask);
+        for (t, row) in y.data.chunks(head_dim).enumerate() {
+            concat[t * d + h * head_dim..][..head_dim].copy_from_slice(row);
+        }
+    }
+    dense(&Tensor { shape: vec![tokens, d], data: concat }, w_out, b_out)
+}
+
+/// One LSTM step from the hidden state `h` and cell state `c`; the gates
+/// `x w + h u + b` are stacked input, forget, cell, output along the last axis
+///
+/// Returns the next hidden and cell states.
+pub fn lstm_cell(x: &[f32], h: &[f32], c: &[f32], w: &Tensor, u: &Tensor, b: &Tensor) -> (Vec<f32>, Vec<f32>) {
+    let units = h.len();
+    let mut gates = b.data.clone();
+    for (input, weights) in [(x, w), (h, u)] {
+        for (xi, row) in input.iter().zip(weights.data.chunks(4 * units)) {
+            for (g, wv) in gates.iter_mut().zip(row) {
+                *g += xi * wv;
+            }
+        }
+    }
+    
+    let sigmoid = |v: f32| 1.0 / (1.0 + (-v).exp());
+    let mut h_next = Vec::with_capacity(units);
+    let mut c_next = Vec::with_capacity(units);
+    for j in 0..units {
+        let input = sigmoid(gates[j]);
+        let forget = sigmoid(gates[units + j]);
+        let cell = gates[2 * units + j].tanh();
+        let output = sigmoid(gates[3 * units + j]);
+        c_next.push(forget * c[j] + input * cell);
+        h_next.push(output * c_next[j].tanh());
+    }
+    (h_next, c_next)
+}
+
+/// LSTM over a `[steps, features]` sequence from zero states, returning the
+/// last hidden state; `w` is `[features, 4 units]` and `u` `[units, 4 units]`
+pub fn lstm(x: &Tensor, w: &Tensor, u: &Tensor, b: &Tensor) -> Tensor {
+    let (steps, features) = (x.shape[0], x.shape[1]);
+    let units = u.shape[0];
+    let (mut h, mut c) = (vec![0.0; units], vec![0.0; units]);
+    for t in 0..steps {
+        (h, c) = lstm_cell(&x.data[t * features..][..features], &h, &c, w, u, b);
+    }
+    Tensor { shape: vec![units], data: h }
+}
+
+/// Apply an activation function; softmax normalises over the last axis
+pub fn activate(mut x: Tensor, activation: &ActivationFunction) -> Tensor {
+    match activation {
+        ActivationFunction::ReLU => x.data.iter_mut().for_each(|v| *v = v.max(0.0)),
+        ActivationFunction::Sigmoid => x.data.iter_mut().for_each(|v| *v = 1.0 / (1.0 + (-*v).exp())),
+        ActivationFunction::Tanh => x.data.iter_mut().for_each(|v| *v = v.tanh()),
+        ActivationFunction::Softmax => {
+            let n = x.shape.last().copied().unwrap_or(1).max(1);
+            for row in x.data.chunks_mut(n) {
+                let max = row.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+                let mut sum = 0.0;
+                for v in row.iter_mut() {
+                    *v = (*v - max).exp();
+                    sum += *v;
+                }
+                row.iter_mut().for_each(|v| *v /= sum);
+            }
+        },
+        ActivationFunction::None => {},
+    }
+    x
+}
@@ -0,0 +1,208 @@
+use crate::ast::*;
+use crate::diagnostics::{codes, Diagnostic, Span};
+use crate::shape_inference::*;
+
+/// Name, default and accepted range of a layer parameter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub default: usize,
+    pub min: usize,
+    pub max: usize,
+}
+
+const fn spec(name: &'static str, default: usize, max: usize) -> ParamSpec {
+    ParamSpec { name, default, min: 1, max }
+}
+
+pub const FILTERS: ParamSpec = spec("filters", DEFAULT_FILTERS, 65536);
+pub const KERNEL: ParamSpec = spec("kernel", DEFAULT_KERNEL, 64);
+pub const STRIDE: ParamSpec = spec("stride", DEFAULT_TRANSPOSE_STRIDE, 64);
+pub const CONV_STRIDE: ParamSpec = spec("stride", 1, 64);
+// 0 steps by the window size, so that pooling windows do not overlap
+pub const POOL_STRIDE: ParamSpec = ParamSpec { name: "stride", default: 0, min: 0, max: 64 };
+// 1 pads the input so that every position starts a window ("same"), 0 only
+// slides over windows that fit ("valid")
+pub const PADDING: ParamSpec = ParamSpec { name: "padding", default: 1, min: 0, max: 1 };
+pub const POOL_PADDING: ParamSpec = ParamSpec { name: "padding", default: 0, min: 0, max: 1 };
+pub const SIZE: ParamSpec = spec("size", DEFAULT_POOL, 64);
+pub const UNITS: ParamSpec = spec("units", DEFAULT_UNITS, 1 << 24);
+pub const FACTOR: ParamSpec = spec("factor", DEFAULT_UPSAMPLE, 64);
+pub const DIM: ParamSpec = spec("dim", DEFAULT_EMBEDDING_DIM, 65536);
+pub const VOCAB: ParamSpec = spec("vocab", DEFAULT_VOCABULARY, 1 << 24);
+pub const HEADS: ParamSpec = spec("heads", 1, 256);
+pub const BATCH: ParamSpec = spec("batch", 32, 1 << 20);
+// 1 masks every later position from each query
+pub const CAUSAL: ParamSpec = ParamSpec { name: "causal", default: 0, min: 0, max: 1 };
+
+/// Parameters of a layer type in the order positional values fill them
+///
+/// `R` and `U` with two or more values take a target shape instead, see
+/// `target_shape`.
+pub fn schema(layer_type: &LayerType) -> &'static [ParamSpec] {
+    match layer_type {
+        LayerType::Convolutional(_) => &[FILTERS, KERNEL, CONV_STRIDE, PADDING],
+        LayerType::TransposeConv => &[FILTERS, KERNEL, STRIDE],
+        LayerType::Pooling => &[SIZE, POOL_STRIDE, POOL_PADDING],
+        LayerType::Dense(_) | LayerType::LSTM => &[UNITS],
+        LayerType::Upsampling => &[FACTOR],
+        LayerType::Embedding => &[DIM, VOCAB],
+        LayerType::AttentionHeads => &[HEADS],
+        LayerType::BatchSize => &[BATCH],
+        LayerType::Attention => &[CAUSAL],
+        LayerType::Flatten | LayerType::Reshape => &[],
+        LayerType::Custom(op) => op.params(),
+    }
+}
+
+/// Target shape of a layer written with dimensions: `R 7×7×64`, or `U 4×4×512`,
+/// which projects its input to that shape
+pub fn target_shape(layer: &LayerNode) -> Option<&[usize]> {
+    match layer.layer_type {
+        LayerType::Reshape if !layer.params.is_empty() => Some(&layer.params),
+        LayerType::Upsampling if layer.params.len() >= 2 => Some(&layer.params),
+        _ => None,
+    }
+}
+
+/// Window a convolution or pooling layer slides over the height and width
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window {
+    pub size: usize,
+    pub stride: usize,
+    // Pad the input like TensorFlow's "same", else use only windows that fit
+    pub same: bool,
+}
+
+impl Window {
+    /// Positions of the window along an axis of `input` values, or None when
+    /// no window fits
+    pub fn positions(&self, input: usize) -> Option<usize> {
+        if self.same {
+            Some(input.div_ceil(self.stride))
+        } else if self.size > input {
+            None
+        } else {
+            Some((input - self.size) / self.stride + 1)
+        }
+    }
+    
+    /// Padding before and after an axis of `input` values; the odd one goes after
+    pub fn padding(&self, input: usize) -> (usize, usize) {
+        if !self.same {
+            return (0, 0);
+        }
+        let covered = input.div_ceil(self.stride).saturating_sub(1) * self.stride + self.size;
+        let total = covered.saturating_sub(input);
+        (total / 2, total - total / 2)
+    }
+}
+
+impl LayerNode {
+    /// Resolved value of a parameter: its `name=value` override, else the
+    /// positional value in schema order, else the default
+    ///
+    /// A parameter the layer type does not have resolves to the default of `spec`.
+    pub fn param(&self, spec: ParamSpec) -> usize {
+        let schema = schema(&self.layer_type);
+        let Some(index) = schema.iter().position(|s| s.name == spec.name) else {
+            return spec.default;
+        };
+        if let Some(named) = self.named_params.iter().rev().find(|p| p.name == spec.name) {
+            return named.value;
+        }
+        let positional = if target_shape(self).is_some() { None } else { self.params.get(index) };
+        positional.copied().unwrap_or(schema[index].default)
+    }
+    
+    /// Window of a convolution or pooling layer
+    pub fn window(&self) -> Option<Window> {
+        match self.layer_type {
+            LayerType::Convolutional(_) => Some(Window {
+                size: self.param(KERNEL),
+                stride: self.param(CONV_STRIDE),
+                same: self.param(PADDING) == 1,
+            }),
+            LayerType::Pooling => {
+                let size = self.param(SIZE).max(1);
+                let stride = match self.param(POOL_STRIDE) {
+                    0 => size,
+                    stride => stride,
+                };
+                Some(Window { size, stride, same: self.param(POOL_PADDING) == 1 })
+            },
+            _ => None,
+        }
+    }
+    
+    /// Every parameter of the layer with its resolved value, in schema order
+    pub fn resolved_params(&self) -> Vec<(&'static str, usize)> {
+        schema(&self.layer_type).iter().map(|s| (s.name, self.param(*s))).collect()
+    }
+}
+
+/// Check the values of a layer against its schema: the number of positional
+/// values, override names, duplicates and ranges, and that only `E` has `+P`
+pub fn check(layer: &LayerNode) -> Vec<Diagnostic> {
+    let schema = schema(&layer.layer_type);
+    let symbol = layer.symbol();
+    let names = || schema.iter().map(|s| format!("`{}`", s.name)).collect::<Vec<_>>().join(", ");
+    let invalid = |message: String, span: Span, label: String| {
+        Diagnostic::error(codes::INVALID_LAYER_PARAM, message).with_primary(span, label)
+    };
+    let mut diagnostics = Vec::new();
+    
+    if layer.positional && !matches!(layer.layer_type, LayerType::Embedding) {
+        diagnostics.push(invalid(format!("`{}` cannot take a positional encoding", symbol),
+                                 layer.span, "`+P` only follows `E`".to_string()));
+    }
+    
+    let positional = match target_shape(layer) {
+        Some(shape) => {
+            if shape.contains(&0) {
+                diagnostics.push(invalid(format!("target shape of `{}` has a zero dimension", symbol),
+                                         layer.span, "every dimension must be at least 1".to_string()));
+            }
+            0
+        },
+        None => layer.params.len(),
+    };
+    if positional > schema.len() {
+        let message = match schema.len() {
+            0 => format!("`{}` takes no values, found {}", symbol, positional),
+            n => format!("`{}` takes at most {} value{} ({}), found {}", symbol, n, if n == 1 { "" } else { "s" }, names(), positional),
+        };
+        diagnostics.push(invalid(message, layer.span, "too many values".to_string()));
+    }
+    
+    for (i, named) in layer.named_params.iter().enumerate() {
+        if target_shape(layer).is_some() {
+            diagnostics.push(invalid(format!("`{}` with a target shape takes no `{}`", symbol, named.name),
+                                     named.span, "remove this or the target shape".to_string()));
+            continue;
+        }
+        let Some(index) = schema.iter().position(|s| s.name == named.name) else {
+            let expected = if schema.is_empty() { "it takes none".to_string() } else { format!("expected {}", names()) };
+            diagnostics.push(invalid(format!("`{}` has no parameter `{}`", symbol, named.name),
+                                     named.span, format!("unknown parameter; {}", expected)));
+            continue;
+        };
+        if index < positional || layer.named_params[..i].iter().any(|p| p.name == named.name) {
+            diagnostics.push(invalid(format!("`{}` of `{}` is given twice", named.name, symbol),
+                                     named.span, "already set".to_string()));
+        }
+    }
+    if !diagnostics.is_empty() {
+        return diagnostics;
+    }
+    
+    for spec in schema {
+        let value = layer.param(*spec);
+        if value < spec.min || value > spec.max {
+            let span = layer.named_params.iter().rev().find(|p| p.name == spec.name).map(|p| p.span).unwrap_or(layer.span);
+            diagnostics.push(invalid(format!("`{}` of `{}` must be between {} and {}, found {}", spec.name, symbol, spec.min, spec.max, value),
+                                     span, "out of range".to_string()));
+        }
+    }
+    diagnostics
+}
@@ -1,16 +1,34 @@
 // Core language components
 pub mod ast;
 pub mod parser;
+pub mod diagnostics;
+pub mod shape_inference;
+pub mod layer_params;
+pub mod custom_ops;
+pub mod summary;
+pub mod diff;
+pub mod graph;
 pub mod interpreter;
+pub mod autodiff;
+pub mod dataset;
+pub mod tokenizer;
+pub mod training;
+pub mod composite;
+pub mod protobuf;
+pub mod onnx;
+pub mod importer;
+pub mod safetensors;
+pub mod quantization;
 pub mod compiler;
 pub mod asm_compiler;
 
 // Platform support
 pub mod platform_detector;
 pub mod universal_compiler;
+pub mod backend;
 
-// Web server
-pub mod web_server;
+// Web server - not in the tree, so disabled to fix the build
+// pub mod web_server;
 
 // Extensions
 pub mod extensions {
@@ -40,6 +58,12 @@ pub mod compilers {
     
     // JVM platforms
     pub mod kotlin_compiler;
+    
+    // Python
+    pub mod pytorch_compiler;
+    
+    // TensorFlow.js models for the web target
+    pub mod tfjs_compiler;
 }
 
 #[cfg(test)]
@@ -61,16 +85,1316 @@ mod tests {
         // X86_64 compilation
         let x86_asm = asm_compiler::compile_to_asm(&ast, asm_compiler::AsmTarget::X86_64);
         assert!(x86_asm.contains("section .text"));
-        assert!(x86_asm.contains("global _start"));
+        assert!(x86_asm.contains("default rel"));
+        // Layers outside components have no function to live in
+        assert!(x86_asm.contains("; Not in a component, so not compiled"));
+        assert!(!x86_asm.contains("_start"));
         
         // ARM64 compilation
         let arm_asm = asm_compiler::compile_to_asm(&ast, asm_compiler::AsmTarget::ARM64);
-        assert!(arm_asm.contains(".global _start"));
+        assert!(arm_asm.contains(".text"));
+        assert!(arm_asm.contains("// Not in a component, so not compiled"));
+        assert!(!arm_asm.contains("_start"));
         
         // WebAssembly compilation
         let wasm_asm = asm_compiler::compile_to_asm(&ast, asm_compiler::AsmTarget::WASM);
         assert!(wasm_asm.contains("(module"));
-        assert!(wasm_asm.contains("(func (export \"run\")"));
+        assert!(wasm_asm.contains(";; Not in a component, so not compiled"));
+        assert!(!wasm_asm.contains("(export \"run\")"));
+    }
+    
+    #[test]
+    fn test_unknown_component_diagnostic() {
+        let source = "N\nG:I→D 10\nL:G(Z)⊳X⟿BCE";
+        let diagnostics = parser::parse_with_diagnostics(source, &custom_ops::CustomOpRegistry::new()).unwrap_err();
+        let error = diagnostics.iter().find(|d| d.is_error()).unwrap();
+        assert_eq!(error.code, Some(diagnostics::codes::UNKNOWN_COMPONENT));
+        
+        let rendered = error.render("test.gaia", source);
+        assert!(rendered.starts_with("error[E0002]: unknown component `X`"));
+        assert!(rendered.contains("--> test.gaia:3:8"));
+        assert!(rendered.contains("3 | L:G(Z)⊳X⟿BCE"));
+        
+        let json = error.to_json("test.gaia", source);
+        assert_eq!(json["code"], "E0002");
+        assert_eq!(json["spans"][0]["line_start"], 3);
+        assert!(diagnostics::explain("e0002").is_some());
+    }
+    
+    #[test]
+    fn test_backend_registry() {
+        let registry = backend::BackendRegistry::with_builtin();
+        assert!(registry.get("react").is_some());
+        assert!(registry.get("asm-x86_64").is_some());
+        assert!(registry.get("missing").is_none());
+        
+        let android = registry.default_for_platform(platform_detector::Platform::Android).unwrap();
+        assert_eq!(android.name(), "android");
+        
+        let mut compiler = universal_compiler::UniversalCompiler::new();
+        compiler.set_backend("wasm");
+        assert_eq!(compiler.select_backend().unwrap().name(), "wasm");
+        compiler.set_backend("missing");
+        assert!(compiler.select_backend().is_err());
+    }
+    
+    #[test]
+    fn test_shape_inference() {
+        fn collect(node: &ast::ASTNode, out: &mut Vec<(String, String)>) {
+            match node {
+                ast::ASTNode::Network(n) => n.body.iter().for_each(|c| collect(c, out)),
+                ast::ASTNode::Component(c) => collect(&c.expr, out),
+                ast::ASTNode::DataFlow(a, b) => { collect(a, out); collect(b, out); },
+                ast::ASTNode::Expression(nodes) => nodes.iter().for_each(|c| collect(c, out)),
+                ast::ASTNode::Block(b) => collect(&b.content, out),
+                ast::ASTNode::Layer(l) => out.push((l.symbol(), l.output_shape.as_ref().unwrap().to_string())),
+                _ => {}
+            }
+        }
+        
+        let mut ast = parser::parse("N\nÑ:I 28×28×1→C₁ 32 3 ρ→P→F→D₁ 128 ρ→D₀ 10").unwrap();
+        assert!(shape_inference::infer_shapes(&mut ast).is_empty());
+        let mut layers = Vec::new();
+        collect(&ast, &mut layers);
+        let shapes: Vec<&str> = layers.iter().map(|(_, shape)| shape.as_str()).collect();
+        assert_eq!(shapes, vec!["28×28×32", "14×14×32", "6272", "128", "10"]);
+        assert_eq!(layers[0].0, "C₁");
+        
+        // A transposed convolution after `→` is a layer, not a text input
+        let mut ast = parser::parse("N\nÑ:I 4×4×2→Cᵀ 4 3→C₁ 2 3").unwrap();
+        assert!(shape_inference::infer_shapes(&mut ast).is_empty());
+        let mut layers = Vec::new();
+        collect(&ast, &mut layers);
+        assert_eq!(layers[0], ("Cᵀ".to_string(), "8×8×4".to_string()));
+        assert!(ast.to_string().contains("→ Cᵀ 4 3"), "{}", ast);
+        
+        let mut ast = parser::parse("N\nÑ:I 28×28×1→F→C₁ 32 3 ρ").unwrap();
+        let diagnostics = shape_inference::infer_shapes(&mut ast);
+        assert_eq!(diagnostics[0].code, Some(diagnostics::codes::SHAPE_RANK_MISMATCH));
+        
+        let mut ast = parser::parse("N\nG:Z 100→D₀ 784 τ\nD:I 28×28→F→D₀ 1 σ\nL:G(Z)⊳D⟿BCE").unwrap();
+        let diagnostics = shape_inference::infer_shapes(&mut ast);
+        assert_eq!(diagnostics[0].code, Some(diagnostics::codes::SHAPE_INJECTION_MISMATCH));
+        
+        // Backends take input shapes from the same rules, defaults included
+        let ast = parser::parse("N\nÑ:I 28×28→F\nQ:S→L 4").unwrap();
+        let kotlin = compilers::kotlin_compiler::KotlinCompiler::new().compile(&ast).unwrap();
+        assert!(kotlin.contains("ImageInput(shape = intArrayOf(1, 28, 28, 1))"));
+        assert!(kotlin.contains("SequenceInput(shape = intArrayOf(1, 100, 1))"));
+    }
+    
+    #[test]
+    fn test_model_summary() {
+        let ast = parser::parse("N\nÑ:I 28×28×1→C₁ 32 3 ρ→P→F→D₁ 128 ρ→D₀ 10").unwrap();
+        let summary = summary::summarize(&ast);
+        let network = &summary.components[0];
+        assert_eq!(network.id, "Ñ");
+        
+        let params: Vec<usize> = network.layers.iter().map(|l| l.params).collect();
+        assert_eq!(params, vec![320, 0, 0, 802_944, 1290]);
+        assert_eq!(network.total_params, 804_554);
+        assert_eq!(network.layers[0].macs, 28 * 28 * 9 * 32);
+        assert_eq!(network.layers[0].activation_bytes, 28 * 28 * 32 * 4);
+        
+        assert!(summary.to_text().contains("Total params: 804,554"));
+        assert_eq!(summary.to_json()["components"][0]["layers"][3]["kind"], "Dense");
+    }
+    
+    #[test]
+    fn test_interpreter_forward() {
+        let ast = parser::parse("N\nÑ:I 8×8×1→C₁ 4 3 ρ→P→F→D₀ 10→S").unwrap();
+        let interpreter = interpreter::Interpreter::seeded(&ast, 42).unwrap();
+        assert_eq!(interpreter.weights().len(), 4);
+        
+        let input = interpreter::Tensor::new(vec![8, 8, 1], (0..64).map(|i| i as f32 / 64.0).collect()).unwrap();
+        let output = interpreter.forward("Ñ", &input).unwrap();
+        assert_eq!(output.shape, vec![10]);
+        assert!((output.data.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        
+        // Same seed, same numbers
+        let again = interpreter::Interpreter::seeded(&ast, 42).unwrap();
+        assert_eq!(again.forward("Ñ", &input).unwrap(), output);
+        
+        // Loaded weights are checked against the network and used as given
+        let ast = parser::parse("N\nÑ:T 2→D₀ 2").unwrap();
+        let mut weights = interpreter::Weights::new();
+        weights.insert("Ñ.D.weight", interpreter::Tensor::new(vec![2, 2], vec![1.0, 2.0, 3.0, 4.0]).unwrap());
+        assert!(interpreter::Interpreter::new(&ast, weights.clone()).is_err());
+        weights.insert("Ñ.D.bias", interpreter::Tensor::new(vec![2], vec![0.5, -0.5]).unwrap());
+        let interpreter = interpreter::Interpreter::new(&ast, weights).unwrap();
+        let output = interpreter.forward("Ñ", &interpreter::Tensor::new(vec![2], vec![1.0, 1.0]).unwrap()).unwrap();
+        assert_eq!(output.data, vec![4.5, 5.5]);
+        
+        // "Same" convolution of ones with a 3×3 kernel of ones counts the neighbours
+        use layer_params::Window;
+        let ones = interpreter::Tensor::new(vec![3, 3, 1], vec![1.0; 9]).unwrap();
+        let kernel = interpreter::Tensor::new(vec![3, 3, 1, 1], vec![1.0; 9]).unwrap();
+        let zero = interpreter::Tensor::zeros(vec![1]);
+        let conv = interpreter::conv2d(&ones, &kernel, &zero, Window { size: 3, stride: 1, same: true });
+        assert_eq!(conv.data, vec![4.0, 6.0, 4.0, 6.0, 9.0, 6.0, 4.0, 6.0, 4.0]);
+        assert_eq!(interpreter::max_pool2d(&conv, Window { size: 2, stride: 2, same: false }).data, vec![9.0]);
+        
+        // With stride 2 only the corners are centres; "valid" keeps the one window that fits
+        assert_eq!(interpreter::conv2d(&ones, &kernel, &zero, Window { size: 3, stride: 2, same: true }).data, vec![4.0; 4]);
+        assert_eq!(interpreter::conv2d(&ones, &kernel, &zero, Window { size: 3, stride: 1, same: false }).data, vec![9.0]);
+        // "Same" pooling pads after the odd row and column
+        assert_eq!(interpreter::max_pool2d(&conv, Window { size: 2, stride: 2, same: true }).data, vec![9.0, 6.0, 6.0, 4.0]);
+    }
+    
+    #[test]
+    fn test_training() {
+        use training::{Dataset, OptimizerKind, TrainConfig, Trainer};
+        
+        // Analytic gradients agree with finite differences, also for strided and padded windows
+        for (source, size) in [
+            ("N\nÑ:I 4×4×1→C₁ 2 3 τ→P→F→D₀ 3→S", 4),
+            ("N\nÑ:I 5×5×1→C₁ 2 3 stride=2 τ→P size=2 stride=1 padding=1→F→D₀ 3→S", 5),
+        ] {
+            let ast = parser::parse(source).unwrap();
+            let mut trainer = Trainer::for_component(&ast, "Ñ", None).unwrap();
+            trainer.seed_weights(7);
+            let input = interpreter::Tensor::new(vec![size, size, 1], (0..size * size).map(|i| (i as f32 * 0.37).sin()).collect()).unwrap();
+            let target = interpreter::Tensor::new(vec![3], vec![0.0, 1.0, 0.0]).unwrap();
+            let batch = vec![(input, target)];
+            let (_, gradients) = trainer.gradients(&batch).unwrap();
+            
+            for name in ["Ñ.C₁.weight", "Ñ.D.weight", "Ñ.D.bias"] {
+                let analytic = gradients[name].data[1];
+                let mut shifted = trainer.weights().clone();
+                shifted.get_mut(name).unwrap().data[1] += 1e-3;
+                let mut plus = Trainer::for_component(&ast, "Ñ", None).unwrap();
+                plus.load_weights(shifted.clone(), 0).unwrap();
+                shifted.get_mut(name).unwrap().data[1] -= 2e-3;
+                let mut minus = Trainer::for_component(&ast, "Ñ", None).unwrap();
+                minus.load_weights(shifted, 0).unwrap();
+                let numeric = (plus.gradients(&batch).unwrap().0 - minus.gradients(&batch).unwrap().0) / 2e-3;
+                assert!((analytic - numeric).abs() < 1e-2, "{}: {} vs {}", name, analytic, numeric);
+            }
+        }
+        
+        // A declared loss trains the chained components and the loss goes down
+        let ast = parser::parse("N\nM:T 2→D₁ 8 τ\nH:T 8→D₀ 1 σ\nL:M⊳H⟿BCE").unwrap();
+        let data = "x,y,label\n0,0,0\n0,1,1\n1,0,1\n1,1,0\n";
+        let mut trainer = Trainer::for_loss(&ast, Some("L")).unwrap();
+        trainer.seed_weights(1);
+        let dataset = Dataset::from_csv(data, trainer.input_shape(), trainer.output_shape()).unwrap();
+        let config = TrainConfig { epochs: 300, batch_size: 4, learning_rate: 0.05, optimizer: OptimizerKind::Adam, seed: 1 };
+        let history = trainer.fit(&dataset, &config, |_, _| {}).unwrap();
+        assert!(history[299] < history[0] * 0.5);
+        assert!(trainer.weights().get("M.D₁.weight").is_some());
+        
+        // Layers without tape operations are refused before any example is seen
+        let ast = parser::parse("N\nQ:S 10 3→L 8 τ→D₀ 1 σ\nH:T 1→D₀ 1 σ\nL:Q⊳H⟿BCE").unwrap();
+        let error = Trainer::for_loss(&ast, Some("L")).err().unwrap();
+        assert!(error.contains("in `Q` cannot be trained yet"), "{}", error);
+        assert!(Trainer::for_component(&ast, "Q", None).is_err());
+    }
+    
+    #[test]
+    fn test_structured_loss() {
+        let source = "N\nG:Z 4→D₀ 4 τ\nD:T 4→D₀ 1 σ\nL:G(Z)⊳D⟿BCE+λ‖∇D‖-0.5·L2+μ‖G‖₁";
+        let ast = parser::parse(source).unwrap();
+        let loss = match &ast {
+            ast::ASTNode::Network(network) => match &network.body[2] {
+                ast::ASTNode::Loss(loss) => loss.clone(),
+                _ => panic!("expected a loss"),
+            },
+            _ => unreachable!(),
+        };
+        assert_eq!(loss.label.as_deref(), Some("L"));
+        assert_eq!(loss.sources, vec!["G".to_string()]);
+        assert_eq!(loss.function.terms.len(), 4);
+        assert_eq!(loss.function.terms[0].term, ast::LossTerm::Base(ast::BaseLoss::BinaryCrossEntropy));
+        assert_eq!(loss.function.terms[1].coefficient, Some(ast::Coefficient::Symbol("λ".to_string())));
+        assert!(matches!(loss.function.terms[1].term, ast::LossTerm::GradientPenalty { ref component, .. } if component == "D"));
+        assert!(loss.function.terms[2].negated);
+        assert_eq!(loss.function.to_string(), "BCE+λ‖∇D‖-0.5L2+μ‖G‖₁");
+        
+        // Symbolic coefficients need a value before training
+        let mut trainer = training::Trainer::for_loss(&ast, None).unwrap();
+        trainer.seed_weights(0);
+        assert_eq!(trainer.unsupported_terms().len(), 1);
+        let batch = vec![(interpreter::Tensor::zeros(vec![4]), interpreter::Tensor::zeros(vec![1]))];
+        assert!(trainer.gradients(&batch).is_err());
+        trainer.set_coefficient("μ", 0.01);
+        assert!(trainer.gradients(&batch).is_ok());
+        
+        let diagnostics = parser::parse_with_diagnostics("N\nD:T 4→D₀ 1 σ\nL:D⊳D⟿BCE+Huber", &custom_ops::CustomOpRegistry::new()).unwrap_err();
+        assert_eq!(diagnostics[0].code, Some(diagnostics::codes::UNKNOWN_LOSS));
+        assert!(diagnostics[0].render("t.gaia", "N\nD:T 4→D₀ 1 σ\nL:D⊳D⟿BCE+Huber").contains("unknown loss function `Huber`"));
+    }
+    
+    #[test]
+    fn test_composite_model() {
+        let source = "N\nG:Z 4→D₀ 4 τ\nK:T 4→D₀ 4 τ\nD:T 4→D₁ 3 ρ→D₀ 1 σ\nE:T 4→D₁ 3 ρ→D₀ 1 σ\nE≜D\n\
+                      L:G(Z)⊳D⊘⟿BCE\nA:G(Z)⊳K⊳E⟿MSE";
+        let (mut ast, _) = parser::parse_with_diagnostics(source, &custom_ops::CustomOpRegistry::new()).unwrap();
+        assert!(shape_inference::infer_shapes(&mut ast).is_empty());
+        assert!(composite::check_shares(&ast).is_empty());
+        
+        let composites = composite::composites(&ast).unwrap();
+        assert_eq!(composites.len(), 2);
+        assert_eq!(composites[0].to_string(), "L: G⊳D⊘⟿BCE");
+        assert_eq!(composites[1].stages.iter().map(|s| s.component.as_str()).collect::<Vec<_>>(), vec!["G", "K", "E"]);
+        assert_eq!(composites[1].output().dims, vec![1]);
+        // E runs with the parameters of D
+        assert!(composites[1].parameter_shapes().iter().any(|(name, _)| name == "D.D₁.weight"));
+        assert!(!composites[1].parameter_shapes().iter().any(|(name, _)| name.starts_with("E.")));
+        
+        // The generator step trains G through the frozen discriminator
+        let mut trainer = training::Trainer::for_loss(&ast, Some("L")).unwrap();
+        trainer.seed_weights(0);
+        let batch = vec![(interpreter::Tensor::new(vec![4], vec![0.5, -0.5, 1.0, 0.0]).unwrap(),
+                          interpreter::Tensor::new(vec![1], vec![1.0]).unwrap())];
+        let (_, gradients) = trainer.gradients(&batch).unwrap();
+        assert!(gradients.contains_key("G.D.weight"));
+        assert!(!gradients.keys().any(|name| name.starts_with("D.")));
+        
+        let asm = asm_compiler::compile_to_asm(&ast, asm_compiler::AsmTarget::X86_64);
+        assert!(asm.contains("call component_K") && asm.contains("Frozen stage: parameters of D"));
+        
+        // Every stage of a chain is checked, not only the first injection
+        let (mut ast, _) = parser::parse_with_diagnostics("N\nG:Z 4→D₀ 4 τ\nK:T 4→D₀ 3 τ\nD:T 4→D₀ 1 σ\nA:G(Z)⊳K⊳D⟿MSE", &custom_ops::CustomOpRegistry::new()).unwrap();
+        let diagnostics = shape_inference::infer_shapes(&mut ast);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("into `D`"));
+        
+        let (ast, _) = parser::parse_with_diagnostics("N\nD:T 4→D₀ 1 σ\nE:T 4→D₀ 2 σ\nE≜D", &custom_ops::CustomOpRegistry::new()).unwrap();
+        let diagnostics = composite::check_shares(&ast);
+        assert_eq!(diagnostics[0].code, Some(diagnostics::codes::SHAPE_SHARE_MISMATCH));
+    }
+    
+    #[test]
+    fn test_onnx_export() {
+        let ast = parser::parse("N\nÑ:I 8×8×1→C₁ 4 3 ρ→P→F→D₀ 10→S").unwrap();
+        let weights = interpreter::Weights::seeded(&interpreter::plan_network(&ast).unwrap(), 3);
+        let models = onnx::export_network(&ast, Some(&weights)).unwrap();
+        assert_eq!(models.len(), 1);
+        let model = &models[0].1;
+        
+        // The in-crate decoder reads back exactly what was written
+        let decoded = onnx::Model::decode(&model.encode()).unwrap();
+        assert_eq!(&decoded, model);
+        assert_eq!(decoded.opset_version, onnx::OPSET_VERSION);
+        let graph = &decoded.graph;
+        let ops: Vec<&str> = graph.nodes.iter().map(|n| n.op_type.as_str()).collect();
+        assert_eq!(ops, vec!["Conv", "Relu", "MaxPool", "Transpose", "Reshape", "Gemm", "Softmax"]);
+        assert_eq!(graph.inputs[0].dims, vec![onnx::Dim::Param("N".to_string()), onnx::Dim::Value(1),
+                                             onnx::Dim::Value(8), onnx::Dim::Value(8)]);
+        assert_eq!(graph.outputs[0].dims[1], onnx::Dim::Value(10));
+        assert_eq!(graph.nodes[0].ints("pads"), Some(&[1, 1, 1, 1][..]));
+        
+        // Convolution weights are stored [out, in, k, k]
+        let conv = graph.initializer("Ñ.C₁.weight").unwrap();
+        assert_eq!(conv.dims, vec![4, 1, 3, 3]);
+        let original = weights.get("Ñ.C₁.weight").unwrap();
+        // [ky=1, kx=2, in=0, out=3] in the interpreter layout
+        assert_eq!(conv.floats[(3 * 3 + 1) * 3 + 2], original.data[(3 + 2) * 4 + 3]);
+        
+        // Transposed convolutions output exactly `stride` times their input,
+        // with the crop split like the interpreter's and weights stored [in, out, k, k]
+        for (kernel, pads, output_padding) in [(3, [0, 0, 1, 1], [0, 0]), (4, [1, 1, 1, 1], [0, 0]), (1, [0, 0, 0, 0], [1, 1])] {
+            let ast = parser::parse(&format!("N\nG:I 4×4×2→Cᵀ 3 {} 2", kernel)).unwrap();
+            let (_, model) = onnx::export_network(&ast, None).unwrap().remove(0);
+            let graph = onnx::Model::decode(&model.encode()).unwrap().graph;
+            let node = graph.nodes.iter().find(|n| n.op_type == "ConvTranspose").unwrap();
+            assert_eq!(node.ints("kernel_shape"), Some(&[kernel, kernel][..]));
+            assert_eq!(node.ints("strides"), Some(&[2, 2][..]));
+            assert_eq!(node.ints("pads"), Some(&pads[..]));
+            assert_eq!(node.ints("output_padding"), Some(&output_padding[..]));
+            assert_eq!(graph.initializer(&node.inputs[1]).unwrap().dims, vec![2, 3, kernel, kernel]);
+            assert_eq!(graph.outputs[0].dims[1..], [onnx::Dim::Value(3), onnx::Dim::Value(8), onnx::Dim::Value(8)]);
+        }
+        
+        // LSTMs take time-major input and return the last hidden state
+        let ast = parser::parse("N\nQ:S 10 3→L 8 τ→D₀ 1 σ").unwrap();
+        let (_, model) = onnx::export_network(&ast, None).unwrap().remove(0);
+        let decoded = onnx::Model::decode(&model.encode()).unwrap();
+        let lstm = decoded.graph.nodes.iter().find(|n| n.op_type == "LSTM").unwrap();
+        assert_eq!(lstm.int("hidden_size"), Some(8));
+        assert_eq!(decoded.graph.initializer(&lstm.inputs[1]).unwrap().dims, vec![1, 32, 3]);
+        assert_eq!(decoded.graph.initializer(&lstm.inputs[3]).unwrap().dims, vec![1, 64]);
+        
+        assert!(backend::BackendRegistry::with_builtin().get("onnx").is_some());
+    }
+    
+    #[test]
+    fn test_import() {
+        // An exported model comes back as the same architecture
+        let source = "N\nÑ:I 8×8×1→C₁ 4 3 ρ→P→F→D₁ 16 ρ→D₀ 10→S\n";
+        let ast = parser::parse(source).unwrap();
+        let (_, model) = onnx::export_network(&ast, None).unwrap().remove(0);
+        let network = importer::import_onnx(&model.encode(), "Ñ").unwrap();
+        assert_eq!(importer::to_source(&network), source);
+        
+        let (_, model) = onnx::export_network(&parser::parse("N\nQ:S 10×3→L 8→D₀ 1 σ").unwrap(), None).unwrap().remove(0);
+        assert_eq!(importer::to_source(&importer::import_onnx(&model.encode(), "Q").unwrap()), "N\nQ:S 10×3→L 8→D₀ 1 σ\n");
+        
+        let keras = r#"{"class_name": "Sequential", "config": {"name": "mnist", "layers": [
+            {"class_name": "InputLayer", "config": {"name": "in", "batch_input_shape": [null, 28, 28, 1]}},
+            {"class_name": "Conv2D", "config": {"name": "c", "filters": 32, "kernel_size": [3, 3], "strides": [1, 1],
+                                                "padding": "same", "activation": "relu"}},
+            {"class_name": "MaxPooling2D", "config": {"name": "p", "pool_size": [2, 2], "strides": null}},
+            {"class_name": "Dropout", "config": {"name": "d", "rate": 0.25}},
+            {"class_name": "Flatten", "config": {"name": "f"}},
+            {"class_name": "Dense", "config": {"name": "h", "units": 128, "activation": "relu"}},
+            {"class_name": "Dense", "config": {"name": "o", "units": 10, "activation": "softmax"}}]}}"#;
+        let network = importer::import_keras(keras, "Ñ").unwrap();
+        let source = importer::to_source(&network);
+        assert_eq!(source, "N\nÑ:I 28×28×1→C₁ 32 3 ρ→P→F→D₁ 128 ρ→D₀ 10→S\n");
+        let mut ast = parser::parse(&source).unwrap();
+        assert!(shape_inference::infer_shapes(&mut ast).is_empty());
+        
+        // Strided, unpadded convolutions and overlapping, padded pooling windows keep their parameters
+        let source = "N\nÑ:I 9×9×1→C₁ 4 3 2 0 ρ→P 3 1 1→P 3 2 0→F→D₀ 2\n";
+        let (_, model) = onnx::export_network(&parser::parse(source).unwrap(), None).unwrap().remove(0);
+        assert_eq!(importer::to_source(&importer::import_onnx(&model.encode(), "Ñ").unwrap()), source);
+        let strided = r#"{"class_name": "Sequential", "config": {"name": "strided", "layers": [
+            {"class_name": "InputLayer", "config": {"name": "in", "batch_input_shape": [null, 9, 9, 1]}},
+            {"class_name": "Conv2D", "config": {"name": "c", "filters": 4, "kernel_size": [3, 3], "strides": [2, 2],
+                                                "padding": "valid", "activation": "relu"}},
+            {"class_name": "MaxPooling2D", "config": {"name": "p", "pool_size": [3, 3], "strides": [1, 1], "padding": "same"}}]}}"#;
+        let source = importer::to_source(&importer::import_keras(strided, "Ñ").unwrap());
+        assert_eq!(source, "N\nÑ:I 9×9×1→C₁ 4 3 2 0 ρ→P 3 1 1\n");
+        let mut ast = parser::parse(&source).unwrap();
+        assert!(shape_inference::infer_shapes(&mut ast).is_empty());
+        
+        // Transposed convolutions come back as `Cᵀ` layers that parse again
+        let source = "N\nG:I 4×4×2→Cᵀ 3 3 2 ρ→F→D₀ 2\n";
+        let (_, model) = onnx::export_network(&parser::parse(source).unwrap(), None).unwrap().remove(0);
+        assert_eq!(importer::to_source(&importer::import_onnx(&model.encode(), "G").unwrap()), source);
+        let decoder = r#"{"class_name": "Sequential", "config": {"name": "decoder", "layers": [
+            {"class_name": "InputLayer", "config": {"name": "in", "batch_input_shape": [null, 7, 7, 16]}},
+            {"class_name": "Conv2DTranspose", "config": {"name": "t", "filters": 8, "kernel_size": [3, 3], "strides": [2, 2],
+                                                         "padding": "same", "activation": "relu"}}]}}"#;
+        let source = importer::to_source(&importer::import_keras(decoder, "G").unwrap());
+        assert_eq!(source, "N\nG:I 7×7×16→Cᵀ 8 3 2 ρ\n");
+        let mut ast = parser::parse(&source).unwrap();
+        assert!(shape_inference::infer_shapes(&mut ast).is_empty());
+        
+        // Every operation without an equivalent is reported
+        let keras = keras.replace("\"Dropout\"", "\"BatchNormalization\"").replace("128, \"activation\": \"relu\"", "128, \"activation\": \"gelu\"");
+        let error = importer::import_keras(&keras, "Ñ").unwrap_err();
+        assert!(error.starts_with("2 operations without a GaiaScript equivalent"));
+        assert!(error.contains("BatchNormalization `d`") && error.contains("activation `gelu` of Dense `h`"));
+    }
+    
+    #[test]
+    fn test_pytorch_backend() {
+        let source = "N\nG:Z 4→D₁ 8 τ\nD:T 8→D₀ 1 σ\nE:T 8→D₀ 1 σ\nE≜D\nL:G(Z)⊳D⊘⟿BCE+0.5·L2";
+        let code = compilers::pytorch_compiler::compile_to_pytorch(&parser::parse(source).unwrap(), "gan").unwrap();
+        let expected = r#"class G(nn.Module):
+    """G: 4 → 8"""
+
+    def __init__(self):
+        super().__init__()
+        self.d1 = nn.Linear(4, 8)
+
+    def forward(self, x):
+        # x: (N, 4)
+        x = torch.tanh(self.d1(x))  # (N, 8)
+        return x
+
+
+class D(nn.Module):
+    """D: 8 → 1"""
+
+    def __init__(self):
+        super().__init__()
+        self.d = nn.Linear(8, 1)
+
+    def forward(self, x):
+        # x: (N, 8)
+        x = torch.sigmoid(self.d(x))  # (N, 1)
+        return x
+
+
+class E(nn.Module):
+    """E: 8 → 1"""
+
+    def __init__(self):
+        super().__init__()
+        self.d = nn.Linear(8, 1)
+
+    def forward(self, x):
+        # x: (N, 8)
+        x = torch.sigmoid(self.d(x))  # (N, 1)
+        return x
+
+
+def loss_L(G, D, x, target):
+    """L: G⊳D⊘⟿BCE+0.5L2"""
+    h1 = G(x)
+    h2 = D(h1)
+    y = h2
+    return F.binary_cross_entropy(y, target) + 0.5 * weight_norm([G, D], p=2)
+
+
+def parameters_L(G, D):
+    """Parameters trained by `loss_L`; frozen (⊘) components are left out"""
+    return list(G.parameters())
+
+
+def build():
+    """One module per component"""
+    models = {
+        "G": G(),
+        "D": D(),
+        "E": E(),
+    }
+    # E≜D
+    models["E"].d = models["D"].d
+    return models
+"#;
+        assert!(code.starts_with("# Generated by the GaiaScript compiler from `gan`"));
+        assert!(code.ends_with(expected), "{}", code);
+        
+        // Images are NCHW, blocks are unrolled and LSTMs return their last hidden state
+        let source = "N\nÑ:I 8×8×1→[C₁ 4 3 ρ→P]×2→F→D₀ 10→S\nQ:S 10×3→L 8→D₀ 1 σ";
+        let code = compilers::pytorch_compiler::compile_to_pytorch(&parser::parse(source).unwrap(), "app").unwrap();
+        for line in ["self.c1_1 = nn.Conv2d(4, 4, 3, padding=\"same\")", "# [C₁ 4 3 ρ → P]×2", "x = self.p_1(x)  # (N, 4, 2, 2)",
+                     "x = torch.flatten(x.permute(0, 2, 3, 1), 1)  # (N, 16)", "x = torch.softmax(self.d(x), dim=-1)  # (N, 10)",
+                     "self.l = nn.LSTM(3, 8, batch_first=True)", "x = h[-1]  # (N, 8)"] {
+            assert!(code.contains(line), "missing `{}` in\n{}", line, code);
+        }
+    }
+    
+    #[test]
+    #[ignore = "needs python3 with torch"]
+    fn test_pytorch_run() {
+        // With the interpreter's weights the generated module computes the same outputs
+        let ast = parser::parse("N\nÑ:I 4×4×2→C₁ 3 3 ρ→F→D₀ 2").unwrap();
+        let interpreter = interpreter::Interpreter::seeded(&ast, 7).unwrap();
+        let input: Vec<f32> = (0..32).map(|i| (i as f32 * 0.37).sin()).collect();
+        let expected = interpreter.forward("Ñ", &interpreter::Tensor::new(vec![4, 4, 2], input.clone()).unwrap()).unwrap();
+        
+        let names: Vec<&String> = interpreter.weights().iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["Ñ.C₁.bias", "Ñ.C₁.weight", "Ñ.D.bias", "Ñ.D.weight"]);
+        
+        let dir = scratch_dir("pytorch");
+        std::fs::write(dir.join("model.py"), compilers::pytorch_compiler::compile_to_pytorch(&ast, "parity").unwrap()).unwrap();
+        std::fs::write(dir.join("weights.json"), interpreter.weights().to_json().to_string()).unwrap();
+        let driver = format!(r#"import json
+import torch
+from model import build
+
+weights = json.load(open("weights.json", encoding="utf-8"))
+tensor = lambda name: torch.tensor(weights[name]["data"]).view(weights[name]["shape"])
+model = build()["Ñ"]
+with torch.no_grad():
+    model.c1.weight.copy_(tensor("Ñ.C₁.weight").permute(3, 2, 0, 1))
+    model.c1.bias.copy_(tensor("Ñ.C₁.bias"))
+    model.d.weight.copy_(tensor("Ñ.D.weight").t())
+    model.d.bias.copy_(tensor("Ñ.D.bias"))
+    x = torch.tensor({:?}).view(1, 4, 4, 2).permute(0, 3, 1, 2)
+    for value in model(x)[0]:
+        print(value.item())
+"#, input);
+        std::fs::write(dir.join("main.py"), driver).unwrap();
+        let printed = run_tool(&dir, "python3", &["main.py"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let outputs: Vec<f32> = printed.lines().map(|line| line.parse().unwrap()).collect();
+        assert_eq!(outputs.len(), expected.data.len());
+        for (output, expected) in outputs.iter().zip(&expected.data) {
+            assert!((output - expected).abs() < 1e-4, "{} != {}", output, expected);
+        }
+    }
+    
+    #[test]
+    fn test_tfjs_export() {
+        use compilers::tfjs_compiler::{compile_to_tfjs, loader_js};
+        
+        let source = "N\nÑ:I 8×8×1→C₁ 4 3 ρ→P→F→D₀ 10→S\nQ:S 10×8→A→D₀ 2\n";
+        let ast = parser::parse(source).unwrap();
+        let export = compile_to_tfjs(&ast, None).unwrap();
+        assert_eq!(export.models.len(), 1);
+        assert_eq!(export.skipped, vec![("Q".to_string(), "`A` has no TensorFlow.js equivalent yet".to_string())]);
+        
+        let model = &export.models[0];
+        assert_eq!((model.directory.as_str(), model.input.clone(), model.output.clone()), ("u00d1", vec![8, 8, 1], vec![10]));
+        let layers = model.model_json["modelTopology"]["config"]["layers"].as_array().unwrap();
+        let classes: Vec<&str> = layers.iter().map(|l| l["class_name"].as_str().unwrap()).collect();
+        assert_eq!(classes, ["InputLayer", "Conv2D", "MaxPooling2D", "Flatten", "Dense"]);
+        
+        // The shard holds the kernels as the interpreter stores them, in manifest order
+        let manifest = model.model_json["weightsManifest"][0]["weights"].as_array().unwrap();
+        let names: Vec<&str> = manifest.iter().map(|w| w["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["c1/kernel", "c1/bias", "d/kernel", "d/bias"]);
+        assert_eq!(model.weights.len(), 4 * (3 * 3 * 4 + 4 + 64 * 10 + 10));
+        let interpreter = interpreter::Interpreter::seeded(&parser::parse("N\nÑ:I 8×8×1→C₁ 4 3 ρ→P→F→D₀ 10→S").unwrap(), 0).unwrap();
+        let kernel = interpreter.weights().get("Ñ.C₁.weight").unwrap();
+        assert_eq!(protobuf::floats_from_le(&model.weights[..4 * 36]).unwrap(), kernel.data);
+        
+        // The topology reads back as the same architecture
+        let network = importer::import_keras(&model.model_json.to_string(), "Ñ").unwrap();
+        assert_eq!(importer::to_source(&network), "N\nÑ:I 8×8×1→C₁ 4 3 ρ→P→F→D₀ 10→S\n");
+        
+        let loader = loader_js(&export, "digits");
+        assert!(loader.contains("\"Ñ\": { url: \"models/u00d1/model.json\", input: [8, 8, 1], output: [10] },"));
+        assert!(loader.contains("\"Q\": { unsupported: \"`A` has no TensorFlow.js equivalent yet\" },"));
+        assert!(loader.contains("gaiaRuntime.network = function()"));
+    }
+    
+    #[test]
+    fn test_safetensors_weights() {
+        assert_eq!(safetensors::qualified_name("Ñ.C₁.weight"), "η.Ñ.C₁.kernel");
+        assert_eq!(safetensors::qualified_name("Q.L.recurrent"), "η.Q.L.recurrent_kernel");
+        assert_eq!(safetensors::parameter_key("η.Ñ.D₁_1.bias").unwrap(), "Ñ.D₁_1.bias");
+        assert!(safetensors::parameter_key("Ñ.C₁.kernel").is_err());
+        assert_eq!(safetensors::parameter_key("η.Ŧ.A.output_bias").unwrap(), "Ŧ.A.output_bias");
+        
+        let ast = parser::parse("N\nÑ:I 8×8×1→C₁ 4 3 ρ→P→F→D₀ 10→S\nQ:S 10×3→L 8→D₀ 1 σ").unwrap();
+        let plans = interpreter::plan_network(&ast).unwrap();
+        let weights = interpreter::Weights::seeded(&plans, 7);
+        let (bytes, entries) = safetensors::encode_with_layout(&weights);
+        let header_len = u64::from_le_bytes(bytes[..8].try_into().unwrap()) as usize;
+        assert_eq!((8 + header_len) % 8, 0);
+        assert_eq!(entries.first().unwrap().offset, 8 + header_len);
+        assert_eq!(entries.iter().find(|e| e.name == "η.Ñ.C₁.kernel").unwrap().shape, vec![3, 3, 1, 4]);
+        assert_eq!(safetensors::decode(&bytes).unwrap(), weights);
+        assert!(safetensors::validate(&weights, &ast).is_ok());
+        
+        // Every mismatch is reported against the inferred shapes
+        let mut wrong = weights.clone();
+        wrong.insert("Ñ.C₁.weight", interpreter::Tensor::zeros(vec![3, 3, 1, 5]));
+        wrong.insert("Ñ.D₉.bias", interpreter::Tensor::zeros(vec![2]));
+        let error = safetensors::validate(&wrong, &ast).unwrap_err();
+        assert!(error.starts_with("2 weights do not match the network"));
+        assert!(error.contains("`η.Ñ.C₁.kernel` has shape [3, 3, 1, 5], expected [3, 3, 1, 4]"));
+        assert!(error.contains("`η.Ñ.D₉.bias` [2] does not belong to any layer"));
+        
+        // Half-precision tensors are widened on load
+        let header = r#"{"η.Q.D.bias":{"dtype":"F16","shape":[2],"data_offsets":[0,4]}}"#;
+        let mut half = (header.len() as u64).to_le_bytes().to_vec();
+        half.extend_from_slice(header.as_bytes());
+        half.extend_from_slice(&[0x00, 0x3c, 0x00, 0xc0]);
+        assert_eq!(safetensors::decode(&half).unwrap().get("Q.D.bias").unwrap().data, vec![1.0, -2.0]);
+        
+        // Generated code embeds or loads the same container
+        let x86 = asm_compiler::compile_to_asm_with_weights(&ast, asm_compiler::AsmTarget::X86_64, &weights);
+        assert!(x86.contains("section .rodata\nalign 8\n") && x86.contains("\ngaia_weight_u00d1_C1_kernel:\n    dd 0x"));
+        assert!(x86.contains("gaia_weights_size:\n    dq gaia_weights_end - gaia_weights"));
+        let wasm = asm_compiler::compile_to_asm_with_weights(&ast, asm_compiler::AsmTarget::WASM, &weights);
+        assert!(wasm.contains("(memory (export \"memory\") 2)") && wasm.contains("(data (i32.const 65536)\n"));
+        let kotlin = compilers::kotlin_compiler::weights_loader(&weights);
+        assert!(kotlin.contains("        \"η.Ñ.C₁.kernel\" to intArrayOf(3, 3, 1, 4),\n"));
+        let export = compilers::tfjs_compiler::compile_to_tfjs(&ast, Some(&weights)).unwrap();
+        assert_eq!(export.weights, weights);
+        assert!(compilers::tfjs_compiler::loader_js(&export, "app").contains("  \"η.Q.L.recurrent_kernel\": [8, 32],\n"));
+    }
+    
+    #[test]
+    fn test_quantization() {
+        use quantization::{Affine, Granularity, QuantizedWeights};
+        let zero = Affine::from_range(-1.0, 3.0);
+        assert_eq!(zero.quantize(0.0) as i32, zero.zero_point);
+        assert_eq!(Affine::from_range(0.5, 2.0).dequantize(-128), 0.0);
+        
+        // Per-channel scales keep a small channel from rounding to zero
+        let kernel = interpreter::Tensor::new(vec![2, 2], vec![1.0, 0.005, -0.5, -0.02]).unwrap();
+        let per_tensor = QuantizedWeights::quantize(&kernel, Granularity::PerTensor);
+        let per_channel = QuantizedWeights::quantize(&kernel, Granularity::PerChannel);
+        assert_eq!(per_tensor.scales.len(), 1);
+        assert_eq!(per_tensor.data, vec![127, 1, -64, -3]);
+        assert_eq!(per_channel.data, vec![127, 32, -64, -127]);
+        
+        let ast = parser::parse("N\nÑ:I 6×6×1→C₁ 4 3 ρ→P→F→D₀ 8 ρ→D₁ 3→S").unwrap();
+        let interp = interpreter::Interpreter::seeded(&ast, 3).unwrap();
+        let inputs: Vec<interpreter::Tensor> = (0..16)
+            .map(|n| interpreter::Tensor::new(vec![6, 6, 1], (0..36).map(|i| ((i * 7 + n * 5) % 11) as f32 / 10.0).collect()).unwrap())
+            .collect();
+        let model = quantization::quantize(&interp, "Ñ", &inputs, Granularity::PerChannel).unwrap();
+        assert_eq!(model.steps.len(), 5);
+        assert_eq!(model.steps[0].multipliers.len(), 4);
+        assert_eq!(model.steps[2].output, model.steps[1].output);
+        let report = quantization::evaluate(&interp, &model, &inputs, None).unwrap();
+        assert!(report.max_abs_diff < 0.05, "{}", report);
+        assert!(report.agreement >= 0.9 && report.float_accuracy.is_none());
+        assert!(report.to_string().starts_with("int8 vs float on 16 examples"));
+        
+        // Targets after the inputs give the accuracy of both models
+        let csv: String = inputs.iter()
+            .map(|x| format!("{},{}\n", x.data.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(","), x.data[0] as usize % 3))
+            .collect();
+        let network = quantization::quantize_network(&interp, &csv, None, Granularity::PerTensor);
+        assert!(network.skipped.is_empty());
+        assert!(network.models[0].1.float_accuracy.is_some());
+        
+        // Layers without an int8 kernel are refused
+        let lstm = parser::parse("N\nQ:S 10×3→L 8→D₀ 1 σ").unwrap();
+        let lstm_interp = interpreter::Interpreter::seeded(&lstm, 0).unwrap();
+        let error = quantization::quantize(&lstm_interp, "Q", &[interpreter::Tensor::zeros(vec![10, 3])], Granularity::PerChannel);
+        assert!(error.unwrap_err().contains("has no int8 kernel"));
+        
+        let (bytes, entries) = quantization::encode(std::slice::from_ref(&model));
+        let dtype = |name: &str| entries.iter().find(|e| e.name == name).unwrap().dtype;
+        assert_eq!(dtype("η.Ñ.C₁.kernel"), "I8");
+        assert_eq!(dtype("η.Ñ.C₁.bias"), "I32");
+        assert_eq!(dtype("η.Ñ.input_scale"), "F32");
+        assert!(entries.iter().skip_while(|e| e.dtype != "I8").all(|e| e.dtype == "I8"));
+        assert!(bytes.windows(15).any(|w| w == b"gaiascript-int8"));
+        
+        let wasm = asm_compiler::compile_to_asm_quantized(&ast, asm_compiler::AsmTarget::WASM, std::slice::from_ref(&model));
+        assert!(wasm.contains("(func $gaia_dense_i8 (export \"gaia_dense_i8\")") && wasm.contains("i32.load8_s"));
+        assert!(wasm.contains(";; η.Ñ.C₁.kernel I8 [3, 3, 1, 4] at "));
+        let x86 = asm_compiler::compile_to_asm_quantized(&ast, asm_compiler::AsmTarget::X86_64, &[model]);
+        assert!(x86.contains("global gaia_dot_i8\ngaia_dot_i8:") && x86.contains("\ngaia_weight_u00d1_C1_kernel:\n    db 0x"));
+    }
+    
+    #[test]
+    fn test_layer_params() {
+        fn first_layer(node: &ast::ASTNode) -> Option<&ast::LayerNode> {
+            match node {
+                ast::ASTNode::Network(n) => n.body.iter().find_map(first_layer),
+                ast::ASTNode::Component(c) => first_layer(&c.expr),
+                ast::ASTNode::DataFlow(a, b) => first_layer(a).or_else(|| first_layer(b)),
+                ast::ASTNode::Expression(nodes) => nodes.iter().find_map(first_layer),
+                ast::ASTNode::Layer(l) => Some(l),
+                _ => None,
+            }
+        }
+        
+        // Named values override positional ones and the rest keep their defaults
+        let mut ast = parser::parse("N\nÑ:I 28×28×1→C₁ 32 kernel=5 ρ→P size=4").unwrap();
+        assert!(shape_inference::infer_shapes(&mut ast).is_empty());
+        let conv = first_layer(&ast).unwrap();
+        assert_eq!(conv.param(layer_params::FILTERS), 32);
+        assert_eq!(conv.param(layer_params::KERNEL), 5);
+        assert_eq!(conv.resolved_params(), vec![("filters", 32), ("kernel", 5), ("stride", 1), ("padding", 1)]);
+        assert_eq!(conv.to_string(), "C₁ 32 kernel=5 ρ");
+        assert_eq!(layer_params::schema(&ast::LayerType::Embedding)[1].name, "vocab");
+        
+        // A value that cannot be read is reported where it is written
+        for (source, value) in [("C₁ 32 kernel=99999999999999999999999", "99999999999999999999999"), ("C₁ 99999999999999999999999", "99999999999999999999999")] {
+            let source = format!("N\nÑ:I 28×28×1→{}", source);
+            let error = parser::parse(&source).unwrap_err();
+            assert_eq!(error.code, Some(diagnostics::codes::INVALID_LAYER_PARAM));
+            assert_eq!(error.message, format!("cannot read `{}` as a count", value));
+            let span = error.primary.unwrap().span;
+            assert_eq!(&source[span.start..span.end], value);
+        }
+        
+        // Stride and padding set how the window slides
+        for (source, output) in [
+            ("C₁ 8 3 stride=2", vec![14, 14, 8]),
+            ("C₁ 8 5 padding=0", vec![24, 24, 8]),
+            ("C₁ 8 3 3 0", vec![9, 9, 8]),
+            ("P size=3 stride=2", vec![13, 13, 1]),
+            ("P size=3 stride=2 padding=1", vec![14, 14, 1]),
+        ] {
+            let mut ast = parser::parse(&format!("N\nÑ:I 28×28×1→{}", source)).unwrap();
+            assert!(shape_inference::infer_shapes(&mut ast).is_empty(), "{}", source);
+            let plan = interpreter::plan_network(&ast).unwrap();
+            assert_eq!(plan[0].output().dims, output, "{}", source);
+        }
+        
+        // Backends write the window out, padding unevenly first, or leave what their kernels cannot run
+        let mut ast = parser::parse("N\nÑ:I 8×8×1→C₁ 4 3 stride=2 ρ→P size=3 stride=2 padding=1→F→D₀ 2").unwrap();
+        assert!(shape_inference::infer_shapes(&mut ast).is_empty());
+        let torch = compilers::pytorch_compiler::compile_to_pytorch(&ast, "strided").unwrap();
+        assert!(torch.contains("self.c1 = nn.Conv2d(1, 4, 3, stride=2, padding=0)"));
+        assert!(torch.contains("x = self.p(F.pad(x, (0, 1, 0, 1), value=float(\"-inf\")))"));
+        let asm = asm_compiler::compile_to_asm(&ast, asm_compiler::AsmTarget::X86_64);
+        assert!(asm.contains("; Component Ñ: the runtime has no kernel for `C₁`"));
+        
+        // `U` with a target shape has no factor to override
+        let ast = parser::parse("N\nÑ:Z 100→U 4×4×8").unwrap();
+        let upsample = first_layer(&ast).unwrap();
+        assert_eq!(layer_params::target_shape(upsample), Some(&[4, 4, 8][..]));
+        assert_eq!(upsample.param(layer_params::FACTOR), 2);
+        // A parameter the layer type does not have resolves to its default
+        assert_eq!(upsample.param(layer_params::KERNEL), shape_inference::DEFAULT_KERNEL);
+        
+        for (source, message) in [
+            ("C₁ 32 3 1 1 1", "`C₁` takes at most 4 values (`filters`, `kernel`, `stride`, `padding`), found 5"),
+            ("C₁ 32 dilation=2", "`C₁` has no parameter `dilation`"),
+            ("C₁ 32 padding=2", "`padding` of `C₁` must be between 0 and 1, found 2"),
+            ("C₁ 8 5 stride=65", "`stride` of `C₁` must be between 1 and 64, found 65"),
+            ("C₁ 32 filters=16", "`filters` of `C₁` is given twice"),
+            ("C₁ 32 kernel=0", "`kernel` of `C₁` must be between 1 and 64, found 0"),
+            ("F 2", "`F` takes no values, found 1"),
+        ] {
+            let mut ast = parser::parse(&format!("N\nÑ:I 28×28×1→{}", source)).unwrap();
+            let diagnostics = shape_inference::infer_shapes(&mut ast);
+            assert_eq!(diagnostics[0].code, Some(diagnostics::codes::INVALID_LAYER_PARAM), "{}", source);
+            assert_eq!(diagnostics[0].message, message);
+        }
+    }
+    
+    #[test]
+    fn test_sequence_kernels() {
+        use interpreter::Tensor;
+        fn close(a: &Tensor, b: &[f32]) -> bool {
+            a.data.len() == b.len() && a.data.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-5)
+        }
+        let t = |shape: Vec<usize>, data: Vec<f32>| Tensor::new(shape, data).unwrap();
+        
+        let table = t(vec![3, 2], vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+        let rows = interpreter::embedding(&t(vec![2], vec![2.0, 0.0]), &table).unwrap();
+        assert_eq!(rows.shape, vec![2, 2]);
+        assert!(close(&rows, &[4.0, 5.0, 0.0, 1.0]));
+        assert!(interpreter::embedding(&t(vec![1], vec![3.0]), &table).is_err());
+        
+        // Position 1 of feature pair 1 turns at 1 / 10000^(2/4)
+        let encoding = interpreter::positional_encoding(2, 4);
+        assert!(close(&encoding, &[0.0, 1.0, 0.0, 1.0, 0.841471, 0.540302, 0.00999983, 0.99995]));
+        
+        // Scores [1/√2, 0] weigh the values 0.66976 and 0.33024
+        let q = t(vec![1, 2], vec![1.0, 0.0]);
+        let k = t(vec![2, 2], vec![1.0, 0.0, 0.0, 1.0]);
+        let v = t(vec![2, 2], vec![1.0, 2.0, 3.0, 4.0]);
+        let y = interpreter::scaled_dot_product_attention(&q, &k, &v, None);
+        assert!(close(&y, &[1.660477, 2.660477]));
+        let q2 = t(vec![2, 2], vec![1.0, 0.0, 1.0, 0.0]);
+        let causal = interpreter::causal_mask(2);
+        assert_eq!(causal, vec![true, false, true, true]);
+        let y = interpreter::scaled_dot_product_attention(&q2, &k, &v, Some(&causal));
+        assert!(close(&y, &[1.0, 2.0, 1.660477, 2.660477]));
+        
+        // Identity projections and one feature per head: σ(1) on the diagonal
+        let identity = t(vec![2, 6], vec![1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0]);
+        let eye = t(vec![2, 2], vec![1.0, 0.0, 0.0, 1.0]);
+        let y = interpreter::multi_head_attention(&eye, &identity, &Tensor::zeros(vec![6]), &eye, &Tensor::zeros(vec![2]), 2, None);
+        assert!(close(&y, &[0.731059, 0.5, 0.5, 0.731059]));
+        
+        // One unit with every input weight 1: i = f = o = σ(1), g = tanh(1)
+        let (w, u, b) = (t(vec![1, 4], vec![1.0; 4]), Tensor::zeros(vec![1, 4]), Tensor::zeros(vec![4]));
+        let (h, c) = interpreter::lstm_cell(&[1.0], &[0.0], &[0.0], &w, &u, &b);
+        assert!((h[0] - 0.369606).abs() < 1e-5 && (c[0] - 0.556770).abs() < 1e-5);
+        assert!(close(&interpreter::lstm(&t(vec![2, 1], vec![1.0, 1.0]), &w, &u, &b), &[0.545346]));
+        
+        // The layers run in the interpreter; a causal first token only sees itself
+        let source = "N\nŦ:T 4→E 8 16 +P→H 2→A causal=1→L 3 τ";
+        let mut ast = parser::parse(source).unwrap();
+        assert!(shape_inference::infer_shapes(&mut ast).is_empty());
+        let interp = interpreter::Interpreter::seeded(&ast, 1).unwrap();
+        assert_eq!(interp.plan("Ŧ").unwrap().steps[2].heads, 2);
+        let trace = interp.trace("Ŧ", &t(vec![4], vec![3.0, 1.0, 4.0, 1.0])).unwrap();
+        assert_eq!(trace[4].shape, vec![3]);
+        let alone = interp.trace("Ŧ", &t(vec![4], vec![3.0, 0.0, 0.0, 0.0])).unwrap();
+        assert!(trace[3].data[..8].iter().zip(&alone[3].data[..8]).all(|(a, b)| (a - b).abs() < 1e-6));
+        assert!(interp.forward("Ŧ", &t(vec![4], vec![16.0, 0.0, 0.0, 0.0])).unwrap_err().contains("outside the vocabulary"));
+        
+        let mut ast = parser::parse("N\nŦ:T 4→E 8 16→D₀ 4 +P").unwrap();
+        let diagnostics = shape_inference::infer_shapes(&mut ast);
+        assert_eq!(diagnostics[0].code, Some(diagnostics::codes::INVALID_LAYER_PARAM));
+        
+        // Heads that do not divide the features are an error, not a single head
+        let mut ast = parser::parse("N\nŦ:T 4→E 8 16→H 3→A").unwrap();
+        let diagnostics = shape_inference::infer_shapes(&mut ast);
+        assert_eq!(diagnostics[0].code, Some(diagnostics::codes::SHAPE_HEADS_MISMATCH));
+        assert_eq!(diagnostics[0].message, "`A` cannot split 8 features between 3 heads");
+        assert!(interpreter::plan_network(&ast).unwrap_err().contains("between 3 heads"));
+    }
+    
+    #[test]
+    fn test_custom_ops() {
+        use custom_ops::{CustomOp, SnippetContext};
+        use interpreter::Tensor;
+        
+        struct Swish;
+        impl CustomOp for Swish {
+            fn symbol(&self) -> &'static str { "swish" }
+            fn output_shape(&self, input: &ast::TensorShape, _: &[(&'static str, usize)]) -> Result<ast::TensorShape, String> {
+                Ok(input.clone())
+            }
+            fn forward(&self, x: &Tensor, _: &[(&'static str, usize)], _: &[&Tensor]) -> Option<Result<Tensor, String>> {
+                let data = x.data.iter().map(|v| v / (1.0 + (-v).exp())).collect();
+                Some(Tensor::new(x.shape.clone(), data))
+            }
+            fn snippet(&self, context: &SnippetContext) -> Option<String> {
+                (context.backend == "pytorch").then(|| "x * torch.sigmoid(x)".to_string())
+            }
+        }
+        
+        // A trainable per-feature gain on vectors, multiplied by `factor`
+        struct Gain;
+        impl CustomOp for Gain {
+            fn symbol(&self) -> &'static str { "gain" }
+            fn params(&self) -> &'static [layer_params::ParamSpec] {
+                &[layer_params::ParamSpec { name: "factor", default: 1, min: 1, max: 10 }]
+            }
+            fn output_shape(&self, input: &ast::TensorShape, _: &[(&'static str, usize)]) -> Result<ast::TensorShape, String> {
+                match input.rank() {
+                    1 => Ok(input.clone()),
+                    rank => Err(format!("expects a vector, found rank {}", rank)),
+                }
+            }
+            fn parameter_shapes(&self, input: &ast::TensorShape, _: &ast::TensorShape, _: &[(&'static str, usize)]) -> Vec<(String, Vec<usize>)> {
+                vec![("scale".to_string(), input.dims.clone())]
+            }
+            fn forward(&self, x: &Tensor, params: &[(&'static str, usize)], weights: &[&Tensor]) -> Option<Result<Tensor, String>> {
+                let factor = params[0].1 as f32;
+                let data = x.data.iter().zip(&weights[0].data).map(|(v, w)| v * w * factor).collect();
+                Some(Tensor::new(x.shape.clone(), data))
+            }
+        }
+        
+        let source = "N\nΩ:Z 4→D₀ 3→⇝swish→⇝gain 2";
+        let error = parser::parse(source).unwrap_err();
+        assert_eq!(error.code, Some(diagnostics::codes::UNKNOWN_CUSTOM_OP));
+        assert_eq!(error.message, "unknown custom operation `⇝swish`");
+        
+        let mut ops = custom_ops::CustomOpRegistry::new();
+        ops.register(Swish);
+        ops.register(Gain);
+        assert_eq!(ops.symbols(), vec!["gain", "swish"]);
+        let parse = |source: &str| parser::parse_with_diagnostics(source, &ops).map(|(ast, _)| ast);
+        let unknown = parse("N\nΩ:Z 4→⇝mish").unwrap_err();
+        assert_eq!(unknown[0].notes, vec!["registered operations: `⇝gain`, `⇝swish`".to_string()]);
+        // The operations belong to the registry, not to the process
+        assert!(parser::parse(source).is_err());
+        let mut ast = parse(source).unwrap();
+        assert!(shape_inference::infer_shapes(&mut ast).is_empty());
+        let mut interp = interpreter::Interpreter::seeded(&ast, 2).unwrap();
+        assert_eq!(interp.plan("Ω").unwrap().parameter_shapes()[2], ("Ω.⇝gain.scale".to_string(), vec![3]));
+        interp.weights_mut().insert("Ω.⇝gain.scale", Tensor::new(vec![3], vec![1.0, 0.0, -1.0]).unwrap());
+        let trace = interp.trace("Ω", &Tensor::new(vec![4], vec![0.5, -1.0, 2.0, 0.25]).unwrap()).unwrap();
+        let dense = &trace[1].data;
+        let expected: Vec<f32> = dense.iter().zip([2.0, 0.0, -2.0]).map(|(v, w)| v / (1.0 + (-v).exp()) * w).collect();
+        assert!(trace[3].data.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-6));
+        assert_eq!(safetensors::parameter_key("η.Ω.⇝gain.scale").unwrap(), "Ω.⇝gain.scale");
+        
+        // Backends use the snippets and refuse operations without one
+        let ast = parse("N\nΩ:Z 4→⇝swish→D₀ 2").unwrap();
+        let code = compilers::pytorch_compiler::compile_to_pytorch(&ast, "custom").unwrap();
+        assert!(code.contains("x = x * torch.sigmoid(x)  # (N, 4)"));
+        let error = compilers::pytorch_compiler::compile_to_pytorch(&parse(source).unwrap(), "custom").unwrap_err();
+        assert_eq!(error, "`⇝gain` has no PyTorch snippet");
+        assert!(asm_compiler::compile_to_asm(&ast, asm_compiler::AsmTarget::X86_64).contains("call gaia_custom_swish"));
+        let wat = asm_compiler::compile_to_asm(&ast, asm_compiler::AsmTarget::WASM);
+        assert!(wat.contains("  (import \"env\" \"gaia_custom_swish\" (func $gaia_custom_swish (param i32) (param i32) (param i32) (param i32)))\n"));
+        
+        let mut ast = parse("N\nΩ:I 4×4×1→⇝gain factor=2").unwrap();
+        let diagnostics = shape_inference::infer_shapes(&mut ast);
+        assert_eq!(diagnostics[0].code, Some(diagnostics::codes::CUSTOM_OP_REJECTED));
+        let mut ast = parse("N\nΩ:Z 4→⇝gain 11").unwrap();
+        let diagnostics = shape_inference::infer_shapes(&mut ast);
+        assert_eq!(diagnostics[0].message, "`factor` of `⇝gain` must be between 1 and 10, found 11");
+    }
+    
+    #[test]
+    fn test_graph() {
+        let source = "N\nG:Z 4→D₀ 8 τ→U 2×2×2→U 2\nD:I 4×4×2→[C₁ 4 3 ρ]×2→F→D₀ 1 σ\nL:G(Z)⊳D⟿BCE";
+        let ast = parser::parse(source).unwrap();
+        let diagram = graph::build(&ast, None).unwrap();
+        assert_eq!(diagram.components.len(), 2);
+        assert_eq!(diagram.blocks.len(), 1);
+        
+        let dot = diagram.to_dot("gan");
+        assert!(dot.starts_with("digraph \"gan\" {"));
+        assert!(dot.contains("label=\"×2\"; style=dashed;"));
+        assert!(dot.contains("n1 [label=\"D Dense\\nunits=8 tanh\\n8\"];"));
+        assert!(dot.contains("[label=\"C₁ Conv2D\\nfilters=4 kernel=3 stride=1 padding=1 relu\\n4×4×4\"]"));
+        // The generator output is injected into the discriminator input
+        assert!(dot.contains("n3 -> n4 [label=\"⊳\", style=dashed"));
+        assert!(dot.contains("L ⟿ BCE\\nG⊳D"));
+        
+        let svg = diagram.to_svg("gan");
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.contains(">×2</text>"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        
+        let single = graph::build(&ast, Some("D")).unwrap();
+        assert_eq!(single.components.len(), 1);
+        assert!(!single.to_dot("d").contains("⊳"));
+        assert!(graph::build(&ast, Some("X")).is_err());
+    }
+    
+    #[test]
+    fn test_block_repetition() {
+        let source = "N\nÑ:I 4×4×2→[C₁ 2 3 ρ]×3≜→[C₁ 2 3 ρ]×2≠→F";
+        let mut ast = parser::parse(source).unwrap();
+        assert!(shape_inference::infer_shapes(&mut ast).is_empty());
+        assert!(ast.to_string().contains("[C₁ 2 3 ρ]×3≜"));
+        
+        // The shared block has one set of parameters, the other one per repetition
+        let interp = interpreter::Interpreter::seeded(&ast, 5).unwrap();
+        let keys: Vec<&str> = interp.plan("Ñ").unwrap().steps.iter().map(|s| s.key.as_str()).collect();
+        assert_eq!(keys, vec!["Ñ.C₁", "Ñ.C₁", "Ñ.C₁", "Ñ.C₁_1", "Ñ.C₁_2", "Ñ.F"]);
+        let summary = summary::summarize(&ast);
+        let params: Vec<usize> = summary.components[0].layers.iter().map(|l| l.params).collect();
+        assert_eq!(params, vec![38, 0, 0, 38, 38, 0]);
+        
+        let x = interpreter::Tensor::new(vec![4, 4, 2], (0..32).map(|i| i as f32 / 32.0).collect()).unwrap();
+        let trace = interp.trace("Ñ", &x).unwrap();
+        let (w, b) = (interp.weights().get("Ñ.C₁.weight").unwrap(), interp.weights().get("Ñ.C₁.bias").unwrap());
+        let mut y = x.clone();
+        for _ in 0..3 {
+            y = interpreter::activate(interpreter::conv2d(&y, w, b, layer_params::Window { size: 3, stride: 1, same: true }), &ast::ActivationFunction::ReLU);
+        }
+        assert_eq!(trace[3].data, y.data);
+        
+        // Backends loop over blocks unless asked to unroll them
+        let x86 = asm_compiler::compile_to_asm(&ast, asm_compiler::AsmTarget::X86_64);
+        assert!(x86.contains("; Block with 3 repetitions (shared parameters)"));
+        assert!(x86.contains("; Block with 2 repetitions (parameters per repetition)"));
+        
+        // The loop over the unshared block picks the weights of each repetition from a table
+        let table = x86.lines().find(|line| line.starts_with("gaia_table_")).unwrap();
+        let labels: Vec<&str> = table.split(" dq ").nth(1).unwrap().split(", ").collect();
+        assert_eq!(labels.len(), 2);
+        assert_ne!(labels[0], labels[1]);
+        
+        // The source marker prints back as written
+        assert!(ast.to_string().contains("[C₁ 2 3 ρ]×2≠"));
+        let unmarked = parser::parse("N\nÑ:I 4×4×2→[C₁ 2 3 ρ]×2→F").unwrap();
+        assert!(unmarked.to_string().contains("[C₁ 2 3 ρ]×2 → F"));
+        let mut compiler = asm_compiler::AsmCompiler::new(asm_compiler::AsmTarget::X86_64);
+        compiler.set_block_mode(ast::BlockMode::Unroll);
+        let unrolled = compiler.compile(&ast);
+        assert_eq!(unrolled.matches("call gaia_conv_relu").count(), 5);
+        let code = compilers::pytorch_compiler::compile_to_pytorch(&ast, "blocks").unwrap();
+        assert_eq!(code.matches("self.c1 = nn.Conv2d").count(), 1);
+        assert_eq!(code.matches("torch.relu(self.c1(x))").count(), 3);
+        // Kotlin creates the shared layers once and feeds each output back through them
+        let kotlin = compilers::kotlin_compiler::KotlinCompiler::new().compile(&ast).unwrap();
+        assert!(kotlin.contains("for (repetition in 2..3) {\n    repeated_"));
+        assert!(kotlin.contains(" = applyShared(shared_"));
+        
+        // Shared parameters need every repetition to see the same shape
+        let mut ast = parser::parse("N\nÑ:I 4×4×1→[C₁ 2 3 ρ]×2≜→F").unwrap();
+        let diagnostics = shape_inference::infer_shapes(&mut ast);
+        assert_eq!(diagnostics[0].code, Some(diagnostics::codes::SHAPE_SHARED_BLOCK));
+        assert_eq!(diagnostics[0].message, "block with shared parameters turns 4×4×1 into 4×4×2");
+        
+        // A single repetition has no next one to share its parameters with
+        let mut ast = parser::parse("N\nÑ:I 4×4×1→[C₁ 2 3]×1≜→F").unwrap();
+        assert!(shape_inference::infer_shapes(&mut ast).is_empty());
+        assert!(interpreter::plan_network(&ast).is_ok());
+        assert!(compilers::pytorch_compiler::compile_to_pytorch(&ast, "single").is_ok());
+        assert!(compilers::tfjs_compiler::compile_to_tfjs(&ast, None).is_ok());
+        assert!(asm_compiler::compile_to_asm(&ast, asm_compiler::AsmTarget::X86_64).contains("call gaia_conv"));
+        
+        // A block that never runs is an error rather than one repetition
+        let mut ast = parser::parse("N\nÑ:Z 4→[D₀ 4]×0").unwrap();
+        let diagnostics = shape_inference::infer_shapes(&mut ast);
+        assert_eq!(diagnostics[0].code, Some(diagnostics::codes::INVALID_LAYER_PARAM));
+        assert!(interpreter::plan_network(&ast).unwrap_err().contains("repeats 0 times"));
+        
+        // A repetition that cannot take the output of the one before
+        let mut ast = parser::parse("N\nÑ:I 8×8×1→[C₁ 2 3 ρ→P]×3→F").unwrap();
+        let diagnostics = shape_inference::infer_shapes(&mut ast);
+        assert_eq!(diagnostics[0].code, Some(diagnostics::codes::SHAPE_TOO_SMALL));
+        assert_eq!(diagnostics[0].notes, vec!["repetition 3 is fed 2×2×2 by repetition 2".to_string()]);
+    }
+    
+    #[test]
+    fn test_datasets() {
+        use dataset::*;
+        use interpreter::Tensor;
+        
+        // `.npy` in Fortran order holding the 2×3 matrix [[0, 1, 2], [3, 4, 5]]
+        let header = "{'descr': '|u1', 'fortran_order': True, 'shape': (2, 3), }";
+        let mut npy = b"\x93NUMPY\x01\x00".to_vec();
+        npy.extend((header.len() as u16).to_le_bytes());
+        npy.extend(header.bytes());
+        npy.extend([0, 3, 1, 4, 2, 5]);
+        let array = read_npy(&npy).unwrap();
+        assert_eq!(array.shape, vec![2, 3]);
+        assert_eq!(array.data, vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+        
+        // MNIST-style IDX files: two 2×2 images and their class indices
+        let images = read_idx(&[0, 0, 0x08, 3, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 2, 0, 255, 0, 255, 255, 0, 255, 0]).unwrap();
+        let labels = read_idx(&[0, 0, 0x08, 1, 0, 0, 0, 2, 1, 0]).unwrap();
+        assert_eq!(images.shape, vec![2, 2, 2]);
+        
+        // Images without a channel axis feed `I 2×2`, and `⍓ 255` scales them
+        let mut ast = parser::parse("N\nÑ:I 2×2 ⍓ 255→F→D₁ 2 S").unwrap();
+        assert!(shape_inference::infer_shapes(&mut ast).is_empty());
+        let trainer = training::Trainer::for_component(&ast, "Ñ", None).unwrap();
+        assert_eq!(trainer.preprocess(), Some(ast::Preprocess::Scale(255)));
+        let mut data = Dataset::from_arrays(&images, &labels, trainer.input_shape(), trainer.output_shape()).unwrap();
+        assert_eq!(data.examples[0].1.data, vec![0.0, 1.0]);
+        data.normalize(ast::Preprocess::Scale(255));
+        assert_eq!(data.examples[1].0.data, vec![1.0, 0.0, 1.0, 0.0]);
+        let declared = ast::TensorShape::new(vec![3, 3, 1]);
+        assert_eq!(Dataset::from_arrays(&images, &labels, &declared, trainer.output_shape()).unwrap_err(),
+                   "examples are 2×2 but the input is declared 3×3×1");
+        
+        // `⍓` alone standardizes every channel
+        let normalization = data.normalize(ast::Preprocess::Standardize);
+        assert_eq!(normalization.mean, vec![0.5]);
+        assert_eq!(data.examples[0].0.data, vec![-1.0, 1.0, -1.0, 1.0]);
+        
+        // Shuffling is reproducible from the seed
+        let examples = (0..6).map(|i| (Tensor::new(vec![1], vec![i as f32]).unwrap(), Tensor::zeros(vec![1]))).collect();
+        let (mut a, mut b) = (Dataset { examples }, Dataset::default());
+        b.examples = a.examples.clone();
+        a.shuffle(3);
+        b.shuffle(3);
+        assert_eq!(a.examples, b.examples);
+        let sizes: Vec<usize> = a.batches(4).map(|batch| batch.len()).collect();
+        assert_eq!(sizes, vec![4, 2]);
+        
+        // PPM in text and binary form
+        let text = read_ppm(b"P3\n# two pixels\n2 1\n255\n1 2 3 4 5 6\n").unwrap();
+        let binary = read_ppm(b"P6 2 1 255\n\x01\x02\x03\x04\x05\x06").unwrap();
+        assert_eq!((text.shape.clone(), text.data.clone()), (vec![1, 2, 3], vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+        assert_eq!(binary.data, text.data);
+        
+        // PNG: a 2×1 RGB row with the `sub` filter in a stored zlib block
+        let chunk = |kind: &[u8], data: &[u8]| {
+            let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+            chunk.extend(kind);
+            chunk.extend(data);
+            chunk.extend([0; 4]);
+            chunk
+        };
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend(chunk(b"IHDR", &[0, 0, 0, 2, 0, 0, 0, 1, 8, 2, 0, 0, 0]));
+        png.extend(chunk(b"IDAT", &[0x78, 0x01, 1, 7, 0, 0xF8, 0xFF, 1, 10, 20, 30, 5, 5, 5, 0, 0, 0, 0]));
+        png.extend(chunk(b"IEND", &[]));
+        let image = read_png(&png).unwrap();
+        assert_eq!(image.shape, vec![1, 2, 3]);
+        assert_eq!(image.data, vec![10.0, 20.0, 30.0, 15.0, 25.0, 35.0]);
+        
+        // Fixed Huffman codes with a back reference
+        assert_eq!(inflate(&[75, 79, 204, 76, 84, 72, 71, 33, 20, 1]).unwrap(), b"gaia gaia gaia gaia!");
+    }
+    
+    #[test]
+    fn test_tokenizer() {
+        use tokenizer::*;
+        use std::path::Path;
+        
+        // `⌸ char` without a vocabulary feeds `E` the 257 byte ids
+        let mut ast = parser::parse("N\nÑ:T 4 ⌸ char→E 8→F→D₀ 2 S").unwrap();
+        assert!(attach(&mut ast, Path::new("")).is_empty());
+        assert!(shape_inference::infer_shapes(&mut ast).is_empty());
+        let inputs = text_inputs(&ast);
+        assert_eq!(inputs[0].tokenizer.vocab_size(), BYTE_VOCABULARY);
+        assert_eq!(inputs[0].tokenizer.encode("hé", inputs[0].length), vec![105, 196, 170, 0]);
+        let plans = interpreter::plan_network(&ast).unwrap();
+        assert_eq!(plans[0].parameter_shapes()[0].1, vec![257, 8]);
+        
+        // An embedding set explicitly smaller than the vocabulary is an error
+        let mut ast = parser::parse("N\nÑ:T 4 ⌸ char→E 8 100→F→D₀ 2 S").unwrap();
+        let diagnostics = attach(&mut ast, Path::new(""));
+        assert_eq!(diagnostics[0].code, Some(diagnostics::codes::TOKENIZER_VOCAB_MISMATCH));
+        let mut ast = parser::parse("N\nÑ:T 4 ⌸ wordpiece \"missing.txt\"→E 8→F→D₀ 2 S").unwrap();
+        assert_eq!(attach(&mut ast, Path::new(""))[0].code, Some(diagnostics::codes::TOKENIZER_ERROR));
+        
+        // WordPiece lowercases for an uncased vocabulary and wraps in [CLS] … [SEP]
+        let vocab = ["[PAD]", "[UNK]", "[CLS]", "[SEP]", "the", "cat", "##s", ","];
+        let wordpiece = Tokenizer::wordpiece(vocab.iter().map(|t| t.to_string()).collect());
+        assert_eq!(wordpiece.encode("The cats, dog", 8), vec![2, 4, 5, 6, 7, 1, 3, 0]);
+        assert_eq!(wordpiece.encode("the cat the cat", 4), vec![2, 4, 5, 3]);
+        
+        // Byte-level BPE applies merges by rank after GPT-2 pre-tokenization
+        assert_eq!(pretokenize("it's  a test!!"), vec!["it", "'s", " ", " a", " test", "!!"]);
+        let chars = byte_chars();
+        let mut vocab: Vec<String> = chars.iter().map(|c| c.to_string()).collect();
+        vocab.extend(["Ġt", "es", "Ġtes", "Ġtest"].map(String::from));
+        let merges = [("Ġ", "t"), ("e", "s"), ("Ġt", "es"), ("Ġtes", "t")];
+        let bpe = Tokenizer::bpe(vocab, merges.iter().map(|(a, b)| (a.to_string(), b.to_string())).collect());
+        assert_eq!(bpe.tokens("a test tes"), vec![97, 259, 258]);
+        
+        // Generated JavaScript carries the same settings
+        let js = tokenizer_js(&inputs, "app");
+        assert!(js.contains(r#""Ñ": {"end":null,"kind":"char","length":4"#));
+        assert!(tokenizer_kotlin(&inputs).contains(r#""Ñ" to Spec("char", 4, null, null, false, 0, null, null, null)"#));
+    }
+    
+    #[test]
+    fn test_diff() {
+        use diff::{diff, Change, Status};
+        
+        let analyze = |source: &str| {
+            let mut ast = parser::parse(source).unwrap();
+            assert!(shape_inference::infer_shapes(&mut ast).is_empty());
+            ast
+        };
+        let before = analyze("N〈Ω⊕Ñ〉\nΩ:∮ 600×400→⊞ 2×2→⌑\"hi\"\nÑ:I 8×8×1→C₁ 4 3 ρ→P 2→F→D₀ 2 S\nC:Z 2→D₀ 2\nL:Ñ⊳C⟿MSE");
+        let after = analyze("N〈Ω⊕Ñ〉\nΩ:∮ 600×400→⊞ 2×2→⌘\"Go\"→⌑\"hello\"\nÑ:I 8×8×1→C₁ 8 3 σ→F→D₀ 2 S\nC:Z 2→D₀ 2\nL:Ñ⊳C⊘⟿MSE");
+        let changes = diff(&before, &after);
+        
+        // Interface elements are compared as a tree of their own
+        let ui = &changes.components[0];
+        assert_eq!((ui.id.as_str(), ui.status), ("Ω", Status::Changed));
+        assert_eq!(ui.changes[0], Change::Added { at: "3".to_string(), node: "⌘\"Go\"".to_string(), kind: "Button" });
+        assert!(matches!(&ui.changes[1], Change::Parameter { name, to, .. } if name == "text" && to == "\"hello\""));
+        
+        // Layers align around the removed pooling; the flatten output follows
+        let network = &changes.components[1];
+        assert_eq!(network.changes, vec![
+            Change::Parameter { at: "2".to_string(), node: "C₁".to_string(), name: "filters".to_string(), from: "4".to_string(), to: "8".to_string() },
+            Change::Activation { at: "2".to_string(), node: "C₁".to_string(), from: "ρ".to_string(), to: "σ".to_string() },
+            Change::Removed { at: "3".to_string(), node: "P 2".to_string(), kind: "MaxPooling2D" },
+            Change::Shape { at: "3".to_string(), node: "F".to_string(), from: "64".to_string(), to: "512".to_string() },
+        ]);
+        assert_eq!(network.parameters, Some((40 + 130, 80 + 1026)));
+        assert_eq!(changes.components.len(), 2);
+        assert_eq!(changes.losses[0].to.as_deref(), Some("L: Ñ⊳C⊘⟿MSE"));
+        
+        let text = changes.to_text();
+        assert!(text.contains("    ~ 2 C₁: filters 4 → 8, activation ρ → σ\n"));
+        assert_eq!(changes.to_json()["components"][1]["changes"][2]["change"], "removed");
+        assert!(diff(&after, &after).is_empty());
+    }
+    
+    // The network every native backend is checked against, its weights, and
+    // one example to run through the generated code
+    struct NativeCase {
+        ast: ast::ASTNode,
+        trainer: training::Trainer,
+        input: Vec<f32>,
+        target: Vec<f32>,
+    }
+    
+    impl NativeCase {
+        fn new() -> Self {
+            let source = "N\nG:Z 4→D₁ 32 ρ→R 4×4×2→[C₁ 2 3 ρ]×2≠→P→F→D₀ 3→S\nD:I 3→D₁ 2 σ\nL:G(Z)⊳D⟿BCE";
+            let mut ast = parser::parse(source).unwrap();
+            assert!(shape_inference::infer_shapes(&mut ast).is_empty());
+            let mut trainer = training::Trainer::for_loss(&ast, None).unwrap();
+            trainer.seed_weights(3);
+            NativeCase { ast, trainer, input: vec![0.5, -1.0, 0.25, 2.0], target: vec![1.0, 0.0] }
+        }
+        
+        fn compile(&self, target: asm_compiler::AsmTarget) -> String {
+            asm_compiler::compile_to_asm_with_weights(&self.ast, target, self.trainer.weights())
+        }
+        
+        // C program printing the three outputs of `G` on the input, then the loss of `L`
+        fn c_driver(&self) -> String {
+            let floats = |values: &[f32]| values.iter().map(|v| format!("{:?}f", v)).collect::<Vec<_>>().join(", ");
+            format!(r#"#include <stdio.h>
+void component_G(const float *in, float *out);
+float loss_L(const float *in, const float *target);
+int main(void) {{
+    float in[] = {{{}}}, target[] = {{{}}}, out[3];
+    component_G(in, out);
+    for (int i = 0; i < 3; i++) printf("%.9g\n", out[i]);
+    printf("%.9g\n", loss_L(in, target));
+    return 0;
+}}
+"#, floats(&self.input), floats(&self.target))
+        }
+        
+        // Compare what a native build printed with the interpreter and the trainer
+        fn check(&self, printed: &str) {
+            let printed: Vec<f32> = printed.lines().map(|l| l.parse().unwrap()).collect();
+            let interp = interpreter::Interpreter::new(&self.ast, self.trainer.weights().clone()).unwrap();
+            let x = interpreter::Tensor::new(vec![4], self.input.clone()).unwrap();
+            let expected = interp.forward("G", &x).unwrap();
+            assert!(printed[..3].iter().zip(&expected.data).all(|(a, b)| (a - b).abs() < 1e-5), "{:?} vs {:?}", printed, expected.data);
+            let examples = vec![(x, interpreter::Tensor::new(vec![2], self.target.clone()).unwrap())];
+            let loss = self.trainer.evaluate(&dataset::Dataset { examples }).unwrap();
+            assert!((printed[3] - loss).abs() < 1e-5, "loss {} vs {}", printed[3], loss);
+        }
+    }
+    
+    // Scratch directory for the files of a toolchain run
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("gaia_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    // Run a tool in `dir`, failing when it is missing or reports an error
+    fn run_tool(dir: &std::path::Path, program: &str, args: &[&str]) -> String {
+        let output = std::process::Command::new(program).args(args).current_dir(dir).output()
+            .unwrap_or_else(|e| panic!("cannot run {}: {}", program, e));
+        assert!(output.status.success(), "{} failed: {}", program, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    }
+    
+    #[test]
+    fn test_x86_64_native_code() {
+        // One System V function per component and composite, calling declared kernels
+        let asm = NativeCase::new().compile(asm_compiler::AsmTarget::X86_64);
+        assert!(asm.contains("extern gaia_conv_relu\n"));
+        assert!(asm.contains("global component_G\n"));
+        assert!(asm.contains("global loss_L\n"));
+        assert!(asm.contains("    call gaia_dense_softmax\n"));
+        // The two repetitions of the block read their own kernel through a table
+        assert!(asm.contains("gaia_table_L"));
+        assert!(!asm.contains(" r0,"));
+    }
+    
+    #[test]
+    #[ignore = "needs nasm and cc"]
+    fn test_x86_64_native_run() {
+        let case = NativeCase::new();
+        let dir = scratch_dir("x86_64");
+        std::fs::write(dir.join("app.s"), case.compile(asm_compiler::AsmTarget::X86_64)).unwrap();
+        std::fs::write(dir.join("runtime.s"), include_str!("asm_runtime_x86_64.s")).unwrap();
+        std::fs::write(dir.join("main.c"), case.c_driver()).unwrap();
+        run_tool(&dir, "nasm", &["-f", "elf64", "app.s", "-o", "app.o"]);
+        run_tool(&dir, "nasm", &["-f", "elf64", "runtime.s", "-o", "runtime.o"]);
+        run_tool(&dir, "cc", &["main.c", "app.o", "runtime.o", "-lm", "-o", "network"]);
+        let printed = run_tool(&dir, dir.join("network").to_str().unwrap(), &[]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        case.check(&printed);
+    }
+    
+    #[test]
+    fn test_arm64_native_code() {
+        // The same functions as on x86-64, following AAPCS64
+        let asm = NativeCase::new().compile(asm_compiler::AsmTarget::ARM64);
+        assert!(asm.contains(".global component_G\n"));
+        assert!(asm.contains(".global loss_L\n"));
+        assert!(asm.contains("    bl gaia_conv_relu\n"));
+        assert!(asm.contains("    ldr x2, [x9, x19, lsl #3]\n"));
+        assert!(!asm.contains("_start"));
+        let runtime = include_str!("asm_runtime_arm64.s");
+        for kernel in ["dense", "conv", "transpose_conv", "pooling", "upsampling", "embedding", "flatten"] {
+            for activation in ["none", "relu", "sigmoid", "tanh", "softmax"] {
+                assert!(runtime.contains(&format!(".global gaia_{}_{}\n", kernel, activation)));
+            }
+        }
+    }
+    
+    #[test]
+    #[ignore = "needs aarch64-linux-gnu-gcc and qemu-aarch64"]
+    fn test_arm64_native_run() {
+        let case = NativeCase::new();
+        let dir = scratch_dir("arm64");
+        std::fs::write(dir.join("app.s"), case.compile(asm_compiler::AsmTarget::ARM64)).unwrap();
+        std::fs::write(dir.join("runtime.s"), include_str!("asm_runtime_arm64.s")).unwrap();
+        std::fs::write(dir.join("main.c"), case.c_driver()).unwrap();
+        run_tool(&dir, "aarch64-linux-gnu-gcc", &["-static", "main.c", "app.s", "runtime.s", "-lm", "-o", "network"]);
+        let printed = run_tool(&dir, "qemu-aarch64", &["./network"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        case.check(&printed);
+    }
+    
+    #[test]
+    fn test_wasm_native_code() {
+        // A self-contained module: the runtime kernels are spliced in, nothing is imported
+        let wat = NativeCase::new().compile(asm_compiler::AsmTarget::WASM);
+        assert!(wat.contains("  (func $component_G (export \"component_G\") (param $in i32) (param $out i32)\n"));
+        assert!(wat.contains("    (call $gaia_conv_relu (local.get $x) (local.get $y) (i32.load (i32.add (global.get $gaia_table_L"));
+        assert!(wat.contains("  (func $gaia_dense_softmax "));
+        assert!(wat.contains("(export \"forward\" (func $composite_L))"));
+        assert!(!wat.contains("(import") && !wat.contains(";; (func"));
+        
+        // It assembles and validates
+        let wasm = wat::parse_str(&wat).unwrap();
+        wasmparser::Validator::new().validate_all(&wasm).unwrap();
+    }
+    
+    #[test]
+    #[ignore = "needs node"]
+    fn test_wasm_native_run() {
+        let case = NativeCase::new();
+        let dir = scratch_dir("wasm");
+        std::fs::write(dir.join("app.wasm"), wat::parse_str(case.compile(asm_compiler::AsmTarget::WASM)).unwrap()).unwrap();
+        let floats = |values: &[f32]| values.iter().map(|v| format!("{:?}", v)).collect::<Vec<_>>().join(", ");
+        let driver = format!(r#"const bytes = require("fs").readFileSync("app.wasm");
+const gaia = new WebAssembly.Instance(new WebAssembly.Module(bytes)).exports;
+const memory = new Float32Array(gaia.memory.buffer);
+memory.set([{}], 0);
+memory.set([{}], 4);
+gaia.component_G(0, 32);
+for (const value of memory.subarray(8, 11)) console.log(value);
+console.log(gaia.loss_L(0, 16));
+"#, floats(&case.input), floats(&case.target));
+        std::fs::write(dir.join("main.js"), driver).unwrap();
+        let printed = run_tool(&dir, "node", &["main.js"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        case.check(&printed);
     }
     
     #[test]
@@ -78,6 +1402,6 @@ mod tests {
         // This just tests that the function runs, actual platform will vary
         let platform = platform_detector::detect_platform();
         println!("Detected platform: {:?}", platform);
-        assert!(platform_detector::platform_name(platform) != "", "Platform name should not be empty");
+        assert!(!platform_detector::platform_name(platform).is_empty(), "Platform name should not be empty");
     }
 }
\ No newline at end of file
@@ -0,0 +1,734 @@
+use crate::ast::*;
+use crate::composite;
+use crate::interpreter::{plan_network, Plan, Step, Tensor, Weights};
+use crate::layer_params::{FACTOR, KERNEL, STRIDE, target_shape, Window};
+use crate::protobuf::{floats_from_le, Decoder, Encoder};
+use std::collections::HashSet;
+
+// Versions written into exported models
+pub const IR_VERSION: i64 = 8;
+pub const OPSET_VERSION: i64 = 13;
+
+// `TensorProto.DataType` values
+pub const FLOAT: i32 = 1;
+pub const INT32: i32 = 6;
+pub const INT64: i32 = 7;
+
+// `AttributeProto.AttributeType` values
+const ATTRIBUTE_FLOAT: i64 = 1;
+const ATTRIBUTE_INT: i64 = 2;
+const ATTRIBUTE_STRING: i64 = 3;
+const ATTRIBUTE_TENSOR: i64 = 4;
+const ATTRIBUTE_FLOATS: i64 = 6;
+const ATTRIBUTE_INTS: i64 = 7;
+
+/// Constant tensor: a graph initializer or a tensor attribute
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Initializer {
+    pub name: String,
+    pub dims: Vec<i64>,
+    pub data_type: i32,
+    // FLOAT data
+    pub floats: Vec<f32>,
+    // INT32 and INT64 data
+    pub ints: Vec<i64>,
+}
+
+impl Initializer {
+    pub fn floats(name: &str, dims: Vec<i64>, floats: Vec<f32>) -> Self {
+        Initializer { name: name.to_string(), dims, data_type: FLOAT, floats, ints: Vec::new() }
+    }
+    
+    pub fn ints(name: &str, dims: Vec<i64>, ints: Vec<i64>) -> Self {
+        Initializer { name: name.to_string(), dims, data_type: INT64, floats: Vec::new(), ints }
+    }
+    
+    /// Float data as a tensor with the same dimensions
+    pub fn to_tensor(&self) -> Result<Tensor, String> {
+        if self.data_type != FLOAT {
+            return Err(format!("initializer `{}` does not hold floats", self.name));
+        }
+        Tensor::new(self.dims.iter().map(|&d| d as usize).collect(), self.floats.clone())
+    }
+    
+    fn encode(&self) -> Encoder {
+        let mut e = Encoder::new();
+        e.int64s(1, &self.dims);
+        e.int64(2, self.data_type as i64);
+        match self.data_type {
+            FLOAT => {
+                let raw: Vec<u8> = self.floats.iter().flat_map(|v| v.to_le_bytes()).collect();
+                e.bytes(9, &raw);
+            },
+            _ => e.packed_int64s(7, &self.ints),
+        }
+        e.string(8, &self.name);
+        e
+    }
+    
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut tensor = Initializer::default();
+        let mut raw = None;
+        for field in Decoder::new(bytes) {
+            let (number, value) = field?;
+            match number {
+                1 => value.push_i64s(&mut tensor.dims)?,
+                2 => tensor.data_type = value.as_i64()? as i32,
+                4 => value.push_f32s(&mut tensor.floats)?,
+                5 | 7 => value.push_i64s(&mut tensor.ints)?,
+                8 => tensor.name = value.as_string()?,
+                9 => raw = Some(value.as_bytes()?),
+                _ => {}
+            }
+        }
+        if let Some(raw) = raw {
+            match tensor.data_type {
+                FLOAT => tensor.floats = floats_from_le(raw)?,
+                INT64 => tensor.ints = raw.chunks(8)
+                    .map(|c| c.try_into().map(i64::from_le_bytes).map_err(|_| "truncated int64 data".to_string()))
+                    .collect::<Result<_, _>>()?,
+                INT32 => tensor.ints = raw.chunks(4)
+                    .map(|c| c.try_into().map(|b| i32::from_le_bytes(b) as i64).map_err(|_| "truncated int32 data".to_string()))
+                    .collect::<Result<_, _>>()?,
+                other => return Err(format!("initializer `{}` has unsupported data type {}", tensor.name, other)),
+            }
+        }
+        Ok(tensor)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    Float(f32),
+    Int(i64),
+    String(String),
+    Tensor(Initializer),
+    Floats(Vec<f32>),
+    Ints(Vec<i64>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub name: String,
+    pub value: AttributeValue,
+}
+
+impl Attribute {
+    pub fn int(name: &str, value: i64) -> Self {
+        Attribute { name: name.to_string(), value: AttributeValue::Int(value) }
+    }
+    
+    pub fn ints(name: &str, values: Vec<i64>) -> Self {
+        Attribute { name: name.to_string(), value: AttributeValue::Ints(values) }
+    }
+    
+    pub fn string(name: &str, value: &str) -> Self {
+        Attribute { name: name.to_string(), value: AttributeValue::String(value.to_string()) }
+    }
+    
+    fn encode(&self) -> Encoder {
+        let mut e = Encoder::new();
+        e.string(1, &self.name);
+        let kind = match &self.value {
+            AttributeValue::Float(v) => { e.float(2, *v); ATTRIBUTE_FLOAT },
+            AttributeValue::Int(v) => { e.int64(3, *v); ATTRIBUTE_INT },
+            AttributeValue::String(v) => { e.string(4, v); ATTRIBUTE_STRING },
+            AttributeValue::Tensor(t) => { e.message(5, t.encode()); ATTRIBUTE_TENSOR },
+            AttributeValue::Floats(v) => { e.floats(7, v); ATTRIBUTE_FLOATS },
+            AttributeValue::Ints(v) => { e.int64s(8, v); ATTRIBUTE_INTS },
+        };
+        e.int64(20, kind);
+        e
+    }
+    
+    // Attributes of kinds the compiler has no use for (graphs, strings) decode to None
+    fn decode(bytes: &[u8]) -> Result<Option<Self>, String> {
+        let (mut name, mut kind) = (String::new(), 0);
+        let (mut f, mut i, mut s, mut t) = (0.0, 0, String::new(), None);
+        let (mut floats, mut ints) = (Vec::new(), Vec::new());
+        for field in Decoder::new(bytes) {
+            let (number, value) = field?;
+            match number {
+                1 => name = value.as_string()?,
+                2 => f = value.as_f32()?,
+                3 => i = value.as_i64()?,
+                4 => s = String::from_utf8_lossy(value.as_bytes()?).into_owned(),
+                5 => t = Some(Initializer::decode(value.as_bytes()?)?),
+                7 => value.push_f32s(&mut floats)?,
+                8 => value.push_i64s(&mut ints)?,
+                20 => kind = value.as_i64()?,
+                _ => {}
+            }
+        }
+        let value = match kind {
+            ATTRIBUTE_FLOAT => AttributeValue::Float(f),
+            ATTRIBUTE_INT => AttributeValue::Int(i),
+            ATTRIBUTE_STRING => AttributeValue::String(s),
+            ATTRIBUTE_TENSOR => match t {
+                Some(t) => AttributeValue::Tensor(t),
+                None => return Ok(None),
+            },
+            ATTRIBUTE_FLOATS => AttributeValue::Floats(floats),
+            ATTRIBUTE_INTS => AttributeValue::Ints(ints),
+            _ => return Ok(None),
+        };
+        Ok(Some(Attribute { name, value }))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Node {
+    pub name: String,
+    pub op_type: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub attributes: Vec<Attribute>,
+}
+
+impl Node {
+    pub fn attribute(&self, name: &str) -> Option<&AttributeValue> {
+        self.attributes.iter().find(|a| a.name == name).map(|a| &a.value)
+    }
+    
+    pub fn int(&self, name: &str) -> Option<i64> {
+        match self.attribute(name) {
+            Some(AttributeValue::Int(v)) => Some(*v),
+            _ => None,
+        }
+    }
+    
+    pub fn ints(&self, name: &str) -> Option<&[i64]> {
+        match self.attribute(name) {
+            Some(AttributeValue::Ints(v)) => Some(v),
+            _ => None,
+        }
+    }
+    
+    fn encode(&self) -> Encoder {
+        let mut e = Encoder::new();
+        for input in &self.inputs {
+            e.string(1, input);
+        }
+        for output in &self.outputs {
+            e.string(2, output);
+        }
+        e.string(3, &self.name);
+        e.string(4, &self.op_type);
+        for attribute in &self.attributes {
+            e.message(5, attribute.encode());
+        }
+        e
+    }
+    
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut node = Node::default();
+        for field in Decoder::new(bytes) {
+            let (number, value) = field?;
+            match number {
+                1 => node.inputs.push(value.as_string()?),
+                2 => node.outputs.push(value.as_string()?),
+                3 => node.name = value.as_string()?,
+                4 => node.op_type = value.as_string()?,
+                5 => node.attributes.extend(Attribute::decode(value.as_bytes()?)?),
+                _ => {}
+            }
+        }
+        Ok(node)
+    }
+}
+
+/// One dimension of a value: fixed, or symbolic like the batch size `N`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Dim {
+    Value(i64),
+    Param(String),
+}
+
+/// Name, element type and shape of a graph input, output or intermediate value
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValueInfo {
+    pub name: String,
+    pub elem_type: i32,
+    pub dims: Vec<Dim>,
+}
+
+impl ValueInfo {
+    /// Float tensor with a leading batch dimension `N`
+    pub fn batched(name: &str, dims: &[i64]) -> Self {
+        let mut all = vec![Dim::Param("N".to_string())];
+        all.extend(dims.iter().map(|&d| Dim::Value(d)));
+        ValueInfo { name: name.to_string(), elem_type: FLOAT, dims: all }
+    }
+    
+    fn encode(&self) -> Encoder {
+        let mut shape = Encoder::new();
+        for dim in &self.dims {
+            let mut d = Encoder::new();
+            match dim {
+                Dim::Value(v) => d.int64(1, *v),
+                Dim::Param(p) => d.string(2, p),
+            }
+            shape.message(1, d);
+        }
+        let mut tensor = Encoder::new();
+        tensor.int64(1, self.elem_type as i64);
+        tensor.message(2, shape);
+        let mut kind = Encoder::new();
+        kind.message(1, tensor);
+        
+        let mut e = Encoder::new();
+        e.string(1, &self.name);
+        e.message(2, kind);
+        e
+    }
+    
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut info = ValueInfo::default();
+        // Walk ValueInfoProto → TypeProto → TypeProto.Tensor → TensorShapeProto
+        for field in Decoder::new(bytes) {
+            let (number, value) = field?;
+            match number {
+                1 => info.name = value.as_string()?,
+                2 => {
+                    for tensor in nested(value.as_bytes()?, 1)? {
+                        for field in Decoder::new(tensor) {
+                            let (number, value) = field?;
+                            match number {
+                                1 => info.elem_type = value.as_i64()? as i32,
+                                2 => {
+                                    for dim in nested(value.as_bytes()?, 1)? {
+                                        let mut parsed = Dim::Param(String::new());
+                                        for field in Decoder::new(dim) {
+                                            match field? {
+                                                (1, v) => parsed = Dim::Value(v.as_i64()?),
+                                                (2, v) => parsed = Dim::Param(v.as_string()?),
+                                                _ => {}
+                                            }
+                                        }
+                                        info.dims.push(parsed);
+                                    }
+                                },
+                                _ => {}
+                            }
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+        Ok(info)
+    }
+}
+
+// Embedded messages stored in field `wanted`
+fn nested(bytes: &[u8], wanted: u32) -> Result<Vec<&[u8]>, String> {
+    Decoder::new(bytes)
+        .filter(|f| f.as_ref().map_or(true, |(n, _)| *n == wanted))
+        .map(|f| f.and_then(|(_, v)| v.as_bytes()))
+        .collect()
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Graph {
+    pub name: String,
+    pub nodes: Vec<Node>,
+    pub initializers: Vec<Initializer>,
+    pub inputs: Vec<ValueInfo>,
+    pub outputs: Vec<ValueInfo>,
+    pub value_info: Vec<ValueInfo>,
+}
+
+impl Graph {
+    pub fn initializer(&self, name: &str) -> Option<&Initializer> {
+        self.initializers.iter().find(|i| i.name == name)
+    }
+    
+    fn encode(&self) -> Encoder {
+        let mut e = Encoder::new();
+        for node in &self.nodes {
+            e.message(1, node.encode());
+        }
+        e.string(2, &self.name);
+        for initializer in &self.initializers {
+            e.message(5, initializer.encode());
+        }
+        for input in &self.inputs {
+            e.message(11, input.encode());
+        }
+        for output in &self.outputs {
+            e.message(12, output.encode());
+        }
+        for info in &self.value_info {
+            e.message(13, info.encode());
+        }
+        e
+    }
+    
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut graph = Graph::default();
+        for field in Decoder::new(bytes) {
+            let (number, value) = field?;
+            match number {
+                1 => graph.nodes.push(Node::decode(value.as_bytes()?)?),
+                2 => graph.name = value.as_string()?,
+                5 => graph.initializers.push(Initializer::decode(value.as_bytes()?)?),
+                11 => graph.inputs.push(ValueInfo::decode(value.as_bytes()?)?),
+                12 => graph.outputs.push(ValueInfo::decode(value.as_bytes()?)?),
+                13 => graph.value_info.push(ValueInfo::decode(value.as_bytes()?)?),
+                _ => {}
+            }
+        }
+        Ok(graph)
+    }
+}
+
+/// An ONNX model: a graph plus the versions needed to run it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Model {
+    pub ir_version: i64,
+    pub opset_version: i64,
+    pub producer_name: String,
+    pub producer_version: String,
+    pub graph: Graph,
+}
+
+impl Model {
+    /// Serialize as an `.onnx` file
+    pub fn encode(&self) -> Vec<u8> {
+        let mut e = Encoder::new();
+        e.int64(1, self.ir_version);
+        e.string(2, &self.producer_name);
+        e.string(3, &self.producer_version);
+        e.message(7, self.graph.encode());
+        let mut opset = Encoder::new();
+        opset.string(1, "");
+        opset.int64(2, self.opset_version);
+        e.message(8, opset);
+        e.finish()
+    }
+    
+    /// Parse an `.onnx` file; fields the compiler does not use are skipped
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut model = Model::default();
+        for field in Decoder::new(bytes) {
+            let (number, value) = field?;
+            match number {
+                1 => model.ir_version = value.as_i64()?,
+                2 => model.producer_name = value.as_string()?,
+                3 => model.producer_version = value.as_string()?,
+                7 => model.graph = Graph::decode(value.as_bytes()?)?,
+                8 => {
+                    let (mut domain, mut version) = (String::new(), 0);
+                    for field in Decoder::new(value.as_bytes()?) {
+                        match field? {
+                            (1, v) => domain = v.as_string()?,
+                            (2, v) => version = v.as_i64()?,
+                            _ => {}
+                        }
+                    }
+                    if domain.is_empty() || domain == "ai.onnx" {
+                        model.opset_version = version;
+                    }
+                },
+                _ => {}
+            }
+        }
+        Ok(model)
+    }
+}
+
+/// Export every component with layers, then every composite, each as its own
+/// model; parameters missing from `weights` are seeded
+pub fn export_network(ast: &ASTNode, weights: Option<&Weights>) -> Result<Vec<(String, Model)>, String> {
+    let mut units: Vec<(String, Vec<Plan>)> = plan_network(ast)?.into_iter()
+        .map(|plan| (plan.component.clone(), vec![plan]))
+        .collect();
+    for composite in composite::composites(ast)? {
+        units.push((composite.name.clone(), composite.plans()));
+    }
+    
+    let all: Vec<Plan> = units.iter().flat_map(|(_, plans)| plans.iter().cloned()).collect();
+    let merged = Weights::for_export(&all, weights);
+    units.iter()
+        .map(|(name, plans)| Ok((name.clone(), export(name, plans, &merged)?)))
+        .collect()
+}
+
+/// Export a chain of plans (a component, or the stages of a composite) as one model
+///
+/// Tensors carry a leading batch dimension `N`. Images use the ONNX NCHW layout,
+/// so convolution weights are transposed from `[k, k, in, out]` and flattening
+/// goes through NHWC to keep the element order of the interpreter.
+pub fn export(name: &str, plans: &[Plan], weights: &Weights) -> Result<Model, String> {
+    let first = plans.first().ok_or("nothing to export")?;
+    let mut builder = GraphBuilder {
+        graph: Graph { name: name.to_string(), ..Graph::default() },
+        weights,
+        exported: HashSet::new(),
+        counter: 0,
+    };
+    builder.graph.inputs.push(ValueInfo::batched("input", &onnx_dims(&first.input)));
+    
+    let mut x = "input".to_string();
+    let steps: Vec<&Step> = plans.iter().flat_map(|p| &p.steps).collect();
+    for (i, step) in steps.iter().enumerate() {
+        x = builder.step(step, x)?;
+        if i + 1 < steps.len() {
+            builder.graph.value_info.push(ValueInfo::batched(&x, &onnx_dims(&step.output)));
+        }
+    }
+    let output = plans.last().unwrap().output();
+    builder.graph.outputs.push(ValueInfo::batched(&x, &onnx_dims(output)));
+    
+    Ok(Model {
+        ir_version: IR_VERSION,
+        opset_version: OPSET_VERSION,
+        producer_name: "gaiascript".to_string(),
+        producer_version: env!("CARGO_PKG_VERSION").to_string(),
+        graph: builder.graph,
+    })
+}
+
+// `pads` attribute of a window over a height×width×channels input:
+// the beginnings of both axes, then their ends
+pub(crate) fn pads(window: &Window, input: &TensorShape) -> Vec<i64> {
+    let ((top, bottom), (left, right)) = (window.padding(input.dims[0]), window.padding(input.dims[1]));
+    [top, left, bottom, right].iter().map(|&p| p as i64).collect()
+}
+
+// ONNX dimensions of a per-example shape: height×width×channels becomes CHW
+fn onnx_dims(shape: &TensorShape) -> Vec<i64> {
+    let dims: Vec<i64> = shape.dims.iter().map(|&d| d as i64).collect();
+    match dims.as_slice() {
+        [h, w, c] => vec![*c, *h, *w],
+        _ => dims,
+    }
+}
+
+struct GraphBuilder<'a> {
+    graph: Graph,
+    weights: &'a Weights,
+    // Parameters already added as initializers, so shared ones appear once
+    exported: HashSet<String>,
+    counter: usize,
+}
+
+impl<'a> GraphBuilder<'a> {
+    fn fresh(&mut self, base: &str) -> String {
+        self.counter += 1;
+        format!("{}_{}", base, self.counter)
+    }
+    
+    fn node_outputs(&mut self, op: &str, inputs: Vec<String>, attributes: Vec<Attribute>, outputs: Vec<String>) {
+        let name = self.fresh(op);
+        self.graph.nodes.push(Node { name, op_type: op.to_string(), inputs, outputs, attributes });
+    }
+    
+    fn node(&mut self, op: &str, inputs: Vec<String>, attributes: Vec<Attribute>) -> String {
+        let output = self.fresh(&op.to_lowercase());
+        self.node_outputs(op, inputs, attributes, vec![output.clone()]);
+        output
+    }
+    
+    fn ints(&mut self, values: Vec<i64>) -> String {
+        let name = self.fresh("shape");
+        self.graph.initializers.push(Initializer::ints(&name, vec![values.len() as i64], values));
+        name
+    }
+    
+    // Add a parameter once, converting it with `convert` into `dims`
+    fn parameter(&mut self, name: String, expected: &[usize], dims: Vec<i64>,
+                 convert: impl Fn(&Tensor) -> Vec<f32>) -> Result<String, String> {
+        if self.exported.insert(name.clone()) {
+            let tensor = self.weights.get(&name).ok_or_else(|| format!("missing weight `{}`", name))?;
+            if tensor.shape != expected {
+                return Err(format!("weight `{}` has shape {:?}, expected {:?}", name, tensor.shape, expected));
+            }
+            self.graph.initializers.push(Initializer::floats(&name, dims, convert(tensor)));
+        }
+        Ok(name)
+    }
+    
+    // Weight and bias stored as in the interpreter
+    fn dense_parameters(&mut self, step: &Step) -> Result<(String, String), String> {
+        let shapes = step.parameter_shapes();
+        let (w, b) = (&shapes[0], &shapes[1]);
+        let weight = self.parameter(w.0.clone(), &w.1, w.1.iter().map(|&d| d as i64).collect(), |t| t.data.clone())?;
+        let bias = self.parameter(b.0.clone(), &b.1, vec![b.1[0] as i64], |t| t.data.clone())?;
+        Ok((weight, bias))
+    }
+    
+    // Convolution weights `[k, k, in, out]` as `[out, in, k, k]`, or as
+    // `[in, out, k, k]` for a transposed convolution
+    fn conv_parameters(&mut self, step: &Step, transposed: bool) -> Result<(String, String), String> {
+        let shapes = step.parameter_shapes();
+        let (w, b) = (&shapes[0], &shapes[1]);
+        let (k, c_in, c_out) = (w.1[0], w.1[2], w.1[3]);
+        let dims = if transposed { [c_in, c_out, k, k] } else { [c_out, c_in, k, k] };
+        let weight = self.parameter(w.0.clone(), &w.1, dims.iter().map(|&d| d as i64).collect(), |t| {
+            let mut out = vec![0.0; t.len()];
+            for ky in 0..k {
+                for kx in 0..k {
+                    for i in 0..c_in {
+                        for o in 0..c_out {
+                            let filter = if transposed { i * c_out + o } else { o * c_in + i };
+                            out[(filter * k + ky) * k + kx] = t.data[((ky * k + kx) * c_in + i) * c_out + o];
+                        }
+                    }
+                }
+            }
+            out
+        })?;
+        let bias = self.parameter(b.0.clone(), &b.1, vec![c_out as i64], |t| t.data.clone())?;
+        Ok((weight, bias))
+    }
+    
+    fn channels_last(&mut self, x: String, rank: usize) -> String {
+        if rank == 3 {
+            self.node("Transpose", vec![x], vec![Attribute::ints("perm", vec![0, 2, 3, 1])])
+        } else {
+            x
+        }
+    }
+    
+    fn channels_first(&mut self, x: String, rank: usize) -> String {
+        if rank == 3 {
+            self.node("Transpose", vec![x], vec![Attribute::ints("perm", vec![0, 3, 1, 2])])
+        } else {
+            x
+        }
+    }
+    
+    // Reshape in the interpreter's (channels-last) element order
+    fn reshape(&mut self, x: String, from: &TensorShape, to: &TensorShape) -> String {
+        let x = self.channels_last(x, from.rank());
+        let shape = self.ints(std::iter::once(-1).chain(to.dims.iter().map(|&d| d as i64)).collect());
+        let y = self.node("Reshape", vec![x, shape], vec![]);
+        self.channels_first(y, to.rank())
+    }
+    
+    fn step(&mut self, step: &Step, x: String) -> Result<String, String> {
+        let layer = &step.layer;
+        let (input, output) = (&step.input, &step.output);
+        
+        let y = match layer.layer_type {
+            LayerType::Convolutional(_) => {
+                let window = layer.window().unwrap();
+                let k = window.size as i64;
+                let (w, b) = self.conv_parameters(step, false)?;
+                self.node("Conv", vec![x, w, b], vec![
+                    Attribute::ints("kernel_shape", vec![k, k]),
+                    Attribute::ints("pads", pads(&window, input)),
+                    Attribute::ints("strides", vec![window.stride as i64; 2]),
+                ])
+            },
+            LayerType::TransposeConv => {
+                let k = layer.param(KERNEL) as i64;
+                let stride = layer.param(STRIDE) as i64;
+                // Output is exactly `stride` times the input, cropped like the interpreter
+                let begin = (k - stride).max(0) / 2;
+                let end = k - stride - begin;
+                let (w, b) = self.conv_parameters(step, true)?;
+                self.node("ConvTranspose", vec![x, w, b], vec![
+                    Attribute::ints("kernel_shape", vec![k, k]),
+                    Attribute::ints("strides", vec![stride, stride]),
+                    Attribute::ints("pads", vec![begin, begin, end.max(0), end.max(0)]),
+                    Attribute::ints("output_padding", vec![(-end).max(0), (-end).max(0)]),
+                ])
+            },
+            LayerType::Pooling => {
+                let window = layer.window().unwrap();
+                self.node("MaxPool", vec![x], vec![
+                    Attribute::ints("kernel_shape", vec![window.size as i64; 2]),
+                    Attribute::ints("pads", pads(&window, input)),
+                    Attribute::ints("strides", vec![window.stride as i64; 2]),
+                ])
+            },
+            LayerType::Flatten => self.reshape(x, input, output),
+            LayerType::Dense(_) => {
+                let (w, b) = self.dense_parameters(step)?;
+                if input.rank() == 1 {
+                    self.node("Gemm", vec![x, w, b], vec![])
+                } else {
+                    // Dense layers act on the last axis, which is channels for images
+                    let x = self.channels_last(x, input.rank());
+                    let y = self.node("MatMul", vec![x, w], vec![]);
+                    let y = self.node("Add", vec![y, b], vec![]);
+                    self.channels_first(y, output.rank())
+                }
+            },
+            LayerType::Upsampling if target_shape(layer).is_some() => {
+                let flat = TensorShape::new(vec![input.elements()]);
+                let x = self.reshape(x, input, &flat);
+                let (w, b) = self.dense_parameters(step)?;
+                let y = self.node("Gemm", vec![x, w, b], vec![]);
+                self.reshape(y, &TensorShape::new(vec![output.elements()]), output)
+            },
+            LayerType::Upsampling => {
+                let factor = layer.param(FACTOR) as f32;
+                let scales = self.fresh("scales");
+                self.graph.initializers.push(Initializer::floats(&scales, vec![4], vec![1.0, 1.0, factor, factor]));
+                self.node("Resize", vec![x, String::new(), scales], vec![
+                    Attribute::string("mode", "nearest"),
+                    Attribute::string("coordinate_transformation_mode", "asymmetric"),
+                    Attribute::string("nearest_mode", "floor"),
+                ])
+            },
+            LayerType::Reshape if target_shape(layer).is_some() => self.reshape(x, input, output),
+            LayerType::Reshape | LayerType::AttentionHeads | LayerType::BatchSize => x,
+            LayerType::LSTM => self.lstm(step, x)?,
+            LayerType::Embedding | LayerType::Attention | LayerType::Custom(_) => {
+                return Err(format!("`{}` cannot be exported to ONNX yet", layer.symbol()));
+            },
+        };
+        
+        Ok(match layer.activation {
+            ActivationFunction::ReLU => self.node("Relu", vec![y], vec![]),
+            ActivationFunction::Sigmoid => self.node("Sigmoid", vec![y], vec![]),
+            ActivationFunction::Tanh => self.node("Tanh", vec![y], vec![]),
+            ActivationFunction::Softmax => {
+                let axis = if output.rank() == 3 { 1 } else { -1 };
+                self.node("Softmax", vec![y], vec![Attribute::int("axis", axis)])
+            },
+            ActivationFunction::None => y,
+        })
+    }
+    
+    // LSTM returning the last hidden state; gates are stored input, forget,
+    // cell, output and ONNX expects input, output, forget, cell
+    fn lstm(&mut self, step: &Step, x: String) -> Result<String, String> {
+        const ONNX_GATES: [usize; 4] = [0, 3, 1, 2];
+        let shapes = step.parameter_shapes();
+        let (features, units) = (step.input.dims[1], step.output.dims[0]);
+        
+        let reorder = move |t: &Tensor, rows: usize| {
+            // `[rows, 4H]` to `[1, 4H, rows]`
+            let mut out = Vec::with_capacity(t.len());
+            for gate in ONNX_GATES {
+                for u in 0..units {
+                    for r in 0..rows {
+                        out.push(t.data[r * 4 * units + gate * units + u]);
+                    }
+                }
+            }
+            out
+        };
+        let gates = 4 * units as i64;
+        let w = self.parameter(shapes[0].0.clone(), &shapes[0].1, vec![1, gates, features as i64], |t| reorder(t, features))?;
+        let r = self.parameter(shapes[1].0.clone(), &shapes[1].1, vec![1, gates, units as i64], |t| reorder(t, units))?;
+        let b = self.parameter(shapes[2].0.clone(), &shapes[2].1, vec![1, 2 * gates], |t| {
+            // Input bias followed by a zero recurrent bias
+            let mut out = reorder(t, 1);
+            out.resize(8 * units, 0.0);
+            out
+        })?;
+        
+        // ONNX LSTM is time-major
+        let x = self.node("Transpose", vec![x], vec![Attribute::ints("perm", vec![1, 0, 2])]);
+        let hidden = self.fresh("lstm_h");
+        self.node_outputs("LSTM", vec![x, w, r, b], vec![Attribute::int("hidden_size", units as i64)],
+                          vec![String::new(), hidden.clone()]);
+        let shape = self.ints(vec![-1, units as i64]);
+        Ok(self.node("Reshape", vec![hidden, shape], vec![]))
+    }
+}
@@ -1,39 +1,94 @@
 use pest::Parser;
-use pest::error::Error;
+use pest::error::{Error, InputLocation};
 use pest::iterators::{Pair, Pairs};
 use pest_derive::Parser;
+use std::collections::HashMap;
 
 use crate::ast::*;
+use crate::custom_ops::CustomOpRegistry;
+use crate::diagnostics::{codes, Diagnostic, Span};
+use crate::extensions::ui_extensions::{UIComponentNode, UIComponentType};
 
 #[derive(Parser)]
 #[grammar = "aopl.pest"]
 pub struct AoplParser;
 
-pub fn parse(input: &str) -> Result<ASTNode, Error<Rule>> {
-    let pairs = AoplParser::parse(Rule::main, input)?;
-    let mut symbol_table = SymbolTable::new();
+// State threaded through AST construction
+struct ParseContext<'a> {
+    symbol_table: SymbolTable,
+    definitions: HashMap<String, Span>,
+    diagnostics: Vec<Diagnostic>,
+    // Operations `⇝symbol` layers resolve to
+    custom_ops: &'a CustomOpRegistry,
+}
+
+impl<'a> ParseContext<'a> {
+    fn new(custom_ops: &'a CustomOpRegistry) -> Self {
+        ParseContext {
+            symbol_table: SymbolTable::new(),
+            definitions: HashMap::new(),
+            diagnostics: Vec::new(),
+            custom_ops,
+        }
+    }
+}
+
+impl From<Error<Rule>> for Diagnostic {
+    fn from(error: Error<Rule>) -> Self {
+        let span = match error.location {
+            InputLocation::Pos(pos) => Span::new(pos, pos),
+            InputLocation::Span((start, end)) => Span::new(start, end),
+        };
+        let message = error.variant.message().to_string();
+        Diagnostic::error(codes::SYNTAX_ERROR, "syntax error")
+            .with_primary(span, message)
+    }
+}
+
+/// Parse a program without custom operations, failing on the first error
+pub fn parse(input: &str) -> Result<ASTNode, Diagnostic> {
+    parse_with_diagnostics(input, &CustomOpRegistry::new()).map(|(ast, _)| ast).map_err(|diagnostics| {
+        diagnostics.into_iter()
+            .find(|d| d.is_error())
+            .expect("failed parse reports at least one error")
+    })
+}
+
+/// Parse a program whose `⇝symbol` layers use `custom_ops`, returning warnings
+/// alongside the AST or every diagnostic on failure
+pub fn parse_with_diagnostics(input: &str, custom_ops: &CustomOpRegistry) -> Result<(ASTNode, Vec<Diagnostic>), Vec<Diagnostic>> {
+    let pairs = AoplParser::parse(Rule::main, input).map_err(|e| vec![Diagnostic::from(e)])?;
+    let mut context = ParseContext::new(custom_ops);
     
     // Process the parsed pairs to construct the AST
-    let ast = process_main(pairs, &mut symbol_table)?;
+    let ast = process_main(pairs, &mut context);
     
-    Ok(ast)
+    match ast {
+        Ok(ast) if !context.diagnostics.iter().any(|d| d.is_error()) => Ok((ast, context.diagnostics)),
+        Ok(_) => Err(context.diagnostics),
+        Err(diagnostic) => {
+            context.diagnostics.push(diagnostic);
+            Err(context.diagnostics)
+        },
+    }
 }
 
-fn process_main(pairs: Pairs<Rule>, symbol_table: &mut SymbolTable) -> Result<ASTNode, Error<Rule>> {
+fn process_main(pairs: Pairs<Rule>, context: &mut ParseContext) -> Result<ASTNode, Diagnostic> {
     // The main rule contains just the network_def rule
     let pair = pairs.into_iter().next().unwrap();
     match pair.as_rule() {
         Rule::main => {
             let inner = pair.into_inner().next().unwrap();
-            process_network_def(inner, symbol_table)
+            process_network_def(inner, context)
         },
         _ => unreachable!(),
     }
 }
 
-fn process_network_def(pair: Pair<Rule>, symbol_table: &mut SymbolTable) -> Result<ASTNode, Error<Rule>> {
+fn process_network_def(pair: Pair<Rule>, context: &mut ParseContext) -> Result<ASTNode, Diagnostic> {
     let mut components = None;
     let mut body = Vec::new();
+    let mut shares = Vec::new();
     
     for inner_pair in pair.into_inner() {
         match inner_pair.as_rule() {
@@ -41,23 +96,26 @@ fn process_network_def(pair: Pair<Rule>, symbol_table: &mut SymbolTable) -> Resu
                 components = Some(process_network_components(inner_pair));
             },
             Rule::component_def => {
-                let component = process_component_def(inner_pair, symbol_table)?;
+                let component = process_component_def(inner_pair, context)?;
                 if let ASTNode::Component(comp_node) = &component {
-                    // Add component to symbol table
-                    symbol_table.add_component(comp_node.id.clone(), *comp_node.expr.clone());
+                    define_component(comp_node, context);
                 }
                 body.push(component);
             },
-            Rule::network_expr => {
-                let expr = process_network_expr(inner_pair, symbol_table)?;
+            Rule::extended_network_expr => {
+                let expr = process_network_expr(inner_pair, context)?;
                 body.push(expr);
             },
             Rule::loss_expr => {
-                let loss = process_loss_expr(inner_pair, symbol_table)?;
+                let loss = process_loss_expr(inner_pair, context)?;
                 body.push(loss);
             },
+            Rule::share_def => {
+                shares.push(process_share_def(inner_pair, context));
+            },
             Rule::direct_expr => {
                 // Process special direct expression syntax for GaiaScript
+                let span = Span::from(inner_pair.as_span());
                 let expr_str = inner_pair.as_str();
                 let parts: Vec<&str> = expr_str.splitn(2, ':').collect();
                 if parts.len() == 2 {
@@ -70,6 +128,7 @@ fn process_network_def(pair: Pair<Rule>, symbol_table: &mut SymbolTable) -> Resu
                         expr: Box::new(ASTNode::Raw(RawNode {
                             content: expr_content.to_string()
                         })),
+                        span,
                     });
                     
                     body.push(component);
@@ -79,7 +138,21 @@ fn process_network_def(pair: Pair<Rule>, symbol_table: &mut SymbolTable) -> Resu
         }
     }
     
-    Ok(ASTNode::Network(NetworkNode { components, body }))
+    Ok(ASTNode::Network(NetworkNode { components, body, shares }))
+}
+
+// Add a component to the symbol table, warning when it shadows an earlier definition
+fn define_component(component: &ComponentNode, context: &mut ParseContext) {
+    if let Some(previous) = context.definitions.get(&component.id) {
+        context.diagnostics.push(
+            Diagnostic::warning(codes::COMPONENT_REDEFINED, format!("component `{}` is defined more than once", component.id))
+                .with_primary(component.span, "redefined here")
+                .with_secondary(*previous, "previous definition here")
+                .with_note("later references resolve to the most recent definition")
+        );
+    }
+    context.definitions.insert(component.id.clone(), component.span);
+    context.symbol_table.add_component(component.id.clone(), *component.expr.clone());
 }
 
 fn process_network_components(pair: Pair<Rule>) -> Vec<String> {
@@ -94,7 +167,8 @@ fn process_network_components(pair: Pair<Rule>) -> Vec<String> {
     components
 }
 
-fn process_component_def(pair: Pair<Rule>, symbol_table: &mut SymbolTable) -> Result<ASTNode, Error<Rule>> {
+fn process_component_def(pair: Pair<Rule>, context: &mut ParseContext) -> Result<ASTNode, Diagnostic> {
+    let span = Span::from(pair.as_span());
     let mut id = String::new();
     let mut expr = None;
     
@@ -103,25 +177,26 @@ fn process_component_def(pair: Pair<Rule>, symbol_table: &mut SymbolTable) -> Re
             Rule::component_id => {
                 id = inner_pair.as_str().to_string();
             },
-            Rule::network_expr => {
-                expr = Some(process_network_expr(inner_pair, symbol_table)?);
+            Rule::extended_network_expr => {
+                expr = Some(process_network_expr(inner_pair, context)?);
             },
             _ => {}
         }
     }
     
-    if let Some(expr_node) = expr {
-        Ok(ASTNode::Component(ComponentNode {
+    match expr {
+        Some(expr_node) => Ok(ASTNode::Component(ComponentNode {
             id,
             expr: Box::new(expr_node),
-        }))
-    } else {
-        // This should never happen with a valid parse
-        panic!("Component definition missing expression")
+            span,
+        })),
+        None => Err(Diagnostic::error(codes::EMPTY_EXPRESSION, format!("component `{}` has no expression", id))
+            .with_primary(span, "")),
     }
 }
 
-fn process_network_expr(pair: Pair<Rule>, symbol_table: &mut SymbolTable) -> Result<ASTNode, Error<Rule>> {
+fn process_network_expr(pair: Pair<Rule>, context: &mut ParseContext) -> Result<ASTNode, Diagnostic> {
+    let span = Span::from(pair.as_span());
     let mut parts = Vec::new();
     
     for inner_pair in pair.into_inner() {
@@ -130,16 +205,19 @@ fn process_network_expr(pair: Pair<Rule>, symbol_table: &mut SymbolTable) -> Res
                 parts.push(process_input_spec(inner_pair));
             },
             Rule::layer_expr => {
-                parts.push(process_layer_expr(inner_pair));
+                parts.push(process_layer_expr(inner_pair, context)?);
             },
             Rule::block_expr => {
-                parts.push(process_block_expr(inner_pair, symbol_table)?);
+                parts.push(process_block_expr(inner_pair, context)?);
             },
             Rule::component_ref => {
-                parts.push(process_component_ref(inner_pair, symbol_table)?);
+                parts.push(process_component_ref(inner_pair, context));
+            },
+            Rule::ui_expr => {
+                parts.push(process_ui_expr(inner_pair));
             },
-            Rule::network_expr => {
-                parts.push(process_network_expr(inner_pair, symbol_table)?);
+            Rule::extended_network_expr => {
+                parts.push(process_network_expr(inner_pair, context)?);
             },
             _ => {}
         }
@@ -148,24 +226,27 @@ fn process_network_expr(pair: Pair<Rule>, symbol_table: &mut SymbolTable) -> Res
     // If there are multiple parts, create a data flow between them
     if parts.len() > 1 {
         let mut result = parts[0].clone();
-        for i in 1..parts.len() {
+        for part in parts.iter().skip(1) {
             result = ASTNode::DataFlow(
                 Box::new(result),
-                Box::new(parts[i].clone()),
+                Box::new(part.clone()),
             );
         }
         Ok(result)
     } else if parts.len() == 1 {
         Ok(parts[0].clone())
     } else {
-        // Empty expression - shouldn't happen with valid input
-        panic!("Empty network expression")
+        Err(Diagnostic::error(codes::EMPTY_EXPRESSION, "expression contains no inputs, layers or components")
+            .with_primary(span, "nothing to compile here"))
     }
 }
 
 fn process_input_spec(pair: Pair<Rule>) -> ASTNode {
+    let span = Span::from(pair.as_span());
     let mut input_type = None;
     let mut params = Vec::new();
+    let mut preprocess = None;
+    let mut tokenizer = None;
     
     for inner_pair in pair.into_inner() {
         match inner_pair.as_rule() {
@@ -184,31 +265,140 @@ fn process_input_spec(pair: Pair<Rule>) -> ASTNode {
             Rule::layer_params => {
                 // Process params
                 for param_pair in inner_pair.into_inner() {
-                    if param_pair.as_rule() == Rule::number {
-                        if let Ok(num) = param_pair.as_str().parse::<usize>() {
-                            params.push(num);
-                        }
+                    push_numeric_param(&param_pair, &mut params);
+                }
+            },
+            Rule::normalization => {
+                preprocess = Some(match inner_pair.into_inner().find(|p| p.as_rule() == Rule::number) {
+                    Some(divisor) => Preprocess::Scale(divisor.as_str().parse().unwrap_or(1)),
+                    None => Preprocess::Standardize,
+                });
+            },
+            Rule::tokenizer_spec => {
+                let span = Span::from(inner_pair.as_span());
+                let mut kind = TokenizerKind::Char;
+                let mut files = Vec::new();
+                for part in inner_pair.into_inner() {
+                    match part.as_rule() {
+                        Rule::tokenizer_kind => kind = TokenizerKind::parse(part.as_str()).unwrap_or(kind),
+                        Rule::string => files.push(part.as_str().trim_matches('"').to_string()),
+                        _ => {}
                     }
                 }
+                tokenizer = Some(TokenizerSpec { kind, files, span, loaded: None });
             },
             _ => {}
         }
     }
     
-    if let Some(input_type) = input_type {
-        ASTNode::Input(InputNode {
-            input_type,
-            params,
-        })
-    } else {
-        // This shouldn't happen with valid input
-        panic!("Input type not specified")
+    // The grammar guarantees one of the input markers
+    ASTNode::Input(InputNode {
+        input_type: input_type.expect("input_spec without input type"),
+        params,
+        preprocess,
+        tokenizer,
+        span,
+    })
+}
+
+/// Value of a numeric literal: ASCII digits, subscript digits or GaiaScript numerals
+///
+/// Numeral digits (`⊹`=0 … `⊧`=9) are positional, so `⋰⋮` is 32. The magnitude
+/// symbols `≡` (100), `≢` (1000), `≋` (10000) and `⋕` (100000) multiply the digits
+/// before them, so `⋮≡` is 200 and a lone `≡` is 100.
+pub fn parse_numeral(text: &str) -> Option<usize> {
+    if let Ok(num) = text.parse::<usize>() {
+        return Some(num);
+    }
+    
+    let mut value: Option<usize> = None;
+    for c in text.chars() {
+        let digit = match c {
+            '⊹' | '₀' => Some(0),
+            '⊿' | '₁' => Some(1),
+            '⋮' | '₂' => Some(2),
+            '⋰' | '₃' => Some(3),
+            '⋱' | '₄' => Some(4),
+            '⌓' | '₅' => Some(5),
+            '⌗' | '₆' => Some(6),
+            '⊥' | '₇' => Some(7),
+            '⊢' | '₈' => Some(8),
+            '⊧' | '₉' => Some(9),
+            '⋈' => Some(10),
+            _ => None,
+        };
+        if let Some(digit) = digit {
+            value = Some(value.unwrap_or(0) * if digit >= 10 { 100 } else { 10 } + digit);
+            continue;
+        }
+        let magnitude = match c {
+            '≡' => 100,
+            '≢' => 1000,
+            '≋' => 10000,
+            '⋕' => 100000,
+            c if c.is_whitespace() => continue,
+            _ => return None,
+        };
+        value = Some(value.unwrap_or(1) * magnitude);
+    }
+    value
+}
+
+// Interface element such as `∮ 600×400` or `⌘"Run"`; in a chain every element
+// contains the ones after it
+fn process_ui_expr(pair: Pair<Rule>) -> ASTNode {
+    let element = pair.into_inner().next().expect("ui_expr without an element");
+    let component_type = match element.as_rule() {
+        Rule::ui_canvas => UIComponentType::Canvas,
+        Rule::ui_panel => UIComponentType::Panel,
+        Rule::ui_layout => UIComponentType::Layout,
+        Rule::ui_button => UIComponentType::Button,
+        _ => UIComponentType::Label,
+    };
+    let mut dimensions = None;
+    let mut properties = HashMap::new();
+    for part in element.into_inner() {
+        match part.as_rule() {
+            Rule::dimension => {
+                let mut axes = Vec::new();
+                push_numeric_param(&part, &mut axes);
+                if let [width, height, ..] = axes[..] {
+                    dimensions = Some((width, height));
+                }
+            },
+            Rule::string => {
+                properties.insert("text".to_string(), part.as_str().trim_matches('"').to_string());
+            },
+            _ => {}
+        }
     }
+    ASTNode::UIComponent(UIComponentNode { component_type, dimensions, properties })
 }
 
-fn process_layer_expr(pair: Pair<Rule>) -> ASTNode {
+// Append the value(s) of a numeric parameter; dimensions contribute one value
+// per axis. Returns false when a value cannot be read
+fn push_numeric_param(pair: &Pair<Rule>, params: &mut Vec<usize>) -> bool {
+    let values: Vec<&str> = match pair.as_rule() {
+        Rule::dimension => pair.as_str().split(['×', 'x']).collect(),
+        Rule::number | Rule::unicode_number | Rule::unicode_composite => vec![pair.as_str()],
+        _ => return true,
+    };
+    let mut read = true;
+    for value in values {
+        match parse_numeral(value) {
+            Some(num) => params.push(num),
+            None => read = false,
+        }
+    }
+    read
+}
+
+fn process_layer_expr(pair: Pair<Rule>, context: &mut ParseContext) -> Result<ASTNode, Diagnostic> {
+    let span = Span::from(pair.as_span());
     let mut layer_type = None;
     let mut params = Vec::new();
+    let mut named_params = Vec::new();
+    let mut positional = false;
     let mut activation = ActivationFunction::None;
     
     for inner_pair in pair.into_inner() {
@@ -217,7 +407,7 @@ fn process_layer_expr(pair: Pair<Rule>) -> ASTNode {
                 let mut idx = 0;
                 for sub_pair in inner_pair.into_inner() {
                     if sub_pair.as_rule() == Rule::subscript {
-                        if let Ok(num) = sub_pair.as_str().parse::<usize>() {
+                        if let Some(num) = parse_numeral(sub_pair.as_str()) {
                             idx = num;
                         }
                     }
@@ -228,7 +418,7 @@ fn process_layer_expr(pair: Pair<Rule>) -> ASTNode {
                 let mut idx = 0;
                 for sub_pair in inner_pair.into_inner() {
                     if sub_pair.as_rule() == Rule::subscript {
-                        if let Ok(num) = sub_pair.as_str().parse::<usize>() {
+                        if let Some(num) = parse_numeral(sub_pair.as_str()) {
                             idx = num;
                         }
                     }
@@ -265,13 +455,31 @@ fn process_layer_expr(pair: Pair<Rule>) -> ASTNode {
             Rule::attention => {
                 layer_type = Some(LayerType::Attention);
             },
+            Rule::custom_layer => {
+                let name = inner_pair.into_inner().find(|p| p.as_rule() == Rule::op_name).unwrap();
+                let op = context.custom_ops.get(name.as_str())
+                    .ok_or_else(|| context.custom_ops.unknown(name.as_str(), Span::from(name.as_span())))?;
+                layer_type = Some(LayerType::Custom(op));
+            },
+            Rule::positional_encoding => positional = true,
             Rule::layer_params => {
                 // Process params and activation
                 for param_pair in inner_pair.into_inner() {
                     match param_pair.as_rule() {
-                        Rule::number => {
-                            if let Ok(num) = param_pair.as_str().parse::<usize>() {
-                                params.push(num);
+                        Rule::number | Rule::dimension | Rule::unicode_number | Rule::unicode_composite => {
+                            let read = push_numeric_param(&param_pair, &mut params);
+                            if !read {
+                                context.diagnostics.push(unreadable_value(&param_pair));
+                            }
+                        },
+                        Rule::named_param => {
+                            let span = Span::from(param_pair.as_span());
+                            let mut inner = param_pair.into_inner();
+                            let name = inner.next().unwrap().as_str().to_string();
+                            let value = inner.next().unwrap();
+                            match parse_numeral(value.as_str()) {
+                                Some(number) => named_params.push(NamedParam { name, value: number, span }),
+                                None => context.diagnostics.push(unreadable_value(&value)),
                             }
                         },
                         Rule::relu => activation = ActivationFunction::ReLU,
@@ -286,91 +494,251 @@ fn process_layer_expr(pair: Pair<Rule>) -> ASTNode {
         }
     }
     
-    if let Some(layer_type) = layer_type {
-        ASTNode::Layer(LayerNode {
-            layer_type,
-            params,
-            activation,
-        })
-    } else {
-        // This shouldn't happen with valid input
-        panic!("Layer type not specified")
-    }
+    // The grammar guarantees one of the layer symbols
+    Ok(ASTNode::Layer(LayerNode {
+        layer_type: layer_type.expect("layer_expr without layer type"),
+        params,
+        named_params,
+        positional,
+        activation,
+        span,
+        input_shape: None,
+        output_shape: None,
+    }))
 }
 
-fn process_block_expr(pair: Pair<Rule>, symbol_table: &mut SymbolTable) -> Result<ASTNode, Error<Rule>> {
+// A layer value that is not a count, e.g. one too large to represent
+fn unreadable_value(pair: &Pair<Rule>) -> Diagnostic {
+    Diagnostic::error(codes::INVALID_LAYER_PARAM, format!("cannot read `{}` as a count", pair.as_str()))
+        .with_primary(Span::from(pair.as_span()), "not a count")
+}
+
+fn process_block_expr(pair: Pair<Rule>, context: &mut ParseContext) -> Result<ASTNode, Diagnostic> {
+    let span = Span::from(pair.as_span());
     let mut content = None;
     let mut repetitions = 1;
+    let mut shared = false;
+    let mut marked = false;
     
     for inner_pair in pair.into_inner() {
         match inner_pair.as_rule() {
-            Rule::network_expr => {
-                content = Some(process_network_expr(inner_pair, symbol_table)?);
+            Rule::extended_network_expr => {
+                content = Some(process_network_expr(inner_pair, context)?);
             },
             Rule::repetition => {
                 let num_str = inner_pair.into_inner().next().unwrap().as_str();
-                if let Ok(num) = num_str.parse::<usize>() {
+                if let Some(num) = parse_numeral(num_str) {
                     repetitions = num;
                 }
             },
+            Rule::weight_sharing => {
+                marked = true;
+                shared = inner_pair.into_inner().next().map(|p| p.as_rule()) == Some(Rule::shared_weights);
+            },
             _ => {}
         }
     }
     
-    if let Some(content_node) = content {
-        Ok(ASTNode::Block(BlockNode {
+    match content {
+        Some(content_node) => Ok(ASTNode::Block(BlockNode {
             content: Box::new(content_node),
             repetitions,
-        }))
-    } else {
-        // This shouldn't happen with valid input
-        panic!("Block missing content")
+            shared,
+            marked,
+            shapes: Vec::new(),
+            span,
+        })),
+        None => Err(Diagnostic::error(codes::EMPTY_EXPRESSION, "block has no content")
+            .with_primary(span, "")),
     }
 }
 
-fn process_component_ref(pair: Pair<Rule>, symbol_table: &mut SymbolTable) -> Result<ASTNode, Error<Rule>> {
-    let component_id = pair.into_inner().next().unwrap().as_str();
+fn process_component_ref(pair: Pair<Rule>, context: &mut ParseContext) -> ASTNode {
+    let id_pair = pair.into_inner().next().unwrap();
+    let component_id = id_pair.as_str();
     
     // Look up component in symbol table
-    if let Some(component) = symbol_table.get_component(component_id) {
-        Ok(component.clone())
+    if let Some(component) = context.symbol_table.get_component(component_id) {
+        component.clone()
     } else {
-        // Component not found - create placeholder
-        // In a real implementation, you might want to report an error
-        Ok(ASTNode::Expression(vec![]))
+        let span = Span::from(id_pair.as_span());
+        let mut diagnostic = Diagnostic::error(codes::UNKNOWN_COMPONENT, format!("unknown component `{}`", component_id))
+            .with_primary(span, "not defined before this point");
+        let known: Vec<&String> = context.symbol_table.components.keys().collect();
+        if !known.is_empty() {
+            let mut names: Vec<String> = known.iter().map(|k| k.to_string()).collect();
+            names.sort();
+            diagnostic = diagnostic.with_note(format!("defined components: {}", names.join(", ")));
+        }
+        context.diagnostics.push(diagnostic);
+        ASTNode::Expression(vec![])
     }
 }
 
-fn process_loss_expr(pair: Pair<Rule>, symbol_table: &mut SymbolTable) -> Result<ASTNode, Error<Rule>> {
+fn process_loss_expr(pair: Pair<Rule>, context: &mut ParseContext) -> Result<ASTNode, Diagnostic> {
+    let span = Span::from(pair.as_span());
+    let mut label = None;
     let mut from = None;
-    let mut to = String::new();
-    let mut function = String::new();
+    let mut sources = Vec::new();
+    let mut targets = Vec::new();
+    let mut frozen = Vec::new();
+    let mut function = None;
     
-    let inner_pairs: Vec<Pair<Rule>> = pair.into_inner().collect();
-    
-    // First is the network expression
-    if !inner_pairs.is_empty() {
-        from = Some(process_network_expr(inner_pairs[0].clone(), symbol_table)?);
+    for inner_pair in pair.into_inner() {
+        match inner_pair.as_rule() {
+            Rule::component_id => {
+                label = Some(inner_pair.as_str().to_string());
+            },
+            // The source expression, optionally preceded by a `name:` label
+            Rule::extended_network_expr => {
+                for reference in inner_pair.clone().into_inner().flatten().filter(|p| p.as_rule() == Rule::component_ref) {
+                    let mut parts = reference.into_inner();
+                    let id = parts.next().unwrap().as_str().to_string();
+                    if parts.next().is_some_and(|p| p.as_rule() == Rule::frozen) {
+                        frozen.push(id.clone());
+                    }
+                    sources.push(id);
+                }
+                from = Some(process_network_expr(inner_pair, context)?);
+            },
+            Rule::injection => {
+                let mut parts = inner_pair.into_inner();
+                let id_pair = parts.next().unwrap();
+                let id = id_pair.as_str().to_string();
+                if context.symbol_table.get_component(&id).is_none() {
+                    context.diagnostics.push(
+                        Diagnostic::error(codes::UNKNOWN_COMPONENT, format!("unknown component `{}`", id))
+                            .with_primary(Span::from(id_pair.as_span()), "injection target is not defined")
+                    );
+                }
+                if parts.next().is_some() {
+                    frozen.push(id.clone());
+                }
+                targets.push(id);
+            },
+            Rule::loss_sum => {
+                function = Some(process_loss_sum(inner_pair, context));
+            },
+            _ => {}
+        }
     }
     
-    // Second is the component ID
-    if inner_pairs.len() > 1 {
-        to = inner_pairs[1].as_str().to_string();
+    match (from, function) {
+        (Some(from_node), Some(function)) => Ok(ASTNode::Loss(LossNode {
+            label,
+            from: Box::new(from_node),
+            sources,
+            targets,
+            frozen,
+            function,
+            span,
+        })),
+        _ => Err(Diagnostic::error(codes::EMPTY_EXPRESSION, "loss expression has no source")
+            .with_primary(span, "")),
+    }
+}
+
+fn process_share_def(pair: Pair<Rule>, context: &mut ParseContext) -> ShareNode {
+    let span = Span::from(pair.as_span());
+    let mut ids = Vec::new();
+    for id_pair in pair.into_inner() {
+        let id = id_pair.as_str().to_string();
+        if context.symbol_table.get_component(&id).is_none() {
+            context.diagnostics.push(
+                Diagnostic::error(codes::UNKNOWN_COMPONENT, format!("unknown component `{}`", id))
+                    .with_primary(Span::from(id_pair.as_span()), "not defined before this point")
+            );
+        }
+        ids.push(id);
     }
+    let owner = ids.pop().unwrap();
+    let component = ids.pop().unwrap();
+    ShareNode { component, owner, span }
+}
+
+fn process_loss_sum(pair: Pair<Rule>, context: &mut ParseContext) -> LossExpr {
+    let mut terms = Vec::new();
+    let mut negated = false;
     
-    // Third is the loss function
-    if inner_pairs.len() > 2 {
-        function = inner_pairs[2].as_str().to_string();
+    for inner_pair in pair.into_inner() {
+        match inner_pair.as_rule() {
+            Rule::loss_op => {
+                negated = inner_pair.as_str() == "-";
+            },
+            Rule::loss_term => {
+                if let Some(term) = process_loss_term(inner_pair, negated, context) {
+                    terms.push(term);
+                }
+                negated = false;
+            },
+            _ => {}
+        }
     }
     
-    if let Some(from_node) = from {
-        Ok(ASTNode::Loss(LossNode {
-            from: Box::new(from_node),
-            to,
-            function,
-        }))
-    } else {
-        // This shouldn't happen with valid input
-        panic!("Loss expression missing source")
+    LossExpr { terms }
+}
+
+fn process_loss_term(pair: Pair<Rule>, negated: bool, context: &mut ParseContext) -> Option<WeightedTerm> {
+    let span = Span::from(pair.as_span());
+    let mut coefficient = None;
+    let mut term = None;
+    
+    for inner_pair in pair.into_inner() {
+        match inner_pair.as_rule() {
+            Rule::loss_coefficient => {
+                let text = inner_pair.as_str();
+                coefficient = Some(match text.parse::<f32>() {
+                    Ok(value) => Coefficient::Value(value),
+                    Err(_) => Coefficient::Symbol(text.to_string()),
+                });
+            },
+            Rule::loss_name => {
+                let name = inner_pair.as_str();
+                term = match name.to_uppercase().as_str() {
+                    "L1" => Some(LossTerm::Regularizer { norm: Norm::L1, component: None }),
+                    "L2" => Some(LossTerm::Regularizer { norm: Norm::L2, component: None }),
+                    _ => match BaseLoss::from_name(name) {
+                        Some(base) => Some(LossTerm::Base(base)),
+                        None => {
+                            context.diagnostics.push(
+                                Diagnostic::error(codes::UNKNOWN_LOSS, format!("unknown loss function `{}`", name))
+                                    .with_primary(Span::from(inner_pair.as_span()), "not a known loss")
+                                    .with_note(format!("known losses are {}, and the regularizers L1 and L2",
+                                                       BaseLoss::NAMES.join(", ")))
+                            );
+                            None
+                        }
+                    },
+                };
+            },
+            Rule::gradient_penalty | Rule::weight_norm => {
+                let penalty = inner_pair.as_rule() == Rule::gradient_penalty;
+                let mut component = String::new();
+                let mut norm = Norm::L2;
+                for part in inner_pair.into_inner() {
+                    match part.as_rule() {
+                        Rule::component_id => {
+                            component = part.as_str().to_string();
+                            if context.symbol_table.get_component(&component).is_none() {
+                                context.diagnostics.push(
+                                    Diagnostic::error(codes::UNKNOWN_COMPONENT, format!("unknown component `{}`", component))
+                                        .with_primary(Span::from(part.as_span()), "not defined before this point")
+                                );
+                            }
+                        },
+                        Rule::norm_order if part.as_str() == "₁" => norm = Norm::L1,
+                        _ => {}
+                    }
+                }
+                term = Some(if penalty {
+                    LossTerm::GradientPenalty { component, norm }
+                } else {
+                    LossTerm::Regularizer { norm, component: Some(component) }
+                });
+            },
+            _ => {}
+        }
     }
-}
\ No newline at end of file
+    
+    term.map(|term| WeightedTerm { negated, coefficient, term, span })
+}
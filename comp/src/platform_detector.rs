@@ -112,8 +112,7 @@ pub fn get_target_platform_from_args() -> Platform {
             "--target=web" | "--web" => return Platform::Web,
             _ => {
                 // Check for --target=xxx format
-                if arg.starts_with("--target=") {
-                    let platform_str = &arg[9..];
+                if let Some(platform_str) = arg.strip_prefix("--target=") {
                     match platform_str {
                         "macos" => return Platform::MacOS,
                         "windows" => return Platform::Windows,
@@ -0,0 +1,234 @@
+// Protocol buffers wire format: just enough to read and write ONNX models
+// without generated code
+
+const VARINT: u32 = 0;
+const FIXED64: u32 = 1;
+const LENGTH_DELIMITED: u32 = 2;
+const FIXED32: u32 = 5;
+
+/// Serializes the fields of one message
+#[derive(Debug, Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Encoder { buf: Vec::new() }
+    }
+    
+    fn raw_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+    
+    fn key(&mut self, field: u32, wire_type: u32) {
+        self.raw_varint(((field << 3) | wire_type) as u64);
+    }
+    
+    /// Integer and enum fields; negative values use ten bytes as in protobuf
+    pub fn int64(&mut self, field: u32, value: i64) {
+        self.key(field, VARINT);
+        self.raw_varint(value as u64);
+    }
+    
+    pub fn float(&mut self, field: u32, value: f32) {
+        self.key(field, FIXED32);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+    
+    pub fn bytes(&mut self, field: u32, value: &[u8]) {
+        self.key(field, LENGTH_DELIMITED);
+        self.raw_varint(value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+    
+    pub fn string(&mut self, field: u32, value: &str) {
+        self.bytes(field, value.as_bytes());
+    }
+    
+    /// Embedded message
+    pub fn message(&mut self, field: u32, message: Encoder) {
+        self.bytes(field, &message.buf);
+    }
+    
+    /// Repeated integers, one key per element (proto2 default)
+    pub fn int64s(&mut self, field: u32, values: &[i64]) {
+        for &value in values {
+            self.int64(field, value);
+        }
+    }
+    
+    pub fn floats(&mut self, field: u32, values: &[f32]) {
+        for &value in values {
+            self.float(field, value);
+        }
+    }
+    
+    /// Repeated integers declared `[packed = true]`
+    pub fn packed_int64s(&mut self, field: u32, values: &[i64]) {
+        let mut packed = Encoder::new();
+        for &value in values {
+            packed.raw_varint(value as u64);
+        }
+        self.bytes(field, &packed.buf);
+    }
+    
+    pub fn packed_floats(&mut self, field: u32, values: &[f32]) {
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.bytes(field, &bytes);
+    }
+    
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Payload of one decoded field
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl<'a> Value<'a> {
+    pub fn as_i64(&self) -> Result<i64, String> {
+        match self {
+            Value::Varint(v) | Value::Fixed64(v) => Ok(*v as i64),
+            Value::Fixed32(v) => Ok(*v as i32 as i64),
+            Value::Bytes(_) => Err("expected an integer field".to_string()),
+        }
+    }
+    
+    pub fn as_f32(&self) -> Result<f32, String> {
+        match self {
+            Value::Fixed32(v) => Ok(f32::from_bits(*v)),
+            _ => Err("expected a float field".to_string()),
+        }
+    }
+    
+    pub fn as_bytes(&self) -> Result<&'a [u8], String> {
+        match self {
+            Value::Bytes(bytes) => Ok(bytes),
+            _ => Err("expected a length-delimited field".to_string()),
+        }
+    }
+    
+    pub fn as_string(&self) -> Result<String, String> {
+        String::from_utf8(self.as_bytes()?.to_vec()).map_err(|_| "string field is not valid UTF-8".to_string())
+    }
+    
+    /// Repeated integers, accepting both packed and unpacked encodings
+    pub fn push_i64s(&self, out: &mut Vec<i64>) -> Result<(), String> {
+        match self {
+            Value::Bytes(bytes) => {
+                let mut decoder = Decoder::new(bytes);
+                while decoder.pos < bytes.len() {
+                    out.push(decoder.raw_varint()? as i64);
+                }
+                Ok(())
+            },
+            _ => {
+                out.push(self.as_i64()?);
+                Ok(())
+            },
+        }
+    }
+    
+    /// Repeated floats, accepting both packed and unpacked encodings
+    pub fn push_f32s(&self, out: &mut Vec<f32>) -> Result<(), String> {
+        match self {
+            Value::Bytes(bytes) => {
+                out.extend(floats_from_le(bytes)?);
+                Ok(())
+            },
+            _ => {
+                out.push(self.as_f32()?);
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Little-endian f32 array, as stored in ONNX `raw_data`
+pub fn floats_from_le(bytes: &[u8]) -> Result<Vec<f32>, String> {
+    if !bytes.len().is_multiple_of(4) {
+        return Err("float data is not a multiple of 4 bytes".to_string());
+    }
+    Ok(bytes.chunks(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect())
+}
+
+/// Iterates over the fields of one message as `(field number, value)`
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Decoder { data, pos: 0 }
+    }
+    
+    fn raw_varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.data.get(self.pos).ok_or("truncated varint")?;
+            self.pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("varint is longer than ten bytes".to_string())
+    }
+    
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.data.len()).ok_or("truncated field")?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+    
+    fn field(&mut self) -> Result<(u32, Value<'a>), String> {
+        let key = self.raw_varint()?;
+        let field = (key >> 3) as u32;
+        let value = match (key & 7) as u32 {
+            VARINT => Value::Varint(self.raw_varint()?),
+            FIXED64 => {
+                let bytes = self.take(8)?;
+                Value::Fixed64(u64::from_le_bytes(bytes.try_into().unwrap()))
+            },
+            LENGTH_DELIMITED => {
+                let len = self.raw_varint()? as usize;
+                Value::Bytes(self.take(len)?)
+            },
+            FIXED32 => {
+                let bytes = self.take(4)?;
+                Value::Fixed32(u32::from_le_bytes(bytes.try_into().unwrap()))
+            },
+            other => return Err(format!("unsupported wire type {} in field {}", other, field)),
+        };
+        Ok((field, value))
+    }
+}
+
+impl<'a> Iterator for Decoder<'a> {
+    type Item = Result<(u32, Value<'a>), String>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+        let field = self.field();
+        if field.is_err() {
+            // Stop after the first malformed field
+            self.pos = self.data.len();
+        }
+        Some(field)
+    }
+}
@@ -0,0 +1,499 @@
+use crate::ast::*;
+use crate::interpreter::{activate, max_pool2d, upsample_nearest, Interpreter, Tensor, Weights};
+use crate::safetensors::{self, Entry, RawTensor};
+use crate::layer_params::{FACTOR, target_shape, Window};
+use crate::training::Dataset;
+use serde_json::json;
+use std::fmt;
+
+/// How finely weights are scaled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Granularity {
+    PerTensor,
+    // One scale per output channel, the last axis of a kernel
+    PerChannel,
+}
+
+impl Granularity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Granularity::PerTensor => "per-tensor",
+            Granularity::PerChannel => "per-channel",
+        }
+    }
+}
+
+/// Affine int8 mapping: `real = scale * (q - zero_point)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Affine {
+    pub scale: f32,
+    pub zero_point: i32,
+}
+
+impl Affine {
+    /// Parameters covering `[min, max]`, widened to include zero so that
+    /// zero padding and ReLU stay exact
+    pub fn from_range(min: f32, max: f32) -> Self {
+        let (min, max) = (min.min(0.0), max.max(0.0));
+        let scale = if max > min { (max - min) / 255.0 } else { 1.0 };
+        let zero_point = (-128.0 - min / scale).round_ties_even().clamp(-128.0, 127.0) as i32;
+        Affine { scale, zero_point }
+    }
+    
+    pub fn quantize(&self, value: f32) -> i8 {
+        ((value / self.scale).round_ties_even() + self.zero_point as f32).clamp(-128.0, 127.0) as i8
+    }
+    
+    pub fn dequantize(&self, q: i8) -> f32 {
+        self.scale * (q as i32 - self.zero_point) as f32
+    }
+}
+
+/// Symmetric int8 weights (zero point 0) with one scale for the tensor or
+/// one per output channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantizedWeights {
+    pub shape: Vec<usize>,
+    pub data: Vec<i8>,
+    pub scales: Vec<f32>,
+}
+
+impl QuantizedWeights {
+    pub fn quantize(tensor: &Tensor, granularity: Granularity) -> Self {
+        let channels = match granularity {
+            Granularity::PerTensor => 1,
+            Granularity::PerChannel => tensor.shape.last().copied().unwrap_or(1).max(1),
+        };
+        let mut max_abs = vec![0.0f32; channels];
+        for (i, v) in tensor.data.iter().enumerate() {
+            max_abs[i % channels] = max_abs[i % channels].max(v.abs());
+        }
+        let scales: Vec<f32> = max_abs.iter().map(|&m| if m > 0.0 { m / 127.0 } else { 1.0 }).collect();
+        let data = tensor.data.iter().enumerate()
+            .map(|(i, v)| (v / scales[i % channels]).round_ties_even().clamp(-127.0, 127.0) as i8)
+            .collect();
+        QuantizedWeights { shape: tensor.shape.clone(), data, scales }
+    }
+    
+    /// Scale of an output channel
+    pub fn scale(&self, channel: usize) -> f32 {
+        self.scales[channel % self.scales.len()]
+    }
+}
+
+/// Int8 kernel of a step
+#[derive(Debug, Clone, PartialEq)]
+pub enum Kernel {
+    // Fully connected on the last axis, also the projection of `U` on the flattened input
+    Dense,
+    Conv2d(Window),
+    MaxPool(Window),
+    Upsample(usize),
+    Reshape,
+}
+
+/// One step of a quantized component
+#[derive(Debug, Clone)]
+pub struct QuantizedStep {
+    // Parameter prefix, as in `Step`
+    pub key: String,
+    pub kernel: Kernel,
+    pub activation: ActivationFunction,
+    pub input_shape: Vec<usize>,
+    pub output_shape: Vec<usize>,
+    pub weights: Option<QuantizedWeights>,
+    // Bias in accumulator units (input scale × weight scale), per output channel
+    pub bias: Vec<i32>,
+    // Input scale × weight scale / output scale, per output channel
+    pub multipliers: Vec<f32>,
+    pub input: Affine,
+    pub output: Affine,
+}
+
+/// A component with int8 weights and activations; inputs and outputs are float
+#[derive(Debug, Clone)]
+pub struct QuantizedModel {
+    pub component: String,
+    pub granularity: Granularity,
+    pub input_shape: Vec<usize>,
+    pub input: Affine,
+    pub steps: Vec<QuantizedStep>,
+}
+
+/// Post-training quantization of one component: weights are scaled per tensor
+/// or per channel, activations with ranges observed on the calibration inputs
+pub fn quantize(interpreter: &Interpreter, component: &str, calibration: &[Tensor], granularity: Granularity) -> Result<QuantizedModel, String> {
+    let plan = interpreter.plan(component).ok_or_else(|| format!("unknown component `{}`", component))?;
+    if calibration.is_empty() {
+        return Err("the calibration set is empty".to_string());
+    }
+    
+    let kernels = plan.steps.iter()
+        .map(|step| kernel_of(&step.layer).ok_or_else(|| format!("`{}` in `{}` has no int8 kernel", step.layer.symbol(), component)))
+        .collect::<Result<Vec<_>, _>>()?;
+    
+    // Range of the input and of every step output
+    let mut ranges = vec![(f32::INFINITY, f32::NEG_INFINITY); plan.steps.len() + 1];
+    for input in calibration {
+        for (range, tensor) in ranges.iter_mut().zip(interpreter.trace(component, input)?) {
+            for &v in &tensor.data {
+                *range = (range.0.min(v), range.1.max(v));
+            }
+        }
+    }
+    
+    let input = Affine::from_range(ranges[0].0, ranges[0].1);
+    let mut current = input;
+    let mut steps: Vec<QuantizedStep> = Vec::new();
+    for ((step, kernel), range) in plan.steps.iter().zip(kernels).zip(&ranges[1..]) {
+        let layer = &step.layer;
+        // Repetitions of a shared block see other ranges, so they get their
+        // own scales and biases under `key_r2`, `key_r3`…
+        let uses = plan.steps.iter().take(steps.len()).filter(|s| s.key == step.key).count();
+        let key = if uses == 0 { step.key.clone() } else { format!("{}_r{}", step.key, uses + 1) };
+        
+        let calibrated = Affine::from_range(range.0, range.1);
+        let mut quantized = QuantizedStep {
+            key,
+            kernel,
+            activation: layer.activation.clone(),
+            input_shape: step.input.dims.clone(),
+            output_shape: step.output.dims.clone(),
+            weights: None,
+            bias: Vec::new(),
+            multipliers: Vec::new(),
+            input: current,
+            // Layers that only move values keep the input scale
+            output: if layer.activation == ActivationFunction::None { current } else { calibrated },
+        };
+        if matches!(quantized.kernel, Kernel::Dense | Kernel::Conv2d(_)) {
+            let weight = require(interpreter.weights(), &format!("{}.weight", step.key))?;
+            let bias = require(interpreter.weights(), &format!("{}.bias", step.key))?;
+            let weights = QuantizedWeights::quantize(weight, granularity);
+            quantized.output = calibrated;
+            quantized.bias = bias.data.iter().enumerate()
+                .map(|(o, b)| (b / (current.scale * weights.scale(o))).round_ties_even() as i32)
+                .collect();
+            quantized.multipliers = (0..bias.len()).map(|o| current.scale * weights.scale(o) / calibrated.scale).collect();
+            quantized.weights = Some(weights);
+        }
+        current = quantized.output;
+        steps.push(quantized);
+    }
+    
+    Ok(QuantizedModel {
+        component: component.to_string(),
+        granularity,
+        input_shape: plan.input.dims.clone(),
+        input,
+        steps,
+    })
+}
+
+fn kernel_of(layer: &LayerNode) -> Option<Kernel> {
+    Some(match layer.layer_type {
+        LayerType::Dense(_) => Kernel::Dense,
+        LayerType::Convolutional(_) => Kernel::Conv2d(layer.window()?),
+        LayerType::Upsampling if target_shape(layer).is_some() => Kernel::Dense,
+        LayerType::Upsampling => Kernel::Upsample(layer.param(FACTOR)),
+        LayerType::Pooling => Kernel::MaxPool(layer.window()?),
+        LayerType::Flatten | LayerType::Reshape | LayerType::AttentionHeads | LayerType::BatchSize => Kernel::Reshape,
+        LayerType::TransposeConv | LayerType::LSTM | LayerType::Embedding | LayerType::Attention |
+        LayerType::Custom(_) => return None,
+    })
+}
+
+fn require<'a>(weights: &'a Weights, name: &str) -> Result<&'a Tensor, String> {
+    weights.get(name).ok_or_else(|| format!("missing weight `{}`", name))
+}
+
+impl QuantizedModel {
+    /// Run a single example: the input is quantized, every layer runs on int8
+    /// values and the output is dequantized
+    pub fn forward(&self, input: &Tensor) -> Result<Tensor, String> {
+        let expected: usize = self.input_shape.iter().product();
+        if input.len() != expected {
+            return Err(format!("component `{}` expects {} inputs, got {:?}", self.component, expected, input.shape));
+        }
+        let mut x: Vec<i8> = input.data.iter().map(|&v| self.input.quantize(v)).collect();
+        for step in &self.steps {
+            x = step.forward(&x);
+        }
+        let (output, shape) = match self.steps.last() {
+            Some(step) => (step.output, step.output_shape.clone()),
+            None => (self.input, self.input_shape.clone()),
+        };
+        Tensor::new(shape, x.iter().map(|&q| output.dequantize(q)).collect())
+    }
+    
+    /// Number of weights
+    pub fn parameters(&self) -> usize {
+        self.steps.iter().map(|s| s.weights.as_ref().map(|w| w.data.len()).unwrap_or(0) + s.bias.len()).sum()
+    }
+    
+    /// Bytes of int8 weights plus int32 biases and f32 scales
+    pub fn size(&self) -> usize {
+        self.steps.iter()
+            .map(|s| {
+                let weights = s.weights.as_ref().map(|w| w.data.len() + 4 * w.scales.len()).unwrap_or(0);
+                weights + 4 * (s.bias.len() + s.multipliers.len())
+            })
+            .sum()
+    }
+}
+
+impl QuantizedStep {
+    fn forward(&self, x: &[i8]) -> Vec<i8> {
+        match &self.kernel {
+            Kernel::Dense | Kernel::Conv2d(_) => {
+                let weights = self.weights.as_ref().unwrap();
+                let acc = match self.kernel {
+                    Kernel::Conv2d(window) => conv2d_i8(x, &self.input_shape, weights, &self.bias, self.input.zero_point, window),
+                    _ => dense_i8(x, weights, &self.bias, self.input.zero_point),
+                };
+                let c_out = self.bias.len();
+                match self.activation {
+                    ActivationFunction::None | ActivationFunction::ReLU => {
+                        let relu = self.activation == ActivationFunction::ReLU;
+                        acc.iter().enumerate()
+                            .map(|(i, &a)| {
+                                let q = requantize(a, self.multipliers[i % c_out], self.output.zero_point);
+                                if relu { q.max(self.output.zero_point as i8) } else { q }
+                            })
+                            .collect()
+                    },
+                    // Other activations run on the dequantized accumulators
+                    _ => {
+                        let real = acc.iter().enumerate()
+                            .map(|(i, &a)| a as f32 * self.input.scale * weights.scale(i % c_out))
+                            .collect();
+                        self.activate_real(real)
+                    },
+                }
+            },
+            Kernel::MaxPool(window) => self.move_values(x, |t| max_pool2d(t, *window)),
+            Kernel::Upsample(factor) => self.move_values(x, |t| upsample_nearest(t, *factor)),
+            Kernel::Reshape => self.move_values(x, |t| t.clone()),
+        }
+    }
+    
+    // Pooling, upsampling and reshaping only move values, so they run on the
+    // integers unchanged (exactly representable as f32)
+    fn move_values(&self, x: &[i8], op: impl Fn(&Tensor) -> Tensor) -> Vec<i8> {
+        let moved = op(&Tensor { shape: self.input_shape.clone(), data: x.iter().map(|&q| q as f32).collect() });
+        if self.activation == ActivationFunction::None {
+            return moved.data.iter().map(|&v| v as i8).collect();
+        }
+        let real = moved.data.iter().map(|&v| self.input.dequantize(v as i8)).collect();
+        self.activate_real(real)
+    }
+    
+    fn activate_real(&self, real: Vec<f32>) -> Vec<i8> {
+        let y = activate(Tensor { shape: self.output_shape.clone(), data: real }, &self.activation);
+        y.data.iter().map(|&v| self.output.quantize(v)).collect()
+    }
+}
+
+/// Int8 fully connected layer on the last axis with int32 accumulators;
+/// `w` is `[in, out]` and `x_zero` the zero point of the input
+pub fn dense_i8(x: &[i8], w: &QuantizedWeights, bias: &[i32], x_zero: i32) -> Vec<i32> {
+    let (n_in, n_out) = (w.shape[0], w.shape[1]);
+    let mut acc = Vec::with_capacity(x.len() / n_in * n_out);
+    for row in x.chunks(n_in) {
+        let mut out = bias.to_vec();
+        for (i, &xi) in row.iter().enumerate() {
+            let xi = xi as i32 - x_zero;
+            for (o, &wv) in out.iter_mut().zip(&w.data[i * n_out..(i + 1) * n_out]) {
+                *o += xi * wv as i32;
+            }
+        }
+        acc.extend(out);
+    }
+    acc
+}
+
+/// Int8 convolution sliding `window`; `w` is `[k, k, in, out]`
+/// and padding reads as the input zero point, contributing nothing
+pub fn conv2d_i8(x: &[i8], shape: &[usize], w: &QuantizedWeights, bias: &[i32], x_zero: i32, window: Window) -> Vec<i32> {
+    let (h, wd, c_in) = (shape[0], shape[1], shape[2]);
+    let (k, c_out) = (w.shape[0], w.shape[3]);
+    let (oh, ow) = (window.positions(h).unwrap_or(0), window.positions(wd).unwrap_or(0));
+    let (pad_y, pad_x) = (window.padding(h).0 as isize, window.padding(wd).0 as isize);
+    let mut acc = Vec::with_capacity(oh * ow * c_out);
+    for oy in 0..oh {
+        for ox in 0..ow {
+            let mut out = bias.to_vec();
+            for ky in 0..k {
+                let iy = (oy * window.stride + ky) as isize - pad_y;
+                if iy < 0 || iy >= h as isize {
+                    continue;
+                }
+                for kx in 0..k {
+                    let ix = (ox * window.stride + kx) as isize - pad_x;
+                    if ix < 0 || ix >= wd as isize {
+                        continue;
+                    }
+                    let src = &x[(iy as usize * wd + ix as usize) * c_in..][..c_in];
+                    for (ci, &xv) in src.iter().enumerate() {
+                        let xv = xv as i32 - x_zero;
+                        let w_row = &w.data[((ky * k + kx) * c_in + ci) * c_out..][..c_out];
+                        for (o, &wv) in out.iter_mut().zip(w_row) {
+                            *o += xv * wv as i32;
+                        }
+                    }
+                }
+            }
+            acc.extend(out);
+        }
+    }
+    acc
+}
+
+/// Scale an int32 accumulator to int8, rounding half to even like the WASM
+/// and asm kernels
+pub fn requantize(acc: i32, multiplier: f32, zero_point: i32) -> i8 {
+    ((acc as f32 * multiplier).round_ties_even() + zero_point as f32).clamp(-128.0, 127.0) as i8
+}
+
+/// Difference between the float and int8 outputs of a component
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccuracyReport {
+    pub examples: usize,
+    pub max_abs_diff: f32,
+    pub mean_abs_diff: f32,
+    // Share of examples where both pick the same class
+    pub agreement: f32,
+    // Accuracy against the targets, when the set has them
+    pub float_accuracy: Option<f32>,
+    pub int8_accuracy: Option<f32>,
+}
+
+// Class of an output: the largest value, or whether a single output is above 0.5
+fn class_of(output: &Tensor) -> usize {
+    if output.len() == 1 {
+        (output.data[0] > 0.5) as usize
+    } else {
+        output.argmax()
+    }
+}
+
+/// Compare the int8 model with the float interpreter on the given inputs
+pub fn evaluate(interpreter: &Interpreter, model: &QuantizedModel, inputs: &[Tensor], targets: Option<&[Tensor]>) -> Result<AccuracyReport, String> {
+    let (mut max_abs_diff, mut total_diff, mut values, mut same) = (0.0f32, 0.0f64, 0usize, 0usize);
+    let (mut float_correct, mut int8_correct) = (0usize, 0usize);
+    for (i, input) in inputs.iter().enumerate() {
+        let expected = interpreter.forward(&model.component, input)?;
+        let actual = model.forward(input)?;
+        for (a, b) in expected.data.iter().zip(&actual.data) {
+            max_abs_diff = max_abs_diff.max((a - b).abs());
+            total_diff += (a - b).abs() as f64;
+        }
+        values += expected.len();
+        same += (class_of(&expected) == class_of(&actual)) as usize;
+        if let Some(target) = targets.and_then(|t| t.get(i)) {
+            float_correct += (class_of(&expected) == class_of(target)) as usize;
+            int8_correct += (class_of(&actual) == class_of(target)) as usize;
+        }
+    }
+    
+    let n = inputs.len().max(1) as f32;
+    let accuracy = |correct: usize| targets.map(|_| correct as f32 / n);
+    Ok(AccuracyReport {
+        examples: inputs.len(),
+        max_abs_diff,
+        mean_abs_diff: (total_diff / values.max(1) as f64) as f32,
+        agreement: same as f32 / n,
+        float_accuracy: accuracy(float_correct),
+        int8_accuracy: accuracy(int8_correct),
+    })
+}
+
+/// Quantized components with their reports, and the components left as float
+/// with the reason
+pub struct QuantizedNetwork {
+    pub models: Vec<(QuantizedModel, AccuracyReport)>,
+    pub skipped: Vec<(String, String)>,
+}
+
+/// Quantize the components of a network with the rows of a calibration CSV
+/// and compare each with the float model; rows that hold targets after the
+/// inputs also give the accuracy of both. Components the rows do not fit, or
+/// with layers that have no int8 kernel, are skipped with the reason.
+pub fn quantize_network(interpreter: &Interpreter, csv: &str, component: Option<&str>, granularity: Granularity) -> QuantizedNetwork {
+    let mut network = QuantizedNetwork { models: Vec::new(), skipped: Vec::new() };
+    for plan in interpreter.plans() {
+        if component.is_some_and(|c| c != plan.component) {
+            continue;
+        }
+        let output = plan.steps.last().map(|s| s.output.clone()).unwrap_or_else(|| plan.input.clone());
+        let result = match Dataset::from_csv(csv, &plan.input, &output) {
+            Ok(dataset) => {
+                let (inputs, targets): (Vec<Tensor>, Vec<Tensor>) = dataset.examples.into_iter().unzip();
+                Ok((inputs, Some(targets)))
+            },
+            Err(_) => Dataset::inputs_from_csv(csv, &plan.input).map(|inputs| (inputs, None)),
+        };
+        let result = result.and_then(|(inputs, targets)| {
+            let model = quantize(interpreter, &plan.component, &inputs, granularity)?;
+            let report = evaluate(interpreter, &model, &inputs, targets.as_deref())?;
+            Ok((model, report))
+        });
+        match result {
+            Ok(entry) => network.models.push(entry),
+            Err(e) => network.skipped.push((plan.component.clone(), e)),
+        }
+    }
+    network
+}
+
+impl fmt::Display for AccuracyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "int8 vs float on {} examples: max |Δ| {:.6}, mean |Δ| {:.6}, same class {:.1}%",
+                 self.examples, self.max_abs_diff, self.mean_abs_diff, 100.0 * self.agreement)?;
+        if let (Some(float), Some(int8)) = (self.float_accuracy, self.int8_accuracy) {
+            writeln!(f, "accuracy: float {:.1}%, int8 {:.1}% ({:+.1} points)", 100.0 * float, 100.0 * int8, 100.0 * (int8 - float))?;
+        }
+        Ok(())
+    }
+}
+
+/// Safetensors container of quantized components, named like the float
+/// weights: `η.Ñ.C₁.kernel` (I8), `kernel_scale`, `bias` (I32), `multiplier`,
+/// `output_scale` and `output_zero_point`, plus `η.Ñ.input_scale` and
+/// `η.Ñ.input_zero_point`. Four-byte tensors come first so they stay aligned.
+pub fn encode(models: &[QuantizedModel]) -> (Vec<u8>, Vec<Entry>) {
+    let floats = |name: String, values: &[f32]| RawTensor {
+        name, dtype: "F32", shape: vec![values.len()], bytes: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+    };
+    let ints = |name: String, values: &[i32]| RawTensor {
+        name, dtype: "I32", shape: vec![values.len()], bytes: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+    };
+    let (mut aligned, mut bytes) = (Vec::new(), Vec::new());
+    for model in models {
+        let prefix = format!("{}.{}", safetensors::NAMESPACE, model.component);
+        aligned.push(floats(format!("{}.input_scale", prefix), &[model.input.scale]));
+        aligned.push(ints(format!("{}.input_zero_point", prefix), &[model.input.zero_point]));
+        for step in &model.steps {
+            let prefix = format!("{}.{}", safetensors::NAMESPACE, step.key);
+            if let Some(weights) = &step.weights {
+                aligned.push(floats(format!("{}.kernel_scale", prefix), &weights.scales));
+                aligned.push(ints(format!("{}.bias", prefix), &step.bias));
+                aligned.push(floats(format!("{}.multiplier", prefix), &step.multipliers));
+                bytes.push(RawTensor {
+                    name: format!("{}.kernel", prefix),
+                    dtype: "I8",
+                    shape: weights.shape.clone(),
+                    bytes: weights.data.iter().map(|&q| q as u8).collect(),
+                });
+            }
+            aligned.push(floats(format!("{}.output_scale", prefix), &[step.output.scale]));
+            aligned.push(ints(format!("{}.output_zero_point", prefix), &[step.output.zero_point]));
+        }
+    }
+    aligned.extend(bytes);
+    let granularity = models.first().map(|m| m.granularity.name()).unwrap_or("per-channel");
+    safetensors::encode_raw(aligned, json!({ "format": FORMAT, "namespace": safetensors::NAMESPACE, "granularity": granularity }))
+}
+
+/// `format` metadata of int8 containers
+pub const FORMAT: &str = "gaiascript-int8";
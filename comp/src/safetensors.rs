@@ -0,0 +1,198 @@
+// Safetensors weights container: an 8-byte little-endian header length, a
+// JSON header mapping tensor names to dtype, shape and byte offsets, then the
+// raw little-endian data
+
+use crate::ast::ASTNode;
+use crate::custom_ops;
+use crate::interpreter::{plan_network, Tensor, Weights};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Namespace of neural network parameters in qualified names
+pub const NAMESPACE: &str = "η";
+
+/// Qualified name of a parameter, e.g. `Ñ.C₁.weight` becomes `η.Ñ.C₁.kernel`
+pub fn qualified_name(key: &str) -> String {
+    let (layer, param) = key.rsplit_once('.').unwrap_or((key, ""));
+    let param = match param {
+        "weight" => "kernel",
+        "recurrent" => "recurrent_kernel",
+        other => other,
+    };
+    format!("{}.{}.{}", NAMESPACE, layer, param)
+}
+
+/// Parameter key of a qualified name, the inverse of `qualified_name`
+pub fn parameter_key(name: &str) -> Result<String, String> {
+    let rest = name.strip_prefix(NAMESPACE).and_then(|r| r.strip_prefix('.'))
+        .ok_or_else(|| format!("weight `{}` is not in the `{}` namespace", name, NAMESPACE))?;
+    let (layer, param) = rest.rsplit_once('.')
+        .filter(|(layer, _)| layer.contains('.'))
+        .ok_or_else(|| format!("weight `{}` is not named `{}.component.layer.parameter`", name, NAMESPACE))?;
+    let param = match param {
+        "kernel" => "weight",
+        "recurrent_kernel" => "recurrent",
+        "bias" => "bias",
+        // Attention output projection
+        "output" => "output",
+        "output_bias" => "output_bias",
+        // Custom operations name their own parameters
+        other if layer.rsplit('.').next().is_some_and(|l| l.starts_with(custom_ops::MARKER)) => other,
+        other => return Err(format!("weight `{}` has unknown parameter `{}`", name, other)),
+    };
+    Ok(format!("{}.{}", layer, param))
+}
+
+/// Where one tensor lives in an encoded container
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub dtype: &'static str,
+    pub shape: Vec<usize>,
+    // Offset of the first byte from the start of the container
+    pub offset: usize,
+    pub len: usize,
+}
+
+/// A tensor to store as little-endian bytes of `dtype` (`F32`, `I32`, `I8`, …)
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawTensor {
+    pub name: String,
+    pub dtype: &'static str,
+    pub shape: Vec<usize>,
+    pub bytes: Vec<u8>,
+}
+
+/// Encode tensors in the given order; returns the container and the
+/// position of every tensor in it
+pub fn encode_raw(tensors: Vec<RawTensor>, metadata: Value) -> (Vec<u8>, Vec<Entry>) {
+    let mut header = serde_json::Map::new();
+    header.insert("__metadata__".to_string(), metadata);
+    let mut offset = 0;
+    for tensor in &tensors {
+        let end = offset + tensor.bytes.len();
+        header.insert(tensor.name.clone(), json!({ "dtype": tensor.dtype, "shape": tensor.shape, "data_offsets": [offset, end] }));
+        offset = end;
+    }
+    let mut header = Value::Object(header).to_string().into_bytes();
+    // Pad with spaces so the data starts 8-byte aligned
+    while !header.len().is_multiple_of(8) {
+        header.push(b' ');
+    }
+    
+    let start = 8 + header.len();
+    let mut bytes = Vec::with_capacity(start + offset);
+    bytes.extend_from_slice(&(header.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&header);
+    let mut entries = Vec::new();
+    for tensor in tensors {
+        entries.push(Entry { name: tensor.name, dtype: tensor.dtype, shape: tensor.shape, offset: bytes.len(), len: tensor.bytes.len() });
+        bytes.extend(tensor.bytes);
+    }
+    (bytes, entries)
+}
+
+/// Encode weights as F32 tensors in name order
+pub fn encode_with_layout(weights: &Weights) -> (Vec<u8>, Vec<Entry>) {
+    let tensors: BTreeMap<String, &Tensor> = weights.iter().map(|(key, t)| (qualified_name(key), t)).collect();
+    let tensors = tensors.into_iter()
+        .map(|(name, tensor)| RawTensor {
+            name,
+            dtype: "F32",
+            shape: tensor.shape.clone(),
+            bytes: tensor.data.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        })
+        .collect();
+    encode_raw(tensors, json!({ "format": "gaiascript", "namespace": NAMESPACE }))
+}
+
+pub fn encode(weights: &Weights) -> Vec<u8> {
+    encode_with_layout(weights).0
+}
+
+/// Decode a container; F16, BF16 and F64 tensors are converted to f32
+pub fn decode(bytes: &[u8]) -> Result<Weights, String> {
+    let header_len = bytes.get(..8).ok_or("the safetensors file is shorter than its header length")?;
+    let header_len = u64::from_le_bytes(header_len.try_into().unwrap()) as usize;
+    let start = header_len.checked_add(8).filter(|&s| s <= bytes.len())
+        .ok_or("the safetensors header runs past the end of the file")?;
+    let header: Value = serde_json::from_slice(&bytes[8..start]).map_err(|e| format!("invalid safetensors header: {}", e))?;
+    let header = header.as_object().ok_or("the safetensors header is not a JSON object")?;
+    let data = &bytes[start..];
+    
+    let mut weights = Weights::new();
+    for (name, entry) in header {
+        if name == "__metadata__" {
+            continue;
+        }
+        let shape: Vec<usize> = serde_json::from_value(entry["shape"].clone())
+            .map_err(|e| format!("weight `{}`: invalid shape: {}", name, e))?;
+        let offsets: [usize; 2] = serde_json::from_value(entry["data_offsets"].clone())
+            .map_err(|e| format!("weight `{}`: invalid data_offsets: {}", name, e))?;
+        let raw = data.get(offsets[0]..offsets[1]).filter(|_| offsets[0] <= offsets[1])
+            .ok_or_else(|| format!("weight `{}`: data_offsets {:?} are outside the data", name, offsets))?;
+        let values: Vec<f32> = match entry["dtype"].as_str().unwrap_or("") {
+            "F32" => raw.chunks_exact(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect(),
+            "F64" => raw.chunks_exact(8).map(|c| f64::from_le_bytes(c.try_into().unwrap()) as f32).collect(),
+            "F16" => raw.chunks_exact(2).map(|c| f16_to_f32(u16::from_le_bytes([c[0], c[1]]))).collect(),
+            "BF16" => raw.chunks_exact(2).map(|c| f32::from_bits((u16::from_le_bytes([c[0], c[1]]) as u32) << 16)).collect(),
+            other => return Err(format!("weight `{}` has unsupported dtype `{}`", name, other)),
+        };
+        let tensor = Tensor::new(shape, values).map_err(|e| format!("weight `{}`: {}", name, e))?;
+        weights.insert(parameter_key(name)?, tensor);
+    }
+    Ok(weights)
+}
+
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => sign * f32::INFINITY,
+        31 => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+pub fn read(path: &str) -> Result<Weights, String> {
+    let bytes = fs::read(path).map_err(|e| format!("failed to read '{}': {}", path, e))?;
+    decode(&bytes).map_err(|e| format!("invalid weights file '{}': {}", path, e))
+}
+
+pub fn write(path: &str, weights: &Weights) -> Result<(), String> {
+    fs::write(path, encode(weights)).map_err(|e| format!("failed to write '{}': {}", path, e))
+}
+
+/// Check weights against the inferred shapes of a network, listing every
+/// missing, misshapen or unused tensor
+pub fn validate(weights: &Weights, ast: &ASTNode) -> Result<(), String> {
+    let mut expected = BTreeMap::new();
+    for plan in plan_network(ast)? {
+        expected.extend(plan.parameter_shapes());
+    }
+    
+    let mut problems = Vec::new();
+    for (key, shape) in &expected {
+        match weights.get(key) {
+            None => problems.push(format!("missing `{}` {:?}", qualified_name(key), shape)),
+            Some(tensor) if &tensor.shape != shape => {
+                problems.push(format!("`{}` has shape {:?}, expected {:?}", qualified_name(key), tensor.shape, shape));
+            },
+            Some(_) => {},
+        }
+    }
+    for (key, tensor) in weights.iter() {
+        if !expected.contains_key(key) {
+            problems.push(format!("`{}` {:?} does not belong to any layer", qualified_name(key), tensor.shape));
+        }
+    }
+    
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} weights do not match the network:\n  {}", problems.len(), problems.join("\n  ")))
+    }
+}
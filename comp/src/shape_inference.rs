@@ -0,0 +1,366 @@
+use crate::ast::*;
+use crate::diagnostics::{codes, Diagnostic, Span};
+use crate::layer_params::{self, DIM, FACTOR, FILTERS, HEADS, STRIDE, target_shape, UNITS};
+use std::collections::HashMap;
+
+// Layer defaults used when a parameter is omitted in the source
+pub const DEFAULT_FILTERS: usize = 32;
+pub const DEFAULT_KERNEL: usize = 3;
+pub const DEFAULT_UNITS: usize = 128;
+pub const DEFAULT_POOL: usize = 2;
+pub const DEFAULT_UPSAMPLE: usize = 2;
+pub const DEFAULT_EMBEDDING_DIM: usize = 128;
+pub const DEFAULT_VOCABULARY: usize = 10000;
+pub const DEFAULT_TRANSPOSE_STRIDE: usize = 2;
+
+/// Shape assumed for an input declared without dimensions
+pub fn default_input_shape(input_type: &InputType) -> TensorShape {
+    match input_type {
+        InputType::Image => TensorShape::new(vec![224, 224, 3]),
+        InputType::Text => TensorShape::new(vec![128]),
+        InputType::Sequence => TensorShape::new(vec![100, 1]),
+        InputType::Latent => TensorShape::new(vec![100]),
+    }
+}
+
+/// Concrete shape of an input; the flag is true when defaults had to be used
+pub fn input_shape(input: &InputNode) -> (TensorShape, bool) {
+    let params = &input.params;
+    if params.is_empty() {
+        return (default_input_shape(&input.input_type), true);
+    }
+    
+    let dims = match input.input_type {
+        // A two-dimensional image is a single-channel image
+        InputType::Image if params.len() == 2 => vec![params[0], params[1], 1],
+        // A sequence without a feature size carries one feature per step
+        InputType::Sequence if params.len() == 1 => vec![params[0], 1],
+        _ => params.clone(),
+    };
+    (TensorShape::new(dims), false)
+}
+
+fn rank_error(layer: &LayerNode, expected: &str, input: &TensorShape) -> Diagnostic {
+    Diagnostic::error(codes::SHAPE_RANK_MISMATCH, format!("`{}` expects {} input, found {}", layer.symbol(), expected, input))
+        .with_primary(layer.span, format!("input has rank {}", input.rank()))
+}
+
+fn spatial_error(layer: &LayerNode, window: usize, input: &TensorShape) -> Diagnostic {
+    Diagnostic::error(codes::SHAPE_TOO_SMALL, format!("`{}` window of {} does not fit input {}", layer.symbol(), window, input))
+        .with_primary(layer.span, format!("window {}×{} is larger than {}×{}", window, window, input.dims[0], input.dims[1]))
+}
+
+/// Output shape of a single layer applied to `input`
+///
+/// Convolutions and pooling slide their window with its stride and padding,
+/// by default "same" padding with stride 1 for convolutions and
+/// non-overlapping windows for pooling; dense layers act on the last axis.
+pub fn layer_output_shape(layer: &LayerNode, input: &TensorShape) -> Result<TensorShape, Diagnostic> {
+    let dims = &input.dims;
+    let output = match layer.layer_type {
+        LayerType::Convolutional(_) => {
+            if input.rank() != 3 {
+                return Err(rank_error(layer, "a height×width×channels", input));
+            }
+            let window = layer.window().unwrap();
+            match (window.positions(dims[0]), window.positions(dims[1])) {
+                (Some(height), Some(width)) if window.size <= dims[0] && window.size <= dims[1] => {
+                    vec![height, width, layer.param(FILTERS)]
+                },
+                _ => return Err(spatial_error(layer, window.size, input)),
+            }
+        },
+        LayerType::TransposeConv => {
+            if input.rank() != 3 {
+                return Err(rank_error(layer, "a height×width×channels", input));
+            }
+            let stride = layer.param(STRIDE);
+            vec![dims[0] * stride, dims[1] * stride, layer.param(FILTERS)]
+        },
+        LayerType::Pooling => {
+            if input.rank() != 3 {
+                return Err(rank_error(layer, "a height×width×channels", input));
+            }
+            let window = layer.window().unwrap();
+            match (window.positions(dims[0]), window.positions(dims[1])) {
+                (Some(height), Some(width)) if window.size <= dims[0] && window.size <= dims[1] => {
+                    vec![height, width, dims[2]]
+                },
+                _ => return Err(spatial_error(layer, window.size, input)),
+            }
+        },
+        LayerType::Flatten => vec![input.elements()],
+        LayerType::Dense(_) => {
+            if input.rank() == 0 {
+                return Err(rank_error(layer, "a non-scalar", input));
+            }
+            let mut out = dims.clone();
+            *out.last_mut().unwrap() = layer.param(UNITS);
+            out
+        },
+        LayerType::Upsampling => {
+            if let Some(shape) = target_shape(layer) {
+                // Explicit target shape: project and reshape (e.g. `U 4×4×512` after a latent)
+                shape.to_vec()
+            } else {
+                if input.rank() != 3 {
+                    return Err(rank_error(layer, "a height×width×channels", input));
+                }
+                let factor = layer.param(FACTOR);
+                vec![dims[0] * factor, dims[1] * factor, dims[2]]
+            }
+        },
+        LayerType::Reshape => {
+            if let Some(shape) = target_shape(layer) {
+                let target = TensorShape::new(shape.to_vec());
+                if target.elements() != input.elements() {
+                    return Err(Diagnostic::error(codes::SHAPE_ELEMENT_MISMATCH,
+                            format!("cannot reshape {} ({} elements) into {} ({} elements)",
+                                    input, input.elements(), target, target.elements()))
+                        .with_primary(layer.span, "element counts differ"));
+                }
+                target.dims
+            } else {
+                dims.clone()
+            }
+        },
+        LayerType::LSTM => {
+            if input.rank() != 2 {
+                return Err(rank_error(layer, "a steps×features", input));
+            }
+            vec![layer.param(UNITS)]
+        },
+        LayerType::Embedding => {
+            if input.rank() != 1 {
+                return Err(rank_error(layer, "a sequence of token ids", input));
+            }
+            vec![dims[0], layer.param(DIM)]
+        },
+        LayerType::Attention => {
+            if input.rank() != 2 {
+                return Err(rank_error(layer, "a tokens×features", input));
+            }
+            dims.clone()
+        },
+        LayerType::AttentionHeads | LayerType::BatchSize => dims.clone(),
+        LayerType::Custom(ref op) => {
+            let output = op.output_shape(input, &layer.resolved_params()).map_err(|reason| {
+                Diagnostic::error(codes::CUSTOM_OP_REJECTED, format!("`{}` does not accept input {}", layer.symbol(), input))
+                    .with_primary(layer.span, reason)
+            })?;
+            output.dims
+        },
+    };
+    Ok(TensorShape::new(output))
+}
+
+/// Propagate shapes through every component, annotating each `LayerNode`
+pub fn infer_shapes(ast: &mut ASTNode) -> Vec<Diagnostic> {
+    let mut inference = ShapeInference::new();
+    inference.infer(ast, None);
+    inference.diagnostics
+}
+
+struct ShapeInference {
+    diagnostics: Vec<Diagnostic>,
+    // Input and output shapes of each component, used to check `⊳` injections
+    component_inputs: HashMap<String, TensorShape>,
+    component_outputs: HashMap<String, TensorShape>,
+    // Heads of the last `H` in the current component, used by the following attention layers
+    heads: Option<(usize, Span)>,
+}
+
+impl ShapeInference {
+    fn new() -> Self {
+        ShapeInference {
+            diagnostics: Vec::new(),
+            component_inputs: HashMap::new(),
+            component_outputs: HashMap::new(),
+            heads: None,
+        }
+    }
+    
+    fn infer(&mut self, node: &mut ASTNode, input: Option<TensorShape>) -> Option<TensorShape> {
+        match node {
+            ASTNode::Network(network) => {
+                for child in network.body.iter_mut() {
+                    self.infer(child, None);
+                }
+                None
+            },
+            ASTNode::Component(component) => {
+                self.heads = None;
+                let output = self.infer(&mut component.expr, None);
+                // Inputs without dimensions accept whatever is injected into them
+                if let Some((shape, false)) = first_input(&component.expr).map(input_shape) {
+                    self.component_inputs.insert(component.id.clone(), shape);
+                    if let Some(output) = &output {
+                        self.component_outputs.insert(component.id.clone(), output.clone());
+                    }
+                }
+                output
+            },
+            ASTNode::Input(input_node) => self.infer_input(input_node, input),
+            ASTNode::Layer(layer) => self.infer_layer(layer, input),
+            ASTNode::DataFlow(from, to) => {
+                let shape = self.infer(from, input);
+                self.infer(to, shape)
+            },
+            ASTNode::Block(block) => self.infer_block(block, input),
+            ASTNode::Expression(nodes) => {
+                let mut shape = input;
+                for child in nodes.iter_mut() {
+                    shape = self.infer(child, shape);
+                }
+                shape
+            },
+            ASTNode::Loss(loss) => {
+                // Follow the composite stage by stage; a target whose input
+                // adapts to what it receives ends the check
+                let mut output = self.infer(&mut loss.from, None);
+                for target in &loss.targets {
+                    let (Some(shape), Some(expected)) = (&output, self.component_inputs.get(target)) else {
+                        return None;
+                    };
+                    if shape != expected {
+                        self.diagnostics.push(
+                            Diagnostic::error(codes::SHAPE_INJECTION_MISMATCH,
+                                    format!("cannot inject {} into `{}`, which expects {}", shape, target, expected))
+                                .with_primary(loss.span, format!("produces {}", shape))
+                        );
+                        return None;
+                    }
+                    output = self.component_outputs.get(target).cloned();
+                }
+                output
+            },
+            ASTNode::UIComponent(_) | ASTNode::EventHandler(_) | ASTNode::DataBinding(_) |
+            ASTNode::ThreeDComponent(_) | ASTNode::Asset(_) | ASTNode::Raw(_) => None,
+        }
+    }
+    
+    fn infer_input(&mut self, input_node: &InputNode, incoming: Option<TensorShape>) -> Option<TensorShape> {
+        let (declared, defaulted) = input_shape(input_node);
+        
+        match incoming {
+            // An inlined component adopts the shape of whatever feeds it
+            Some(incoming) if defaulted => Some(incoming),
+            Some(incoming) => {
+                if incoming != declared {
+                    self.diagnostics.push(
+                        Diagnostic::error(codes::SHAPE_INJECTION_MISMATCH,
+                                format!("input declared as {} receives {}", declared, incoming))
+                            .with_primary(input_node.span, format!("expects {}", declared))
+                    );
+                }
+                Some(declared)
+            },
+            None => {
+                if defaulted {
+                    self.diagnostics.push(
+                        Diagnostic::warning(codes::SHAPE_DEFAULTED, format!("input has no dimensions, assuming {}", declared))
+                            .with_primary(input_node.span, "add dimensions, e.g. `I 28×28×1`")
+                    );
+                }
+                Some(declared)
+            },
+        }
+    }
+    
+    fn infer_layer(&mut self, layer: &mut LayerNode, input: Option<TensorShape>) -> Option<TensorShape> {
+        layer.input_shape = input.clone();
+        layer.output_shape = None;
+        let invalid = layer_params::check(layer);
+        if !invalid.is_empty() {
+            self.diagnostics.extend(invalid);
+            return None;
+        }
+        
+        let input = match input {
+            Some(input) => input,
+            None => {
+                self.diagnostics.push(
+                    Diagnostic::error(codes::SHAPE_UNKNOWN_INPUT, format!("cannot infer the input shape of `{}`", layer.symbol()))
+                        .with_primary(layer.span, "no input flows into this layer")
+                        .with_note("start the component with an input such as `I 28×28×1` or `Z 100`")
+                );
+                return None;
+            }
+        };
+        
+        match layer_output_shape(layer, &input) {
+            Ok(output) => {
+                match (&layer.layer_type, self.heads) {
+                    (LayerType::AttentionHeads, _) => self.heads = Some((layer.param(HEADS), layer.span)),
+                    (LayerType::Attention, Some((heads, span))) if input.dims[1] % heads != 0 => {
+                        self.diagnostics.push(
+                            Diagnostic::error(codes::SHAPE_HEADS_MISMATCH,
+                                    format!("`{}` cannot split {} features between {} heads", layer.symbol(), input.dims[1], heads))
+                                .with_primary(layer.span, format!("{} is not a multiple of {}", input.dims[1], heads))
+                                .with_secondary(span, "heads declared here"));
+                        return None;
+                    },
+                    _ => {},
+                }
+                layer.output_shape = Some(output.clone());
+                Some(output)
+            },
+            Err(diagnostic) => {
+                self.diagnostics.push(diagnostic);
+                None
+            },
+        }
+    }
+    
+    fn infer_block(&mut self, block: &mut BlockNode, input: Option<TensorShape>) -> Option<TensorShape> {
+        // Each repetition is fed the output of the previous one
+        block.shapes.clear();
+        block.shapes.extend(input.clone());
+        if block.repetitions == 0 {
+            self.diagnostics.push(
+                Diagnostic::error(codes::INVALID_LAYER_PARAM, format!("block `{}` repeats 0 times", block))
+                    .with_primary(block.span, "a block runs at least once, `×1`"));
+            return None;
+        }
+        let mut shape = input;
+        for iteration in 0..block.repetitions {
+            let before = self.diagnostics.len();
+            let fed = shape.clone();
+            shape = self.infer(&mut block.content, shape);
+            // The first new diagnostic is the one the later ones follow from
+            if self.diagnostics.len() > before && iteration > 0 {
+                let diagnostic = &mut self.diagnostics[before];
+                let mut annotated = diagnostic.clone()
+                    .with_secondary(block.span, format!("in repetition {} of ×{}", iteration + 1, block.repetitions));
+                if let Some(fed) = &fed {
+                    annotated = annotated.with_note(format!("repetition {} is fed {} by repetition {}", iteration + 1, fed, iteration));
+                }
+                *diagnostic = annotated;
+            }
+            let Some(output) = &shape else {
+                break;
+            };
+            // Shared parameters only fit a next repetition that sees the same shape
+            if block.shared && block.repetitions > 1 && iteration == 0 && fed.as_ref() != Some(output) {
+                let fed = fed.map(|s| s.to_string()).unwrap_or_else(|| "?".to_string());
+                self.diagnostics.push(
+                    Diagnostic::error(codes::SHAPE_SHARED_BLOCK, format!("block with shared parameters turns {} into {}", fed, output))
+                        .with_primary(block.span, format!("the next repetition would need parameters for {}", output))
+                        .with_note("drop `≜` to give every repetition its own parameters"));
+            }
+            block.shapes.push(output.clone());
+        }
+        shape
+    }
+}
+
+// First input node reached when walking a component expression
+fn first_input(node: &ASTNode) -> Option<&InputNode> {
+    match node {
+        ASTNode::Input(input) => Some(input),
+        ASTNode::DataFlow(from, to) => first_input(from).or_else(|| first_input(to)),
+        ASTNode::Block(block) => first_input(&block.content),
+        ASTNode::Expression(nodes) => nodes.iter().find_map(first_input),
+        _ => None,
+    }
+}
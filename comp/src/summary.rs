@@ -0,0 +1,255 @@
+use crate::ast::*;
+use crate::layer_params::{KERNEL, target_shape, VOCAB};
+use crate::shape_inference::{input_shape, layer_output_shape};
+use serde::Serialize;
+use serde_json::Value;
+
+// Activations are stored as 32-bit floats
+const BYTES_PER_ELEMENT: usize = 4;
+
+/// One row of a model summary
+#[derive(Debug, Clone, Serialize)]
+pub struct LayerSummary {
+    pub name: String,
+    pub kind: &'static str,
+    pub output_shape: Vec<usize>,
+    pub params: usize,
+    pub macs: usize,
+    pub activation_bytes: usize,
+}
+
+/// Summary of a single network component such as `Ñ` or `G`
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentSummary {
+    pub id: String,
+    pub layers: Vec<LayerSummary>,
+    pub total_params: usize,
+    pub total_macs: usize,
+    pub total_activation_bytes: usize,
+}
+
+/// Keras-style summary of every network component in a program
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelSummary {
+    pub components: Vec<ComponentSummary>,
+}
+
+/// Summarize every component that contains at least one layer
+///
+/// Shapes are recomputed from the inputs, so the AST does not have to be
+/// annotated, but components with shape errors are cut short at the error.
+pub fn summarize(ast: &ASTNode) -> ModelSummary {
+    let mut components = Vec::new();
+    if let ASTNode::Network(network) = ast {
+        for node in &network.body {
+            if let ASTNode::Component(component) = node {
+                let mut layers = Vec::new();
+                walk(&component.expr, None, "", &mut layers);
+                if !layers.is_empty() {
+                    components.push(ComponentSummary::new(&component.id, layers));
+                }
+            }
+        }
+    }
+    ModelSummary { components }
+}
+
+impl ComponentSummary {
+    fn new(id: &str, layers: Vec<LayerSummary>) -> Self {
+        ComponentSummary {
+            id: id.to_string(),
+            total_params: layers.iter().map(|l| l.params).sum(),
+            total_macs: layers.iter().map(|l| l.macs).sum(),
+            total_activation_bytes: layers.iter().map(|l| l.activation_bytes).sum(),
+            layers,
+        }
+    }
+}
+
+// Walk an expression, appending a row per layer; returns the output shape
+fn walk(node: &ASTNode, input: Option<TensorShape>, suffix: &str, rows: &mut Vec<LayerSummary>) -> Option<TensorShape> {
+    match node {
+        ASTNode::Input(input_node) => {
+            let (declared, defaulted) = input_shape(input_node);
+            match input {
+                Some(incoming) if defaulted => Some(incoming),
+                _ => Some(declared),
+            }
+        },
+        ASTNode::Layer(layer) => {
+            let input = input?;
+            let output = layer_output_shape(layer, &input).ok()?;
+            let (params, macs) = layer_cost(layer, &input, &output);
+            rows.push(LayerSummary {
+                name: format!("{}{}", layer.symbol(), suffix),
+                kind: layer_kind(layer),
+                output_shape: output.dims.clone(),
+                params,
+                macs,
+                activation_bytes: output.elements() * BYTES_PER_ELEMENT,
+            });
+            Some(output)
+        },
+        ASTNode::DataFlow(from, to) => {
+            let shape = walk(from, input, suffix, rows);
+            walk(to, shape, suffix, rows)
+        },
+        ASTNode::Expression(nodes) => {
+            let mut shape = input;
+            for child in nodes {
+                shape = walk(child, shape, suffix, rows);
+            }
+            shape
+        },
+        ASTNode::Block(block) => {
+            let repetitions = block.repetitions.max(1);
+            let mut shape = input;
+            for iteration in 0..repetitions {
+                let suffix = if repetitions > 1 {
+                    format!("{} ({}/{})", suffix, iteration + 1, repetitions)
+                } else {
+                    suffix.to_string()
+                };
+                let start = rows.len();
+                shape = walk(&block.content, shape, &suffix, rows);
+                // Shared repetitions reuse the parameters counted for the first
+                if block.shared && iteration > 0 {
+                    for row in &mut rows[start..] {
+                        row.params = 0;
+                    }
+                }
+            }
+            shape
+        },
+        _ => input,
+    }
+}
+
+/// Human readable layer kind, using the Keras names
+pub fn layer_kind(layer: &LayerNode) -> &'static str {
+    match layer.layer_type {
+        LayerType::Convolutional(_) => "Conv2D",
+        LayerType::Dense(_) => "Dense",
+        LayerType::Pooling => "MaxPooling2D",
+        LayerType::Flatten => "Flatten",
+        LayerType::Upsampling if target_shape(layer).is_some() => "Projection",
+        LayerType::Upsampling => "UpSampling2D",
+        LayerType::LSTM => "LSTM",
+        LayerType::AttentionHeads => "AttentionHeads",
+        LayerType::Reshape => "Reshape",
+        LayerType::Embedding => "Embedding",
+        LayerType::BatchSize => "BatchSize",
+        LayerType::TransposeConv => "Conv2DTranspose",
+        LayerType::Attention => "Attention",
+        LayerType::Custom(_) => "Custom",
+    }
+}
+
+/// Trainable parameters and multiply-accumulates of a layer for one example
+pub fn layer_cost(layer: &LayerNode, input: &TensorShape, output: &TensorShape) -> (usize, usize) {
+    let last = |shape: &TensorShape| shape.dims.last().copied().unwrap_or(1);
+    match layer.layer_type {
+        LayerType::Convolutional(_) => {
+            let kernel = layer.param(KERNEL);
+            let (c_in, filters) = (last(input), last(output));
+            let weights = kernel * kernel * c_in * filters;
+            (weights + filters, output.dims[0] * output.dims[1] * weights)
+        },
+        LayerType::TransposeConv => {
+            let kernel = layer.param(KERNEL);
+            let (c_in, filters) = (last(input), last(output));
+            let weights = kernel * kernel * c_in * filters;
+            (weights + filters, input.dims[0] * input.dims[1] * weights)
+        },
+        LayerType::Dense(_) => {
+            let (c_in, units) = (last(input), last(output));
+            (c_in * units + units, input.elements() * units)
+        },
+        LayerType::Upsampling if target_shape(layer).is_some() => {
+            // Projection from the flattened input to the target shape
+            let (n_in, n_out) = (input.elements(), output.elements());
+            (n_in * n_out + n_out, n_in * n_out)
+        },
+        LayerType::LSTM => {
+            let (steps, features) = (input.dims[0], input.dims[1]);
+            let units = last(output);
+            let gates = 4 * units * (features + units);
+            (gates + 4 * units, steps * gates)
+        },
+        LayerType::Embedding => {
+            let vocabulary = layer.param(VOCAB);
+            (vocabulary * last(output), 0)
+        },
+        LayerType::Attention => {
+            // Query, key, value and output projections plus the attention scores
+            let dim = last(input);
+            let tokens = input.elements() / dim.max(1);
+            (4 * dim * dim + 4 * dim, 4 * tokens * dim * dim + 2 * tokens * tokens * dim)
+        },
+        LayerType::Pooling | LayerType::Flatten | LayerType::Upsampling | LayerType::Reshape |
+        LayerType::AttentionHeads | LayerType::BatchSize => (0, 0),
+        LayerType::Custom(ref op) => {
+            // The cost of the computation itself is not known
+            let params = op.parameter_shapes(input, output, &layer.resolved_params());
+            (params.iter().map(|(_, shape)| shape.iter().product::<usize>()).sum(), 0)
+        },
+    }
+}
+
+// Group digits in thousands, e.g. 1,234,567
+pub(crate) fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn human_bytes(bytes: usize) -> String {
+    if bytes >= 1 << 20 {
+        format!("{:.2} MB", bytes as f64 / (1 << 20) as f64)
+    } else if bytes >= 1 << 10 {
+        format!("{:.2} KB", bytes as f64 / (1 << 10) as f64)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+impl ModelSummary {
+    /// Render the summary as text tables, one per component
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for component in &self.components {
+            let rule = "=".repeat(88);
+            out.push_str(&format!("Component: {}\n", component.id));
+            out.push_str(&format!("{}\n", "_".repeat(88)));
+            out.push_str(&format!("{:<24}{:<18}{:>14}{:>18}{:>14}\n", "Layer (type)", "Output Shape", "Param #", "MACs", "Memory"));
+            out.push_str(&format!("{}\n", rule));
+            for layer in &component.layers {
+                let shape = TensorShape::new(layer.output_shape.clone()).to_string();
+                out.push_str(&format!("{:<24}{:<18}{:>14}{:>18}{:>14}\n",
+                                      format!("{} ({})", layer.name, layer.kind), shape,
+                                      thousands(layer.params), thousands(layer.macs),
+                                      human_bytes(layer.activation_bytes)));
+            }
+            out.push_str(&format!("{}\n", rule));
+            out.push_str(&format!("Total params: {} ({})\n", thousands(component.total_params),
+                                  human_bytes(component.total_params * BYTES_PER_ELEMENT)));
+            out.push_str(&format!("Total MACs: {}\n", thousands(component.total_macs)));
+            out.push_str(&format!("Activation memory: {}\n\n", human_bytes(component.total_activation_bytes)));
+        }
+        if self.components.is_empty() {
+            out.push_str("No network components found\n");
+        }
+        out
+    }
+    
+    /// Render the summary as JSON
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+}
@@ -0,0 +1,833 @@
+// Text tokenizers for `T` inputs: WordPiece and byte-level BPE from local
+// vocabulary files, and a character or byte fallback. Generated JavaScript and
+// Kotlin carry the same algorithms, so deployed apps produce the same ids.
+
+use crate::ast::*;
+use crate::diagnostics::{codes, Diagnostic, Span};
+use crate::layer_params::VOCAB;
+use crate::shape_inference::input_shape;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Ids of the UTF-8 bytes for `char` without a vocabulary: 0 pads, byte `b` is `b + 1`
+pub const BYTE_VOCABULARY: usize = 257;
+
+// Longest word WordPiece splits; longer ones are unknown, as in BERT
+const MAX_WORD_CHARS: usize = 100;
+
+/// A vocabulary and the rules that map text onto it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tokenizer {
+    pub kind: TokenizerKind,
+    /// Token of every id; empty for the byte fallback
+    pub vocab: Vec<String>,
+    /// BPE merges in priority order
+    pub merges: Vec<(String, String)>,
+    /// Text is lowercased first when the vocabulary has no uppercase letters
+    /// outside its special tokens
+    pub lowercase: bool,
+    pub pad: u32,
+    /// Id of unknown tokens; without one they are left out
+    pub unknown: Option<u32>,
+    /// `[CLS]` and `[SEP]` around a WordPiece sequence
+    pub start: Option<u32>,
+    pub end: Option<u32>,
+    ids: HashMap<String, u32>,
+    ranks: HashMap<String, usize>,
+}
+
+impl Tokenizer {
+    fn new(kind: TokenizerKind, vocab: Vec<String>, merges: Vec<(String, String)>) -> Self {
+        let mut ids = HashMap::new();
+        for (id, token) in vocab.iter().enumerate() {
+            ids.entry(token.clone()).or_insert(id as u32);
+        }
+        let ranks = merges.iter().enumerate().map(|(rank, (a, b))| (format!("{} {}", a, b), rank)).collect();
+        let special = |names: &[&str]| names.iter().find_map(|name| ids.get(*name).copied());
+        let (start, end) = match kind {
+            TokenizerKind::WordPiece => (special(&["[CLS]"]), special(&["[SEP]"])),
+            _ => (None, None),
+        };
+        Tokenizer {
+            kind,
+            lowercase: !vocab.is_empty() && kind != TokenizerKind::Bpe
+                && !vocab.iter().filter(|token| !is_special(token)).any(|token| token.chars().any(char::is_uppercase)),
+            pad: special(&["[PAD]", "<pad>"]).unwrap_or(0),
+            unknown: special(&["[UNK]", "<unk>"]),
+            start,
+            end,
+            vocab,
+            merges,
+            ids,
+            ranks,
+        }
+    }
+    
+    /// WordPiece over tokens given in id order; continuations start with `##`
+    pub fn wordpiece(vocab: Vec<String>) -> Self {
+        Self::new(TokenizerKind::WordPiece, vocab, Vec::new())
+    }
+    
+    /// Byte-level BPE as in GPT-2: tokens in id order and merges by priority
+    pub fn bpe(vocab: Vec<String>, merges: Vec<(String, String)>) -> Self {
+        Self::new(TokenizerKind::Bpe, vocab, merges)
+    }
+    
+    /// One token per character of `vocab`, or per UTF-8 byte without one
+    pub fn chars(vocab: Option<Vec<String>>) -> Self {
+        Self::new(TokenizerKind::Char, vocab.unwrap_or_default(), Vec::new())
+    }
+    
+    /// Read the vocabulary files of a tokenizer, relative to `base`
+    pub fn load(kind: TokenizerKind, files: &[String], base: &Path) -> Result<Self, String> {
+        let read = |file: &String| {
+            fs::read_to_string(base.join(file)).map_err(|e| format!("failed to read '{}': {}", file, e))
+        };
+        let lines = |text: String| -> Vec<String> {
+            text.lines().map(|line| line.trim_end_matches('\r').to_string()).collect()
+        };
+        match (kind, files) {
+            (TokenizerKind::WordPiece, [vocab]) => Ok(Self::wordpiece(lines(read(vocab)?))),
+            (TokenizerKind::Char, []) => Ok(Self::chars(None)),
+            (TokenizerKind::Char, [vocab]) => Ok(Self::chars(Some(lines(read(vocab)?)))),
+            (TokenizerKind::Bpe, [vocab, merges]) => {
+                let json: Value = serde_json::from_str(&read(vocab)?).map_err(|e| format!("invalid '{}': {}", vocab, e))?;
+                let entries = json.as_object().ok_or_else(|| format!("'{}' is not a map of tokens to ids", vocab))?;
+                let mut tokens = vec![String::new(); entries.len()];
+                for (token, id) in entries {
+                    match id.as_u64().map(|id| id as usize) {
+                        Some(id) if id < tokens.len() => tokens[id] = token.clone(),
+                        _ => return Err(format!("'{}' gives `{}` the id {}, expected ids 0 to {}", vocab, token, id, tokens.len() - 1)),
+                    }
+                }
+                let merges = lines(read(merges)?).into_iter()
+                    .filter(|line| !line.starts_with("#version") && !line.trim().is_empty())
+                    .map(|line| match line.split_once(' ') {
+                        Some((a, b)) => Ok((a.to_string(), b.to_string())),
+                        None => Err(format!("invalid merge '{}' in '{}'", line, merges)),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Self::bpe(tokens, merges))
+            },
+            (TokenizerKind::WordPiece, _) => Err("`wordpiece` takes one vocabulary file".to_string()),
+            (TokenizerKind::Char, _) => Err("`char` takes at most one vocabulary file".to_string()),
+            (TokenizerKind::Bpe, _) => Err("`bpe` takes a vocabulary file and a merges file".to_string()),
+        }
+        .and_then(|tokenizer| match tokenizer.vocab.iter().position(|t| t.contains(['\n', '\r'])) {
+            Some(id) => Err(format!("token {} contains a line break", id)),
+            None => Ok(tokenizer),
+        })
+    }
+    
+    /// Number of ids, and so of rows in the embedding that follows
+    pub fn vocab_size(&self) -> usize {
+        match self.kind {
+            TokenizerKind::Char if self.vocab.is_empty() => BYTE_VOCABULARY,
+            _ => self.vocab.len(),
+        }
+    }
+    
+    /// Ids of `text` as a sequence of exactly `length`: specials around the
+    /// tokens, which are cut to fit, then padding
+    pub fn encode(&self, text: &str, length: usize) -> Vec<u32> {
+        let mut ids = self.tokens(text);
+        let specials = self.start.is_some() as usize + self.end.is_some() as usize;
+        ids.truncate(length.saturating_sub(specials));
+        if let Some(start) = self.start {
+            ids.insert(0, start);
+        }
+        ids.extend(self.end);
+        ids.resize(length, self.pad);
+        ids
+    }
+    
+    /// Ids of the tokens of `text`, without specials or padding
+    pub fn tokens(&self, text: &str) -> Vec<u32> {
+        let text = if self.lowercase { text.to_lowercase() } else { text.to_string() };
+        let mut ids = Vec::new();
+        match self.kind {
+            TokenizerKind::Char if self.vocab.is_empty() => ids.extend(text.bytes().map(|b| b as u32 + 1)),
+            TokenizerKind::Char => {
+                for c in text.chars() {
+                    self.push(&c.to_string(), &mut ids);
+                }
+            },
+            TokenizerKind::WordPiece => {
+                for word in words(&text) {
+                    self.wordpiece_word(word, &mut ids);
+                }
+            },
+            TokenizerKind::Bpe => {
+                for piece in pretokenize(&text) {
+                    for symbol in self.bpe_piece(piece) {
+                        self.push(&symbol, &mut ids);
+                    }
+                }
+            },
+        }
+        ids
+    }
+    
+    fn push(&self, token: &str, ids: &mut Vec<u32>) {
+        if let Some(id) = self.ids.get(token).copied().or(self.unknown) {
+            ids.push(id);
+        }
+    }
+    
+    // Greedy longest-match-first split of one word; a word with a part
+    // outside the vocabulary is unknown as a whole
+    fn wordpiece_word(&self, word: &str, ids: &mut Vec<u32>) {
+        let chars: Vec<char> = word.chars().collect();
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        while start < chars.len() && chars.len() <= MAX_WORD_CHARS {
+            let found = (start + 1..=chars.len()).rev().find_map(|end| {
+                let piece: String = chars[start..end].iter().collect();
+                let piece = if start > 0 { format!("##{}", piece) } else { piece };
+                self.ids.get(&piece).map(|&id| (id, end))
+            });
+            match found {
+                Some((id, end)) => {
+                    pieces.push(id);
+                    start = end;
+                },
+                None => break,
+            }
+        }
+        if start == chars.len() && chars.len() <= MAX_WORD_CHARS {
+            ids.extend(pieces);
+        } else {
+            ids.extend(self.unknown);
+        }
+    }
+    
+    // Symbols of one pre-token: its bytes as characters, joined by the
+    // merges with the lowest rank first
+    fn bpe_piece(&self, piece: &str) -> Vec<String> {
+        let table = byte_chars();
+        let mut symbols: Vec<String> = piece.bytes().map(|b| table[b as usize].to_string()).collect();
+        while symbols.len() > 1 {
+            let best = (0..symbols.len() - 1)
+                .filter_map(|i| self.ranks.get(&format!("{} {}", symbols[i], symbols[i + 1])).map(|&rank| (rank, i)))
+                .min();
+            let Some((_, i)) = best else {
+                break;
+            };
+            let (a, b) = (symbols[i].clone(), symbols[i + 1].clone());
+            let mut merged = Vec::with_capacity(symbols.len());
+            let mut i = 0;
+            while i < symbols.len() {
+                if i + 1 < symbols.len() && symbols[i] == a && symbols[i + 1] == b {
+                    merged.push(format!("{}{}", a, b));
+                    i += 2;
+                } else {
+                    merged.push(symbols[i].clone());
+                    i += 1;
+                }
+            }
+            symbols = merged;
+        }
+        symbols
+    }
+}
+
+// `[UNK]`, `<pad>` and the like
+fn is_special(token: &str) -> bool {
+    (token.starts_with('[') && token.ends_with(']') || token.starts_with('<') && token.ends_with('>')) && token.len() > 2
+}
+
+// Only these separate words, so every runtime agrees on them
+fn is_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r')
+}
+
+// Words for WordPiece: runs between spaces, with ASCII punctuation split off
+fn words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        if is_space(c) || c.is_ascii_punctuation() {
+            if let Some(s) = start.take() {
+                words.push(&text[s..i]);
+            }
+            if c.is_ascii_punctuation() {
+                words.push(&text[i..i + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        words.push(&text[s..]);
+    }
+    words
+}
+
+/// GPT-2 pre-tokenization, with letters being Unicode `Alphabetic`, numbers
+/// category `N` and spaces `White_Space`:
+/// `'s|'t|'re|'ve|'m|'ll|'d| ?\p{L}+| ?\p{N}+| ?[^\s\p{L}\p{N}]+|\s+(?!\S)|\s+`
+pub fn pretokenize(text: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let offset = |i: usize| chars.get(i).map_or(text.len(), |&(at, _)| at);
+    let other = |c: char| !c.is_whitespace() && !c.is_alphabetic() && !c.is_numeric();
+    let mut pieces = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let (at, c) = chars[i];
+        let contraction = ["s", "t", "re", "ve", "m", "ll", "d"].iter()
+            .find(|suffix| c == '\'' && text[at + 1..].starts_with(**suffix));
+        let end = if let Some(suffix) = contraction {
+            i + 1 + suffix.len()
+        } else {
+            // A single space joins the run that follows it
+            let first = if c == ' ' && chars.get(i + 1).is_some_and(|&(_, d)| !d.is_whitespace()) { i + 1 } else { i };
+            let run = |test: &dyn Fn(char) -> bool| (first..chars.len()).find(|&j| !test(chars[j].1)).unwrap_or(chars.len());
+            let d = chars[first].1;
+            if d.is_alphabetic() {
+                run(&|c| c.is_alphabetic())
+            } else if d.is_numeric() {
+                run(&|c| c.is_numeric())
+            } else if other(d) {
+                run(&other)
+            } else {
+                // Spaces, leaving the last one to a word that follows
+                let end = run(&|c| c.is_whitespace());
+                if end < chars.len() && end - i > 1 { end - 1 } else { end }
+            }
+        };
+        pieces.push(&text[at..offset(end)]);
+        i = end;
+    }
+    pieces
+}
+
+/// Characters GPT-2 writes bytes as: printable ones stand for themselves, the
+/// others for 256 onwards in byte order
+pub fn byte_chars() -> [char; 256] {
+    let mut table = ['\0'; 256];
+    let mut next = 256;
+    for (b, slot) in table.iter_mut().enumerate() {
+        let printable = (33..=126).contains(&b) || (161..=172).contains(&b) || (174..=255).contains(&b);
+        *slot = if printable {
+            char::from_u32(b as u32).unwrap()
+        } else {
+            next += 1;
+            char::from_u32(next - 1).unwrap()
+        };
+    }
+    table
+}
+
+/// Load the tokenizers of the text inputs, with files relative to `base`, and
+/// give their vocabulary size to the `E` that embeds the tokens unless it sets
+/// one itself
+pub fn attach(ast: &mut ASTNode, base: &Path) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    visit(ast, base, &mut None, &mut diagnostics);
+    diagnostics
+}
+
+// `pending` is the tokenizer whose ids have not reached an embedding yet
+fn visit(node: &mut ASTNode, base: &Path, pending: &mut Option<(usize, Span)>, diagnostics: &mut Vec<Diagnostic>) {
+    match node {
+        ASTNode::Network(network) => {
+            for node in &mut network.body {
+                visit(node, base, &mut None, diagnostics);
+            }
+        },
+        ASTNode::Component(component) => visit(&mut component.expr, base, &mut None, diagnostics),
+        ASTNode::DataFlow(from, to) => {
+            visit(from, base, pending, diagnostics);
+            visit(to, base, pending, diagnostics);
+        },
+        ASTNode::Block(block) => visit(&mut block.content, base, pending, diagnostics),
+        ASTNode::Expression(nodes) => {
+            for node in nodes {
+                visit(node, base, pending, diagnostics);
+            }
+        },
+        ASTNode::Input(input) => {
+            let Some(spec) = &mut input.tokenizer else {
+                return;
+            };
+            if !matches!(input.input_type, InputType::Text) {
+                diagnostics.push(Diagnostic::error(codes::TOKENIZER_ERROR, format!("`{}` inputs take no tokenizer", input_letter(&input.input_type)))
+                    .with_primary(spec.span, "only `T` inputs are tokenized"));
+                return;
+            }
+            match Tokenizer::load(spec.kind, &spec.files, base) {
+                Ok(tokenizer) => {
+                    *pending = Some((tokenizer.vocab_size(), spec.span));
+                    spec.loaded = Some(Arc::new(tokenizer));
+                },
+                Err(reason) => diagnostics.push(
+                    Diagnostic::error(codes::TOKENIZER_ERROR, format!("cannot load the {} tokenizer", spec.kind.name()))
+                        .with_primary(spec.span, reason)
+                ),
+            }
+        },
+        ASTNode::Layer(layer) if matches!(layer.layer_type, LayerType::Embedding) => {
+            let Some((size, span)) = pending.take() else {
+                return;
+            };
+            let declared = layer.named_params.iter().any(|p| p.name == "vocab") || layer.params.len() >= 2;
+            if !declared {
+                layer.named_params.push(NamedParam { name: "vocab".to_string(), value: size, span });
+            } else if layer.param(VOCAB) < size {
+                diagnostics.push(
+                    Diagnostic::error(codes::TOKENIZER_VOCAB_MISMATCH,
+                                      format!("`E` has a vocabulary of {} but its tokenizer has {} tokens", layer.param(VOCAB), size))
+                        .with_primary(layer.span, "too few rows for the token ids")
+                        .with_secondary(span, format!("{} tokens", size))
+                        .with_note("leave out the vocabulary of `E` to take it from the tokenizer")
+                );
+            }
+        },
+        _ => {},
+    }
+}
+
+fn input_letter(input_type: &InputType) -> &'static str {
+    match input_type {
+        InputType::Text => "T",
+        InputType::Image => "I",
+        InputType::Sequence => "S",
+        InputType::Latent => "Z",
+    }
+}
+
+/// A tokenized text input of a component
+#[derive(Debug, Clone)]
+pub struct TextInput {
+    pub component: String,
+    /// Sequence length declared on the input
+    pub length: usize,
+    pub tokenizer: Arc<Tokenizer>,
+}
+
+impl TextInput {
+    /// Folder the vocabulary files of this input ship in
+    pub fn directory(&self) -> String {
+        format!("tokenizers/{}", identifier(&self.component, true))
+    }
+    
+    /// Files generated code reads: `vocab.txt` with a token per line, and
+    /// `merges.txt` for BPE
+    pub fn files(&self) -> Vec<(String, String)> {
+        let lines = |lines: Vec<String>| lines.into_iter().map(|line| line + "\n").collect::<String>();
+        let mut files = Vec::new();
+        if !self.tokenizer.vocab.is_empty() {
+            files.push((format!("{}/vocab.txt", self.directory()), lines(self.tokenizer.vocab.clone())));
+        }
+        if self.tokenizer.kind == TokenizerKind::Bpe {
+            let merges = self.tokenizer.merges.iter().map(|(a, b)| format!("{} {}", a, b)).collect();
+            files.push((format!("{}/merges.txt", self.directory()), lines(merges)));
+        }
+        files
+    }
+    
+    // Settings of the tokenizer as a JSON object, shared by the runtimes
+    fn spec(&self) -> Value {
+        let tokenizer = &self.tokenizer;
+        let file = |name: &str, present: bool| if present { json!(format!("{}/{}", self.directory(), name)) } else { Value::Null };
+        json!({
+            "kind": tokenizer.kind.name(),
+            "length": self.length,
+            "vocab": file("vocab.txt", !tokenizer.vocab.is_empty()),
+            "merges": file("merges.txt", tokenizer.kind == TokenizerKind::Bpe),
+            "lowercase": tokenizer.lowercase,
+            "pad": tokenizer.pad,
+            "unknown": tokenizer.unknown,
+            "start": tokenizer.start,
+            "end": tokenizer.end,
+        })
+    }
+}
+
+/// Every text input with a loaded tokenizer, in component order
+pub fn text_inputs(ast: &ASTNode) -> Vec<TextInput> {
+    let mut inputs = Vec::new();
+    let ASTNode::Network(network) = ast else {
+        return inputs;
+    };
+    for node in &network.body {
+        let ASTNode::Component(component) = node else {
+            continue;
+        };
+        let mut expr = component.expr.as_ref();
+        while let ASTNode::DataFlow(from, _) = expr {
+            expr = from;
+        }
+        if let ASTNode::Input(input) = expr {
+            if let Some(tokenizer) = input.tokenizer.as_ref().and_then(|spec| spec.loaded.clone()) {
+                let length = input_shape(input).0.dims[0];
+                inputs.push(TextInput { component: component.id.clone(), length, tokenizer });
+            }
+        }
+    }
+    inputs
+}
+
+const JS_RUNTIME: &str = r#"
+// Only these separate words, as in the compiler
+const gaiaIsSpace = c => c === ' ' || c === '\t' || c === '\n' || c === '\r';
+const gaiaIsPunctuation = c => /^[!-\/:-@\[-`{-~]$/.test(c);
+const gaiaPretokenize = /'s|'t|'re|'ve|'m|'ll|'d| ?\p{Alphabetic}+| ?\p{N}+| ?[^\p{White_Space}\p{Alphabetic}\p{N}]+|\p{White_Space}+(?!\P{White_Space})|\p{White_Space}+/gu;
+
+// Characters GPT-2 writes bytes as
+const gaiaByteChars = (() => {
+  const chars = [];
+  let next = 256;
+  for (let b = 0; b < 256; b++) {
+    const printable = (b >= 33 && b <= 126) || (b >= 161 && b <= 172) || (b >= 174 && b <= 255);
+    chars.push(String.fromCodePoint(printable ? b : next++));
+  }
+  return chars;
+})();
+
+async function gaiaFetchLines(url) {
+  const response = await fetch(url);
+  if (!response.ok) throw new Error(`failed to load ${url}: ${response.status}`);
+  const lines = (await response.text()).split('\n');
+  lines.pop();
+  return lines;
+}
+
+// Load the vocabulary of a component's text input once
+function gaiaLoadTokenizer(component) {
+  const spec = gaiaTokenizerSpecs[component];
+  if (!spec) return Promise.reject(new Error(`component ${component} has no tokenizer`));
+  if (!gaiaLoadedTokenizers[component]) {
+    gaiaLoadedTokenizers[component] = (async () => {
+      const tokenizer = { ...spec, ids: new Map(), ranks: new Map(), bytes: spec.vocab === null };
+      if (spec.vocab !== null) {
+        (await gaiaFetchLines(spec.vocab)).forEach((token, id) => {
+          if (!tokenizer.ids.has(token)) tokenizer.ids.set(token, id);
+        });
+      }
+      if (spec.merges !== null) {
+        (await gaiaFetchLines(spec.merges)).forEach((merge, rank) => tokenizer.ranks.set(merge, rank));
+      }
+      return tokenizer;
+    })();
+  }
+  return gaiaLoadedTokenizers[component];
+}
+
+function gaiaPush(tokenizer, token, ids) {
+  const id = tokenizer.ids.has(token) ? tokenizer.ids.get(token) : tokenizer.unknown;
+  if (id !== null) ids.push(id);
+}
+
+function gaiaWords(text) {
+  const words = [];
+  let word = '';
+  for (const c of text) {
+    if (gaiaIsSpace(c) || gaiaIsPunctuation(c)) {
+      if (word) words.push(word);
+      if (gaiaIsPunctuation(c)) words.push(c);
+      word = '';
+    } else {
+      word += c;
+    }
+  }
+  if (word) words.push(word);
+  return words;
+}
+
+function gaiaWordPiece(tokenizer, word, ids) {
+  const chars = Array.from(word);
+  const pieces = [];
+  let start = 0;
+  while (start < chars.length && chars.length <= 100) {
+    let found = null;
+    for (let end = chars.length; end > start; end--) {
+      const piece = (start > 0 ? '##' : '') + chars.slice(start, end).join('');
+      if (tokenizer.ids.has(piece)) {
+        found = [tokenizer.ids.get(piece), end];
+        break;
+      }
+    }
+    if (!found) break;
+    pieces.push(found[0]);
+    start = found[1];
+  }
+  if (start === chars.length && chars.length <= 100) {
+    ids.push(...pieces);
+  } else if (tokenizer.unknown !== null) {
+    ids.push(tokenizer.unknown);
+  }
+}
+
+function gaiaBpe(tokenizer, piece) {
+  let symbols = Array.from(new TextEncoder().encode(piece), b => gaiaByteChars[b]);
+  while (symbols.length > 1) {
+    let best = -1;
+    let bestRank = Infinity;
+    for (let i = 0; i + 1 < symbols.length; i++) {
+      const rank = tokenizer.ranks.get(symbols[i] + ' ' + symbols[i + 1]);
+      if (rank !== undefined && rank < bestRank) {
+        bestRank = rank;
+        best = i;
+      }
+    }
+    if (best < 0) break;
+    const [a, b] = [symbols[best], symbols[best + 1]];
+    const merged = [];
+    for (let i = 0; i < symbols.length; i++) {
+      if (i + 1 < symbols.length && symbols[i] === a && symbols[i + 1] === b) {
+        merged.push(a + b);
+        i++;
+      } else {
+        merged.push(symbols[i]);
+      }
+    }
+    symbols = merged;
+  }
+  return symbols;
+}
+
+// Ids of the tokens of a text, without specials or padding
+function gaiaTokens(tokenizer, text) {
+  if (tokenizer.lowercase) text = text.toLowerCase();
+  const ids = [];
+  if (tokenizer.kind === 'char' && tokenizer.bytes) {
+    for (const b of new TextEncoder().encode(text)) ids.push(b + 1);
+  } else if (tokenizer.kind === 'char') {
+    for (const c of text) gaiaPush(tokenizer, c, ids);
+  } else if (tokenizer.kind === 'wordpiece') {
+    for (const word of gaiaWords(text)) gaiaWordPiece(tokenizer, word, ids);
+  } else {
+    for (const piece of text.match(gaiaPretokenize) || []) {
+      for (const symbol of gaiaBpe(tokenizer, piece)) gaiaPush(tokenizer, symbol, ids);
+    }
+  }
+  return ids;
+}
+
+// Ids of a text as the input of a component: specials around the tokens, which
+// are cut to fit the sequence length, then padding
+async function gaiaEncode(component, text) {
+  const tokenizer = await gaiaLoadTokenizer(component);
+  const specials = (tokenizer.start !== null) + (tokenizer.end !== null);
+  const ids = gaiaTokens(tokenizer, text).slice(0, Math.max(tokenizer.length - specials, 0));
+  if (tokenizer.start !== null) ids.unshift(tokenizer.start);
+  if (tokenizer.end !== null) ids.push(tokenizer.end);
+  while (ids.length < tokenizer.length) ids.push(tokenizer.pad);
+  return ids.slice(0, tokenizer.length);
+}
+
+const gaiaTokenizer = {
+  specs: gaiaTokenizerSpecs,
+  load: gaiaLoadTokenizer,
+  tokens: gaiaTokens,
+  encode: gaiaEncode,
+};
+
+if (typeof window !== 'undefined') {
+  window.gaiaTokenizer = gaiaTokenizer;
+}
+if (typeof module !== 'undefined') {
+  module.exports = gaiaTokenizer;
+}
+"#;
+
+/// JavaScript that tokenizes text for the text inputs exactly as `Tokenizer`
+/// does, loading vocabularies from the files of `TextInput::files`
+pub fn tokenizer_js(inputs: &[TextInput], app_name: &str) -> String {
+    let mut specs = String::new();
+    for input in inputs {
+        specs.push_str(&format!("  {}: {},\n", json!(input.component), input.spec()));
+    }
+    format!("// Generated by the GaiaScript compiler for {}; do not edit\n\
+             // Turns text into the token ids of the text inputs, as the compiler does\n\
+             const gaiaTokenizerSpecs = {{\n{}}};\n\
+             const gaiaLoadedTokenizers = {{}};\n{}", app_name, specs, JS_RUNTIME)
+}
+
+const KOTLIN_RUNTIME: &str = r###"// Generated by the GaiaScript compiler: turns text into the token ids of the
+// text inputs, as the compiler does, e.g.
+// GaiaTokenizer.load("N") { path -> assets.open(path) }.encode("some text")
+import java.io.InputStream
+
+class GaiaTokenizer(
+    val kind: String,
+    val length: Int,
+    vocab: List<String>?,
+    merges: List<String>?,
+    val lowercase: Boolean,
+    val pad: Int,
+    val unknown: Int?,
+    val start: Int?,
+    val end: Int?,
+) {
+    private val bytes = vocab == null
+    private val ids = HashMap<String, Int>().apply { vocab?.forEachIndexed { id, token -> putIfAbsent(token, id) } }
+    private val ranks = HashMap<String, Int>().apply { merges?.forEachIndexed { rank, merge -> put(merge, rank) } }
+
+    /** Ids of a text as the input: specials around the tokens, which are cut to fit, then padding */
+    fun encode(text: String): IntArray {
+        val specials = (if (start != null) 1 else 0) + (if (end != null) 1 else 0)
+        val ids = tokens(text).take(maxOf(length - specials, 0)).toMutableList()
+        if (start != null) ids.add(0, start)
+        if (end != null) ids.add(end)
+        while (ids.size < length) ids.add(pad)
+        return ids.take(length).toIntArray()
+    }
+
+    /** Ids of the tokens of a text, without specials or padding */
+    fun tokens(input: String): List<Int> {
+        val text = if (lowercase) input.lowercase() else input
+        val ids = mutableListOf<Int>()
+        when {
+            kind == "char" && bytes -> text.toByteArray(Charsets.UTF_8).forEach { ids.add((it.toInt() and 0xFF) + 1) }
+            kind == "char" -> codePoints(text).forEach { push(it, ids) }
+            kind == "wordpiece" -> words(text).forEach { wordPiece(it, ids) }
+            else -> PRETOKENIZE.findAll(text).forEach { piece -> bpe(piece.value).forEach { push(it, ids) } }
+        }
+        return ids
+    }
+
+    private fun push(token: String, ids: MutableList<Int>) {
+        (this.ids[token] ?: unknown)?.let { ids.add(it) }
+    }
+
+    private fun words(text: String): List<String> {
+        val words = mutableListOf<String>()
+        val word = StringBuilder()
+        for (c in codePoints(text)) {
+            val punctuation = c.length == 1 && c[0] in '!'..'~' && !c[0].isLetterOrDigit()
+            if (c in SPACES || punctuation) {
+                if (word.isNotEmpty()) words.add(word.toString())
+                if (punctuation) words.add(c)
+                word.setLength(0)
+            } else {
+                word.append(c)
+            }
+        }
+        if (word.isNotEmpty()) words.add(word.toString())
+        return words
+    }
+
+    private fun wordPiece(word: String, out: MutableList<Int>) {
+        val chars = codePoints(word)
+        val pieces = mutableListOf<Int>()
+        var start = 0
+        while (start < chars.size && chars.size <= 100) {
+            var found: Pair<Int, Int>? = null
+            for (end in chars.size downTo start + 1) {
+                val piece = (if (start > 0) "##" else "") + chars.subList(start, end).joinToString("")
+                val id = ids[piece]
+                if (id != null) {
+                    found = id to end
+                    break
+                }
+            }
+            if (found == null) break
+            pieces.add(found.first)
+            start = found.second
+        }
+        if (start == chars.size && chars.size <= 100) {
+            out.addAll(pieces)
+        } else if (unknown != null) {
+            out.add(unknown)
+        }
+    }
+
+    private fun bpe(piece: String): List<String> {
+        var symbols = piece.toByteArray(Charsets.UTF_8).map { BYTE_CHARS[it.toInt() and 0xFF] }
+        while (symbols.size > 1) {
+            var best = -1
+            var bestRank = Int.MAX_VALUE
+            for (i in 0 until symbols.size - 1) {
+                val rank = ranks[symbols[i] + " " + symbols[i + 1]] ?: continue
+                if (rank < bestRank) {
+                    bestRank = rank
+                    best = i
+                }
+            }
+            if (best < 0) break
+            val a = symbols[best]
+            val b = symbols[best + 1]
+            val merged = mutableListOf<String>()
+            var i = 0
+            while (i < symbols.size) {
+                if (i + 1 < symbols.size && symbols[i] == a && symbols[i + 1] == b) {
+                    merged.add(a + b)
+                    i += 2
+                } else {
+                    merged.add(symbols[i])
+                    i += 1
+                }
+            }
+            symbols = merged
+        }
+        return symbols
+    }
+
+    companion object {
+        private val SPACES = setOf(" ", "\t", "\n", "\r")
+        private val PRETOKENIZE = Regex(
+            """'s|'t|'re|'ve|'m|'ll|'d| ?\p{IsAlphabetic}+| ?\p{N}+| ?[^\p{IsWhite_Space}\p{IsAlphabetic}\p{N}]+|\p{IsWhite_Space}+(?!\P{IsWhite_Space})|\p{IsWhite_Space}+"""
+        )
+
+        /** Characters GPT-2 writes bytes as */
+        private val BYTE_CHARS: List<String> = run {
+            var next = 256
+            (0 until 256).map { b ->
+                val printable = b in 33..126 || b in 161..172 || b in 174..255
+                String(Character.toChars(if (printable) b else next++))
+            }
+        }
+
+        private fun codePoints(text: String): List<String> {
+            val out = mutableListOf<String>()
+            var i = 0
+            while (i < text.length) {
+                val cp = text.codePointAt(i)
+                out.add(String(Character.toChars(cp)))
+                i += Character.charCount(cp)
+            }
+            return out
+        }
+
+        private fun lines(stream: InputStream): List<String> =
+            stream.use { it.readBytes().toString(Charsets.UTF_8) }.split("\n").dropLast(1)
+
+        /** Tokenizer of a component's text input, reading its files through `open` */
+        fun load(component: String, open: (String) -> InputStream): GaiaTokenizer {
+            val spec = SPECS[component] ?: throw IllegalArgumentException("component $component has no tokenizer")
+            return GaiaTokenizer(
+                spec.kind, spec.length,
+                spec.vocab?.let { lines(open(it)) },
+                spec.merges?.let { lines(open(it)) },
+                spec.lowercase, spec.pad, spec.unknown, spec.start, spec.end,
+            )
+        }
+
+        class Spec(
+            val kind: String, val length: Int, val vocab: String?, val merges: String?,
+            val lowercase: Boolean, val pad: Int, val unknown: Int?, val start: Int?, val end: Int?,
+        )
+"###;
+
+/// Kotlin `GaiaTokenizer` class that tokenizes text exactly as `Tokenizer`
+/// does, reading the files of `TextInput::files`
+pub fn tokenizer_kotlin(inputs: &[TextInput]) -> String {
+    let mut code = KOTLIN_RUNTIME.to_string();
+    code.push_str("\n        /** Tokenizers of the text inputs by component */\n");
+    code.push_str("        val SPECS: Map<String, Spec> = mapOf(\n");
+    for input in inputs {
+        let spec = input.spec();
+        let text = |key: &str| spec[key].as_str().map_or("null".to_string(), |s| format!("{:?}", s));
+        let number = |key: &str| spec[key].as_u64().map_or("null".to_string(), |n| n.to_string());
+        code.push_str(&format!("            {:?} to Spec({:?}, {}, {}, {}, {}, {}, {}, {}, {}),\n",
+            input.component, input.tokenizer.kind.name(), input.length, text("vocab"), text("merges"),
+            input.tokenizer.lowercase, input.tokenizer.pad, number("unknown"), number("start"), number("end")));
+    }
+    code.push_str("        )\n    }\n}\n");
+    code
+}
@@ -0,0 +1,440 @@
+use crate::ast::*;
+use crate::autodiff::{Tape, Var};
+use crate::composite::{self, Composite};
+use crate::dataset;
+use crate::interpreter::{Plan, Rng, Step, Tensor, Weights};
+use crate::layer_params::{FACTOR, STRIDE, target_shape};
+use crate::tokenizer::Tokenizer;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+pub use crate::dataset::Dataset;
+
+// Record one base loss on the tape
+fn record_base(tape: &mut Tape, base: BaseLoss, prediction: Var, target: &Tensor) -> Var {
+    match base {
+        BaseLoss::MeanSquaredError => tape.mse(prediction, target),
+        BaseLoss::BinaryCrossEntropy => tape.bce(prediction, target),
+        BaseLoss::CrossEntropy => tape.cross_entropy(prediction, target),
+        BaseLoss::Hinge => tape.hinge(prediction, target),
+        BaseLoss::KullbackLeibler => tape.kl_divergence(prediction, target),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OptimizerKind {
+    Sgd,
+    Momentum,
+    Adam,
+}
+
+impl OptimizerKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "sgd" => Some(OptimizerKind::Sgd),
+            "momentum" => Some(OptimizerKind::Momentum),
+            "adam" => Some(OptimizerKind::Adam),
+            _ => None,
+        }
+    }
+}
+
+/// Gradient descent optimizer with per-parameter state
+pub struct Optimizer {
+    kind: OptimizerKind,
+    learning_rate: f32,
+    step: i32,
+    // First and second moment estimates (velocity for momentum)
+    moments: HashMap<String, (Vec<f32>, Vec<f32>)>,
+}
+
+impl Optimizer {
+    pub fn new(kind: OptimizerKind, learning_rate: f32) -> Self {
+        Optimizer {
+            kind,
+            learning_rate,
+            step: 0,
+            moments: HashMap::new(),
+        }
+    }
+    
+    /// Apply one update to every parameter that has a gradient
+    pub fn update(&mut self, weights: &mut Weights, gradients: &BTreeMap<String, Tensor>) {
+        const MOMENTUM: f32 = 0.9;
+        const BETA1: f32 = 0.9;
+        const BETA2: f32 = 0.999;
+        const EPSILON: f32 = 1e-8;
+        
+        self.step += 1;
+        for (name, grad) in gradients {
+            let tensor = match weights.get_mut(name) {
+                Some(tensor) => tensor,
+                None => continue,
+            };
+            let (m, v) = self.moments.entry(name.clone())
+                .or_insert_with(|| (vec![0.0; grad.len()], vec![0.0; grad.len()]));
+            
+            match self.kind {
+                OptimizerKind::Sgd => {
+                    for (w, g) in tensor.data.iter_mut().zip(&grad.data) {
+                        *w -= self.learning_rate * g;
+                    }
+                },
+                OptimizerKind::Momentum => {
+                    for ((w, g), m) in tensor.data.iter_mut().zip(&grad.data).zip(m.iter_mut()) {
+                        *m = MOMENTUM * *m + g;
+                        *w -= self.learning_rate * *m;
+                    }
+                },
+                OptimizerKind::Adam => {
+                    let correction1 = 1.0 - BETA1.powi(self.step);
+                    let correction2 = 1.0 - BETA2.powi(self.step);
+                    for (((w, g), m), v) in tensor.data.iter_mut().zip(&grad.data).zip(m.iter_mut()).zip(v.iter_mut()) {
+                        *m = BETA1 * *m + (1.0 - BETA1) * g;
+                        *v = BETA2 * *v + (1.0 - BETA2) * g * g;
+                        let m_hat = *m / correction1;
+                        let v_hat = *v / correction2;
+                        *w -= self.learning_rate * m_hat / (v_hat.sqrt() + EPSILON);
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// Hyper-parameters of a training run
+#[derive(Debug, Clone)]
+pub struct TrainConfig {
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub learning_rate: f32,
+    pub optimizer: OptimizerKind,
+    pub seed: u64,
+}
+
+impl Default for TrainConfig {
+    fn default() -> Self {
+        TrainConfig {
+            epochs: 10,
+            batch_size: 32,
+            learning_rate: 0.001,
+            optimizer: OptimizerKind::Adam,
+            seed: 0,
+        }
+    }
+}
+
+/// Trains a chain of components, e.g. `G⊳D`, against a loss function
+pub struct Trainer {
+    plans: Vec<Plan>,
+    loss: LossExpr,
+    // Parameters that take part in the forward pass but are never updated
+    frozen: BTreeSet<String>,
+    // Values of symbolic coefficients such as `λ`
+    coefficients: HashMap<String, f32>,
+    weights: Weights,
+}
+
+impl Trainer {
+    /// Train a single component; without an explicit loss, cross-entropy is
+    /// used after a softmax, binary cross-entropy after a sigmoid and MSE otherwise
+    pub fn for_component(ast: &ASTNode, component: &str, loss: Option<BaseLoss>) -> Result<Self, String> {
+        let plan = composite::component_plan(ast, component, None)?;
+        check_trainable(std::slice::from_ref(&plan))?;
+        let base = match loss {
+            Some(loss) => loss,
+            None => match plan.steps.last().map(|s| &s.layer.activation) {
+                Some(ActivationFunction::Softmax) => BaseLoss::CrossEntropy,
+                Some(ActivationFunction::Sigmoid) => BaseLoss::BinaryCrossEntropy,
+                _ => BaseLoss::MeanSquaredError,
+            },
+        };
+        Ok(Trainer {
+            plans: vec![plan],
+            loss: LossExpr::single(base),
+            frozen: BTreeSet::new(),
+            coefficients: HashMap::new(),
+            weights: Weights::new(),
+        })
+    }
+    
+    /// Train the composite a declared loss chains together; `label` selects
+    /// among several losses (`L:…⟿…`), otherwise the first one is used
+    pub fn for_loss(ast: &ASTNode, label: Option<&str>) -> Result<Self, String> {
+        Self::for_composite(composite::find(ast, label)?)
+    }
+    
+    /// Train a composite model; stages marked `⊘` run but are not updated
+    pub fn for_composite(composite: Composite) -> Result<Self, String> {
+        if composite.loss.base_losses().next().is_none() {
+            return Err(format!("loss `{}` has no base loss to train", composite.loss));
+        }
+        let plans = composite.plans();
+        check_trainable(&plans)?;
+        Ok(Trainer {
+            plans,
+            frozen: composite.frozen_parameters(),
+            loss: composite.loss,
+            coefficients: HashMap::new(),
+            weights: Weights::new(),
+        })
+    }
+    
+    /// Keep the parameters of a component fixed, in addition to those marked `⊘`
+    pub fn freeze(&mut self, component: &str) -> Result<(), String> {
+        let plans: Vec<&Plan> = self.plans.iter().filter(|p| p.component == component).collect();
+        if plans.is_empty() {
+            return Err(format!("component `{}` is not part of the trained model", component));
+        }
+        for plan in plans {
+            self.frozen.extend(plan.parameter_shapes().into_iter().map(|(name, _)| name));
+        }
+        Ok(())
+    }
+    
+    /// Names of the parameters that are not updated
+    pub fn frozen(&self) -> &BTreeSet<String> {
+        &self.frozen
+    }
+    
+    pub fn loss(&self) -> &LossExpr {
+        &self.loss
+    }
+    
+    /// Give a symbolic coefficient such as `λ` its value
+    pub fn set_coefficient(&mut self, symbol: &str, value: f32) {
+        self.coefficients.insert(symbol.to_string(), value);
+    }
+    
+    /// Loss terms the trainer cannot optimize and skips
+    pub fn unsupported_terms(&self) -> Vec<&WeightedTerm> {
+        self.loss.terms.iter()
+            .filter(|t| matches!(t.term, LossTerm::GradientPenalty { .. }))
+            .collect()
+    }
+    
+    fn coefficient(&self, term: &WeightedTerm) -> Result<f32, String> {
+        let value = match &term.coefficient {
+            None => 1.0,
+            Some(Coefficient::Value(value)) => *value,
+            Some(Coefficient::Symbol(symbol)) => *self.coefficients.get(symbol)
+                .ok_or_else(|| format!("no value for the coefficient `{}` (use --coefficient={}=VALUE)", symbol, symbol))?,
+        };
+        Ok(if term.negated { -value } else { value })
+    }
+    
+    /// Start from deterministic random weights
+    pub fn seed_weights(&mut self, seed: u64) {
+        self.weights = Weights::seeded(&self.plans, seed);
+    }
+    
+    /// Start from existing weights; missing parameters are seeded and
+    /// parameters of other components are kept, so that composites sharing a
+    /// weights file (the two halves of a GAN) can be trained in turn
+    pub fn load_weights(&mut self, weights: Weights, seed: u64) -> Result<(), String> {
+        let mut merged = Weights::seeded(&self.plans, seed);
+        for plan in &self.plans {
+            for (name, shape) in plan.parameter_shapes() {
+                if let Some(tensor) = weights.get(&name) {
+                    if tensor.shape != shape {
+                        return Err(format!("weight `{}` has shape {:?}, expected {:?}", name, tensor.shape, shape));
+                    }
+                }
+            }
+        }
+        for (name, tensor) in weights.iter() {
+            merged.insert(name.clone(), tensor.clone());
+        }
+        self.weights = merged;
+        Ok(())
+    }
+    
+    pub fn weights(&self) -> &Weights {
+        &self.weights
+    }
+    
+    pub fn input_shape(&self) -> &TensorShape {
+        &self.plans[0].input
+    }
+    
+    pub fn output_shape(&self) -> &TensorShape {
+        self.plans.last().unwrap().output()
+    }
+    
+    /// Normalization declared with `⍓` on the input of the first component
+    pub fn preprocess(&self) -> Option<Preprocess> {
+        self.plans[0].preprocess
+    }
+    
+    /// Tokenizer declared with `⌸` on the input of the first component
+    pub fn tokenizer(&self) -> Option<&Arc<Tokenizer>> {
+        self.plans[0].tokenizer.as_ref()
+    }
+    
+    /// Record the forward pass and loss of one example on a tape
+    fn record(&self, tape: &mut Tape, input: &Tensor, target: &Tensor,
+              params: &mut BTreeMap<String, Var>) -> Result<Var, String> {
+        let mut x = tape.leaf(input.clone().reshape(self.input_shape().dims.clone())?);
+        for plan in &self.plans {
+            for step in &plan.steps {
+                x = record_step(tape, step, x, &self.weights, params)?;
+            }
+        }
+        let target = target.clone().reshape(tape.value(x).shape.clone())?;
+        
+        let mut total = None;
+        for term in &self.loss.terms {
+            let value = match &term.term {
+                LossTerm::Base(base) => record_base(tape, *base, x, &target),
+                LossTerm::Regularizer { norm, component } => {
+                    // Weights only; biases are not regularized
+                    let prefix = component.as_ref().map(|id| format!("{}.", id));
+                    let weights: Vec<Var> = params.iter()
+                        .filter(|(name, _)| name.ends_with(".weight"))
+                        .filter(|(name, _)| prefix.as_ref().is_none_or(|p| name.starts_with(p)))
+                        .map(|(_, var)| *var)
+                        .collect();
+                    let mut sum = tape.leaf(Tensor::zeros(vec![1]));
+                    for weight in weights {
+                        let norm = tape.norm(weight, *norm);
+                        sum = tape.add(sum, norm);
+                    }
+                    sum
+                },
+                LossTerm::GradientPenalty { .. } => continue,
+            };
+            let scaled = tape.scale(value, self.coefficient(term)?);
+            total = Some(match total {
+                Some(total) => tape.add(total, scaled),
+                None => scaled,
+            });
+        }
+        total.ok_or_else(|| "the loss has no trainable terms".to_string())
+    }
+    
+    /// Mean loss and parameter gradients over a batch of examples
+    pub fn gradients(&self, batch: &[(Tensor, Tensor)]) -> Result<(f32, BTreeMap<String, Tensor>), String> {
+        let mut total = 0.0;
+        let mut sums: BTreeMap<String, Tensor> = BTreeMap::new();
+        let scale = 1.0 / batch.len().max(1) as f32;
+        
+        for (input, target) in batch {
+            let mut tape = Tape::new();
+            let mut params = BTreeMap::new();
+            let loss = self.record(&mut tape, input, target, &mut params)?;
+            total += tape.value(loss).data[0];
+            
+            let grads = tape.backward(loss);
+            for (name, var) in params {
+                if self.frozen.contains(&name) {
+                    continue;
+                }
+                if let Some(grad) = grads.get(var) {
+                    let sum = sums.entry(name).or_insert_with(|| Tensor::zeros(grad.shape.clone()));
+                    for (s, g) in sum.data.iter_mut().zip(&grad.data) {
+                        *s += g * scale;
+                    }
+                }
+            }
+        }
+        Ok((total * scale, sums))
+    }
+    
+    /// Mean loss over a dataset without updating the weights
+    pub fn evaluate(&self, dataset: &Dataset) -> Result<f32, String> {
+        let mut total = 0.0;
+        for (input, target) in &dataset.examples {
+            let mut tape = Tape::new();
+            let loss = self.record(&mut tape, input, target, &mut BTreeMap::new())?;
+            total += tape.value(loss).data[0];
+        }
+        Ok(total / dataset.len().max(1) as f32)
+    }
+    
+    /// Train for `config.epochs` epochs, calling `on_epoch` with the epoch
+    /// number and mean training loss; returns the loss of every epoch
+    pub fn fit(&mut self, dataset: &Dataset, config: &TrainConfig,
+               mut on_epoch: impl FnMut(usize, f32)) -> Result<Vec<f32>, String> {
+        if dataset.is_empty() {
+            return Err("the dataset is empty".to_string());
+        }
+        let mut optimizer = Optimizer::new(config.optimizer, config.learning_rate);
+        let mut rng = Rng::new(config.seed);
+        let mut order: Vec<usize> = (0..dataset.len()).collect();
+        let mut history = Vec::new();
+        
+        for epoch in 0..config.epochs {
+            dataset::shuffle(&mut order, &mut rng);
+            
+            let mut epoch_loss = 0.0;
+            for chunk in order.chunks(config.batch_size.max(1)) {
+                let batch: Vec<(Tensor, Tensor)> = chunk.iter().map(|&i| dataset.examples[i].clone()).collect();
+                let (loss, gradients) = self.gradients(&batch)?;
+                optimizer.update(&mut self.weights, &gradients);
+                epoch_loss += loss * batch.len() as f32;
+            }
+            epoch_loss /= dataset.len() as f32;
+            on_epoch(epoch + 1, epoch_loss);
+            history.push(epoch_loss);
+        }
+        Ok(history)
+    }
+}
+
+// The tape has no operations for these layers, so training is refused
+// before it starts rather than when the first example reaches them
+fn check_trainable(plans: &[Plan]) -> Result<(), String> {
+    for plan in plans {
+        for step in &plan.steps {
+            let layer = &step.layer;
+            if matches!(layer.layer_type, LayerType::LSTM | LayerType::Embedding | LayerType::Attention | LayerType::Custom(_)) {
+                return Err(format!("`{}` in `{}` cannot be trained yet", layer.symbol(), plan.component));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Record one layer of a plan on the tape, registering its parameters
+fn record_step(tape: &mut Tape, step: &Step, x: Var, weights: &Weights,
+               params: &mut BTreeMap<String, Var>) -> Result<Var, String> {
+    let layer = &step.layer;
+    let mut parameter = |suffix: &str| -> Result<Var, String> {
+        let name = format!("{}.{}", step.key, suffix);
+        if let Some(var) = params.get(&name) {
+            return Ok(*var);
+        }
+        let tensor = weights.get(&name).ok_or_else(|| format!("missing weight `{}`", name))?;
+        let var = tape.leaf(tensor.clone());
+        params.insert(name, var);
+        Ok(var)
+    };
+    
+    let y = match layer.layer_type {
+        LayerType::Dense(_) => {
+            let (w, b) = (parameter("weight")?, parameter("bias")?);
+            tape.dense(x, w, b)
+        },
+        LayerType::Convolutional(_) => {
+            let (w, b) = (parameter("weight")?, parameter("bias")?);
+            tape.conv2d(x, w, b, layer.window().unwrap())
+        },
+        LayerType::TransposeConv => {
+            let (w, b) = (parameter("weight")?, parameter("bias")?);
+            tape.conv2d_transpose(x, w, b, layer.param(STRIDE))
+        },
+        LayerType::Pooling => tape.max_pool2d(x, layer.window().unwrap()),
+        LayerType::Upsampling if target_shape(layer).is_some() => {
+            let (w, b) = (parameter("weight")?, parameter("bias")?);
+            let flat = tape.reshape(x, vec![step.input.elements()])?;
+            tape.dense(flat, w, b)
+        },
+        LayerType::Upsampling => tape.upsample(x, layer.param(FACTOR)),
+        LayerType::Flatten | LayerType::Reshape | LayerType::AttentionHeads | LayerType::BatchSize => x,
+        LayerType::LSTM | LayerType::Embedding | LayerType::Attention | LayerType::Custom(_) => {
+            unreachable!("untrainable layers are rejected by `check_trainable`")
+        },
+    };
+    let y = tape.reshape(y, step.output.dims.clone())?;
+    Ok(tape.activation(y, &layer.activation))
+}
@@ -1,12 +1,25 @@
-use crate::ast::ASTNode;
 use crate::platform_detector::{Platform, determine_best_target};
 use crate::parser;
-use crate::compiler::JsCompiler;
-use crate::compilers::react_compiler::ReactCompiler;
-use crate::compilers::android_compiler::AndroidCompiler;
+use crate::shape_inference;
+use crate::composite;
+use crate::summary;
+use crate::diff;
+use crate::tokenizer;
+use crate::graph;
+use crate::importer;
+use crate::safetensors;
+use crate::dataset::LoadOptions;
+use crate::training::{Dataset, OptimizerKind, TrainConfig, Trainer};
+use crate::interpreter::{plan_network, Interpreter, Weights};
+use crate::quantization::{self, Granularity, QuantizedModel};
+use crate::ast::{ASTNode, BaseLoss, BlockMode};
+use crate::backend::{Artifact, Backend, BackendOptions, BackendRegistry};
+use crate::custom_ops::{CustomOp, CustomOpRegistry};
+use crate::diagnostics::{codes, explain, render_all, Diagnostic, MessageFormat};
 use std::fs;
 use std::path::Path;
 use std::env;
+use std::process;
 
 /// Represents the web framework/library to target
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -23,6 +36,20 @@ pub struct UniversalCompiler {
     force_platform: Option<Platform>,
     output_directory: String,
     web_framework: WebFramework,
+    backend: Option<String>,
+    registry: BackendRegistry,
+    custom_ops: CustomOpRegistry,
+    message_format: MessageFormat,
+    weights: Option<Weights>,
+    // Calibration CSV and granularity for int8 code paths
+    calibration: Option<(String, Granularity)>,
+    blocks: BlockMode,
+}
+
+impl Default for UniversalCompiler {
+    fn default() -> Self {
+        UniversalCompiler::new()
+    }
 }
 
 impl UniversalCompiler {
@@ -32,6 +59,13 @@ impl UniversalCompiler {
             force_platform: None,
             output_directory: String::from("."),
             web_framework: WebFramework::PureJs, // Default to pure JS
+            backend: None,
+            registry: BackendRegistry::with_builtin(),
+            custom_ops: CustomOpRegistry::new(),
+            message_format: MessageFormat::Human,
+            weights: None,
+            calibration: None,
+            blocks: BlockMode::Loop,
         }
     }
     
@@ -47,367 +81,716 @@ impl UniversalCompiler {
         self.web_framework = framework;
     }
     
+    /// Select a backend by name, bypassing platform detection
+    pub fn set_backend(&mut self, name: &str) {
+        self.backend = Some(name.to_string());
+    }
+    
+    /// Choose between rustc-style and JSON diagnostics
+    pub fn set_message_format(&mut self, format: MessageFormat) {
+        self.message_format = format;
+    }
+    
+    /// Trained parameters passed to backends that embed weights
+    pub fn set_weights(&mut self, weights: Weights) {
+        self.weights = Some(weights);
+    }
+    
+    /// Quantize the network to int8 with the rows of a calibration CSV for
+    /// backends with quantized code paths
+    pub fn set_calibration(&mut self, csv: String, granularity: Granularity) {
+        self.calibration = Some((csv, granularity));
+    }
+    
+    /// Emit repeated blocks as loops or as unrolled copies
+    pub fn set_block_mode(&mut self, mode: BlockMode) {
+        self.blocks = mode;
+    }
+    
     pub fn get_target_platform(&self) -> Platform {
         self.force_platform.unwrap_or(self.platform)
     }
     
+    pub fn registry(&self) -> &BackendRegistry {
+        &self.registry
+    }
+    
+    /// Register an additional backend so it can be listed and selected
+    pub fn register_backend(&mut self, backend: Box<dyn Backend>) {
+        self.registry.register(backend);
+    }
+    
+    pub fn custom_ops(&self) -> &CustomOpRegistry {
+        &self.custom_ops
+    }
+    
+    /// Make `⇝symbol` available to the programs this compiler compiles
+    pub fn register_custom_op(&mut self, op: impl CustomOp + 'static) {
+        self.custom_ops.register(op);
+    }
+    
+    /// Pick the backend for this compilation
+    pub fn select_backend(&self) -> Result<&dyn Backend, Diagnostic> {
+        // An explicitly requested backend always wins
+        if let Some(name) = &self.backend {
+            return self.registry.get(name).ok_or_else(|| {
+                Diagnostic::error(codes::UNKNOWN_BACKEND, format!("unknown backend `{}`", name))
+                    .with_note(format!("available backends: {}", self.registry.names().join(", ")))
+            });
+        }
+        
+        let target_platform = self.get_target_platform();
+        
+        // For web, use the specified framework
+        if target_platform == Platform::Web || self.registry.default_for_platform(target_platform).is_none() {
+            if target_platform != Platform::Web {
+                println!("Platform {:?} compilation is redirecting to web output with {:?} framework.", 
+                         target_platform, self.web_framework);
+            }
+            
+            let name = match self.web_framework {
+                WebFramework::PureJs => "web",
+                WebFramework::React => "react",
+                WebFramework::Angular => {
+                    println!("Angular support is limited, falling back to pure JS");
+                    "web"
+                },
+                WebFramework::Vue => {
+                    println!("Vue support is limited, falling back to pure JS");
+                    "web"
+                },
+                WebFramework::Svelte => {
+                    println!("Svelte support is limited, falling back to pure JS");
+                    "web"
+                },
+            };
+            
+            return self.registry.get(name).ok_or_else(|| {
+                Diagnostic::error(codes::UNKNOWN_BACKEND, format!("no backend registered for web framework {:?}", self.web_framework))
+            });
+        }
+        
+        self.registry.default_for_platform(target_platform).ok_or_else(|| {
+            Diagnostic::error(codes::UNKNOWN_BACKEND, format!("no backend registered for platform {:?}", target_platform))
+        })
+    }
+    
     pub fn compile(&self, source_file: &str) -> Result<(), String> {
         // Read the source file
         let source_content = fs::read_to_string(source_file)
             .map_err(|e| format!("Failed to read source file: {}", e))?;
         
-        // Parse the source
-        let ast = parser::parse(&source_content)
-            .map_err(|e| format!("Failed to parse source: {}", e))?;
-        
         // Extract the app name from the file name
         let path = Path::new(source_file);
         let app_name = path.file_stem()
             .and_then(|s| s.to_str())
             .ok_or_else(|| "Invalid source file name".to_string())?;
         
-        let target_platform = self.get_target_platform();
+        let base = path.parent().unwrap_or(Path::new(""));
+        let diagnostics = match self.compile_source(&source_content, app_name, base) {
+            Ok(warnings) => warnings,
+            Err(diagnostics) => diagnostics,
+        };
+        eprint!("{}", render_all(&diagnostics, source_file, &source_content, self.message_format));
         
-        // Compile based on target platform
-        match target_platform {
-            Platform::Android => self.compile_for_android(&ast, app_name),
-            Platform::Web => {
-                // For web, use the specified framework
-                match self.web_framework {
-                    WebFramework::PureJs => self.compile_for_web_js(&ast, app_name),
-                    WebFramework::React => self.compile_for_web_react(&ast, app_name),
-                    WebFramework::Angular => {
-                        println!("Angular support is limited, falling back to pure JS");
-                        self.compile_for_web_js(&ast, app_name)
-                    },
-                    WebFramework::Vue => {
-                        println!("Vue support is limited, falling back to pure JS");
-                        self.compile_for_web_js(&ast, app_name)
-                    },
-                    WebFramework::Svelte => {
-                        println!("Svelte support is limited, falling back to pure JS");
-                        self.compile_for_web_js(&ast, app_name)
-                    },
-                }
-            },
-            // For all other platforms, fall back to web (using the specified framework)
-            _ => {
-                println!("Platform {:?} compilation is redirecting to web output with {:?} framework.", 
-                         target_platform, self.web_framework);
-                
-                match self.web_framework {
-                    WebFramework::React => self.compile_for_web_react(&ast, app_name),
-                    _ => self.compile_for_web_js(&ast, app_name), // Default to pure JS for other frameworks
-                }
-            }
+        let error_count = diagnostics.iter().filter(|d| d.is_error()).count();
+        if error_count > 0 {
+            return Err(format!("could not compile `{}` due to {} previous error{}",
+                               source_file, error_count, if error_count == 1 { "" } else { "s" }));
         }
-    }
-    
-    fn compile_for_android(&self, ast: &ASTNode, app_name: &str) -> Result<(), String> {
-        println!("Compiling for Android...");
-        
-        let mut compiler = AndroidCompiler::new();
-        
-        // Compile the AST to Kotlin code
-        let kotlin_code = compiler.compile(ast)?;
-        
-        // Save Kotlin code to file for inspection
-        let kotlin_file = format!("{}/{}.kt", self.output_directory, app_name);
-        fs::write(&kotlin_file, &kotlin_code).map_err(|e| format!("Failed to write Kotlin file: {}", e))?;
-        
-        println!("Generated Kotlin code at {}", kotlin_file);
-        println!("You can build this into an Android app using Android Studio.");
         
         Ok(())
     }
     
-    // Pure JavaScript compilation for web
-    fn compile_for_web_js(&self, ast: &ASTNode, app_name: &str) -> Result<(), String> {
-        println!("Compiling for Web (Pure JavaScript)...");
+    /// Parse and shape-check a source file without compiling it, printing any diagnostics
+    pub fn analyze(&self, source_file: &str) -> Result<ASTNode, String> {
+        let source_content = fs::read_to_string(source_file)
+            .map_err(|e| format!("Failed to read source file: {}", e))?;
         
-        // JavaScript is the primary target for web
-        let mut js_compiler = JsCompiler::new();
-        let js_code = match js_compiler.compile(ast) {
-            Ok(code) => code,
-            Err(e) => return Err(format!("JavaScript compilation error: {}", e))
+        let (ast, diagnostics) = match parser::parse_with_diagnostics(&source_content, &self.custom_ops) {
+            Ok((mut ast, mut diagnostics)) => {
+                diagnostics.extend(tokenizer::attach(&mut ast, Path::new(source_file).parent().unwrap_or(Path::new(""))));
+                diagnostics.extend(shape_inference::infer_shapes(&mut ast));
+                diagnostics.extend(composite::check_shares(&ast));
+                (Some(ast), diagnostics)
+            },
+            Err(diagnostics) => (None, diagnostics),
         };
+        eprint!("{}", render_all(&diagnostics, source_file, &source_content, self.message_format));
         
-        // Create web directory
-        let web_dir = format!("{}/{}_web", self.output_directory, app_name);
-        let web_dir_path = Path::new(&web_dir);
+        let error_count = diagnostics.iter().filter(|d| d.is_error()).count();
+        match ast {
+            Some(ast) if error_count == 0 => Ok(ast),
+            _ => Err(format!("could not analyze `{}` due to {} previous error{}",
+                             source_file, error_count, if error_count == 1 { "" } else { "s" })),
+        }
+    }
+    
+    /// Compile source text, returning warnings on success or every diagnostic on
+    /// failure; tokenizer files are read relative to `base`
+    pub fn compile_source(&self, source: &str, app_name: &str, base: &Path) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        // Parse the source
+        let (mut ast, mut diagnostics) = parser::parse_with_diagnostics(source, &self.custom_ops)?;
         
-        if !web_dir_path.exists() {
-            fs::create_dir_all(&web_dir).map_err(|e| format!("Failed to create web project directory: {}", e))?;
+        diagnostics.extend(tokenizer::attach(&mut ast, base));
+        diagnostics.extend(shape_inference::infer_shapes(&mut ast));
+        diagnostics.extend(composite::check_shares(&ast));
+        if diagnostics.iter().any(|d| d.is_error()) {
+            return Err(diagnostics);
         }
         
-        // Write the JavaScript code
-        let js_path = web_dir_path.join(format!("{}.js", app_name));
-        fs::write(&js_path, &js_code).map_err(|e| format!("Failed to write JavaScript file: {}", e))?;
+        let backend = match self.select_backend() {
+            Ok(backend) => backend,
+            Err(diagnostic) => {
+                diagnostics.push(diagnostic);
+                return Err(diagnostics);
+            }
+        };
+        println!("Compiling with backend '{}' ({})...", backend.name(), backend.description());
         
-        // Create a basic HTML file
-        let html_content = format!(r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>{} - GaiaScript Web App</title>
-    <style>
-        body {{ font-family: Arial, sans-serif; margin: 0; padding: 20px; }}
-        .container {{ max-width: 1200px; margin: 0 auto; padding: 1rem; }}
-        header {{ background-color: #f0f0f0; padding: 1rem; border-radius: 4px; margin-bottom: 1rem; }}
-        h1 {{ margin-top: 0; }}
-    </style>
-</head>
-<body>
-    <div class="container">
-        <header>
-            <h1>{} - GaiaScript App</h1>
-        </header>
-        <div id="app"></div>
-    </div>
-    <script src="{}.js"></script>
-</body>
-</html>"#, app_name, app_name, app_name);
+        let mut options = BackendOptions::new(app_name);
+        options.web_framework = self.web_framework;
+        options.weights = self.weights.clone();
+        options.blocks = self.blocks;
+        if let Some((csv, granularity)) = &self.calibration {
+            match self.quantize(&ast, csv, *granularity) {
+                Ok(models) => options.quantized = models,
+                Err(diagnostic) => {
+                    diagnostics.push(diagnostic);
+                    return Err(diagnostics);
+                }
+            }
+        }
         
-        let html_path = web_dir_path.join("index.html");
-        fs::write(&html_path, &html_content).map_err(|e| format!("Failed to write HTML file: {}", e))?;
+        let result = backend.compile(&ast, &options)
+            .and_then(|artifacts| self.write_artifacts(&artifacts));
+        if let Err(diagnostic) = result {
+            diagnostics.push(diagnostic);
+            return Err(diagnostics);
+        }
         
-        println!("Generated Pure JavaScript Web application at {}", web_dir);
-        println!("To serve the application:");
-        println!("  cd {}", web_dir);
-        println!("  python -m http.server 8000  # or any other web server");
-        println!("  Open http://localhost:8000 in your browser");
+        for line in backend.next_steps(&self.output_directory, &options) {
+            println!("{}", line);
+        }
         
-        Ok(())
+        Ok(diagnostics)
     }
     
-    // React-specific compilation for web
-    fn compile_for_web_react(&self, ast: &ASTNode, app_name: &str) -> Result<(), String> {
-        println!("Compiling for Web (React.js)...");
-        
-        // Use the React compiler
-        let mut react_compiler = ReactCompiler::new();
-        let react_code = match react_compiler.compile(ast) {
-            Ok(code) => code,
-            Err(e) => return Err(format!("React compilation error: {}", e))
-        };
-        
-        // Create React app directory structure
-        let react_dir = format!("{}/{}_react", self.output_directory, app_name);
-        let react_dir_path = Path::new(&react_dir);
-        let src_dir_path = react_dir_path.join("src");
-        let public_dir_path = react_dir_path.join("public");
-        
-        // Create directories
-        if !react_dir_path.exists() {
-            fs::create_dir_all(&react_dir_path).map_err(|e| format!("Failed to create React project directory: {}", e))?;
-        }
-        if !src_dir_path.exists() {
-            fs::create_dir_all(&src_dir_path).map_err(|e| format!("Failed to create src directory: {}", e))?;
+    // Quantize every component the calibration rows fit, printing how far
+    // int8 drifts from float
+    fn quantize(&self, ast: &ASTNode, csv: &str, granularity: Granularity) -> Result<Vec<QuantizedModel>, Diagnostic> {
+        let weights = Weights::for_export(&plan_network(ast)?, self.weights.as_ref());
+        let interpreter = Interpreter::new(ast, weights)?;
+        let network = quantization::quantize_network(&interpreter, csv, None, granularity);
+        for (component, reason) in network.skipped {
+            eprintln!("warning: component `{}` stays float: {}", component, reason);
         }
-        if !public_dir_path.exists() {
-            fs::create_dir_all(&public_dir_path).map_err(|e| format!("Failed to create public directory: {}", e))?;
+        let mut models = Vec::new();
+        for (model, report) in network.models {
+            print!("{} ({}): {}", model.component, granularity.name(), report);
+            models.push(model);
         }
+        Ok(models)
+    }
+    
+    /// Write backend artifacts below the output directory
+    fn write_artifacts(&self, artifacts: &[Artifact]) -> Result<(), Diagnostic> {
+        let output_dir = Path::new(&self.output_directory);
         
-        // Write React component
-        let app_js_path = src_dir_path.join("App.js");
-        fs::write(&app_js_path, &react_code).map_err(|e| format!("Failed to write App.js file: {}", e))?;
+        for artifact in artifacts {
+            let artifact_path = output_dir.join(&artifact.path);
+            if let Some(parent) = artifact_path.parent() {
+                if !parent.exists() {
+                    fs::create_dir_all(parent).map_err(|e| {
+                        Diagnostic::error(codes::IO_ERROR, format!("failed to create directory {}: {}", parent.display(), e))
+                    })?;
+                }
+            }
+            fs::write(&artifact_path, &artifact.contents).map_err(|e| {
+                Diagnostic::error(codes::IO_ERROR, format!("failed to write {}: {}", artifact_path.display(), e))
+            })?;
+        }
         
-        // Create App.css
-        let app_css_content = r#"
-.App {
-  text-align: center;
+        Ok(())
+    }
 }
 
-.App-header {
-  background-color: #282c34;
-  min-height: 100vh;
-  display: flex;
-  flex-direction: column;
-  align-items: center;
-  justify-content: center;
-  font-size: calc(10px + 2vmin);
-  color: white;
+// Helper function to parse web framework from string
+pub fn parse_web_framework(framework_str: &str) -> WebFramework {
+    match framework_str.to_lowercase().as_str() {
+        "react" => WebFramework::React,
+        "angular" => WebFramework::Angular,
+        "vue" => WebFramework::Vue,
+        "svelte" => WebFramework::Svelte,
+        _ => WebFramework::PureJs, // Default to pure JS
+    }
 }
 
-.App-link {
-  color: #61dafb;
+fn print_usage() {
+    eprintln!("Usage: gaia [options] <file.gaia>");
+    eprintln!("       gaia targets");
+    eprintln!("       gaia explain <CODE>");
+    eprintln!("       gaia summary [--json] <file.gaia>");
+    eprintln!("       gaia diff [--json] <before.gaia> <after.gaia>");
+    eprintln!("       gaia graph [--component=ID] [--format=dot|svg] [--output=FILE] <file.gaia>");
+    eprintln!("       gaia train --data=FILE [train options] <file.gaia>");
+    eprintln!("       gaia import [--component=ID] [--output=FILE] <model.onnx|model.json>");
+    eprintln!("       gaia weights [--output=FILE] <file.gaia> [weights]");
+    eprintln!("       gaia quantize --data=FILE [quantize options] <file.gaia>");
+    eprintln!("       gaia tokenize [--component=ID] <file.gaia> [TEXT...]");
+    eprintln!("Commands:");
+    eprintln!("  targets               List the available compilation backends");
+    eprintln!("  explain CODE          Print a detailed explanation of a diagnostic code");
+    eprintln!("  summary FILE          Print layers, parameters, MACs and memory per component");
+    eprintln!("  diff FILE FILE        Compare two programs by component: layers, parameters, activations, UI");
+    eprintln!("  graph FILE            Draw the architecture as Graphviz DOT or SVG (default: DOT on stdout)");
+    eprintln!("  train FILE            Train a declared loss (or --component) on a dataset");
+    eprintln!("  import FILE           Convert an ONNX model or Keras model.json into GaiaScript");
+    eprintln!("  weights FILE          Check weights against a network, or write seeded ones with --output");
+    eprintln!("  quantize FILE         Quantize to int8 with a calibration CSV and report the accuracy change");
+    eprintln!("  tokenize FILE         Print the token ids of each TEXT (or stdin line) for a text input");
+    eprintln!("Options:");
+    eprintln!("  --platform=PLATFORM   Force a specific target platform");
+    eprintln!("                        Supported platforms: macos, windows, linux, ios, android, web");
+    eprintln!("  --framework=FRAMEWORK Force a specific web framework");
+    eprintln!("                        Supported frameworks: js, react, angular, vue, svelte");
+    eprintln!("  --backend=NAME        Compile with a specific backend (see `gaia targets`)");
+    eprintln!("  --output=DIR          Specify output directory (default: current directory)");
+    eprintln!("  --message-format=FMT  Diagnostic format: human (default) or json");
+    eprintln!("  --weights=FILE        Trained weights (.safetensors or .json) for backends that ship them");
+    eprintln!("  --calibration=FILE    Quantize to int8 with CSV inputs for the asm and wasm backends");
+    eprintln!("  --per-tensor          One weight scale per tensor instead of per output channel");
+    eprintln!("  --blocks=MODE         Repeated blocks as loop (default) or unroll in the asm and Kotlin backends");
+    eprintln!("  --help                Show this help message");
+    eprintln!("Train options:");
+    eprintln!("  --data=FILE           CSV rows of inputs followed by targets (or a class index), .npy,");
+    eprintln!("                        .npz, IDX (e.g. MNIST, optionally .gz) or a folder of PPM/PNG");
+    eprintln!("                        images with one subfolder per class; for a tokenized `T` input,");
+    eprintln!("                        .tsv lines of text<TAB>label");
+    eprintln!("  --labels=FILE         Targets or class indices for .npy and IDX data");
+    eprintln!("  --label-column=COL    CSV column (name or position) holding the class index or target");
+    eprintln!("  --loss=LABEL          Train the loss declared as `LABEL:…⟿…` (default: the first loss)");
+    eprintln!("  --component=ID        Train a single component instead of a declared loss");
+    eprintln!("  --loss-fn=NAME        Loss for --component: mse, bce, ce, hinge or kl");
+    eprintln!("  --coefficient=SYM=F   Value of a loss coefficient such as λ (repeatable)");
+    eprintln!("  --freeze=ID           Do not update the parameters of a component (repeatable)");
+    eprintln!("  --optimizer=NAME      sgd, momentum or adam (default: adam)");
+    eprintln!("  --epochs=N            Number of passes over the data (default: 10)");
+    eprintln!("  --batch-size=N        Examples per update (default: 32)");
+    eprintln!("  --learning-rate=F     Step size (default: 0.001)");
+    eprintln!("  --seed=N              Seed for initial weights and shuffling (default: 0)");
+    eprintln!("  --weights=FILE        Start from previously saved weights");
+    eprintln!("  --save=FILE           Where to write the trained weights (default: <file>.safetensors)");
+    eprintln!("Quantize options:");
+    eprintln!("  --data=FILE           CSV rows of inputs, optionally followed by targets for accuracy");
+    eprintln!("  --component=ID        Quantize a single component (default: every component)");
+    eprintln!("  --per-tensor          One weight scale per tensor instead of per output channel");
+    eprintln!("  --weights=FILE        Weights to quantize (default: seeded weights)");
+    eprintln!("  --output=FILE         Where to write the int8 weights (default: <file>.int8.safetensors)");
 }
-"#;
-        let app_css_path = src_dir_path.join("App.css");
-        fs::write(&app_css_path, app_css_content).map_err(|e| format!("Failed to write App.css file: {}", e))?;
-        
-        // Create index.js
-        let index_js_content = r#"
-import React from 'react';
-import ReactDOM from 'react-dom/client';
-import './index.css';
-import App from './App';
 
-const root = ReactDOM.createRoot(document.getElementById('root'));
-root.render(
-  <React.StrictMode>
-    <App />
-  </React.StrictMode>
-);
-"#;
-        let index_js_path = src_dir_path.join("index.js");
-        fs::write(&index_js_path, index_js_content).map_err(|e| format!("Failed to write index.js file: {}", e))?;
-        
-        // Create index.css
-        let index_css_content = r#"
-body {
-  margin: 0;
-  font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', 'Roboto', 'Oxygen',
-    'Ubuntu', 'Cantarell', 'Fira Sans', 'Droid Sans', 'Helvetica Neue',
-    sans-serif;
-  -webkit-font-smoothing: antialiased;
-  -moz-osx-font-smoothing: grayscale;
+// Print every registered backend with its capabilities
+fn list_targets(registry: &BackendRegistry) {
+    // Columns as wide as their longest entry
+    let name_width = registry.iter().map(|b| b.name().chars().count()).max().unwrap_or(0);
+    let description_width = registry.iter().map(|b| b.description().chars().count()).max().unwrap_or(0);
+    println!("Available targets:");
+    for backend in registry.iter() {
+        let capabilities: Vec<String> = backend.capabilities().iter()
+            .map(|c| c.to_string())
+            .collect();
+        println!("  {:<name_width$} {:<description_width$} [{}]", backend.name(), backend.description(), capabilities.join(", "));
+    }
 }
 
-code {
-  font-family: source-code-pro, Menlo, Monaco, Consolas, 'Courier New',
-    monospace;
+// Print the long explanation of a diagnostic code
+fn explain_code(code: Option<&str>) -> Result<(), String> {
+    let code = code.ok_or("expected a diagnostic code: gaia explain <CODE>")?;
+    let explanation = explain(code).ok_or_else(|| format!("no explanation for diagnostic code '{}'", code))?;
+    println!("{}: {}", explanation.code, explanation.title);
+    println!();
+    println!("{}", explanation.text);
+    Ok(())
 }
-"#;
-        let index_css_path = src_dir_path.join("index.css");
-        fs::write(&index_css_path, index_css_content).map_err(|e| format!("Failed to write index.css file: {}", e))?;
-        
-        // Create index.html
-        let index_html_content = format!(r#"<!DOCTYPE html>
-<html lang="en">
-  <head>
-    <meta charset="utf-8" />
-    <meta name="viewport" content="width=device-width, initial-scale=1" />
-    <meta name="theme-color" content="#000000" />
-    <meta name="description" content="Web app created using GaiaScript" />
-    <title>{} - GaiaScript React App</title>
-  </head>
-  <body>
-    <noscript>You need to enable JavaScript to run this app.</noscript>
-    <div id="root"></div>
-  </body>
-</html>"#, app_name);
-        let index_html_path = public_dir_path.join("index.html");
-        fs::write(&index_html_path, index_html_content).map_err(|e| format!("Failed to write index.html file: {}", e))?;
-        
-        // Create package.json
-        let package_json_content = format!(r#"{{
-  "name": "{}",
-  "version": "0.1.0",
-  "private": true,
-  "dependencies": {{
-    "react": "^18.2.0",
-    "react-dom": "^18.2.0",
-    "react-scripts": "5.0.1"
-  }},
-  "scripts": {{
-    "start": "react-scripts start",
-    "build": "react-scripts build",
-    "test": "react-scripts test",
-    "eject": "react-scripts eject"
-  }},
-  "eslintConfig": {{
-    "extends": [
-      "react-app"
-    ]
-  }},
-  "browserslist": {{
-    "production": [
-      ">0.2%",
-      "not dead",
-      "not op_mini all"
-    ],
-    "development": [
-      "last 1 chrome version",
-      "last 1 firefox version",
-      "last 1 safari version"
-    ]
-  }}
-}}"#, app_name.to_lowercase().replace(" ", "-"));
-        let package_json_path = react_dir_path.join("package.json");
-        fs::write(&package_json_path, package_json_content).map_err(|e| format!("Failed to write package.json file: {}", e))?;
-        
-        // Create README.md
-        let readme_content = format!(r#"# {} React App
-
-This is a React application generated from GaiaScript.
 
-## Available Scripts
-
-In the project directory, you can run:
+// Print a model summary of every network component in a file
+fn summarize_file(compiler: &mut UniversalCompiler, args: &[String]) -> Result<(), String> {
+    let mut json = false;
+    let mut source_file = None;
+    for arg in args {
+        if arg == "--json" {
+            json = true;
+            compiler.set_message_format(MessageFormat::Json);
+        } else if !arg.starts_with("--") {
+            source_file = Some(arg.as_str());
+        }
+    }
+    
+    let source_file = source_file.ok_or("no source file specified: gaia summary [--json] <file.gaia>")?;
+    let summary = summary::summarize(&compiler.analyze(source_file)?);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&summary.to_json()).unwrap_or_default());
+    } else {
+        print!("{}", summary.to_text());
+    }
+    Ok(())
+}
 
-### `npm start`
+// Draw the components of a file as DOT or SVG, to stdout or a file
+fn graph_file(compiler: &UniversalCompiler, args: &[String]) -> Result<(), String> {
+    let mut source_file = None;
+    let mut component = None;
+    let mut format = None;
+    let mut output = None;
+    for arg in args {
+        if let Some(name) = arg.strip_prefix("--component=") {
+            component = Some(name);
+        } else if let Some(kind) = arg.strip_prefix("--format=") {
+            format = Some(kind);
+        } else if let Some(path) = arg.strip_prefix("--output=") {
+            output = Some(path);
+        } else if !arg.starts_with("--") {
+            source_file = Some(arg.as_str());
+        }
+    }
+    
+    let source_file = source_file.ok_or("no source file specified")?;
+    // Without --format the output extension decides
+    let format = format.unwrap_or(match output {
+        Some(path) if path.ends_with(".svg") => "svg",
+        _ => "dot",
+    });
+    let ast = compiler.analyze(source_file)?;
+    let diagram = graph::build(&ast, component)?;
+    let title = Path::new(source_file).file_stem().and_then(|s| s.to_str()).unwrap_or("network");
+    let text = match format {
+        "dot" => diagram.to_dot(title),
+        "svg" => diagram.to_svg(title),
+        other => return Err(format!("unknown graph format '{}' (expected dot or svg)", other)),
+    };
+    
+    match output {
+        Some(path) => {
+            fs::write(path, text).map_err(|e| format!("failed to write '{}': {}", path, e))?;
+            println!("Wrote {}", path);
+        },
+        None => print!("{}", text),
+    }
+    Ok(())
+}
 
-Runs the app in the development mode.\
-Open [http://localhost:3000](http://localhost:3000) to view it in your browser.
+// Print the structural differences between two programs
+fn diff_files(compiler: &mut UniversalCompiler, args: &[String]) -> Result<(), String> {
+    let mut json = false;
+    let mut files = Vec::new();
+    for arg in args {
+        if arg == "--json" {
+            json = true;
+            compiler.set_message_format(MessageFormat::Json);
+        } else if !arg.starts_with("--") {
+            files.push(arg.as_str());
+        }
+    }
+    
+    let [before, after] = files[..] else {
+        return Err("expected two source files: gaia diff [--json] <before.gaia> <after.gaia>".to_string());
+    };
+    let changes = diff::diff(&compiler.analyze(before)?, &compiler.analyze(after)?);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&changes.to_json()).unwrap_or_default());
+    } else {
+        print!("{}", changes.to_text());
+    }
+    Ok(())
+}
 
-### `npm run build`
+// Train a network declared in a file and save its weights
+fn train_file(compiler: &UniversalCompiler, args: &[String]) -> Result<(), String> {
+    let mut config = TrainConfig::default();
+    let mut source_file = None;
+    let mut data_file = None;
+    let mut load_options = LoadOptions::default();
+    let mut loss_label = None;
+    let mut component = None;
+    let mut loss_fn = None;
+    let mut weights_file = None;
+    let mut save_file = None;
+    let mut coefficients = Vec::new();
+    let mut frozen = Vec::new();
+    
+    for arg in args {
+        if let Some(path) = arg.strip_prefix("--data=") {
+            data_file = Some(path);
+        } else if let Some(path) = arg.strip_prefix("--labels=") {
+            load_options.labels = Some(path.to_string());
+        } else if let Some(column) = arg.strip_prefix("--label-column=") {
+            load_options.label_column = Some(column.to_string());
+        } else if let Some(label) = arg.strip_prefix("--loss=") {
+            loss_label = Some(label);
+        } else if let Some(name) = arg.strip_prefix("--component=") {
+            component = Some(name);
+        } else if let Some(name) = arg.strip_prefix("--loss-fn=") {
+            loss_fn = Some(BaseLoss::from_name(name)
+                .ok_or_else(|| format!("unknown loss function '{}'", name))?);
+        } else if let Some(assignment) = arg.strip_prefix("--coefficient=") {
+            let (symbol, value) = assignment.split_once('=')
+                .ok_or_else(|| format!("expected --coefficient=SYMBOL=VALUE, got '{}'", arg))?;
+            let value = value.parse().map_err(|_| format!("invalid coefficient value '{}'", value))?;
+            coefficients.push((symbol.to_string(), value));
+        } else if let Some(name) = arg.strip_prefix("--freeze=") {
+            frozen.push(name);
+        } else if let Some(name) = arg.strip_prefix("--optimizer=") {
+            config.optimizer = OptimizerKind::parse(name)
+                .ok_or_else(|| format!("unknown optimizer '{}'", name))?;
+        } else if let Some(value) = arg.strip_prefix("--epochs=") {
+            config.epochs = value.parse().map_err(|_| format!("invalid epoch count '{}'", value))?;
+        } else if let Some(value) = arg.strip_prefix("--batch-size=") {
+            config.batch_size = value.parse().map_err(|_| format!("invalid batch size '{}'", value))?;
+        } else if let Some(value) = arg.strip_prefix("--learning-rate=") {
+            config.learning_rate = value.parse().map_err(|_| format!("invalid learning rate '{}'", value))?;
+        } else if let Some(value) = arg.strip_prefix("--seed=") {
+            config.seed = value.parse().map_err(|_| format!("invalid seed '{}'", value))?;
+        } else if let Some(path) = arg.strip_prefix("--weights=") {
+            weights_file = Some(path);
+        } else if let Some(path) = arg.strip_prefix("--save=") {
+            save_file = Some(path.to_string());
+        } else if !arg.starts_with("--") {
+            source_file = Some(arg.as_str());
+        }
+    }
+    
+    let source_file = source_file.ok_or("no source file specified")?;
+    let data_file = data_file.ok_or("no dataset specified (use --data=FILE)")?;
+    let ast = compiler.analyze(source_file)?;
+    
+    let mut trainer = match component {
+        Some(component) => Trainer::for_component(&ast, component, loss_fn)?,
+        None => Trainer::for_loss(&ast, loss_label)?,
+    };
+    for (symbol, value) in coefficients {
+        trainer.set_coefficient(&symbol, value);
+    }
+    for component in frozen {
+        trainer.freeze(component)?;
+    }
+    for term in trainer.unsupported_terms() {
+        eprintln!("warning: the gradient penalty `{}` is not supported by the trainer and is ignored", term.term);
+    }
+    match weights_file {
+        Some(path) => trainer.load_weights(read_weights(path)?, config.seed)?,
+        None => trainer.seed_weights(config.seed),
+    }
+    
+    load_options.tokenizer = trainer.tokenizer().cloned();
+    let mut dataset = Dataset::load(data_file, trainer.input_shape(), trainer.output_shape(), &load_options)?;
+    println!("Training on {} examples ({} → {})", dataset.len(), trainer.input_shape(), trainer.output_shape());
+    if let Some(preprocess) = trainer.preprocess() {
+        let normalization = dataset.normalize(preprocess);
+        println!("Normalizing inputs with mean {:?} and scale {:?}", normalization.mean, normalization.scale);
+    }
+    if !trainer.frozen().is_empty() {
+        println!("Keeping {} frozen parameters fixed", trainer.frozen().len());
+    }
+    
+    trainer.fit(&dataset, &config, |epoch, loss| {
+        println!("epoch {:>4}/{}  loss {:.6}", epoch, config.epochs, loss);
+    })?;
+    
+    let save_file = save_file.unwrap_or_else(|| {
+        let stem = Path::new(source_file).file_stem().and_then(|s| s.to_str()).unwrap_or("model");
+        format!("{}.safetensors", stem)
+    });
+    write_weights(&save_file, trainer.weights())?;
+    println!("Saved {} tensors to {}", trainer.weights().len(), save_file);
+    Ok(())
+}
 
-Builds the app for production to the `build` folder.
+// Print the ids a text input is fed for each text, one sequence per line
+fn tokenize_text(compiler: &UniversalCompiler, args: &[String]) -> Result<(), String> {
+    let mut component = None;
+    let mut source_file = None;
+    let mut texts = Vec::new();
+    for arg in args {
+        if let Some(name) = arg.strip_prefix("--component=") {
+            component = Some(name);
+        } else if source_file.is_none() && !arg.starts_with("--") {
+            source_file = Some(arg.as_str());
+        } else {
+            texts.push(arg.clone());
+        }
+    }
+    
+    let source_file = source_file.ok_or("no source file specified")?;
+    let ast = compiler.analyze(source_file)?;
+    let inputs = tokenizer::text_inputs(&ast);
+    let input = match component {
+        Some(component) => inputs.iter().find(|input| input.component == component)
+            .ok_or_else(|| format!("component `{}` has no tokenized text input", component))?,
+        None => inputs.first().ok_or("the network has no tokenized text input (`T n ⌸ …`)")?,
+    };
+    if texts.is_empty() {
+        texts = std::io::stdin().lines().collect::<Result<_, _>>().map_err(|e| format!("failed to read stdin: {}", e))?;
+    }
+    for text in texts {
+        let ids: Vec<String> = input.tokenizer.encode(&text, input.length).iter().map(u32::to_string).collect();
+        println!("{}", ids.join(" "));
+    }
+    Ok(())
+}
 
-## Learn More
+// Convert an ONNX or Keras model to GaiaScript source
+fn import_model(args: &[String]) -> Result<(), String> {
+    let mut component = "Ñ".to_string();
+    let mut output = None;
+    let mut model_file = None;
+    for arg in args {
+        if let Some(name) = arg.strip_prefix("--component=") {
+            component = name.to_string();
+        } else if let Some(path) = arg.strip_prefix("--output=") {
+            output = Some(path);
+        } else if !arg.starts_with("--") {
+            model_file = Some(arg.as_str());
+        }
+    }
+    let model_file = model_file.ok_or("no model file specified")?;
+    
+    let network = importer::import_file(Path::new(model_file), &component)?;
+    let source = importer::to_source(&network);
+    // The generated source must compile as it is; imports contain no custom operations
+    let (mut ast, _) = parser::parse_with_diagnostics(&source, &CustomOpRegistry::new())
+        .map_err(|diagnostics| format!("generated source does not parse: {}", diagnostics[0]))?;
+    if let Some(diagnostic) = shape_inference::infer_shapes(&mut ast).into_iter().find(|d| d.is_error()) {
+        return Err(format!("generated source does not type-check: {}", diagnostic));
+    }
+    
+    match output {
+        Some(path) => {
+            fs::write(path, &source).map_err(|e| format!("failed to write '{}': {}", path, e))?;
+            println!("Imported {} into {}", model_file, path);
+        },
+        None => print!("{}", source),
+    }
+    Ok(())
+}
 
-This app was generated using GaiaScript's Universal Compiler with React support.
-"#, app_name);
-        let readme_path = react_dir_path.join("README.md");
-        fs::write(&readme_path, readme_content).map_err(|e| format!("Failed to write README.md file: {}", e))?;
-        
-        println!("Generated React application at {}", react_dir);
-        println!("To run the application:");
-        println!("  cd {}", react_dir);
-        println!("  npm install");
-        println!("  npm start");
-        
-        Ok(())
+// Load weights saved by `gaia train`: safetensors, or JSON for `.json` files
+fn read_weights(path: &str) -> Result<Weights, String> {
+    if !path.ends_with(".json") {
+        return safetensors::read(path);
     }
+    let text = fs::read_to_string(path).map_err(|e| format!("failed to read '{}': {}", path, e))?;
+    let json = serde_json::from_str(&text).map_err(|e| format!("invalid weights file '{}': {}", path, e))?;
+    Weights::from_json(&json)
 }
 
-// Helper function to parse web framework from string
-pub fn parse_web_framework(framework_str: &str) -> WebFramework {
-    match framework_str.to_lowercase().as_str() {
-        "react" => WebFramework::React,
-        "angular" => WebFramework::Angular,
-        "vue" => WebFramework::Vue,
-        "svelte" => WebFramework::Svelte,
-        _ => WebFramework::PureJs, // Default to pure JS
+fn write_weights(path: &str, weights: &Weights) -> Result<(), String> {
+    if !path.ends_with(".json") {
+        return safetensors::write(path, weights);
     }
+    let json = serde_json::to_string(&weights.to_json()).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| format!("failed to write '{}': {}", path, e))
 }
 
-// Standalone binary for the universal compiler
-pub fn main() {
-    let args: Vec<String> = env::args().collect();
+// List the weights of a network and check them against the inferred shapes;
+// without a weights file, seeded weights are written to --output
+fn check_weights(compiler: &UniversalCompiler, args: &[String]) -> Result<(), String> {
+    let mut output = None;
+    let mut files = Vec::new();
+    for arg in args {
+        if let Some(path) = arg.strip_prefix("--output=") {
+            output = Some(path);
+        } else if !arg.starts_with("--") {
+            files.push(arg.as_str());
+        }
+    }
     
-    if args.len() < 2 {
-        eprintln!("Usage: gaia [options] <file.gaia>");
-        eprintln!("Options:");
-        eprintln!("  --platform=PLATFORM   Force a specific target platform");
-        eprintln!("                        Supported platforms: macos, windows, linux, ios, android, web");
-        eprintln!("  --framework=FRAMEWORK Force a specific web framework");
-        eprintln!("                        Supported frameworks: js, react, angular, vue, svelte");
-        eprintln!("  --output=DIR          Specify output directory (default: current directory)");
-        eprintln!("  --help                Show this help message");
-        return;
+    let (source_file, weights_file) = match files.as_slice() {
+        [source_file] => (*source_file, None),
+        [source_file, weights_file] => (*source_file, Some(*weights_file)),
+        _ => return Err("expected a source file and optionally a weights file".to_string()),
+    };
+    let ast = compiler.analyze(source_file)?;
+    let weights = match weights_file {
+        Some(path) => read_weights(path)?,
+        None => Weights::for_export(&plan_network(&ast)?, None),
+    };
+    for (name, tensor) in weights.iter() {
+        println!("{:<32} F32 {:?}", safetensors::qualified_name(name), tensor.shape);
     }
+    safetensors::validate(&weights, &ast)?;
+    let parameters: usize = weights.iter().map(|(_, t)| t.len()).sum();
+    println!("{} tensors, {} parameters match {}", weights.len(), parameters, source_file);
     
-    // Process arguments
-    let mut compiler = UniversalCompiler::new();
+    if let Some(output) = output {
+        write_weights(output, &weights)?;
+        println!("Wrote {}", output);
+    }
+    Ok(())
+}
+
+// Quantize the components of a network to int8, report the accuracy change on
+// the calibration set and save the int8 weights
+fn quantize_file(compiler: &UniversalCompiler, args: &[String]) -> Result<(), String> {
     let mut source_file = None;
+    let mut data_file = None;
+    let mut component = None;
+    let mut weights_file = None;
+    let mut output = None;
+    let mut granularity = Granularity::PerChannel;
+    for arg in args {
+        if let Some(path) = arg.strip_prefix("--data=") {
+            data_file = Some(path);
+        } else if let Some(name) = arg.strip_prefix("--component=") {
+            component = Some(name);
+        } else if arg == "--per-tensor" {
+            granularity = Granularity::PerTensor;
+        } else if let Some(path) = arg.strip_prefix("--weights=") {
+            weights_file = Some(path);
+        } else if let Some(path) = arg.strip_prefix("--output=") {
+            output = Some(path.to_string());
+        } else if !arg.starts_with("--") {
+            source_file = Some(arg.as_str());
+        }
+    }
+    
+    let source_file = source_file.ok_or("no source file specified")?;
+    let data_file = data_file.ok_or("no calibration set specified (use --data=FILE)")?;
+    let ast = compiler.analyze(source_file)?;
+    let trained = weights_file.map(read_weights).transpose()?;
+    let interpreter = Interpreter::new(&ast, Weights::for_export(&plan_network(&ast)?, trained.as_ref()))?;
+    let csv = fs::read_to_string(data_file).map_err(|e| format!("failed to read '{}': {}", data_file, e))?;
     
-    for arg in &args[1..] {
-        if arg == "--help" {
-            eprintln!("GaiaScript Universal Compiler");
-            eprintln!("Usage: gaia [options] <file.gaia>");
-            eprintln!("Options:");
-            eprintln!("  --platform=PLATFORM   Force a specific target platform");
-            eprintln!("                        Supported platforms: macos, windows, linux, ios, android, web");
-            eprintln!("  --framework=FRAMEWORK Force a specific web framework");
-            eprintln!("                        Supported frameworks: js, react, angular, vue, svelte");
-            eprintln!("  --output=DIR          Specify output directory (default: current directory)");
-            eprintln!("  --help                Show this help message");
-            return;
-        } else if arg.starts_with("--platform=") {
-            let platform_str = &arg[11..];
+    let network = quantization::quantize_network(&interpreter, &csv, component, granularity);
+    for (component, reason) in &network.skipped {
+        eprintln!("warning: component `{}` stays float: {}", component, reason);
+    }
+    if network.models.is_empty() {
+        return Err("no component could be quantized".to_string());
+    }
+    let mut models = Vec::new();
+    for (model, report) in network.models {
+        let float_size = 4 * model.parameters();
+        println!("{}: {} parameters, {} → {} bytes ({})", model.component, model.parameters(), float_size, model.size(), granularity.name());
+        print!("{}", report);
+        models.push(model);
+    }
+    
+    let output = output.unwrap_or_else(|| {
+        let stem = Path::new(source_file).file_stem().and_then(|s| s.to_str()).unwrap_or("model");
+        format!("{}.int8.safetensors", stem)
+    });
+    fs::write(&output, quantization::encode(&models).0).map_err(|e| format!("failed to write '{}': {}", output, e))?;
+    println!("Saved {} int8 components to {}", models.len(), output);
+    Ok(())
+}
+
+// Compile a source file for the platform and backend chosen by the flags
+fn compile_file(compiler: &mut UniversalCompiler, args: &[String]) -> Result<(), String> {
+    let mut source_file = None;
+    let mut calibration = None;
+    let mut granularity = Granularity::PerChannel;
+    for arg in args {
+        if let Some(platform_str) = arg.strip_prefix("--platform=") {
             let platform = match platform_str {
                 "macos" => Platform::MacOS,
                 "windows" => Platform::Windows,
@@ -415,40 +798,83 @@ pub fn main() {
                 "ios" => Platform::IOS,
                 "android" => Platform::Android,
                 "web" => Platform::Web,
-                _ => {
-                    eprintln!("Error: Unknown platform '{}'", platform_str);
-                    eprintln!("Supported platforms: macos, windows, linux, ios, android, web");
-                    return;
-                }
+                _ => return Err(format!("unknown platform '{}' (supported: macos, windows, linux, ios, android, web)", platform_str)),
             };
             compiler.set_platform(platform);
-        } else if arg.starts_with("--framework=") {
-            let framework_str = &arg[12..];
+        } else if let Some(framework_str) = arg.strip_prefix("--framework=") {
             let framework = parse_web_framework(framework_str);
             compiler.set_web_framework(framework);
-        } else if arg.starts_with("--output=") {
-            let output_dir = &arg[9..];
+        } else if let Some(backend_name) = arg.strip_prefix("--backend=") {
+            compiler.set_backend(backend_name);
+        } else if let Some(format) = arg.strip_prefix("--message-format=") {
+            let format = match format {
+                "json" => MessageFormat::Json,
+                _ => MessageFormat::Human,
+            };
+            compiler.set_message_format(format);
+        } else if let Some(output_dir) = arg.strip_prefix("--output=") {
             compiler.set_output_directory(output_dir);
+        } else if let Some(path) = arg.strip_prefix("--weights=") {
+            compiler.set_weights(read_weights(path)?);
+        } else if let Some(path) = arg.strip_prefix("--calibration=") {
+            calibration = Some(fs::read_to_string(path).map_err(|e| format!("failed to read '{}': {}", path, e))?);
+        } else if arg == "--per-tensor" {
+            granularity = Granularity::PerTensor;
+        } else if let Some(mode) = arg.strip_prefix("--blocks=") {
+            let mode = BlockMode::parse(mode).ok_or_else(|| format!("unknown block mode '{}' (supported: loop, unroll)", mode))?;
+            compiler.set_block_mode(mode);
         } else if !arg.starts_with("--") {
-            source_file = Some(arg.clone());
+            source_file = Some(arg.as_str());
         }
     }
     
-    // Check if source file is provided
-    let source_path = match source_file {
-        Some(path) => path,
-        None => {
-            eprintln!("Error: No source file specified");
-            return;
-        }
-    };
+    if let Some(csv) = calibration {
+        compiler.set_calibration(csv, granularity);
+    }
+    let source_file = source_file.ok_or("no source file specified")?;
     
     // Compile the source file
     let platform = compiler.get_target_platform();
     println!("Compiling for platform: {:?}", platform);
+    compiler.compile(source_file).map_err(|e| format!("compilation failed: {}", e))?;
+    println!("Compilation successful!");
+    Ok(())
+}
+
+// Standalone binary for the universal compiler; exits with status 1 on errors
+// so that scripts can rely on it
+pub fn main() {
+    let args: Vec<String> = env::args().collect();
+    
+    if args.len() < 2 {
+        print_usage();
+        process::exit(1);
+    }
+    if args[1..].iter().any(|arg| arg == "--help") {
+        eprintln!("GaiaScript Universal Compiler");
+        print_usage();
+        return;
+    }
     
-    match compiler.compile(&source_path) {
-        Ok(_) => println!("Compilation successful!"),
-        Err(e) => eprintln!("Compilation failed: {}", e),
+    let mut compiler = UniversalCompiler::new();
+    let result = match args[1].as_str() {
+        "targets" => {
+            list_targets(compiler.registry());
+            Ok(())
+        },
+        "explain" => explain_code(args.get(2).map(|s| s.as_str())),
+        "summary" => summarize_file(&mut compiler, &args[2..]),
+        "diff" => diff_files(&mut compiler, &args[2..]),
+        "graph" => graph_file(&compiler, &args[2..]),
+        "import" => import_model(&args[2..]),
+        "weights" => check_weights(&compiler, &args[2..]),
+        "quantize" => quantize_file(&compiler, &args[2..]),
+        "tokenize" => tokenize_text(&compiler, &args[2..]),
+        "train" => train_file(&compiler, &args[2..]),
+        _ => compile_file(&mut compiler, &args[1..]),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(1);
     }
 }